@@ -0,0 +1,53 @@
+#ifndef BVH_GLSL
+#define BVH_GLSL
+
+#include "ray.glsl"
+
+// Deepest tree that can be traversed, must match bvh::MAX_DEPTH on the Rust side.
+#define BVH_STACK_SIZE 64
+
+// Marks a sphere in BvhPrimitive.instance.
+#define BVH_SPHERE 0xffffffffu
+
+// Nodes the traversal popped since it was last reset, for the debug views.
+uint bvhNodesVisited = 0u;
+
+// Must match BvhNode on the Rust side. Interior nodes have count == 0 and
+// their children at leftOrFirst and leftOrFirst + 1, leaves hold
+// bvhPrimitives[leftOrFirst .. leftOrFirst + count].
+struct BvhNode {
+    vec3 aabbMin;
+    uint leftOrFirst;
+    vec3 aabbMax;
+    uint count;
+};
+
+// A sphere, or a triangle of a mesh instance.
+struct BvhPrimitive {
+    uint instance;
+    uint index;
+};
+
+layout(std430, set = 0, binding = 7) readonly buffer BvhNodeBuffer {
+    BvhNode bvhNodes[];
+};
+
+layout(std430, set = 0, binding = 8) readonly buffer BvhPrimitiveBuffer {
+    BvhPrimitive bvhPrimitives[];
+};
+
+// Slab test, returns the entry distance or a negative value on a miss.
+float intersectAabb(vec3 origin, vec3 invDirection, vec3 aabbMin, vec3 aabbMax, float tMax)
+{
+    vec3 t0 = (aabbMin - origin) * invDirection;
+    vec3 t1 = (aabbMax - origin) * invDirection;
+    vec3 tNear = min(t0, t1);
+    vec3 tFar = max(t0, t1);
+
+    float entry = max(max(tNear.x, tNear.y), max(tNear.z, 0.0));
+    float exit = min(min(tFar.x, tFar.y), min(tFar.z, tMax));
+
+    return entry <= exit ? entry : -1.0;
+}
+
+#endif
@@ -0,0 +1,33 @@
+#ifndef CAMERA_GLSL
+#define CAMERA_GLSL
+
+#include "frame_constants.glsl"
+#include "ray.glsl"
+#include "sampler.glsl"
+
+// CAMERA
+
+struct Camera {
+    vec3 origin, lowerLeftCorner, horizontal, vertical, lensHorizontal, lensVertical;
+    float shutterOpen, shutterClose;
+};
+
+// The view is computed on the CPU, see `Camera::rays`.
+Camera makeCamera()
+{
+    return Camera(frame.cameraOrigin, frame.cameraLowerLeftCorner, frame.cameraHorizontal, frame.cameraVertical,
+        frame.cameraLensHorizontal, frame.cameraLensVertical, frame.cameraShutterOpen, frame.cameraShutterClose);
+}
+
+// The ray through uv on the viewport, which lies on the focus plane, leaving
+// the lens at the point lensSample in [0, 1)^2 maps to, timeSample of the way
+// through the shutter interval.
+Ray cameraRay(Camera c, vec2 uv, vec2 lensSample, float timeSample)
+{
+    vec2 lens = sampleDisk(lensSample);
+    vec3 origin = c.origin + lens.x * c.lensHorizontal + lens.y * c.lensVertical;
+    vec3 direction = normalize(c.lowerLeftCorner + uv.x * c.horizontal + uv.y * c.vertical - origin);
+    return Ray(origin, direction, mix(c.shutterOpen, c.shutterClose, timeSample));
+}
+
+#endif
@@ -0,0 +1,7 @@
+#ifndef COMMON_GLSL
+#define COMMON_GLSL
+
+#define PI 3.1415926535
+#define MAX_FLOAT 99999.99
+
+#endif
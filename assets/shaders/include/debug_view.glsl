@@ -0,0 +1,117 @@
+#ifndef DEBUG_VIEW_GLSL
+#define DEBUG_VIEW_GLSL
+
+// Views that show what the path tracer sees instead of the light it finds,
+// picked with the DEBUG_VIEW specialization constant. Include it after
+// integrator.glsl.
+
+#include "common.glsl"
+#include "gbuffer.glsl"
+#include "integrator.glsl"
+#include "material.glsl"
+#include "ray.glsl"
+#include "sampler.glsl"
+#include "settings.glsl"
+
+// Must match DebugView on the Rust side.
+const uint DEBUG_VIEW_NONE = 0u;
+const uint DEBUG_VIEW_NORMAL = 1u;
+const uint DEBUG_VIEW_ALBEDO = 2u;
+const uint DEBUG_VIEW_MATERIAL = 3u;
+const uint DEBUG_VIEW_DISTANCE = 4u;
+const uint DEBUG_VIEW_BOUNCES = 5u;
+const uint DEBUG_VIEW_VARIANCE = 6u;
+const uint DEBUG_VIEW_BVH_COST = 7u;
+
+// Hit distance where the heatmap is halfway.
+const float DEBUG_DISTANCE_SCALE = 5.0;
+// BVH nodes visited by a camera ray where the heatmap tops out.
+const float DEBUG_MAX_BVH_NODES = 128.0;
+
+// Blue through cyan, green and yellow to red for x from 0 to 1.
+vec3 heatmap(float x)
+{
+    x = clamp(x, 0.0, 1.0) * 4.0;
+    return clamp(vec3(x - 2.0, x < 2.0 ? x : 4.0 - x, 2.0 - x), 0.0, 1.0);
+}
+
+// A color that tells neighbouring ids apart.
+vec3 idColor(uint id)
+{
+    uint hash = pcgHash(id);
+    return vec3(hash & 0xffu, (hash >> 8) & 0xffu, (hash >> 16) & 0xffu) / 255.0;
+}
+
+// The color of r in the current view, and what it hit first. The variance
+// view traces like the final image, see debugVariance().
+vec3 debugColor(Ray r, inout Sampler rng, out FirstHit firstHit)
+{
+    if (DEBUG_VIEW == DEBUG_VIEW_NONE || DEBUG_VIEW == DEBUG_VIEW_VARIANCE)
+    {
+        return rayColor(r, rng, firstHit);
+    }
+
+    if (DEBUG_VIEW == DEBUG_VIEW_BOUNCES)
+    {
+        rayColor(r, rng, firstHit);
+        return heatmap(float(pathBounces) / float(MAX_RECURSION));
+    }
+
+    firstHit = FirstHit(-r.direction, MAX_FLOAT, vec3(1.0));
+
+#ifdef BVH_GLSL
+    bvhNodesVisited = 0u;
+#endif
+    Hit rec;
+    bool didHit = raycast(r, MAX_FLOAT, rec);
+
+    if (DEBUG_VIEW == DEBUG_VIEW_BVH_COST)
+    {
+        // The hardware ray tracing pipeline has no counter and shows nothing,
+        // the renderer uses the fragment shader tracer for this view.
+#ifdef BVH_GLSL
+        return heatmap(float(bvhNodesVisited) / DEBUG_MAX_BVH_NODES);
+#else
+        return vec3(0.0);
+#endif
+    }
+
+    if (!didHit)
+    {
+        return vec3(0.0);
+    }
+
+    Material material = materials[rec.material];
+    material.albedo = materialAlbedo(material, rec);
+    firstHit = FirstHit(rec.normal, rec.t, material.albedo);
+
+    if (DEBUG_VIEW == DEBUG_VIEW_NORMAL)
+    {
+        return rec.normal * 0.5 + 0.5;
+    }
+    if (DEBUG_VIEW == DEBUG_VIEW_ALBEDO)
+    {
+        return material.albedo;
+    }
+    if (DEBUG_VIEW == DEBUG_VIEW_MATERIAL)
+    {
+        return idColor(rec.material);
+    }
+    return heatmap(rec.t / (rec.t + DEBUG_DISTANCE_SCALE));
+}
+
+// The variance of the luminance of a pixel's samples as a heatmap, on a log
+// scale from 2^-10 to 4. Zero with a single sample per pixel.
+vec3 debugVariance(float luminanceSum, float luminanceSquaredSum)
+{
+    float n = float(SAMPLES_PER_PIXEL);
+    if (n < 2.0)
+    {
+        return vec3(0.0);
+    }
+
+    float variance = max(luminanceSquaredSum - luminanceSum * luminanceSum / n, 0.0) / (n - 1.0);
+    return heatmap((log2(max(variance, 1e-10)) + 10.0) / 12.0);
+}
+
+#endif
@@ -0,0 +1,194 @@
+#ifndef DENOISE_GLSL
+#define DENOISE_GLSL
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010) with SVGF's
+// variance guided luminance weights (Schied et al. 2017). Ported to the CPU in
+// denoiser.rs, so it can be tested there. Change both together.
+//
+// The filter reads its inputs through the functions declared below, which the
+// including shader defines. denoise.comp reads images, the golden value test
+// shared memory.
+
+#include "gbuffer.glsl"
+
+// The tracer's running average.
+vec3 denoiseColor(ivec2 p);
+// The G-buffer, see gbuffer.glsl.
+vec4 denoiseNormalDepth(ivec2 p);
+vec4 denoiseAlbedo(ivec2 p);
+// The previous iteration's output, demodulated color in rgb and its variance
+// in a.
+vec4 denoiseFiltered(ivec2 p);
+
+// Must match the constants in denoiser.rs.
+const float SIGMA_LUMINANCE = 4.0;
+const float SIGMA_NORMAL = 128.0;
+const float SIGMA_DEPTH = 1.0;
+// Depth differences below this fraction of the depth are ignored.
+const float DEPTH_TOLERANCE = 0.01;
+// Frames the moments need before they are trusted over the neighbours.
+const uint MIN_TEMPORAL_FRAMES = 4u;
+// The B3 spline, from the center out.
+const float KERNEL[3] = float[](3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0);
+
+bool inside(ivec2 p, ivec2 size)
+{
+    return all(greaterThanEqual(p, ivec2(0))) && all(lessThan(p, size));
+}
+
+float demodulatedLuminance(ivec2 p)
+{
+    return dot(LUMINANCE, demodulate(denoiseColor(p), denoiseAlbedo(p).rgb));
+}
+
+// The demodulated color at p and the variance of its mean over frames, at
+// least 1.
+vec4 estimateVariance(ivec2 p, ivec2 size, uint frames)
+{
+    vec4 albedo = denoiseAlbedo(p);
+    vec3 irradiance = demodulate(denoiseColor(p), albedo.rgb);
+    float l = dot(LUMINANCE, irradiance);
+
+    if (frames >= MIN_TEMPORAL_FRAMES)
+    {
+        // The moments are over single frames, their mean varies less.
+        return vec4(irradiance, max(albedo.a - l * l, 0.0) / float(frames));
+    }
+
+    // Too few frames for the moments, the spread of the neighbours stands in.
+    float sum = 0.0;
+    float sumSquared = 0.0;
+    float count = 0.0;
+    for (int y = -1; y <= 1; y++)
+    {
+        for (int x = -1; x <= 1; x++)
+        {
+            ivec2 q = p + ivec2(x, y);
+            if (inside(q, size))
+            {
+                float lq = demodulatedLuminance(q);
+                sum += lq;
+                sumSquared += lq * lq;
+                count += 1.0;
+            }
+        }
+    }
+
+    float mean = sum / count;
+    return vec4(irradiance, max(sumSquared / count - mean * mean, 0.0));
+}
+
+// The variance around p blurred with a 3x3 Gaussian, a single pixel's is too
+// noisy to steer the filter.
+float blurredVariance(ivec2 p, ivec2 size)
+{
+    const float weights[2] = float[](0.5, 0.25);
+    float sum = 0.0;
+    float total = 0.0;
+    for (int y = -1; y <= 1; y++)
+    {
+        for (int x = -1; x <= 1; x++)
+        {
+            ivec2 q = p + ivec2(x, y);
+            if (inside(q, size))
+            {
+                float w = weights[abs(x)] * weights[abs(y)];
+                sum += w * denoiseFiltered(q).a;
+                total += w;
+            }
+        }
+    }
+    return sum / total;
+}
+
+// How fast the depth changes per pixel at p. Each axis takes the difference to
+// the neighbour closer in depth, so silhouettes don't count as slopes.
+vec2 depthGradient(ivec2 p, float depth, ivec2 size)
+{
+    vec2 gradient = vec2(0.0);
+    for (int axis = 0; axis < 2; axis++)
+    {
+        ivec2 offset = ivec2(0);
+        offset[axis] = 1;
+
+        float forward = MAX_FLOAT;
+        float backward = MAX_FLOAT;
+        if (inside(p + offset, size))
+        {
+            forward = denoiseNormalDepth(p + offset).w - depth;
+        }
+        if (inside(p - offset, size))
+        {
+            backward = depth - denoiseNormalDepth(p - offset).w;
+        }
+
+        float difference = abs(forward) < abs(backward) ? forward : backward;
+        gradient[axis] = difference == MAX_FLOAT ? 0.0 : difference;
+    }
+    return gradient;
+}
+
+// How much a tap offset from the filtered pixel contributes, by how alike their
+// first hits and luminance are.
+float edgeWeight(vec4 normalDepthP, vec4 normalDepthQ, vec2 gradient, vec2 offset, float lp, float lq, float sigmaL)
+{
+    float normalWeight = pow(max(dot(normalDepthP.xyz, normalDepthQ.xyz), 0.0), SIGMA_NORMAL);
+
+    float depthScale = SIGMA_DEPTH * abs(dot(gradient, offset)) + DEPTH_TOLERANCE * normalDepthP.w;
+    float depthWeight = exp(-abs(normalDepthP.w - normalDepthQ.w) / max(depthScale, 1e-6));
+
+    float luminanceWeight = exp(-abs(lp - lq) / (sigmaL + 1e-10));
+
+    return normalWeight * depthWeight * luminanceWeight;
+}
+
+// Iteration 1 and later filter with a 5x5 kernel whose taps are
+// 2^(iteration - 1) pixels apart.
+vec4 filterIteration(ivec2 p, ivec2 size, uint iteration)
+{
+    vec4 center = denoiseFiltered(p);
+    vec4 normalDepth = denoiseNormalDepth(p);
+    vec2 gradient = depthGradient(p, normalDepth.w, size);
+    float l = dot(LUMINANCE, center.rgb);
+    float sigmaL = SIGMA_LUMINANCE * sqrt(blurredVariance(p, size));
+    int stepSize = 1 << (iteration - 1u);
+
+    // The center always counts in full, even where its normal is degenerate.
+    float weightSum = KERNEL[0] * KERNEL[0];
+    vec3 sum = center.rgb * weightSum;
+    float variance = center.a * weightSum * weightSum;
+
+    for (int y = -2; y <= 2; y++)
+    {
+        for (int x = -2; x <= 2; x++)
+        {
+            ivec2 offset = ivec2(x, y) * stepSize;
+            ivec2 q = p + offset;
+            if ((x == 0 && y == 0) || !inside(q, size))
+            {
+                continue;
+            }
+
+            vec4 tap = denoiseFiltered(q);
+            float lq = dot(LUMINANCE, tap.rgb);
+            vec4 normalDepthQ = denoiseNormalDepth(q);
+
+            float w = KERNEL[abs(x)] * KERNEL[abs(y)]
+                * edgeWeight(normalDepth, normalDepthQ, gradient, vec2(offset), l, lq, sigmaL);
+            sum += tap.rgb * w;
+            variance += tap.a * w * w;
+            weightSum += w;
+        }
+    }
+
+    return vec4(sum / weightSum, variance / (weightSum * weightSum));
+}
+
+// The last iteration's demodulated color at p with the albedo multiplied back
+// in.
+vec3 remodulate(ivec2 p, vec3 irradiance)
+{
+    return irradiance * max(denoiseAlbedo(p).rgb, vec3(MIN_ALBEDO));
+}
+
+#endif
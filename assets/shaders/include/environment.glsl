@@ -0,0 +1,121 @@
+#ifndef ENVIRONMENT_GLSL
+#define ENVIRONMENT_GLSL
+
+#include "common.glsl"
+#include "frame_constants.glsl"
+
+// An equirectangular image, see Environment on the Rust side. Without one
+// environmentWidth is zero and the sky is a gradient instead.
+layout(std430, set = 0, binding = 10) readonly buffer EnvironmentBuffer {
+    uint environmentWidth;
+    uint environmentHeight;
+    uint environmentPad0, environmentPad1;
+    vec4 environmentPixels[];
+};
+
+// EnvironmentDistribution: environmentHeight + 1 entries picking the row, then
+// environmentWidth + 1 per row picking the column.
+layout(std430, set = 0, binding = 11) readonly buffer EnvironmentCdfBuffer {
+    float environmentCdf[];
+};
+
+bool hasEnvironment()
+{
+    return environmentWidth > 0u;
+}
+
+// Rotates by frame.environmentRotation around +Y, or back with a negative sign.
+vec3 rotateEnvironment(vec3 d, float sign)
+{
+    float s = sin(sign * frame.environmentRotation);
+    float c = cos(sign * frame.environmentRotation);
+    return vec3(c * d.x + s * d.z, d.y, -s * d.x + c * d.z);
+}
+
+// The top row looks up along +Y, the middle column along -Z.
+vec2 directionToUv(vec3 d)
+{
+    d = normalize(d);
+    return vec2(0.5 + atan(d.x, -d.z) * 0.5 / PI, acos(clamp(d.y, -1.0, 1.0)) / PI);
+}
+
+vec3 uvToDirection(vec2 uv)
+{
+    float phi = 2.0 * PI * (uv.x - 0.5);
+    float theta = PI * uv.y;
+    return vec3(sin(theta) * sin(phi), cos(theta), -sin(theta) * cos(phi));
+}
+
+uvec2 environmentPixel(vec2 uv)
+{
+    return min(uvec2(uv * vec2(environmentWidth, environmentHeight)), uvec2(environmentWidth, environmentHeight) - 1u);
+}
+
+// The light arriving from the world space direction d.
+vec3 environmentRadiance(vec3 d)
+{
+    if (!hasEnvironment())
+    {
+        vec3 unitDirection = normalize(d);
+        float t = 0.5 * (unitDirection.y + 1.0);
+        return mix(vec3(1.0), vec3(0.5,0.7,1.0), t);
+    }
+
+    uvec2 pixel = environmentPixel(directionToUv(rotateEnvironment(d, -1.0)));
+    return environmentPixels[pixel.y * environmentWidth + pixel.x].rgb * frame.environmentIntensity;
+}
+
+// The last index of cdf[first .. first + count] at most u, skipping empty buckets.
+uint sampleCdf(uint first, uint count, float u)
+{
+    uint lo = 0u;
+    uint hi = count - 1u;
+    while (lo + 1u < hi)
+    {
+        uint mid = (lo + hi) / 2u;
+        if (environmentCdf[first + mid] <= u)
+        {
+            lo = mid;
+        }
+        else
+        {
+            hi = mid;
+        }
+    }
+    return lo;
+}
+
+// The density per unit solid angle sampleEnvironment() picks the world space
+// direction d with. Mirrors EnvironmentDistribution::pdf on the Rust side.
+float environmentPdf(vec3 d)
+{
+    vec2 uv = directionToUv(rotateEnvironment(d, -1.0));
+    uvec2 pixel = environmentPixel(uv);
+    uint row = environmentHeight + 1u + pixel.y * (environmentWidth + 1u);
+
+    float pdfUv = (environmentCdf[pixel.y + 1u] - environmentCdf[pixel.y]) * float(environmentHeight)
+        * (environmentCdf[row + pixel.x + 1u] - environmentCdf[row + pixel.x]) * float(environmentWidth);
+
+    float sinTheta = sin(PI * uv.y);
+    return sinTheta > 0.0 ? pdfUv / (2.0 * PI * PI * sinTheta) : 0.0;
+}
+
+// Picks a world space direction proportional to the brightness of the
+// environment with u in [0, 1)². Mirrors EnvironmentDistribution::sample.
+vec3 sampleEnvironment(vec2 u, out float pdf)
+{
+    uint y = sampleCdf(0u, environmentHeight + 1u, u.y);
+    uint row = environmentHeight + 1u + y * (environmentWidth + 1u);
+    uint x = sampleCdf(row, environmentWidth + 1u, u.x);
+
+    // Uniform within the pixel.
+    float du = (u.x - environmentCdf[row + x]) / (environmentCdf[row + x + 1u] - environmentCdf[row + x]);
+    float dv = (u.y - environmentCdf[y]) / (environmentCdf[y + 1u] - environmentCdf[y]);
+    vec2 uv = (vec2(x, y) + clamp(vec2(du, dv), 0.0, 1.0)) / vec2(environmentWidth, environmentHeight);
+
+    vec3 d = rotateEnvironment(uvToDirection(uv), 1.0);
+    pdf = environmentPdf(d);
+    return d;
+}
+
+#endif
@@ -0,0 +1,26 @@
+#ifndef FRAME_CONSTANTS_GLSL
+#define FRAME_CONSTANTS_GLSL
+
+// Written by the renderer at the start of every frame, see `GpuFrameConstants`.
+layout(std430, set = 0, binding = 3) readonly buffer FrameConstants {
+    vec3 cameraOrigin;
+    float time;
+    vec3 cameraLowerLeftCorner;
+    uint accumulatedFrames;
+    vec3 cameraHorizontal;
+    uint frameIndex;
+    vec3 cameraVertical;
+    uint pad0;
+    // The lens radius along the camera's right and up vectors.
+    vec3 cameraLensHorizontal;
+    // Radians around +Y.
+    float environmentRotation;
+    vec3 cameraLensVertical;
+    float environmentIntensity;
+    // The scene time rays are spread over.
+    float cameraShutterOpen;
+    float cameraShutterClose;
+    uint pad1, pad2;
+} frame;
+
+#endif
@@ -0,0 +1,64 @@
+#ifndef GBUFFER_GLSL
+#define GBUFFER_GLSL
+
+// First-hit AOVs for the denoiser, averaged over every frame like the color.
+// Define GBUFFER_SET as the descriptor set the pipeline's GBuffer is bound to
+// before including this to declare its images. Without it, only the constants
+// and demodulate() are declared.
+
+#include "common.glsl"
+
+#ifdef GBUFFER_SET
+
+// The normal in xyz and the distance to the first hit in w, MAX_FLOAT for
+// rays that escaped.
+layout(set = GBUFFER_SET, binding = 0, rgba32f) uniform image2D gbufferNormalDepth;
+// The albedo in rgb, and the mean over frames of the squared luminance of
+// each frame's demodulated color in a.
+layout(set = GBUFFER_SET, binding = 1, rgba32f) uniform image2D gbufferAlbedo;
+
+#endif
+
+// Must match the constants in denoiser.rs.
+const vec3 LUMINANCE = vec3(0.2126, 0.7152, 0.0722);
+// Keeps black surfaces from dividing by zero when demodulating.
+const float MIN_ALBEDO = 0.001;
+
+// What a camera ray found first, a surface or a medium, see rayColor().
+struct FirstHit {
+    vec3 normal;
+    float depth;
+    // 1 where there is no texture detail to keep out of the filter.
+    vec3 albedo;
+};
+
+vec3 demodulate(vec3 color, vec3 albedo)
+{
+    return color / max(albedo, vec3(MIN_ALBEDO));
+}
+
+#ifdef GBUFFER_SET
+
+// Averages this frame's first hits and the second moment of its color into
+// the G-buffer, with the same weights as the color's running average over
+// accumulatedFrames earlier frames.
+void accumulateGBuffer(ivec2 texel, vec3 color, FirstHit hit, uint accumulatedFrames)
+{
+    float l = dot(LUMINANCE, demodulate(color, hit.albedo));
+    vec4 normalDepth = vec4(hit.normal, hit.depth);
+    vec4 albedo = vec4(hit.albedo, l * l);
+
+    if (accumulatedFrames > 0u)
+    {
+        float weight = 1.0 / float(accumulatedFrames + 1u);
+        normalDepth = mix(imageLoad(gbufferNormalDepth, texel), normalDepth, weight);
+        albedo = mix(imageLoad(gbufferAlbedo, texel), albedo, weight);
+    }
+
+    imageStore(gbufferNormalDepth, texel, normalDepth);
+    imageStore(gbufferAlbedo, texel, albedo);
+}
+
+#endif
+
+#endif
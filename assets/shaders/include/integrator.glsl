@@ -0,0 +1,339 @@
+#ifndef INTEGRATOR_GLSL
+#define INTEGRATOR_GLSL
+
+// The path tracing loop shared by the fragment and ray tracing pipelines.
+// Include it after defining
+//
+//     bool raycast(Ray r, float tMax, inout Hit h)
+//
+// which finds the closest hit along r up to tMax.
+//
+// The surface and environment lighting is ported to the CPU in integrator.rs,
+// so its multiple importance sampling can be tested there. Change both
+// together.
+
+#include "common.glsl"
+#include "environment.glsl"
+#include "gbuffer.glsl"
+#include "light.glsl"
+#include "material.glsl"
+#include "medium.glsl"
+#include "ray.glsl"
+#include "sampler.glsl"
+#include "settings.glsl"
+
+#ifdef RAY_QUERY
+#include "ray_query.glsl"
+#endif
+
+// Veach's power heuristic with beta = 2, the weight of a sample with density
+// pdf against one from another strategy with density otherPdf.
+float powerHeuristic(float pdf, float otherPdf)
+{
+    float a = pdf * pdf;
+    float b = otherPdf * otherPdf;
+    return a + b > 0.0 ? a / (a + b) : 0.0;
+}
+
+// Surfaces that get next event estimation. Their scatter() samples the cosine
+// weighted hemisphere.
+bool isDiffuse(Material m)
+{
+    return m.kind == MATERIAL_LAMBERTIAN || (m.kind == MATERIAL_METAL && !ENABLE_METAL);
+}
+
+// The chance of direct lighting sampling the environment map rather than the
+// light list. The sky gradient isn't sampled.
+float environmentProbability()
+{
+    if (!hasEnvironment())
+    {
+        return 0.0;
+    }
+    return lightCount > 0u ? 0.5 : 1.0;
+}
+
+// Picks a light or the environment and a direction from origin towards it.
+// Returns the light arriving from there if nothing is in the way, along with
+// the distance to the light and the density of the direction, zero if
+// sampling failed.
+vec3 sampleIncidentLight(vec3 origin, inout Sampler rng, out vec3 direction, out float distance, out float pdf)
+{
+    float pick = sample1D(rng);
+    vec2 u = sample2D(rng);
+    float environmentChance = environmentProbability();
+
+    if (pick < environmentChance)
+    {
+        direction = sampleEnvironment(u, pdf);
+        pdf *= environmentChance;
+        distance = MAX_FLOAT;
+        return environmentRadiance(direction);
+    }
+
+    // Reuse pick to pick the light.
+    pick = (pick - environmentChance) / (1.0 - environmentChance);
+
+    uint light;
+    LightSample s;
+    if (!sampleLight(origin, vec3(pick, u), light, s))
+    {
+        pdf = 0.0;
+        return vec3(0.0);
+    }
+
+    direction = s.direction;
+    // Stop short of the light so it doesn't shadow itself.
+    distance = s.distance * 0.999;
+    pdf = s.pdf * (1.0 - environmentChance);
+    return materials[lights[light].material].emission;
+}
+
+// How much light from origin makes it distance along direction at time,
+// through media and past surfaces.
+float visibility(vec3 origin, vec3 direction, float distance, float time)
+{
+    Ray shadowRay = Ray(origin, direction, time);
+#ifdef RAY_QUERY
+    if (RAY_QUERIES)
+    {
+        return occluded(shadowRay, distance) ? 0.0 : transmittance(shadowRay, distance);
+    }
+#endif
+    Hit shadow;
+    if (raycast(shadowRay, distance, shadow))
+    {
+        return 0.0;
+    }
+    return transmittance(shadowRay, distance);
+}
+
+// A ray towards a light, and the light it brings if nothing is in the way.
+struct ShadowRay {
+    vec3 origin;
+    float distance;
+    vec3 direction;
+    float time;
+    vec3 radiance;
+};
+
+// A shadow ray that brings nothing and isn't traced.
+ShadowRay noShadowRay()
+{
+    return ShadowRay(vec3(0.0), 0.0, vec3(0.0, 1.0, 0.0), 0.0, vec3(0.0));
+}
+
+// The light shadow brings past surfaces and through media.
+vec3 traceShadowRay(ShadowRay shadow)
+{
+    if (all(equal(shadow.radiance, vec3(0.0))))
+    {
+        return vec3(0.0);
+    }
+    return shadow.radiance * visibility(shadow.origin, shadow.direction, shadow.distance, shadow.time);
+}
+
+// A shadow ray from the diffuse hit rec at time towards a randomly picked
+// light or the environment, its light weighted against the chance of
+// scatter() finding the same light.
+ShadowRay sampleDirectLight(Hit rec, float time, Material material, inout Sampler rng)
+{
+    vec3 origin = rec.point + rec.normal * 0.001;
+    vec3 direction;
+    float distance;
+    float pdf;
+    vec3 emission = sampleIncidentLight(origin, rng, direction, distance, pdf);
+
+    float cosine = dot(direction, rec.normal);
+    if (cosine <= 0.0 || pdf <= 0.0)
+    {
+        return noShadowRay();
+    }
+
+    vec3 brdf = material.albedo / PI;
+    float bsdfPdf = cosine / PI;
+
+    vec3 radiance = brdf * cosine * emission * powerHeuristic(pdf, bsdfPdf) / pdf;
+    return ShadowRay(origin, distance, direction, time, radiance);
+}
+
+// Like sampleDirectLight(), for light scattered at point in the medium towards
+// the reverse of the normalized direction the path arrived along.
+ShadowRay sampleDirectLightInMedium(vec3 point, vec3 incoming, float time, Medium medium, inout Sampler rng)
+{
+    vec3 direction;
+    float distance;
+    float pdf;
+    vec3 emission = sampleIncidentLight(point, rng, direction, distance, pdf);
+    if (pdf <= 0.0)
+    {
+        return noShadowRay();
+    }
+
+    // The phase function is its own density.
+    float phase = phasePdf(medium, dot(incoming, direction));
+    vec3 radiance = phase * emission * powerHeuristic(pdf, phase) / pdf;
+    return ShadowRay(point, distance, direction, time, radiance);
+}
+
+// Everything a path carries from one bounce to the next. The wavefront tracer
+// keeps it in a buffer between its stages.
+struct PathState {
+    // Where the path goes next.
+    Ray ray;
+    vec3 throughput;
+    // The density scatter() picked ray's direction with at a diffuse hit, or
+    // the phase function in a medium. Zero for camera rays and after specular
+    // bounces, which lights can't sample.
+    float bsdfPdf;
+    // The light found so far.
+    vec3 radiance;
+    // How many times the path scattered, for the debug views.
+    uint bounces;
+    FirstHit firstHit;
+    Sampler rng;
+};
+
+// A path leaving the camera along r.
+PathState startPath(Ray r, Sampler rng)
+{
+    FirstHit firstHit = FirstHit(-r.direction, MAX_FLOAT, vec3(1.0));
+    return PathState(r, vec3(1.0), 0.0, vec3(0.0), 0u, firstHit, rng);
+}
+
+// Whether smoke or fog scatters the path on bounce before it reaches tMax,
+// the distance to the surface it hits.
+bool sampleMediumCollision(inout PathState path, uint bounce, float tMax, out Collision collision)
+{
+    seekDimension(path.rng, bounce, MEDIUM_DIMENSION);
+    return sampleCollision(path.ray, tMax, sample1D(path.rng), collision);
+}
+
+// Scatters the path at collision on bounce. Light from there that reaches the
+// path is left in shadow.
+void scatterInMedium(inout PathState path, uint bounce, Collision collision, out ShadowRay shadow)
+{
+    vec3 point = rayAt(path.ray, collision.t);
+    path.throughput *= collision.medium.albedo;
+    if (bounce == 0u)
+    {
+        path.firstHit.depth = collision.t;
+    }
+
+    shadow = noShadowRay();
+    if (bounce + 1u < MAX_RECURSION)
+    {
+        seekDimension(path.rng, bounce, LIGHT_DIMENSION);
+        shadow = sampleDirectLightInMedium(point, path.ray.direction, path.ray.time, collision.medium, path.rng);
+        shadow.radiance *= path.throughput;
+    }
+
+    seekDimension(path.rng, bounce, SCATTER_DIMENSION);
+    vec3 direction = samplePhase(collision.medium, path.ray.direction, sample2D(path.rng));
+    path.bsdfPdf = phasePdf(collision.medium, dot(path.ray.direction, direction));
+    path.ray = Ray(point, direction, path.ray.time);
+    path.bounces++;
+}
+
+// The environment's light reaching the path as it escapes the scene.
+vec3 escapedLight(PathState path)
+{
+    float weight = 1.0;
+    float environmentChance = environmentProbability();
+    if (path.bsdfPdf > 0.0 && environmentChance > 0.0)
+    {
+        weight = powerHeuristic(path.bsdfPdf, environmentPdf(path.ray.direction) * environmentChance);
+    }
+    return path.throughput * environmentRadiance(path.ray.direction) * weight;
+}
+
+// Adds the light the surface the path hit at rec on bounce emits, and
+// scatters the path off material, materials[rec.material] before texturing.
+// Light from a light source that reaches the hit is left in shadow. Returns
+// false if the path ends there.
+bool shadeSurface(inout PathState path, uint bounce, Hit rec, Material material, out ShadowRay shadow)
+{
+    material.albedo = materialAlbedo(material, rec);
+    if (bounce == 0u)
+    {
+        vec3 albedo = material.kind == MATERIAL_EMISSIVE ? vec3(1.0) : material.albedo;
+        path.firstHit = FirstHit(rec.normal, rec.t, albedo);
+    }
+
+    // The previous hit already sampled this light directly.
+    float weight = 1.0;
+    if (path.bsdfPdf > 0.0 && rec.light != NO_LIGHT)
+    {
+        float pdf = lightPdf(rec.light, path.ray.origin, rec.point) * (1.0 - environmentProbability());
+        weight = powerHeuristic(path.bsdfPdf, pdf);
+    }
+    path.radiance += path.throughput * material.emission * weight;
+
+    bool diffuse = isDiffuse(material);
+
+    // Light found by the next hit only counts if there is one.
+    shadow = noShadowRay();
+    if (diffuse && bounce + 1u < MAX_RECURSION)
+    {
+        seekDimension(path.rng, bounce, LIGHT_DIMENSION);
+        shadow = sampleDirectLight(rec, path.ray.time, material, path.rng);
+        shadow.radiance *= path.throughput;
+    }
+
+    seekDimension(path.rng, bounce, SCATTER_DIMENSION);
+    vec3 attenuation;
+    if (!scatter(material, path.ray, rec, path.rng, attenuation))
+    {
+        return false;
+    }
+    path.throughput *= attenuation;
+    path.bounces++;
+    path.bsdfPdf = diffuse ? max(dot(path.ray.direction, rec.normal), 0.0) / PI : 0.0;
+    return true;
+}
+
+// How many times the path last traced by rayColor() scattered, for the debug
+// views.
+uint pathBounces = 0u;
+
+// The light arriving along r, and what r hit first for the denoiser.
+vec3 rayColor(Ray r, inout Sampler rng, out FirstHit firstHit)
+{
+    PathState path = startPath(r, rng);
+
+    for (uint i = 0u; i < MAX_RECURSION; i++)
+    {
+        Hit rec;
+        bool hitSurface = raycast(path.ray, MAX_FLOAT, rec);
+
+        // Smoke or fog in front of the surface scatters the path first.
+        Collision collision;
+        ShadowRay shadow;
+        if (sampleMediumCollision(path, i, hitSurface ? rec.t : MAX_FLOAT, collision))
+        {
+            scatterInMedium(path, i, collision, shadow);
+            path.radiance += traceShadowRay(shadow);
+            continue;
+        }
+
+        if (!hitSurface)
+        {
+            path.radiance += escapedLight(path);
+            break;
+        }
+
+        bool scattered = shadeSurface(path, i, rec, materials[rec.material], shadow);
+        path.radiance += traceShadowRay(shadow);
+        if (!scattered)
+        {
+            break;
+        }
+    }
+
+    rng = path.rng;
+    firstHit = path.firstHit;
+    pathBounces = path.bounces;
+    return path.radiance;
+}
+
+#endif
@@ -0,0 +1,170 @@
+#ifndef LIGHT_GLSL
+#define LIGHT_GLSL
+
+#include "common.glsl"
+#include "ray.glsl"
+
+// Must match the constants in Light on the Rust side.
+const uint LIGHT_SPHERE = 0u;
+const uint LIGHT_TRIANGLE = 1u;
+
+// Must match GpuLight on the Rust side. Spheres keep their center in p0 and
+// radius in p1.x, triangles their world space vertices. Triangles emit from
+// both faces.
+struct Light {
+    vec3 p0;
+    uint kind;
+    vec3 p1;
+    uint material;
+    vec3 p2;
+    uint pad;
+};
+
+layout(std430, set = 0, binding = 9) readonly buffer LightBuffer {
+    uint lightCount;
+    uint lightPad0, lightPad1, lightPad2;
+    Light lights[];
+};
+
+// A direction towards a light from sampleLight().
+struct LightSample {
+    vec3 direction;
+    // To the light's surface along direction.
+    float distance;
+    // Per unit solid angle, including picking the light.
+    float pdf;
+};
+
+// One minus the cosine of the half-angle of the cone a sphere subtends,
+// without cancellation for far away spheres.
+float oneMinusCosCone(float distanceSquared, float radius)
+{
+    float sinSquaredMax = radius * radius / distanceSquared;
+    float cosMax = sqrt(max(1.0 - sinSquaredMax, 0.0));
+    return sinSquaredMax / (1.0 + cosMax);
+}
+
+// Converts the uniform area density of a triangle to solid angle, zero when
+// it's seen edge on.
+float trianglePdf(Light light, vec3 direction, float distance)
+{
+    vec3 normal = cross(light.p1 - light.p0, light.p2 - light.p0);
+    float doubleArea = length(normal);
+    float cosine = doubleArea > 0.0 ? abs(dot(normal, direction)) / doubleArea : 0.0;
+    if (cosine < 1e-6)
+    {
+        return 0.0;
+    }
+
+    return distance * distance / (0.5 * doubleArea * cosine);
+}
+
+// Duff et al., "Building an Orthonormal Basis, Revisited".
+void orthonormalBasis(vec3 n, out vec3 t, out vec3 b)
+{
+    float s = n.z >= 0.0 ? 1.0 : -1.0;
+    float a = -1.0 / (s + n.z);
+    float c = n.x * n.y * a;
+    t = vec3(1.0 + s * n.x * n.x * a, s * c, -s * n.x);
+    b = vec3(c, s + n.y * n.y * a, -n.y);
+}
+
+// The density sampleLight() from origin would have picked the direction to
+// point on the light with. Mirrors LightList::pdf on the Rust side.
+float lightPdf(uint index, vec3 origin, vec3 point)
+{
+    Light light = lights[index];
+    float pdf;
+
+    if (light.kind == LIGHT_SPHERE)
+    {
+        vec3 toCenter = light.p0 - origin;
+        float distanceSquared = dot(toCenter, toCenter);
+        float radius = light.p1.x;
+        if (distanceSquared <= radius * radius)
+        {
+            return 0.0;
+        }
+
+        pdf = 1.0 / (2.0 * PI * oneMinusCosCone(distanceSquared, radius));
+    }
+    else
+    {
+        vec3 toPoint = point - origin;
+        float distance = length(toPoint);
+        if (distance <= 0.0)
+        {
+            return 0.0;
+        }
+
+        pdf = trianglePdf(light, toPoint / distance, distance);
+    }
+
+    return pdf / float(lightCount);
+}
+
+// Picks a light uniformly with u.x and a direction towards it with u.yz. Spheres
+// are sampled over the cone they subtend, triangles over their area. Mirrors
+// LightList::sample on the Rust side.
+bool sampleLight(vec3 origin, vec3 u, out uint index, out LightSample s)
+{
+    if (lightCount == 0u)
+    {
+        return false;
+    }
+
+    index = min(uint(u.x * float(lightCount)), lightCount - 1u);
+    Light light = lights[index];
+
+    if (light.kind == LIGHT_SPHERE)
+    {
+        vec3 toCenter = light.p0 - origin;
+        float distanceSquared = dot(toCenter, toCenter);
+        float radius = light.p1.x;
+        if (distanceSquared <= radius * radius)
+        {
+            return false;
+        }
+
+        vec3 w = toCenter / sqrt(distanceSquared);
+        float oneMinusCosMax = oneMinusCosCone(distanceSquared, radius);
+
+        float cosTheta = 1.0 - u.y * oneMinusCosMax;
+        float sinTheta = sqrt(max(1.0 - cosTheta * cosTheta, 0.0));
+        float phi = 2.0 * PI * u.z;
+        vec3 t, b;
+        orthonormalBasis(w, t, b);
+        s.direction = normalize(t * (sinTheta * cos(phi)) + b * (sinTheta * sin(phi)) + w * cosTheta);
+
+        // The near intersection, the direction is inside the cone.
+        float halfB = dot(origin - light.p0, s.direction);
+        float c = distanceSquared - radius * radius;
+        s.distance = -halfB - sqrt(max(halfB * halfB - c, 0.0));
+        s.pdf = 1.0 / (2.0 * PI * oneMinusCosMax);
+    }
+    else
+    {
+        float su = sqrt(u.y);
+        float b0 = 1.0 - su;
+        float b1 = u.z * su;
+        vec3 point = light.p0 * b0 + light.p1 * b1 + light.p2 * (1.0 - b0 - b1);
+
+        vec3 toPoint = point - origin;
+        s.distance = length(toPoint);
+        if (s.distance <= 0.0)
+        {
+            return false;
+        }
+        s.direction = toPoint / s.distance;
+        s.pdf = trianglePdf(light, s.direction, s.distance);
+        if (s.pdf <= 0.0)
+        {
+            return false;
+        }
+    }
+
+    s.pdf /= float(lightCount);
+    return true;
+}
+
+#endif
@@ -0,0 +1,131 @@
+#ifndef MATERIAL_GLSL
+#define MATERIAL_GLSL
+
+#include "ray.glsl"
+#include "sampler.glsl"
+#include "settings.glsl"
+#include "texture.glsl"
+
+// Must match the constants in Material on the Rust side.
+const uint MATERIAL_LAMBERTIAN = 0u;
+const uint MATERIAL_METAL = 1u;
+const uint MATERIAL_DIELECTRIC = 2u;
+const uint MATERIAL_EMISSIVE = 3u;
+
+struct Material {
+    vec3 albedo;
+    uint kind;
+    vec3 emission;
+    // Fuzz for metals, the index of refraction for dielectrics.
+    float param;
+    // The odd cubes of a checker.
+    vec3 oddAlbedo;
+    // One of the TEXTURE_* constants.
+    uint texture;
+    float textureScale;
+    // Index into the image buffer.
+    uint image;
+    uint octaves;
+    uint pad0;
+};
+
+layout(std430, set = 0, binding = 4) readonly buffer MaterialBuffer {
+    Material materials[];
+};
+
+// The albedo at the hit, images are mapped by its UV coordinates, patterns
+// are evaluated at the world space point.
+vec3 materialAlbedo(Material m, Hit rec)
+{
+    vec3 p = rec.point;
+    if (m.texture == TEXTURE_IMAGE)
+    {
+        return sampleImage(m.image, rec.uv);
+    }
+    else if (m.texture == TEXTURE_CHECKER)
+    {
+        vec3 cell = floor(p * m.textureScale);
+        return mod(cell.x + cell.y + cell.z, 2.0) < 1.0 ? m.albedo : m.oddAlbedo;
+    }
+    else if (m.texture == TEXTURE_NOISE)
+    {
+        return m.albedo * 0.5 * (1.0 + perlinNoise(p * m.textureScale));
+    }
+    else if (m.texture == TEXTURE_TURBULENCE)
+    {
+        return m.albedo * min(turbulence(p * m.textureScale, m.octaves), 1.0);
+    }
+    else if (m.texture == TEXTURE_MARBLE)
+    {
+        return m.albedo * 0.5 * (1.0 + sin(m.textureScale * p.z + 10.0 * turbulence(p, m.octaves)));
+    }
+    return m.albedo;
+}
+
+// Schlick's approximation of the Fresnel reflectance.
+float reflectance(float cosine, float refractionRatio)
+{
+    float r0 = (1.0 - refractionRatio) / (1.0 + refractionRatio);
+    r0 = r0 * r0;
+    return r0 + (1.0 - r0) * pow(1.0 - cosine, 5.0);
+}
+
+// Continues the ray r from the hit rec. Returns false if the path ends there,
+// either because the surface absorbed it or because it emits instead.
+bool scatter(Material m, inout Ray r, Hit rec, inout Sampler rng, out vec3 attenuation)
+{
+    attenuation = m.albedo;
+    vec3 unitDirection = normalize(r.direction);
+    vec3 direction;
+
+    // Drawn up front so every material takes the same dimensions.
+    vec2 u = sample2D(rng);
+    float v = sample1D(rng);
+
+    if (m.kind == MATERIAL_EMISSIVE)
+    {
+        return false;
+    }
+    else if (m.kind == MATERIAL_LAMBERTIAN || (m.kind == MATERIAL_METAL && !ENABLE_METAL))
+    {
+        direction = rec.normal + sampleSphere(u);
+        // The random vector can cancel out the normal.
+        if (dot(direction, direction) < 1e-8)
+        {
+            direction = rec.normal;
+        }
+    }
+    else if (m.kind == MATERIAL_METAL)
+    {
+        direction = reflect(unitDirection, rec.normal) + m.param * sampleBall(vec3(u, v));
+        // Fuzz pushed the ray below the surface.
+        if (dot(direction, rec.normal) <= 0.0)
+        {
+            return false;
+        }
+    }
+    else
+    {
+        float refractionRatio = rec.frontFace ? 1.0 / m.param : m.param;
+        float cosTheta = min(dot(-unitDirection, rec.normal), 1.0);
+        float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+
+        bool cannotRefract = refractionRatio * sinTheta > 1.0;
+        if (cannotRefract || reflectance(cosTheta, refractionRatio) > v)
+        {
+            direction = reflect(unitDirection, rec.normal);
+        }
+        else
+        {
+            direction = refract(unitDirection, rec.normal, refractionRatio);
+        }
+    }
+
+    // Offset to the side the ray leaves from, refracted rays go below the surface.
+    float side = dot(direction, rec.normal) > 0.0 ? 1.0 : -1.0;
+    r.origin = rec.point + rec.normal * side * 0.001;
+    r.direction = normalize(direction);
+    return true;
+}
+
+#endif
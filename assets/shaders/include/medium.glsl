@@ -0,0 +1,199 @@
+#ifndef MEDIUM_GLSL
+#define MEDIUM_GLSL
+
+// Participating media of constant density: fog below a height and volumes
+// bounded by spheres. Ported to the CPU in medium.rs, so the sampling can be
+// tested there. Change both together.
+
+#include "common.glsl"
+#include "light.glsl"
+#include "ray.glsl"
+#include "sampler.glsl"
+
+// Must match GpuMedium on the Rust side.
+struct Medium {
+    // The fraction of collisions that scatter rather than absorb.
+    vec3 albedo;
+    // Collisions per unit of distance, zero for no medium.
+    float density;
+    // The Henyey-Greenstein g, 0 is isotropic.
+    float anisotropy;
+    uint pad0, pad1, pad2;
+};
+
+// Must match GpuVolume on the Rust side.
+struct Volume {
+    vec3 center;
+    float radius;
+    Medium medium;
+};
+
+layout(std430, set = 0, binding = 14) readonly buffer MediumBuffer {
+    uint volumeCount;
+    // The fog fills everything below this height.
+    float fogTop;
+    uint mediumPad0, mediumPad1;
+    Medium fog;
+    Volume volumes[];
+};
+
+// Where a ray collided with a medium.
+struct Collision {
+    float t;
+    Medium medium;
+};
+
+// Clips start..end to 0..tMax, false if nothing is left.
+bool clipInterval(inout float start, inout float end, float tMax)
+{
+    start = max(start, 0.0);
+    end = min(end, tMax);
+    return start < end;
+}
+
+bool volumeInterval(Volume volume, Ray r, float tMax, out float start, out float end)
+{
+    vec3 oc = r.origin - volume.center;
+    float a = dot(r.direction, r.direction);
+    float halfB = dot(oc, r.direction);
+    float c = dot(oc, oc) - volume.radius * volume.radius;
+
+    float discriminant = halfB * halfB - a * c;
+    if (discriminant < 0.0)
+    {
+        return false;
+    }
+
+    float sqrtd = sqrt(discriminant);
+    start = (-halfB - sqrtd) / a;
+    end = (-halfB + sqrtd) / a;
+    return clipInterval(start, end, tMax);
+}
+
+bool fogInterval(Ray r, float tMax, out float start, out float end)
+{
+    bool below = r.origin.y < fogTop;
+    start = 0.0;
+    end = tMax;
+    if (r.direction.y == 0.0)
+    {
+        return below && clipInterval(start, end, tMax);
+    }
+
+    float t = (fogTop - r.origin.y) / r.direction.y;
+    if (below && r.direction.y > 0.0)
+    {
+        end = t;
+    }
+    else if (!below)
+    {
+        if (r.direction.y > 0.0)
+        {
+            return false;
+        }
+        start = t;
+    }
+    return clipInterval(start, end, tMax);
+}
+
+// A random number for the medium index, counting the fog and the volumes that
+// follow it. The first is u, the others are hashed from it.
+float mediumRandom(float u, uint index)
+{
+    return index == 0u ? u : toFloat(pcgHash(floatBitsToUint(u) + index));
+}
+
+// The first collision of r with the fog or a volume before tMax. Every medium
+// samples its own exponentially distributed distance with u, the closest one
+// wins. r.direction must be normalized.
+bool sampleCollision(Ray r, float tMax, float u, out Collision collision)
+{
+    collision.t = tMax;
+    bool collided = false;
+
+    float start, end;
+    if (fog.density > 0.0 && fogInterval(r, tMax, start, end))
+    {
+        float t = start - log(1.0 - mediumRandom(u, 0u)) / fog.density;
+        if (t < end)
+        {
+            collision = Collision(t, fog);
+            collided = true;
+        }
+    }
+
+    // Without fog the first volume gets u itself.
+    uint firstVolume = fog.density > 0.0 ? 1u : 0u;
+    for (uint i = 0u; i < volumeCount; i++)
+    {
+        Volume volume = volumes[i];
+        if (volume.medium.density > 0.0 && volumeInterval(volume, r, tMax, start, end))
+        {
+            float t = start - log(1.0 - mediumRandom(u, firstVolume + i)) / volume.medium.density;
+            if (t < end && t < collision.t)
+            {
+                collision = Collision(t, volume.medium);
+                collided = true;
+            }
+        }
+    }
+
+    return collided;
+}
+
+// The fraction of light that makes it along r from 0 to tMax through the
+// media, ignoring surfaces.
+float transmittance(Ray r, float tMax)
+{
+    float opticalDepth = 0.0;
+
+    float start, end;
+    if (fog.density > 0.0 && fogInterval(r, tMax, start, end))
+    {
+        opticalDepth += fog.density * (end - start);
+    }
+
+    for (uint i = 0u; i < volumeCount; i++)
+    {
+        Volume volume = volumes[i];
+        if (volume.medium.density > 0.0 && volumeInterval(volume, r, tMax, start, end))
+        {
+            opticalDepth += volume.medium.density * (end - start);
+        }
+    }
+
+    return exp(-opticalDepth);
+}
+
+// The density of scattering into a direction at an angle with cosine to the
+// one the light travelled in, per unit solid angle.
+float phasePdf(Medium medium, float cosine)
+{
+    float g = medium.anisotropy;
+    float denominator = 1.0 + g * g - 2.0 * g * cosine;
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(max(denominator, 1e-12)));
+}
+
+// A new direction for light travelling along the normalized direction.
+vec3 samplePhase(Medium medium, vec3 direction, vec2 u)
+{
+    float g = medium.anisotropy;
+    float cosTheta;
+    if (abs(g) < 1e-3)
+    {
+        cosTheta = 1.0 - 2.0 * u.x;
+    }
+    else
+    {
+        float s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u.x);
+        cosTheta = clamp((1.0 + g * g - s * s) / (2.0 * g), -1.0, 1.0);
+    }
+
+    float sinTheta = sqrt(max(1.0 - cosTheta * cosTheta, 0.0));
+    float phi = 2.0 * PI * u.y;
+    vec3 t, b;
+    orthonormalBasis(direction, t, b);
+    return normalize(t * (sinTheta * cos(phi)) + b * (sinTheta * sin(phi)) + direction * cosTheta);
+}
+
+#endif
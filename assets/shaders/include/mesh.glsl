@@ -0,0 +1,116 @@
+#ifndef MESH_GLSL
+#define MESH_GLSL
+
+#include "ray.glsl"
+
+struct Vertex {
+    vec4 position;
+};
+
+// Must match GpuMeshInstance on the Rust side.
+struct MeshInstance {
+    // Row-major 3x4 affine transforms.
+    vec4 worldFromObject[3];
+    vec4 objectFromWorld[3];
+    uint firstIndex;
+    uint triangleCount;
+    uint baseVertex;
+    uint material;
+    // The light of the first triangle, or NO_LIGHT. The others follow in order.
+    uint firstLight;
+    uint pad0, pad1, pad2;
+};
+
+layout(std430, set = 0, binding = 0) readonly buffer VertexBuffer {
+    Vertex vertices[];
+};
+
+layout(std430, set = 0, binding = 5) readonly buffer IndexBuffer {
+    uint indices[];
+};
+
+layout(std430, set = 0, binding = 6) readonly buffer MeshInstanceBuffer {
+    MeshInstance meshInstances[];
+};
+
+vec3 transformPoint(vec4 rows[3], vec3 p)
+{
+    return vec3(dot(rows[0], vec4(p, 1.0)), dot(rows[1], vec4(p, 1.0)), dot(rows[2], vec4(p, 1.0)));
+}
+
+vec3 transformVector(vec4 rows[3], vec3 v)
+{
+    return vec3(dot(rows[0].xyz, v), dot(rows[1].xyz, v), dot(rows[2].xyz, v));
+}
+
+// Normals transform with the inverse transpose.
+vec3 objectToWorldNormal(MeshInstance instance, vec3 n)
+{
+    vec4 rows[3] = instance.objectFromWorld;
+    return normalize(n.x * rows[0].xyz + n.y * rows[1].xyz + n.z * rows[2].xyz);
+}
+
+// The object space corners of a triangle of the instance's mesh.
+void triangleVertices(MeshInstance instance, uint triangle, out vec3 v0, out vec3 v1, out vec3 v2)
+{
+    uint first = instance.firstIndex + 3 * triangle;
+    v0 = vertices[instance.baseVertex + indices[first + 0]].position.xyz;
+    v1 = vertices[instance.baseVertex + indices[first + 1]].position.xyz;
+    v2 = vertices[instance.baseVertex + indices[first + 2]].position.xyz;
+}
+
+// Moller-Trumbore, hits both faces. The direction doesn't need to be normalized,
+// t is in units of it.
+bool intersectTriangle(vec3 origin, vec3 direction, vec3 v0, vec3 v1, vec3 v2, float tMin, float tMax, out float t)
+{
+    vec3 edge1 = v1 - v0;
+    vec3 edge2 = v2 - v0;
+    vec3 p = cross(direction, edge2);
+    float det = dot(edge1, p);
+
+    // Parallel to the triangle.
+    if (abs(det) < 1e-12)
+    {
+        return false;
+    }
+
+    float invDet = 1.0 / det;
+    vec3 s = origin - v0;
+    float u = dot(s, p) * invDet;
+    if (u < 0.0 || u > 1.0)
+    {
+        return false;
+    }
+
+    vec3 q = cross(s, edge1);
+    float v = dot(direction, q) * invDet;
+    if (v < 0.0 || u + v > 1.0)
+    {
+        return false;
+    }
+
+    t = dot(edge2, q) * invDet;
+    return t >= tMin && t <= tMax;
+}
+
+// The hit at t on a triangle of the instance, r is in world space.
+Hit triangleHit(Ray r, float t, MeshInstance instance, uint triangle)
+{
+    vec3 v0, v1, v2;
+    triangleVertices(instance, triangle, v0, v1, v2);
+
+    vec3 normal = objectToWorldNormal(instance, cross(v1 - v0, v2 - v0));
+    bool frontFace = dot(r.direction, normal) < 0.0;
+    normal = frontFace ? normal : -normal;
+
+    // Barycentric coordinates of the hit, from the areas of the sub-triangles.
+    vec3 p = transformPoint(instance.objectFromWorld, rayAt(r, t));
+    vec3 area = cross(v1 - v0, v2 - v0);
+    float invArea = 1.0 / dot(area, area);
+    vec2 uv = vec2(dot(cross(p - v0, v2 - v0), area), dot(cross(v1 - v0, p - v0), area)) * invArea;
+
+    uint light = instance.firstLight == NO_LIGHT ? NO_LIGHT : instance.firstLight + triangle;
+    return Hit(t, rayAt(r, t), normal, frontFace, uv, instance.material, light);
+}
+
+#endif
@@ -0,0 +1,40 @@
+#ifndef RAY_GLSL
+#define RAY_GLSL
+
+// RAY
+
+struct Ray {
+    vec3 origin;
+    vec3 direction;
+    // Moving geometry is intersected where it is at this time.
+    float time;
+};
+
+vec3 rayAt(Ray ray, float t)
+{
+    return ray.origin + t*ray.direction;
+}
+
+// INTERSECTIONS
+
+// Hit.light of geometry that doesn't emit.
+#define NO_LIGHT 0xffffffffu
+
+struct Hit
+{
+    float t;
+    vec3 point;
+    // Faces against the ray.
+    vec3 normal;
+    // Whether the ray hit the outside of the surface.
+    bool frontFace;
+    // Spherical coordinates on spheres, the barycentric coordinates of the
+    // second and third corner on triangles.
+    vec2 uv;
+    // Index into the material buffer.
+    uint material;
+    // Index into the light buffer, or NO_LIGHT.
+    uint light;
+};
+
+#endif
@@ -0,0 +1,16 @@
+#ifndef RAY_PAYLOAD_GLSL
+#define RAY_PAYLOAD_GLSL
+
+// Filled in by the closest hit and miss shaders of the ray tracing pipeline.
+struct HitPayload {
+    float t;
+    vec3 point;
+    vec3 normal;
+    bool frontFace;
+    vec2 uv;
+    uint material;
+    uint light;
+    bool didHit;
+};
+
+#endif
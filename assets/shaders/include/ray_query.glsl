@@ -0,0 +1,46 @@
+#ifndef RAY_QUERY_GLSL
+#define RAY_QUERY_GLSL
+
+// Occlusion queries against the scene's acceleration structure with
+// VK_KHR_ray_query, for the tracers that otherwise walk the BVH in software.
+// Only the shader variants compiled with RAY_QUERY defined include it, see
+// glsl::RAY_QUERY_SHADERS, so devices without ray queries never see the
+// capability.
+
+#extension GL_EXT_ray_query : require
+
+#include "ray.glsl"
+#include "scene.glsl"
+
+layout(set = 0, binding = 2) uniform accelerationStructureEXT tlas;
+
+// Whether anything is in the way along r before tMax.
+bool occluded(Ray r, float tMax)
+{
+    rayQueryEXT query;
+    rayQueryInitializeEXT(query, tlas, gl_RayFlagsOpaqueEXT | gl_RayFlagsTerminateOnFirstHitEXT, 0xff, r.origin, 0.00001, r.direction, tMax);
+
+    // Triangles are opaque and commit themselves, the spheres are AABBs that
+    // need an intersection test at the ray's time.
+    while (rayQueryProceedEXT(query))
+    {
+        if (rayQueryGetIntersectionTypeEXT(query, false) != gl_RayQueryCandidateIntersectionAABBEXT)
+        {
+            continue;
+        }
+
+        Sphere sphere = scene.spheres[rayQueryGetIntersectionPrimitiveIndexEXT(query, false)];
+        float t;
+        if (intersectSphere(sphere, r.time,
+                rayQueryGetIntersectionObjectRayOriginEXT(query, false),
+                rayQueryGetIntersectionObjectRayDirectionEXT(query, false),
+                rayQueryGetRayTMinEXT(query), tMax, t))
+        {
+            rayQueryGenerateIntersectionEXT(query, t);
+        }
+    }
+
+    return rayQueryGetIntersectionTypeEXT(query, true) != gl_RayQueryCommittedIntersectionNoneEXT;
+}
+
+#endif
@@ -0,0 +1,127 @@
+#ifndef RAYCAST_GLSL
+#define RAYCAST_GLSL
+
+// Closest hit queries against the BVH in software, for the tracers that
+// don't use the ray tracing pipeline. Include it after declaring the push
+// constant block pc with numBvhPrimitives, the length of bvhPrimitives.
+
+#include "bvh.glsl"
+#include "mesh.glsl"
+#include "ray.glsl"
+#include "scene.glsl"
+
+bool hit(Ray r, int index, float t_min, float t_max, inout Hit rec)
+{
+    float t;
+    if (!intersectSphere(scene.spheres[index], r.time, r.origin, r.direction, t_min, t_max, t))
+    {
+        return false;
+    }
+
+    vec3 center = sphereCenter(scene.spheres[index], r.time);
+    vec3 p = rayAt(r, t);
+        
+    vec3 normal = (p - center) / scene.spheres[index].radius;
+    vec2 uv = sphereUv(normal);
+
+    bool frontFace = dot(r.direction, normal) < 0.0;
+    normal = frontFace ? normal : -normal;
+    
+    rec = Hit(t, p, normal, frontFace, uv, scene.spheres[index].material, scene.spheres[index].light);
+    
+    return true;
+}
+
+bool raycast(Ray r, float tMax, inout Hit h)
+{
+    if (pc.numBvhPrimitives == 0u)
+    {
+        return false;
+    }
+
+    const float tMin = 0.00001;
+    bool didHit = false;
+    vec3 invDirection = 1.0 / r.direction;
+
+    uint stack[BVH_STACK_SIZE];
+    int stackSize = 0;
+
+    BvhNode root = bvhNodes[0];
+    if (intersectAabb(r.origin, invDirection, root.aabbMin, root.aabbMax, tMax) >= 0.0)
+    {
+        stack[stackSize++] = 0u;
+    }
+
+    while (stackSize > 0)
+    {
+        BvhNode node = bvhNodes[stack[--stackSize]];
+        bvhNodesVisited++;
+
+        if (node.count > 0u)
+        {
+            for (uint i = 0u; i < node.count; i++)
+            {
+                BvhPrimitive primitive = bvhPrimitives[node.leftOrFirst + i];
+
+                if (primitive.instance == BVH_SPHERE)
+                {
+                    if (hit(r, int(primitive.index), tMin, tMax, h))
+                    {
+                        didHit = true;
+                        tMax = h.t;
+                    }
+                    continue;
+                }
+
+                MeshInstance instance = meshInstances[primitive.instance];
+
+                // Object space keeps t, the direction isn't renormalized.
+                vec3 origin = transformPoint(instance.objectFromWorld, r.origin);
+                vec3 direction = transformVector(instance.objectFromWorld, r.direction);
+
+                vec3 v0, v1, v2;
+                triangleVertices(instance, primitive.index, v0, v1, v2);
+
+                float t;
+                if (intersectTriangle(origin, direction, v0, v1, v2, tMin, tMax, t))
+                {
+                    h = triangleHit(r, t, instance, primitive.index);
+                    didHit = true;
+                    tMax = t;
+                }
+            }
+            continue;
+        }
+
+        uint near = node.leftOrFirst;
+        uint far = near + 1u;
+        BvhNode left = bvhNodes[near];
+        BvhNode right = bvhNodes[far];
+        float tNear = intersectAabb(r.origin, invDirection, left.aabbMin, left.aabbMax, tMax);
+        float tFar = intersectAabb(r.origin, invDirection, right.aabbMin, right.aabbMax, tMax);
+
+        // Visit the closer child first so hits in it cull the other one.
+        if (tFar >= 0.0 && (tNear < 0.0 || tFar < tNear))
+        {
+            uint tmpIndex = near;
+            near = far;
+            far = tmpIndex;
+            float tmp = tNear;
+            tNear = tFar;
+            tFar = tmp;
+        }
+
+        if (tFar >= 0.0 && stackSize < BVH_STACK_SIZE)
+        {
+            stack[stackSize++] = far;
+        }
+        if (tNear >= 0.0 && stackSize < BVH_STACK_SIZE)
+        {
+            stack[stackSize++] = near;
+        }
+    }
+
+    return didHit;
+}
+
+#endif
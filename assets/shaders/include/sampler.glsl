@@ -0,0 +1,188 @@
+#ifndef SAMPLER_GLSL
+#define SAMPLER_GLSL
+
+// Random numbers for the path tracer. Ported to the CPU in sampler.rs, so the
+// sequences can be tested there. Change both together.
+
+#include "common.glsl"
+#include "settings.glsl"
+
+// Must match SamplerKind on the Rust side.
+const uint SAMPLER_PCG = 0u;
+const uint SAMPLER_SOBOL = 1u;
+
+// Dimensions drawn before the first bounce: the jitter within the pixel, the
+// point on the lens and the time within the shutter interval.
+const uint CAMERA_DIMENSIONS = 3u;
+// Dimensions every bounce draws.
+const uint BOUNCE_DIMENSIONS = 5u;
+// The first dimension of a bounce picks a light, the second the direction to it.
+const uint LIGHT_DIMENSION = 0u;
+// The third is the scattered direction, the fourth the choice between
+// reflection and refraction or the length of a metal's fuzz.
+const uint SCATTER_DIMENSION = 2u;
+// The fifth is the distance to a collision with a medium.
+const uint MEDIUM_DIMENSION = 4u;
+
+struct Sampler {
+    // Decorrelates pixels.
+    uint seed;
+    // The point of the Sobol sequence.
+    uint index;
+    // Each draw takes the next dimension of the point.
+    uint dimension;
+    // PCG state.
+    uint state;
+};
+
+// The output permutation of PCG-RXS-M-XS.
+uint pcgPermute(uint state)
+{
+    uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+uint pcgStep(uint state)
+{
+    return state * 747796405u + 2891336453u;
+}
+
+// Jarzynski and Olano, "Hash Functions for GPU Rendering".
+uint pcgHash(uint v)
+{
+    return pcgPermute(pcgStep(v));
+}
+
+uint pcgNext(inout uint state)
+{
+    state = pcgStep(state);
+    return pcgPermute(state);
+}
+
+// The top 24 bits as a float in [0, 1).
+float toFloat(uint x)
+{
+    return float(x >> 8u) * (1.0 / 16777216.0);
+}
+
+// The second dimension of the Sobol sequence. The first is the bit reversed
+// index.
+uint sobol1(uint index)
+{
+    uint result = 0u;
+    uint v = 0x80000000u;
+    for (; index != 0u; index >>= 1u)
+    {
+        if ((index & 1u) != 0u)
+        {
+            result ^= v;
+        }
+        v ^= v >> 1u;
+    }
+    return result;
+}
+
+// Scrambles the bits of x, each depending only on the ones below it.
+uint laineKarrasPermutation(uint x, uint seed)
+{
+    x += seed;
+    x ^= x * 0x6c50b47cu;
+    x ^= x * 0xb82f1e52u;
+    x ^= x * 0xc7afe638u;
+    x ^= x * 0x8d22f6e6u;
+    return x;
+}
+
+// Burley, "Practical Hash-based Owen Scrambling".
+uint nestedUniformScramble(uint x, uint seed)
+{
+    return bitfieldReverse(laineKarrasPermutation(bitfieldReverse(x), seed));
+}
+
+// Point index of a shuffled, Owen scrambled 2D Sobol sequence. The first 2^k
+// points of every seed stay a (0, k, 2)-net.
+vec2 sobol2D(uint index, uint seed)
+{
+    index = nestedUniformScramble(index, seed);
+    uint x = nestedUniformScramble(bitfieldReverse(index), pcgHash(seed));
+    uint y = nestedUniformScramble(sobol1(index), pcgHash(seed + 1u));
+    return vec2(toFloat(x), toFloat(y));
+}
+
+// sampleIndex counts the samples of pixel since accumulation started,
+// frameIndex keeps independent samples changing between frames even when
+// accumulation restarts.
+Sampler makeSampler(uvec2 pixel, uint frameIndex, uint sampleIndex)
+{
+    uint seed = pcgHash(pixel.x + pcgHash(pixel.y));
+    return Sampler(seed, sampleIndex, 0u, pcgHash(seed ^ pcgHash(frameIndex ^ pcgHash(sampleIndex))));
+}
+
+// Continues from dimension offset of bounce, so the same decisions of every
+// path use the same dimensions.
+void seekDimension(inout Sampler s, uint bounce, uint offset)
+{
+    s.dimension = CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS + offset;
+}
+
+// A point in [0, 1)^2.
+vec2 sample2D(inout Sampler s)
+{
+    uint dimension = s.dimension;
+    s.dimension++;
+
+    if (SAMPLER == SAMPLER_SOBOL)
+    {
+        return sobol2D(s.index, pcgHash(s.seed ^ pcgHash(dimension)));
+    }
+
+    float x = toFloat(pcgNext(s.state));
+    float y = toFloat(pcgNext(s.state));
+    return vec2(x, y);
+}
+
+// A number in [0, 1).
+float sample1D(inout Sampler s)
+{
+    return sample2D(s).x;
+}
+
+// Uniform on the unit sphere.
+vec3 sampleSphere(vec2 u)
+{
+    float z = 1.0 - 2.0 * u.x;
+    float r = sqrt(max(1.0 - z * z, 0.0));
+    float phi = 2.0 * PI * u.y;
+    return vec3(r * cos(phi), r * sin(phi), z);
+}
+
+// Uniform in the unit ball.
+vec3 sampleBall(vec3 u)
+{
+    return sampleSphere(u.xy) * pow(u.z, 1.0 / 3.0);
+}
+
+// Uniform on the unit disk, with Shirley and Chiu's concentric mapping.
+vec2 sampleDisk(vec2 u)
+{
+    vec2 p = 2.0 * u - 1.0;
+    if (p.x == 0.0 && p.y == 0.0)
+    {
+        return vec2(0.0);
+    }
+
+    float r, theta;
+    if (abs(p.x) > abs(p.y))
+    {
+        r = p.x;
+        theta = 0.25 * PI * (p.y / p.x);
+    }
+    else
+    {
+        r = p.y;
+        theta = 0.5 * PI - 0.25 * PI * (p.x / p.y);
+    }
+    return r * vec2(cos(theta), sin(theta));
+}
+
+#endif
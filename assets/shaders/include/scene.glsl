@@ -0,0 +1,66 @@
+#ifndef SCENE_GLSL
+#define SCENE_GLSL
+
+#include "common.glsl"
+
+struct Sphere {
+    // At time 0.
+    vec3 center;
+    float radius;
+    // At time 1, the same as center unless the sphere moves.
+    vec3 endCenter;
+    // Index into the material buffer.
+    uint material;
+    // Index into the light buffer, or NO_LIGHT.
+    uint light;
+    uint pad0, pad1, pad2;
+};
+
+layout(std430, set = 0, binding = 1) buffer spheres {
+    Sphere spheres[];
+} scene;
+
+// Spheres move in a straight line over the scene time from 0 to 1.
+vec3 sphereCenter(Sphere sphere, float time)
+{
+    return mix(sphere.center, sphere.endCenter, time);
+}
+
+// The closest t in [tMin, tMax] where origin + t * direction hits sphere at
+// time, if there is one.
+bool intersectSphere(Sphere sphere, float time, vec3 origin, vec3 direction, float tMin, float tMax, out float t)
+{
+    vec3 oc = origin - sphereCenter(sphere, time);
+
+    float a = dot(direction, direction);
+    float halfB = dot(oc, direction);
+    float c = dot(oc, oc) - sphere.radius * sphere.radius;
+
+    float discriminant = halfB * halfB - a * c;
+
+    if (discriminant < 0.0)
+    {
+        return false;
+    }
+
+    float sqrtd = sqrt(discriminant);
+
+    t = (-halfB - sqrtd) / a;
+    if (t < tMin || t > tMax)
+    {
+        t = (-halfB + sqrtd) / a;
+    }
+
+    return t >= tMin && t <= tMax;
+}
+
+// Spherical coordinates of a point on the unit sphere, u around +Y starting at
+// -X, v from the bottom to the top. Like sphere_uv() on the Rust side.
+vec2 sphereUv(vec3 normal)
+{
+    float theta = acos(clamp(-normal.y, -1.0, 1.0));
+    float phi = atan(-normal.z, normal.x) + PI;
+    return vec2(phi / (2.0 * PI), theta / PI);
+}
+
+#endif
@@ -0,0 +1,18 @@
+#ifndef SETTINGS_GLSL
+#define SETTINGS_GLSL
+
+// Specialization constants, set from RenderSettings on the Rust side. The ids
+// must match RenderSettings::specialization_constants.
+layout(constant_id = 0) const uint SAMPLES_PER_PIXEL = 8;
+layout(constant_id = 1) const uint MAX_RECURSION = 4;
+// When disabled, metal spheres are shaded as diffuse.
+layout(constant_id = 2) const bool ENABLE_METAL = true;
+// Which sequence sampler.glsl draws from, one of the SAMPLER_* constants.
+layout(constant_id = 3) const uint SAMPLER = 0;
+// What the tracers show instead of the image, one of the DEBUG_VIEW_* constants.
+layout(constant_id = 4) const uint DEBUG_VIEW = 0;
+// Whether visibility() traces shadow rays with ray queries. Only has an effect
+// in the variants compiled with RAY_QUERY defined.
+layout(constant_id = 5) const bool RAY_QUERIES = false;
+
+#endif
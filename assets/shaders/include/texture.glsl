@@ -0,0 +1,111 @@
+#ifndef TEXTURE_GLSL
+#define TEXTURE_GLSL
+
+// Albedo textures. The patterns are ported to the CPU in texture.rs, so they
+// can be tested there. Change both together.
+
+#include "sampler.glsl"
+
+// Must match the constants in Texture on the Rust side.
+const uint TEXTURE_SOLID = 0u;
+const uint TEXTURE_IMAGE = 1u;
+const uint TEXTURE_CHECKER = 2u;
+const uint TEXTURE_NOISE = 3u;
+const uint TEXTURE_TURBULENCE = 4u;
+const uint TEXTURE_MARBLE = 5u;
+
+// Must match GpuImage on the Rust side.
+struct Image {
+    uint width;
+    uint height;
+    uint firstTexel;
+    uint pad0;
+};
+
+layout(std430, set = 0, binding = 12) readonly buffer ImageBuffer {
+    Image images[];
+};
+
+// Linear RGB, row by row from the top. A plain buffer filtered by hand rather
+// than sampled images, see Texture in texture.rs.
+layout(std430, set = 0, binding = 13) readonly buffer TexelBuffer {
+    vec4 texels[];
+};
+
+vec3 imageTexel(Image image, int x, int y)
+{
+    uint tx = uint(x - int(image.width) * int(floor(float(x) / float(image.width))));
+    uint ty = uint(y - int(image.height) * int(floor(float(y) / float(image.height))));
+    return texels[image.firstTexel + ty * image.width + tx].rgb;
+}
+
+// Bilinearly filtered, repeating outside [0, 1]. V runs up from the bottom row.
+vec3 sampleImage(uint index, vec2 uv)
+{
+    Image image = images[index];
+    float x = uv.x * float(image.width) - 0.5;
+    float y = (1.0 - uv.y) * float(image.height) - 0.5;
+    vec2 cell = floor(vec2(x, y));
+    vec2 f = vec2(x, y) - cell;
+    int x0 = int(cell.x);
+    int y0 = int(cell.y);
+
+    vec3 top = mix(imageTexel(image, x0, y0), imageTexel(image, x0 + 1, y0), f.x);
+    vec3 bottom = mix(imageTexel(image, x0, y0 + 1), imageTexel(image, x0 + 1, y0 + 1), f.x);
+    return mix(top, bottom, f.y);
+}
+
+// The edges of a cube, Perlin's "Improving Noise" gradients.
+const vec3 GRADIENTS[12] = vec3[](
+    vec3(1, 1, 0), vec3(-1, 1, 0), vec3(1, -1, 0), vec3(-1, -1, 0),
+    vec3(1, 0, 1), vec3(-1, 0, 1), vec3(1, 0, -1), vec3(-1, 0, -1),
+    vec3(0, 1, 1), vec3(0, -1, 1), vec3(0, 1, -1), vec3(0, -1, -1)
+);
+
+// Hashed instead of looked up in a permutation table.
+vec3 noiseGradient(ivec3 cell)
+{
+    uint hash = pcgHash(uint(cell.x) + pcgHash(uint(cell.y) + pcgHash(uint(cell.z))));
+    return GRADIENTS[hash % 12u];
+}
+
+float noiseCorner(ivec3 cell, vec3 f, ivec3 offset)
+{
+    return dot(noiseGradient(cell + offset), f - vec3(offset));
+}
+
+// Gradient noise in roughly [-1, 1], zero at every integer point.
+float perlinNoise(vec3 p)
+{
+    vec3 cell = floor(p);
+    vec3 f = p - cell;
+    ivec3 c = ivec3(cell);
+
+    vec3 w = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    return mix(
+        mix(
+            mix(noiseCorner(c, f, ivec3(0, 0, 0)), noiseCorner(c, f, ivec3(1, 0, 0)), w.x),
+            mix(noiseCorner(c, f, ivec3(0, 1, 0)), noiseCorner(c, f, ivec3(1, 1, 0)), w.x),
+            w.y),
+        mix(
+            mix(noiseCorner(c, f, ivec3(0, 0, 1)), noiseCorner(c, f, ivec3(1, 0, 1)), w.x),
+            mix(noiseCorner(c, f, ivec3(0, 1, 1)), noiseCorner(c, f, ivec3(1, 1, 1)), w.x),
+            w.y),
+        w.z);
+}
+
+// The sum of octaves of absolute noise, at least one.
+float turbulence(vec3 p, uint octaves)
+{
+    float sum = 0.0;
+    float weight = 1.0;
+    for (uint i = 0u; i < max(octaves, 1u); i++)
+    {
+        sum += weight * abs(perlinNoise(p));
+        weight *= 0.5;
+        p *= 2.0;
+    }
+    return sum;
+}
+
+#endif
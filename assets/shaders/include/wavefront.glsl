@@ -0,0 +1,145 @@
+#ifndef WAVEFRONT_GLSL
+#define WAVEFRONT_GLSL
+
+// State shared by the stages of the wavefront tracer, see wavefront.rs. The
+// image is traced in waves of pixels, every pixel of a wave traces one path at
+// a time. The stages pass paths on through queues of path indices, which they
+// append to atomically. wavefront_queue.comp runs
+// before every stage, empties the queues it appends to and turns the length
+// of every queue into an indirect dispatch.
+
+#include "ray.glsl"
+
+// Must match WavefrontPushConstant on the Rust side.
+layout(push_constant) uniform PushConstants {
+    uint numBvhPrimitives;
+    // The image size.
+    uint width;
+    uint height;
+    // Which of the frame's samples the paths trace.
+    uint sampleIndex;
+    // How many bounces the queued paths are into the scene.
+    uint bounce;
+    // The stage wavefront_queue.comp prepares for, one of the STAGE_* constants.
+    uint stage;
+    // The wave's first pixel, in row-major order, and its size. Path i traces
+    // pixel waveOffset + i.
+    uint waveOffset;
+    uint waveSize;
+} pc;
+
+// Only wavefront_accumulate.comp writes the G-buffer.
+#define GBUFFER_SET 2
+
+#include "gbuffer.glsl"
+#include "medium.glsl"
+#include "raycast.glsl"
+#include "integrator.glsl"
+
+// Workgroup size of the stages that take their paths from a queue.
+#define WAVEFRONT_GROUP_SIZE 64
+
+// Must match the constants in wavefront.rs. Paths waiting for their next hit,
+// surface hits by material kind from QUEUE_SHADE on, collisions with media
+// and shadow rays.
+const uint QUEUE_EXTEND = 0u;
+const uint QUEUE_SHADE = 1u;
+const uint QUEUE_MEDIUM = 5u;
+const uint QUEUE_SHADOW = 6u;
+const uint QUEUE_COUNT = 7u;
+
+// Must match the constants in wavefront.rs.
+const uint STAGE_GENERATE = 0u;
+const uint STAGE_EXTEND = 1u;
+const uint STAGE_SHADE = 2u;
+const uint STAGE_SHADOW = 3u;
+
+// A vk::DispatchIndirectCommand.
+struct DispatchArgs {
+    uint x;
+    uint y;
+    uint z;
+};
+
+// What wavefront_extend.comp found for a path, a surface for the material
+// queues and a medium for the medium queue.
+struct PathHit {
+    Hit hit;
+    Collision collision;
+};
+
+// The samples so far this frame of a path's pixel.
+struct PixelSamples {
+    vec3 color;
+    float luminance;
+    vec3 normal;
+    float depth;
+    vec3 albedo;
+    float luminanceSquared;
+};
+
+layout(std430, set = 1, binding = 0) buffer QueueBuffer {
+    DispatchArgs queueDispatches[QUEUE_COUNT];
+    uint queueLengths[QUEUE_COUNT];
+};
+
+// Queue q holds its path indices from q * pathCount() on.
+layout(std430, set = 1, binding = 1) buffer QueueEntryBuffer {
+    uint queueEntries[];
+};
+
+layout(std430, set = 1, binding = 2) buffer PathBuffer {
+    PathState paths[];
+};
+
+layout(std430, set = 1, binding = 3) buffer PathHitBuffer {
+    PathHit pathHits[];
+};
+
+// The shadow ray of every path in the shadow queue.
+layout(std430, set = 1, binding = 4) buffer ShadowRayBuffer {
+    ShadowRay shadowRays[];
+};
+
+layout(std430, set = 1, binding = 5) buffer PixelBuffer {
+    PixelSamples pixels[];
+};
+
+uint pathCount()
+{
+    return pc.waveSize;
+}
+
+// The pixel the path traces, false past the end of the wave.
+bool pathTexel(uint path, out uvec2 texel)
+{
+    if (path >= pathCount())
+    {
+        return false;
+    }
+
+    uint pixel = pc.waveOffset + path;
+    texel = uvec2(pixel % pc.width, pixel / pc.width);
+    return true;
+}
+
+void pushPath(uint queue, uint path)
+{
+    uint index = atomicAdd(queueLengths[queue], 1u);
+    queueEntries[queue * pathCount() + index] = path;
+}
+
+// The path this invocation takes from queue, false past its end.
+bool popPath(uint queue, out uint path)
+{
+    uint index = gl_GlobalInvocationID.x;
+    if (index >= queueLengths[queue])
+    {
+        return false;
+    }
+
+    path = queueEntries[queue * pathCount() + index];
+    return true;
+}
+
+#endif
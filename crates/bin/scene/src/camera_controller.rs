@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+
+use strale::{math::Vec3, renderer::camera::Camera};
+use winit::event::{
+    DeviceEvent, ElementState, Event, KeyboardInput, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+/// Radians per pixel of mouse movement.
+const LOOK_SENSITIVITY: f32 = 0.003;
+/// Keeps the camera from flipping over at the poles.
+const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+/// Keyboard and mouse state gathered from winit events between two frames.
+#[derive(Default)]
+struct InputState {
+    held_keys: HashSet<VirtualKeyCode>,
+    /// Mouse look only happens while a button is held.
+    mouse_held: bool,
+    mouse_delta: (f32, f32),
+    scroll: f32,
+}
+
+impl InputState {
+    fn is_held(&self, key: VirtualKeyCode) -> bool {
+        self.held_keys.contains(&key)
+    }
+
+    /// -1, 0 or 1 depending on which of the two keys are held.
+    fn axis(&self, negative: VirtualKeyCode, positive: VirtualKeyCode) -> f32 {
+        self.is_held(positive) as i32 as f32 - self.is_held(negative) as i32 as f32
+    }
+}
+
+/// Moves freely: WASD to move, Q/E to go down/up, Shift to go faster and the
+/// mouse to look around.
+pub struct FlyController {
+    /// Units per second.
+    pub speed: f32,
+}
+
+impl FlyController {
+    fn update(&self, camera: &mut Camera, input: &InputState, dt: f32) {
+        let (dx, dy) = input.mouse_delta;
+        camera.yaw += dx * LOOK_SENSITIVITY;
+        camera.pitch = (camera.pitch - dy * LOOK_SENSITIVITY).clamp(-MAX_PITCH, MAX_PITCH);
+
+        let movement = input.axis(VirtualKeyCode::S, VirtualKeyCode::W) * camera.forward()
+            + input.axis(VirtualKeyCode::A, VirtualKeyCode::D) * camera.right()
+            + input.axis(VirtualKeyCode::Q, VirtualKeyCode::E) * Vec3::Y;
+
+        let speed = if input.is_held(VirtualKeyCode::LShift) {
+            self.speed * 4.0
+        } else {
+            self.speed
+        };
+
+        camera.position += movement * speed * dt;
+    }
+}
+
+/// Circles `target`: the mouse rotates around it, the wheel zooms and Space
+/// toggles a slow automatic orbit.
+pub struct OrbitController {
+    pub target: Vec3,
+    pub auto_rotate: bool,
+}
+
+impl OrbitController {
+    fn update(&self, camera: &mut Camera, input: &InputState, dt: f32) {
+        let (dx, dy) = input.mouse_delta;
+        // Recomputing the position drifts by rounding errors, which would
+        // restart accumulation every frame.
+        if dx == 0.0 && dy == 0.0 && input.scroll == 0.0 && !self.auto_rotate {
+            return;
+        }
+
+        camera.yaw += dx * LOOK_SENSITIVITY;
+        camera.pitch = (camera.pitch - dy * LOOK_SENSITIVITY).clamp(-MAX_PITCH, MAX_PITCH);
+
+        if self.auto_rotate {
+            camera.yaw += 0.5 * dt;
+        }
+
+        let distance =
+            ((camera.position - self.target).length() * 0.9f32.powf(input.scroll)).max(0.1);
+        camera.position = self.target - camera.forward() * distance;
+    }
+}
+
+enum Mode {
+    Fly,
+    Orbit,
+}
+
+/// Turns winit events into camera movement. Tab switches between the fly and
+/// orbit controllers.
+pub struct CameraControls {
+    input: InputState,
+    mode: Mode,
+    pub fly: FlyController,
+    pub orbit: OrbitController,
+}
+
+impl CameraControls {
+    pub fn new(orbit_target: Vec3) -> Self {
+        Self {
+            input: InputState::default(),
+            mode: Mode::Orbit,
+            fly: FlyController { speed: 5.0 },
+            orbit: OrbitController {
+                target: orbit_target,
+                auto_rotate: false,
+            },
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event<()>) {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                } => match state {
+                    ElementState::Pressed => {
+                        // Ignore key repeats for the toggles.
+                        if self.input.held_keys.insert(*key) {
+                            self.handle_key_press(*key);
+                        }
+                    }
+                    ElementState::Released => {
+                        self.input.held_keys.remove(key);
+                    }
+                },
+                WindowEvent::MouseInput { state, .. } => {
+                    self.input.mouse_held = *state == ElementState::Pressed;
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    self.input.scroll += match delta {
+                        MouseScrollDelta::LineDelta(_, y) => *y,
+                        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 100.0,
+                    };
+                }
+                WindowEvent::Focused(false) => {
+                    self.input.held_keys.clear();
+                    self.input.mouse_held = false;
+                }
+                _ => (),
+            },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } if self.input.mouse_held => {
+                self.input.mouse_delta.0 += delta.0 as f32;
+                self.input.mouse_delta.1 += delta.1 as f32;
+            }
+            _ => (),
+        }
+    }
+
+    fn handle_key_press(&mut self, key: VirtualKeyCode) {
+        match key {
+            VirtualKeyCode::Tab => {
+                self.mode = match self.mode {
+                    Mode::Fly => Mode::Orbit,
+                    Mode::Orbit => Mode::Fly,
+                }
+            }
+            VirtualKeyCode::Space => self.orbit.auto_rotate = !self.orbit.auto_rotate,
+            _ => (),
+        }
+    }
+
+    /// Applies the input since the last call to `camera`.
+    pub fn update(&mut self, camera: &Camera, dt: f32) -> Camera {
+        let mut camera = *camera;
+
+        match self.mode {
+            Mode::Fly => self.fly.update(&mut camera, &self.input, dt),
+            Mode::Orbit => self.orbit.update(&mut camera, &self.input, dt),
+        }
+
+        self.input.mouse_delta = (0.0, 0.0);
+        self.input.scroll = 0.0;
+
+        camera
+    }
+}
@@ -0,0 +1,142 @@
+use std::{cell::Cell, rc::Rc};
+
+use ash::vk;
+use strale::renderer::{
+    render_graph::RenderGraph,
+    render_pass::{FrameContext, RenderPass, SetupContext},
+    vulkan::image::{Image, ImageDesc},
+};
+use vk_sync::AccessType;
+
+/// Half the length of each bar, in pixels.
+const HALF_LENGTH: i32 = 8;
+/// Half the width of each bar, in pixels.
+const HALF_WIDTH: i32 = 1;
+
+/// Marks the center of the window, where the camera looks. Blits a single
+/// pixel image, cleared to white every frame, into two bars over the target.
+pub struct Crosshair {
+    /// Shared with whoever shows and hides the crosshair.
+    visible: Rc<Cell<bool>>,
+    color: Option<Image>,
+    /// How the last frame left `color`.
+    color_access: Cell<AccessType>,
+}
+
+impl Crosshair {
+    pub fn new(visible: Rc<Cell<bool>>) -> Self {
+        Self {
+            visible,
+            color: None,
+            color_access: Cell::new(AccessType::Nothing),
+        }
+    }
+}
+
+impl RenderPass for Crosshair {
+    fn setup(&mut self, ctx: &SetupContext) -> anyhow::Result<()> {
+        self.color = Some(ctx.device.create_image(
+            ImageDesc {
+                extent: vk::Extent2D {
+                    width: 1,
+                    height: 1,
+                },
+                format: vk::Format::R8G8B8A8_UNORM,
+                usage: vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+            },
+            "crosshair color",
+        ));
+        Ok(())
+    }
+
+    fn record<'a>(&'a self, graph: &mut RenderGraph<'a>, frame: &FrameContext) {
+        let color = match &self.color {
+            Some(color) if self.visible.get() => color,
+            _ => return,
+        };
+
+        let color = graph.import_image(color, self.color_access.get());
+        graph
+            .add_pass("clear crosshair")
+            .write_image(color, AccessType::TransferWrite)
+            .render(move |ctx| unsafe {
+                ctx.device.raw.cmd_clear_color_image(
+                    ctx.cb.raw,
+                    ctx.image(color).raw,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &vk::ClearColorValue {
+                        float32: [1.0, 1.0, 1.0, 1.0],
+                    },
+                    &[subresource_range()],
+                );
+            });
+
+        let center = [
+            frame.constants.dims.width as i32 / 2,
+            frame.constants.dims.height as i32 / 2,
+        ];
+        let target = frame.target;
+        graph
+            .add_pass("draw crosshair")
+            .read_image(color, AccessType::TransferRead)
+            .write_image(target, AccessType::TransferWrite)
+            .render(move |ctx| unsafe {
+                let bars = [[HALF_LENGTH, HALF_WIDTH], [HALF_WIDTH, HALF_LENGTH]];
+                let blits = bars.map(|[half_x, half_y]| vk::ImageBlit {
+                    src_subresource: subresource_layers(),
+                    src_offsets: [vk::Offset3D::default(), vk::Offset3D { x: 1, y: 1, z: 1 }],
+                    dst_subresource: subresource_layers(),
+                    dst_offsets: [
+                        vk::Offset3D {
+                            x: center[0] - half_x,
+                            y: center[1] - half_y,
+                            z: 0,
+                        },
+                        vk::Offset3D {
+                            x: center[0] + half_x,
+                            y: center[1] + half_y,
+                            z: 1,
+                        },
+                    ],
+                });
+
+                ctx.device.raw.cmd_blit_image(
+                    ctx.cb.raw,
+                    ctx.image(color).raw,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    ctx.image(target).raw,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &blits,
+                    vk::Filter::NEAREST,
+                );
+            });
+
+        graph.export_image(color, AccessType::TransferRead);
+        self.color_access.set(AccessType::TransferRead);
+    }
+
+    fn destroy(&mut self, ctx: &SetupContext) {
+        if let Some(color) = self.color.take() {
+            ctx.device.destroy_image(color);
+        }
+    }
+}
+
+fn subresource_range() -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
+
+fn subresource_layers() -> vk::ImageSubresourceLayers {
+    vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level: 0,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
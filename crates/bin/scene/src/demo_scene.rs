@@ -0,0 +1,143 @@
+use std::path::Path;
+
+use strale::{
+    math::{Affine3, Vec3},
+    renderer::{
+        environment::Environment,
+        material::Material,
+        medium::{Fog, Medium, PhaseFunction},
+        scene::{Mesh, Scene},
+        texture::{Image, Texture},
+    },
+};
+
+/// A few spheres, one of them rising over the animation, a cube and a puff of
+/// smoke on a checkered floor. `obj` is placed next to them, scaled to a
+/// similar size. `environment` replaces the sky gradient, `texture` the marble
+/// of one sphere. Fog of `fog_density` covers the floor if it's above zero.
+pub fn demo_scene(
+    obj: Option<&Path>,
+    environment: Option<&Path>,
+    texture: Option<&Path>,
+    fog_density: f32,
+) -> Scene {
+    let mut scene = Scene::new();
+
+    if let Some(path) = environment {
+        scene.environment = match Environment::load(path) {
+            Ok(environment) => Some(environment),
+            Err(err) => {
+                log::error!("{:#}", err);
+                std::process::exit(1);
+            }
+        };
+    }
+
+    let ground = scene.add_material(Material::Lambertian {
+        albedo: Texture::Checker {
+            even: Vec3::new(0.2, 0.3, 0.1),
+            odd: Vec3::splat(0.8),
+            scale: 2.0,
+        },
+    });
+    let light = scene.add_material(Material::Emissive {
+        radiance: Vec3::new(8.0, 6.0, 4.0),
+    });
+    let glass = scene.add_material(Material::Dielectric { ior: 1.5 });
+    let gold = scene.add_material(Material::Metal {
+        albedo: Vec3::new(0.8, 0.6, 0.2).into(),
+        fuzz: 0.2,
+    });
+    let red = scene.add_material(Material::Lambertian {
+        albedo: Vec3::new(0.7, 0.15, 0.1).into(),
+    });
+    let blue = scene.add_material(Material::Lambertian {
+        albedo: Vec3::new(0.1, 0.2, 0.7).into(),
+    });
+    let stone = match texture {
+        Some(path) => match Image::load(path) {
+            Ok(image) => Texture::Image(scene.add_image(image)),
+            Err(err) => {
+                log::error!("{:#}", err);
+                std::process::exit(1);
+            }
+        },
+        None => Texture::Marble {
+            color: Vec3::splat(0.9),
+            scale: 8.0,
+            octaves: 7,
+        },
+    };
+    let stone = scene.add_material(Material::Lambertian { albedo: stone });
+
+    scene.add_sphere(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground);
+    scene.add_sphere(Vec3::new(2.0, 0.2, 0.0), 0.2, light);
+    scene.add_sphere(Vec3::new(0.0, 1.2, 1.0), 1.0, glass);
+    scene.add_sphere(Vec3::new(1.0, 0.2, 1.0), 0.2, gold);
+    scene.add_sphere(Vec3::new(-1.4, 0.4, 0.6), 0.4, stone);
+    scene.add_moving_sphere(
+        Vec3::new(-0.5, 0.25, 2.2),
+        Vec3::new(-0.5, 1.0, 2.2),
+        0.25,
+        blue,
+    );
+
+    scene.add_volume(
+        Vec3::new(-0.4, 0.5, -0.8),
+        0.5,
+        Medium {
+            density: 4.0,
+            albedo: Vec3::splat(0.8),
+            phase: PhaseFunction::HenyeyGreenstein { g: 0.3 },
+        },
+    );
+
+    if fog_density > 0.0 {
+        scene.fog = Some(Fog {
+            medium: Medium {
+                density: fog_density,
+                albedo: Vec3::splat(0.9),
+                phase: PhaseFunction::HenyeyGreenstein { g: 0.6 },
+            },
+            top: 1.0,
+        });
+    }
+
+    let cube = scene.add_mesh(Mesh::cube());
+    scene.add_instance(
+        cube,
+        Affine3::from_translation(Vec3::new(0.5, 0.4, -1.8))
+            * Affine3::from_rotation_y(30f32.to_radians())
+            * Affine3::from_scale(Vec3::splat(0.4)),
+        red,
+    );
+
+    if let Some(path) = obj {
+        let mesh = match Mesh::load_obj(path) {
+            Ok(mesh) => mesh,
+            Err(err) => {
+                log::error!("{:#}", err);
+                std::process::exit(1);
+            }
+        };
+
+        let (min, max) = mesh.positions.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), &p| (min.min(p), max.max(p)),
+        );
+        // 1.5 units tall, standing on the ground.
+        let scale = 1.5 / (max.y - min.y).max(1e-6);
+        let base = Vec3::new((min.x + max.x) / 2.0, min.y, (min.z + max.z) / 2.0);
+
+        let mesh = scene.add_mesh(mesh);
+        scene.add_instance(
+            mesh,
+            Affine3::from_translation(Vec3::new(-1.5, 0.0, -2.5))
+                * Affine3::from_scale(Vec3::splat(scale))
+                * Affine3::from_translation(-base),
+            gold,
+        );
+    }
+
+    scene
+}
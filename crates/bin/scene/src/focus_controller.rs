@@ -0,0 +1,98 @@
+use strale::renderer::{bvh::Bvh, camera::Camera, scene::Scene};
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent},
+};
+
+/// Lens radius added or removed per key press.
+const APERTURE_STEP: f32 = 0.05;
+
+/// Depth of field: right click focuses on the surface under the cursor, `,`
+/// and `.` shrink and grow the aperture.
+pub struct FocusControls {
+    /// Finds the surface under the cursor on the CPU.
+    bvh: Bvh,
+    /// In physical pixels from the top left corner of the window.
+    cursor: (f32, f32),
+    focus_requested: bool,
+    aperture_change: f32,
+}
+
+impl FocusControls {
+    pub fn new(scene: &Scene) -> Self {
+        Self {
+            bvh: Bvh::build(scene),
+            cursor: (0.0, 0.0),
+            focus_requested: false,
+            aperture_change: 0.0,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event<()>) {
+        let event = match event {
+            Event::WindowEvent { event, .. } => event,
+            _ => return,
+        };
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = (position.x as f32, position.y as f32);
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Right,
+                ..
+            } => self.focus_requested = true,
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => match key {
+                VirtualKeyCode::Comma => self.aperture_change -= APERTURE_STEP,
+                VirtualKeyCode::Period => self.aperture_change += APERTURE_STEP,
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+
+    /// Applies the input since the last call to `camera`, which renders
+    /// `scene` into a window of `size`.
+    pub fn update(&mut self, scene: &Scene, camera: &Camera, size: PhysicalSize<u32>) -> Camera {
+        let mut camera = *camera;
+
+        camera.aperture_radius = (camera.aperture_radius + self.aperture_change).max(0.0);
+        self.aperture_change = 0.0;
+
+        if std::mem::take(&mut self.focus_requested) {
+            match self.distance_under_cursor(scene, &camera, size) {
+                Some(distance) => camera.focus_distance = distance,
+                None => log::info!("Nothing under the cursor to focus on"),
+            }
+        }
+
+        camera
+    }
+
+    /// How far along the view direction the surface under the cursor is.
+    fn distance_under_cursor(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        size: PhysicalSize<u32>,
+    ) -> Option<f32> {
+        let (width, height) = (size.width as f32, size.height as f32);
+        // The viewport starts at the bottom.
+        let uv = [self.cursor.0 / width, 1.0 - self.cursor.1 / height];
+
+        // Through the center of the lens, when the shutter opens.
+        let ray = camera.rays(width / height).ray(uv, [0.5, 0.5], 0.0);
+        let hit = self.bvh.intersect(scene, &ray, 1e-4, f32::MAX)?;
+
+        Some((ray.at(hit.t) - camera.position).dot(camera.forward()))
+    }
+}
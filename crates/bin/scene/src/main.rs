@@ -1,18 +1,243 @@
-use strale::renderer::{vulkan::backend::Backend, Renderer};
+mod camera_controller;
+mod crosshair;
+mod demo_scene;
+mod focus_controller;
+mod playback;
+
+use std::{cell::Cell, path::PathBuf, rc::Rc, time::Instant};
+
+use camera_controller::CameraControls;
+use crosshair::Crosshair;
+use demo_scene::demo_scene;
+use focus_controller::FocusControls;
+use playback::Playback;
+use strale::{
+    math::Vec3,
+    renderer::{
+        denoiser::DenoiserSettings,
+        environment::EnvironmentControls,
+        render_settings::{RenderSettings, Tracer},
+        sampler::SamplerKind,
+        vulkan::backend::Backend,
+        Renderer,
+    },
+};
 use winit::{
-    event::{Event, WindowEvent},
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     platform::run_return::EventLoopExtRunReturn,
     window::WindowBuilder,
 };
 
+struct Options {
+    settings: RenderSettings,
+    denoiser: DenoiserSettings,
+    /// The renderer's default if not given.
+    tracer: Option<Tracer>,
+    /// A model to add to the scene.
+    obj: Option<PathBuf>,
+    /// An equirectangular HDR light probe.
+    environment: Option<PathBuf>,
+    /// An image to wrap around one of the spheres.
+    texture: Option<PathBuf>,
+    /// Density of ground fog, none if zero.
+    fog: f32,
+    /// Scene time the shutter stays open for.
+    shutter: f32,
+    /// Renders the animation offline instead of interactively.
+    sequence: Option<Sequence>,
+}
+
+/// An offline render of the animation: `frames` images evenly spaced over scene
+/// time [0, 1), each converged to `samples` per pixel and written to `out`. The
+/// animation loops, so time 1 would repeat the first frame.
+struct Sequence {
+    frames: u32,
+    samples: u32,
+    out: PathBuf,
+}
+
+impl Sequence {
+    fn path(&self, frame: u32) -> PathBuf {
+        self.out.join(format!("frame_{:04}.png", frame))
+    }
+}
+
+/// Reads `--spp <n>`, `--bounces <n>`, `--no-metal`, `--sampler <pcg|sobol>`,
+/// `--no-denoise`, `--denoise-iterations <n>`,
+/// `--tracer <fragment|ray-tracing|wavefront>`, `--obj <path>`, `--env <path>`,
+/// `--texture <path>`, `--fog <density>`, `--shutter <time>`, `--frames <n>`,
+/// `--samples <n>` and `--out <dir>` from the command line. `--frames` renders
+/// that many frames of the animation offline, each to `--samples` samples per
+/// pixel (256 if not given), and writes them to `--out` (`frames` if not given).
+fn parse_options() -> Options {
+    let mut settings = RenderSettings::default();
+    let mut denoiser = DenoiserSettings::default();
+    let mut tracer = None;
+    let mut obj = None;
+    let mut environment = None;
+    let mut texture = None;
+    let mut fog = 0.0;
+    let mut shutter = 0.0;
+    let mut frames = None;
+    let mut samples = 256;
+    let mut out = PathBuf::from("frames");
+    let mut args = std::env::args().skip(1);
+
+    let parse_count = |flag: &str, value: Option<String>| -> u32 {
+        match value.as_deref().map(str::parse) {
+            Some(Ok(count)) if count > 0 => count,
+            _ => {
+                log::error!("{} expects a positive number", flag);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let parse_path = |flag: &str, value: Option<String>| -> PathBuf {
+        match value {
+            Some(path) => PathBuf::from(path),
+            None => {
+                log::error!("{} expects a path", flag);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--spp" => settings.samples_per_pixel = parse_count("--spp", args.next()),
+            "--bounces" => settings.max_bounces = parse_count("--bounces", args.next()),
+            "--no-metal" => settings.metal_materials = false,
+            "--sampler" => {
+                settings.sampler = match args.next().as_deref() {
+                    Some("pcg") => SamplerKind::Pcg,
+                    Some("sobol") => SamplerKind::Sobol,
+                    _ => {
+                        log::error!("--sampler expects pcg or sobol");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--no-denoise" => denoiser.enabled = false,
+            "--denoise-iterations" => {
+                denoiser.iterations = parse_count("--denoise-iterations", args.next())
+            }
+            "--tracer" => {
+                tracer = match args.next().as_deref() {
+                    Some("fragment") => Some(Tracer::Fragment),
+                    Some("ray-tracing") => Some(Tracer::RayTracing),
+                    Some("wavefront") => Some(Tracer::Wavefront),
+                    _ => {
+                        log::error!("--tracer expects fragment, ray-tracing or wavefront");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--obj" => obj = Some(parse_path("--obj", args.next())),
+            "--env" => environment = Some(parse_path("--env", args.next())),
+            "--texture" => texture = Some(parse_path("--texture", args.next())),
+            "--fog" => {
+                fog = match args.next().as_deref().map(str::parse) {
+                    Some(Ok(density)) if density >= 0.0 => density,
+                    _ => {
+                        log::error!("--fog expects a density of at least 0");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--shutter" => {
+                shutter = match args.next().as_deref().map(str::parse) {
+                    Some(Ok(time)) if (0.0..=1.0).contains(&time) => time,
+                    _ => {
+                        log::error!("--shutter expects a scene time between 0 and 1");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--frames" => frames = Some(parse_count("--frames", args.next())),
+            "--samples" => samples = parse_count("--samples", args.next()),
+            "--out" => out = parse_path("--out", args.next()),
+            _ => log::warn!("Ignoring unknown argument {}", arg),
+        }
+    }
+
+    Options {
+        settings,
+        denoiser,
+        tracer,
+        obj,
+        environment,
+        texture,
+        fog,
+        shutter,
+        sequence: frames.map(|frames| Sequence {
+            frames,
+            samples,
+            out,
+        }),
+    }
+}
+
+/// `[` and `]` rotate the environment by 15 degrees, `-` and `=` scale its
+/// brightness.
+fn adjust_environment(environment: &mut EnvironmentControls, key: VirtualKeyCode) {
+    match key {
+        VirtualKeyCode::LBracket => environment.rotation -= 15f32.to_radians(),
+        VirtualKeyCode::RBracket => environment.rotation += 15f32.to_radians(),
+        VirtualKeyCode::Minus => environment.intensity /= 1.25,
+        VirtualKeyCode::Equals => environment.intensity *= 1.25,
+        _ => (),
+    }
+}
+
+/// `N` switches between the denoised and the raw output, `V` cycles through
+/// the debug views, `T` through the tracers and `C` shows and hides the
+/// crosshair. Handled on release, so key repeats don't flicker.
+fn switch_view(renderer: &mut Renderer, crosshair: &Cell<bool>, key: VirtualKeyCode) {
+    match key {
+        VirtualKeyCode::C => crosshair.set(!crosshair.get()),
+        VirtualKeyCode::N => {
+            let mut denoiser = renderer.denoiser();
+            denoiser.enabled = !denoiser.enabled;
+            log::info!("Denoiser {}", if denoiser.enabled { "on" } else { "off" });
+            renderer.set_denoiser(denoiser);
+        }
+        VirtualKeyCode::V => {
+            let mut settings = renderer.settings();
+            settings.debug_view = settings.debug_view.next();
+            match renderer.set_settings(settings) {
+                Ok(()) => log::info!("Debug view: {:?}", settings.debug_view),
+                Err(err) => log::error!("Failed to switch the debug view: {:#}", err),
+            }
+        }
+        VirtualKeyCode::T => {
+            // Skips tracers that fail to start, like ray tracing without device support.
+            let current = renderer.tracer();
+            let mut tracer = current.next();
+            while tracer != current {
+                match renderer.set_tracer(tracer) {
+                    Ok(()) => {
+                        log::info!("Tracer: {:?}", tracer);
+                        break;
+                    }
+                    Err(err) => {
+                        log::warn!("Skipping the {:?} tracer: {:#}", tracer, err);
+                        tracer = tracer.next();
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
 fn main() {
     env_logger::init();
     log::info!("Running Strale");
 
     let mut event_loop = EventLoop::new();
     let window = WindowBuilder::new()
-        .with_resizable(false)
         .with_title("hello-kajiya")
         .with_inner_size(winit::dpi::LogicalSize::new(1920, 1080))
         //.with_fullscreen(Some(winit::window::Fullscreen::Borderless(None)))
@@ -21,23 +246,106 @@ fn main() {
 
     let mut backend = Backend::new(&window).unwrap();
 
-    let mut renderer = Renderer::new(&backend).unwrap();
+    let options = parse_options();
+    log::info!("Render settings: {:?}", options.settings);
+
+    let scene = demo_scene(
+        options.obj.as_deref(),
+        options.environment.as_deref(),
+        options.texture.as_deref(),
+        options.fog,
+    );
+    let mut renderer = Renderer::new(&backend, options.settings, &scene).unwrap();
+    renderer.set_denoiser(options.denoiser);
+    if let Some(tracer) = options.tracer {
+        if let Err(err) = renderer.set_tracer(tracer) {
+            log::error!("Keeping the {:?} tracer: {:#}", renderer.tracer(), err);
+        }
+    }
+
+    // Captured sequences are left without the overlay.
+    let crosshair = Rc::new(Cell::new(false));
+    if options.sequence.is_none() {
+        if let Err(err) = renderer.add_render_pass(Crosshair::new(crosshair.clone())) {
+            log::error!("Failed to add the crosshair: {:#}", err);
+        }
+    }
+
+    if let Some(sequence) = &options.sequence {
+        if let Err(err) = std::fs::create_dir_all(&sequence.out) {
+            log::error!("Failed to create {}: {}", sequence.out.display(), err);
+            std::process::exit(1);
+        }
+    }
 
     //let mut events = Vec::new();
 
     let mut running = true;
 
+    // The image only converges while the camera stands still.
+    let mut controls = CameraControls::new(Vec3::ZERO);
+    let mut focus = FocusControls::new(&scene);
+    let mut playback = Playback::new(options.shutter);
+    let mut last_frame = Instant::now();
+    let mut resized = false;
+    // The sequence frame being converged.
+    let mut frame = 0;
+
+    if options.sequence.is_some() {
+        let camera = playback.step(renderer.camera(), 0.0);
+        renderer.set_camera(camera);
+    }
+
     while running {
         event_loop.run_return(|event, _, control_flow| {
             *control_flow = ControlFlow::Poll;
 
+            controls.handle_event(&event);
+            focus.handle_event(&event);
+            playback.handle_event(&event);
+
             match &event {
-                Event::WindowEvent { event, .. } => {
-                    if event == &WindowEvent::CloseRequested {
-                        *control_flow = ControlFlow::Exit;
-                        running = false;
-                    }
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    ..
+                } => {
+                    *control_flow = ControlFlow::Exit;
+                    running = false;
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::Resized(_),
+                    ..
+                } => resized = true,
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Pressed,
+                                    virtual_keycode: Some(key),
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    let mut environment = renderer.environment();
+                    adjust_environment(&mut environment, *key);
+                    renderer.set_environment(environment);
                 }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Released,
+                                    virtual_keycode: Some(key),
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => switch_view(&mut renderer, &crosshair, *key),
                 Event::MainEventsCleared => {
                     *control_flow = ControlFlow::Exit;
                 }
@@ -45,6 +353,77 @@ fn main() {
             }
         });
 
-        renderer.draw(&mut backend.swapchain);
+        // A minimized window has no area to present to.
+        let size = window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            continue;
+        }
+
+        if std::mem::take(&mut resized) {
+            if let Err(err) = backend.resize(&window) {
+                log::error!("Failed to recreate the swapchain: {:#}", err);
+                break;
+            }
+        }
+
+        let now = Instant::now();
+        let dt = (now - last_frame).as_secs_f32();
+        last_frame = now;
+
+        if let Some(sequence) = &options.sequence {
+            // Offline, input doesn't move the camera and the animation only
+            // steps once a frame has converged.
+            let samples_per_pixel = renderer.settings().samples_per_pixel;
+            if renderer.accumulated_samples() + samples_per_pixel < sequence.samples {
+                if let Err(err) = renderer.draw(&mut backend.swapchain) {
+                    log::error!("{:#}", err);
+                    break;
+                }
+            } else {
+                let image = match renderer.capture(&mut backend.swapchain) {
+                    Ok(image) => image,
+                    Err(err) => {
+                        log::error!("{:#}", err);
+                        break;
+                    }
+                };
+
+                let path = sequence.path(frame);
+                if let Err(err) = image.save(&path) {
+                    log::error!("Failed to write {}: {}", path.display(), err);
+                    break;
+                }
+                log::info!(
+                    "Wrote {} with {} spp",
+                    path.display(),
+                    renderer.accumulated_samples()
+                );
+
+                frame += 1;
+                if frame == sequence.frames {
+                    break;
+                }
+                let camera = playback.step(renderer.camera(), 1.0 / sequence.frames as f32);
+                renderer.set_camera(camera);
+            }
+        } else {
+            let camera = controls.update(renderer.camera(), dt);
+            let camera = focus.update(&scene, &camera, window.inner_size());
+            let camera = playback.update(&camera, dt);
+            renderer.set_camera(camera);
+
+            if let Err(err) = renderer.draw(&mut backend.swapchain) {
+                log::error!("{:#}", err);
+                break;
+            }
+        }
+
+        // The frame time is there to compare the tracers.
+        window.set_title(&format!(
+            "hello-kajiya - {:?} - {:.1} ms - {} spp",
+            renderer.tracer(),
+            dt * 1000.0,
+            renderer.accumulated_samples()
+        ));
     }
 }
@@ -0,0 +1,70 @@
+use strale::renderer::camera::Camera;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+/// Scene time per second of playback.
+const SPEED: f32 = 0.25;
+
+/// Plays the scene's animation: P starts and stops it, looping over scene time
+/// 0 to 1. The camera's shutter follows it.
+pub struct Playback {
+    playing: bool,
+    /// Ignores key repeats.
+    key_held: bool,
+    /// When the shutter opens.
+    time: f32,
+    /// How much scene time the shutter stays open for.
+    shutter: f32,
+}
+
+impl Playback {
+    pub fn new(shutter: f32) -> Self {
+        Self {
+            playing: false,
+            key_held: false,
+            time: 0.0,
+            shutter,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event<()>) {
+        if let Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state,
+                            virtual_keycode: Some(VirtualKeyCode::P),
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } = event
+        {
+            let pressed = *state == ElementState::Pressed;
+            if pressed && !self.key_held {
+                self.playing = !self.playing;
+            }
+            self.key_held = pressed;
+        }
+    }
+
+    /// Advances the animation by `dt` seconds and opens `camera`'s shutter at
+    /// the current time.
+    pub fn update(&mut self, camera: &Camera, dt: f32) -> Camera {
+        let time = if self.playing { SPEED * dt } else { 0.0 };
+        self.step(camera, time)
+    }
+
+    /// Advances the animation by `time` in scene time, whether it is playing or
+    /// not, and opens `camera`'s shutter at the new time. Offline renders step
+    /// by a fixed time per frame.
+    pub fn step(&mut self, camera: &Camera, time: f32) -> Camera {
+        self.time = (self.time + time) % 1.0;
+
+        let mut camera = *camera;
+        camera.shutter_open = self.time;
+        camera.shutter_close = (self.time + self.shutter).min(1.0);
+        camera
+    }
+}
@@ -0,0 +1,84 @@
+use std::{env, ffi::OsStr, fs, path::PathBuf};
+
+#[path = "src/renderer/glsl.rs"]
+mod glsl;
+
+/// Compiles every shader in `assets/shaders` and generates `shaders.rs`, which
+/// embeds the SPIR-V into the crate.
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let shader_dir = manifest_dir
+        .join("../../../assets/shaders")
+        .canonicalize()
+        .expect("Shader directory not found");
+
+    // Scans the directory recursively, so edits to includes rebuild too.
+    println!("cargo:rerun-if-changed={}", shader_dir.display());
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(&shader_dir)
+        .expect("Failed to read shader directory")
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+
+    let compiler = shaderc::Compiler::new().expect("Failed to create shader compiler");
+
+    let mut embedded = String::new();
+    let mut errors = Vec::new();
+
+    for path in paths {
+        // SPIR-V next to the sources is never read, and would drift from them.
+        if path.extension() == Some(OsStr::new("spv")) {
+            println!(
+                "cargo:warning={} is stale, shaders are compiled at build time",
+                path.display()
+            );
+            continue;
+        }
+
+        if glsl::shader_kind(&path).is_none() {
+            continue;
+        }
+
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+
+        for variant in glsl::variants(&name) {
+            let (_, defines) = glsl::variant_source(&variant);
+
+            match glsl::compile(&compiler, &path, &shader_dir, defines) {
+                Ok(artifact) => {
+                    for warning in artifact.get_warning_messages().lines() {
+                        println!("cargo:warning={}", warning);
+                    }
+
+                    let spirv_path = out_dir.join(format!("{}.spv", variant));
+                    fs::write(&spirv_path, artifact.as_binary_u8())
+                        .expect("Failed to write SPIR-V");
+
+                    embedded +=
+                        &format!("    ({:?}, include_bytes!({:?})),\n", variant, spirv_path);
+                }
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        for err in &errors {
+            eprintln!("{}\n", err);
+        }
+
+        eprintln!("{} shader(s) failed to compile", errors.len());
+        std::process::exit(1);
+    }
+
+    fs::write(
+        out_dir.join("shaders.rs"),
+        format!(
+            "pub static EMBEDDED_SHADERS: &[(&str, &[u8])] = &[\n{}];\n",
+            embedded
+        ),
+    )
+    .expect("Failed to write shaders.rs");
+}
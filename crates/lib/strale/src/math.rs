@@ -0,0 +1,386 @@
+use std::ops::{Add, AddAssign, Div, Index, Mul, Neg, Sub, SubAssign};
+
+use bytemuck::{Pod, Zeroable};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Zeroable, Pod)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const ZERO: Self = Self::splat(0.0);
+    pub const ONE: Self = Self::splat(1.0);
+    pub const X: Self = Self::new(1.0, 0.0, 0.0);
+    pub const Y: Self = Self::new(0.0, 1.0, 0.0);
+    pub const Z: Self = Self::new(0.0, 0.0, 1.0);
+
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub const fn splat(v: f32) -> Self {
+        Self::new(v, v, v)
+    }
+
+    pub fn dot(self, rhs: Self) -> f32 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    pub fn cross(self, rhs: Self) -> Self {
+        Self::new(
+            self.y * rhs.z - self.z * rhs.y,
+            self.z * rhs.x - self.x * rhs.z,
+            self.x * rhs.y - self.y * rhs.x,
+        )
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(self) -> Self {
+        self / self.length()
+    }
+
+    pub fn min(self, rhs: Self) -> Self {
+        Self::new(self.x.min(rhs.x), self.y.min(rhs.y), self.z.min(rhs.z))
+    }
+
+    pub fn max(self, rhs: Self) -> Self {
+        Self::new(self.x.max(rhs.x), self.y.max(rhs.y), self.z.max(rhs.z))
+    }
+
+    pub fn to_array(self) -> [f32; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+impl From<[f32; 3]> for Vec3 {
+    fn from([x, y, z]: [f32; 3]) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+impl From<Vec3> for [f32; 3] {
+    fn from(v: Vec3) -> Self {
+        v.to_array()
+    }
+}
+
+/// Components by axis, 0 to 2.
+impl Index<usize> for Vec3 {
+    type Output = f32;
+
+    fn index(&self, axis: usize) -> &f32 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis {} out of range", axis),
+        }
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self {
+        Self::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl Mul<Vec3> for f32 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: Vec3) -> Vec3 {
+        rhs * self
+    }
+}
+
+/// Component-wise.
+impl Mul for Vec3 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(self.x * rhs.x, self.y * rhs.y, self.z * rhs.z)
+    }
+}
+
+impl Div<f32> for Vec3 {
+    type Output = Self;
+
+    fn div(self, rhs: f32) -> Self {
+        Self::new(self.x / rhs, self.y / rhs, self.z / rhs)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
+
+/// A 3D affine transform: a linear part given by its columns, then a translation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Affine3 {
+    pub x_axis: Vec3,
+    pub y_axis: Vec3,
+    pub z_axis: Vec3,
+    pub translation: Vec3,
+}
+
+impl Default for Affine3 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Affine3 {
+    pub const IDENTITY: Self = Self {
+        x_axis: Vec3::X,
+        y_axis: Vec3::Y,
+        z_axis: Vec3::Z,
+        translation: Vec3::ZERO,
+    };
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        Self {
+            x_axis: Vec3::X * scale.x,
+            y_axis: Vec3::Y * scale.y,
+            z_axis: Vec3::Z * scale.z,
+            translation: Vec3::ZERO,
+        }
+    }
+
+    /// Rotates counter-clockwise around +X by `angle` radians.
+    pub fn from_rotation_x(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self {
+            y_axis: Vec3::new(0.0, cos, sin),
+            z_axis: Vec3::new(0.0, -sin, cos),
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Rotates counter-clockwise around +Y by `angle` radians.
+    pub fn from_rotation_y(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self {
+            x_axis: Vec3::new(cos, 0.0, -sin),
+            z_axis: Vec3::new(sin, 0.0, cos),
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Rotates counter-clockwise around +Z by `angle` radians.
+    pub fn from_rotation_z(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self {
+            x_axis: Vec3::new(cos, sin, 0.0),
+            y_axis: Vec3::new(-sin, cos, 0.0),
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.transform_vector(point) + self.translation
+    }
+
+    /// Ignores the translation.
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        self.x_axis * vector.x + self.y_axis * vector.y + self.z_axis * vector.z
+    }
+
+    /// Panics in debug builds if the transform isn't invertible.
+    pub fn inverse(&self) -> Self {
+        let (a, b, c) = (self.x_axis, self.y_axis, self.z_axis);
+        let det = a.dot(b.cross(c));
+        debug_assert!(det != 0.0, "Affine3 isn't invertible");
+
+        // Rows of the inverse of the linear part.
+        let rows = [b.cross(c) / det, c.cross(a) / det, a.cross(b) / det];
+        let linear = Self {
+            x_axis: Vec3::new(rows[0].x, rows[1].x, rows[2].x),
+            y_axis: Vec3::new(rows[0].y, rows[1].y, rows[2].y),
+            z_axis: Vec3::new(rows[0].z, rows[1].z, rows[2].z),
+            translation: Vec3::ZERO,
+        };
+
+        Self {
+            translation: -linear.transform_vector(self.translation),
+            ..linear
+        }
+    }
+
+    /// Row-major 3x4, as in `VkTransformMatrixKHR` and the shaders.
+    pub fn to_rows(&self) -> [[f32; 4]; 3] {
+        let row = |i: usize| {
+            [
+                self.x_axis[i],
+                self.y_axis[i],
+                self.z_axis[i],
+                self.translation[i],
+            ]
+        };
+        [row(0), row(1), row(2)]
+    }
+}
+
+/// Applies `rhs` first.
+impl Mul for Affine3 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self {
+            x_axis: self.transform_vector(rhs.x_axis),
+            y_axis: self.transform_vector(rhs.y_axis),
+            z_axis: self.transform_vector(rhs.z_axis),
+            translation: self.transform_point(rhs.translation),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+    /// When the ray is traced, moving geometry is intersected where it is then.
+    pub time: f32,
+}
+
+impl Ray {
+    /// A ray at time 0.
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction,
+            time: 0.0,
+        }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
+/// An axis-aligned bounding box. `Aabb::EMPTY` contains nothing, and grows to
+/// exactly what's added to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl Aabb {
+    pub const EMPTY: Self = Self {
+        min: Vec3::splat(f32::INFINITY),
+        max: Vec3::splat(f32::NEG_INFINITY),
+    };
+
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points
+            .into_iter()
+            .fold(Self::EMPTY, |aabb, point| aabb.grow(point))
+    }
+
+    pub fn grow(self, point: Vec3) -> Self {
+        Self {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// 0 for empty boxes.
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let e = self.extent();
+        2.0 * (e.x * e.y + e.y * e.z + e.z * e.x)
+    }
+
+    /// The distance along `ray` where it enters the box, if that's before
+    /// `t_max`. Starting inside the box counts as entering at 0.
+    pub fn intersect(&self, ray: &Ray, t_max: f32) -> Option<f32> {
+        let inv_direction = Vec3::new(
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        );
+        let t0 = (self.min - ray.origin) * inv_direction;
+        let t1 = (self.max - ray.origin) * inv_direction;
+
+        let near = t0.min(t1);
+        let far = t0.max(t1);
+        let t_enter = near.x.max(near.y).max(near.z).max(0.0);
+        let t_exit = far.x.min(far.y).min(far.z).min(t_max);
+
+        (t_enter <= t_exit).then_some(t_enter)
+    }
+}
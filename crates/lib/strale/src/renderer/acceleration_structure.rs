@@ -0,0 +1,180 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+
+use super::{
+    scene::{Scene, SceneBuffers},
+    vertex::Vertex,
+    vulkan::{
+        buffer::{Buffer, BufferDesc},
+        device::Device,
+        ray_tracing::AccelerationStructure,
+    },
+};
+
+/// Hit group of the sphere instance, see `RayTracingPipeline`.
+const SPHERE_HIT_GROUP: u32 = 0;
+/// Hit group of mesh instances.
+const TRIANGLE_HIT_GROUP: u32 = 1;
+
+/// Acceleration structures over the scene. The TLAS goes into binding 2 of the
+/// bindless set, for both ray tracing pipelines and ray queries.
+///
+/// All spheres share one instance. Mesh instances get one each, with
+/// `gl_InstanceCustomIndexEXT` indexing the mesh instance buffer.
+pub struct SceneAccelerationStructure {
+    pub aabb_buffer: Option<Buffer>,
+    pub blases: Vec<AccelerationStructure>,
+    pub tlas: AccelerationStructure,
+}
+
+impl SceneAccelerationStructure {
+    pub fn new(device: &Device, scene: &Scene, buffers: &SceneBuffers) -> anyhow::Result<Self> {
+        let mut blases = Vec::new();
+        let mut aabb_buffer = None;
+
+        match Self::build(device, scene, buffers, &mut blases, &mut aabb_buffer) {
+            Ok(tlas) => Ok(Self {
+                aabb_buffer,
+                blases,
+                tlas,
+            }),
+            Err(err) => {
+                destroy_parts(device, aabb_buffer, blases);
+                Err(err)
+            }
+        }
+    }
+
+    /// Frees the acceleration structures and their inputs. The GPU must be done
+    /// with them.
+    pub fn destroy(self, device: &Device) {
+        self.tlas.destroy(device);
+        destroy_parts(device, self.aabb_buffer, self.blases);
+    }
+
+    /// Builds the BLASes into `blases` and returns the TLAS over them. What was
+    /// created before an error is left in `blases` and `aabb_buffer` for the
+    /// caller to free.
+    fn build(
+        device: &Device,
+        scene: &Scene,
+        buffers: &SceneBuffers,
+        blases: &mut Vec<AccelerationStructure>,
+        aabb_buffer: &mut Option<Buffer>,
+    ) -> anyhow::Result<AccelerationStructure> {
+        let mut instances = Vec::new();
+
+        if !scene.spheres.is_empty() {
+            // One AABB per sphere, so gl_PrimitiveID indexes the sphere buffer.
+            // Moving spheres are bounded over their whole motion.
+            let aabbs: Vec<vk::AabbPositionsKHR> = scene
+                .spheres
+                .iter()
+                .map(|sphere| {
+                    let bounds = sphere.bounds();
+                    vk::AabbPositionsKHR {
+                        min_x: bounds.min.x,
+                        min_y: bounds.min.y,
+                        min_z: bounds.min.z,
+                        max_x: bounds.max.x,
+                        max_y: bounds.max.y,
+                        max_z: bounds.max.z,
+                    }
+                })
+                .collect();
+
+            let buffer = aabb_buffer.insert(device.create_buffer(
+                BufferDesc {
+                    size: std::mem::size_of_val(aabbs.as_slice()),
+                    usage: vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                        | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                    memory_location: MemoryLocation::GpuOnly,
+                },
+                "sphere aabbs",
+                Some(unsafe {
+                    std::slice::from_raw_parts(
+                        aabbs.as_ptr() as *const u8,
+                        std::mem::size_of_val(aabbs.as_slice()),
+                    )
+                }),
+            ));
+
+            let blas = device.create_blas_from_aabbs(buffer, aabbs.len() as u32)?;
+
+            instances.push(instance(
+                &blas,
+                [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+                0,
+                SPHERE_HIT_GROUP,
+            ));
+            blases.push(blas);
+        }
+
+        // One BLAS per mesh, shared by its instances. Empty meshes get none.
+        let mut mesh_blases = Vec::with_capacity(scene.meshes.len());
+        for range in &buffers.mesh_ranges {
+            if range.triangle_count == 0 {
+                mesh_blases.push(None);
+                continue;
+            }
+
+            let vertex_stride = std::mem::size_of::<Vertex>() as u64;
+            let blas = device.create_blas_from_triangles(
+                &buffers.vertices,
+                range.base_vertex as u64 * vertex_stride,
+                vertex_stride,
+                range.vertex_count,
+                &buffers.indices,
+                range.first_index as u64 * std::mem::size_of::<u32>() as u64,
+                range.triangle_count,
+            )?;
+
+            mesh_blases.push(Some(blases.len()));
+            blases.push(blas);
+        }
+
+        for (instance_idx, mesh_instance) in scene.instances.iter().enumerate() {
+            if let Some(blas_idx) = mesh_blases[mesh_instance.mesh] {
+                let rows = mesh_instance.transform.to_rows();
+                instances.push(instance(
+                    &blases[blas_idx],
+                    bytemuck::cast(rows),
+                    instance_idx as u32,
+                    TRIANGLE_HIT_GROUP,
+                ));
+            }
+        }
+
+        anyhow::ensure!(!instances.is_empty(), "The scene has no geometry");
+        device.create_tlas(&instances)
+    }
+}
+
+/// Frees what `SceneAccelerationStructure::build` created besides the TLAS.
+fn destroy_parts(device: &Device, aabb_buffer: Option<Buffer>, blases: Vec<AccelerationStructure>) {
+    for blas in blases {
+        blas.destroy(device);
+    }
+    if let Some(buffer) = aabb_buffer {
+        device.destroy_buffer(buffer);
+    }
+}
+
+fn instance(
+    blas: &AccelerationStructure,
+    transform: [f32; 12],
+    custom_index: u32,
+    hit_group: u32,
+) -> vk::AccelerationStructureInstanceKHR {
+    vk::AccelerationStructureInstanceKHR {
+        transform: vk::TransformMatrixKHR { matrix: transform },
+        instance_custom_index_and_mask: vk::Packed24_8::new(custom_index, 0xff),
+        instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(
+            hit_group,
+            vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() as u8,
+        ),
+        acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+            device_handle: blas.device_address,
+        },
+    }
+}
@@ -1,39 +1,144 @@
 use ash::vk;
 
-use super::vulkan::device::Device;
+use super::vulkan::{device::Device, shader::DescriptorSetLayoutDesc};
 
-pub fn create_bindless_descriptor_set_layout(device: &Device) -> vk::DescriptorSetLayout {
+pub fn bindless_descriptor_set_layout_bindings(
+    device: &Device,
+) -> Vec<vk::DescriptorSetLayoutBinding> {
+    let mut bindings = vec![
+        // Mesh vertices
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // Spheres
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(1)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // Frame constants
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(3)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // Materials
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(4)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // Mesh indices
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(5)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // Mesh instances
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(6)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // BVH nodes
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(7)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // BVH primitives
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(8)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // Lights
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(9)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // Environment map
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(10)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // Environment map CDFs
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(11)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // Images
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(12)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // Image texels
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(13)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+        // Participating media
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(14)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::ALL)
+            .build(),
+    ];
+
+    // Scene TLAS, the descriptor type only exists with the acceleration structure extension
+    if device.acceleration_structure_enabled {
+        bindings.push(
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(2)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
+                .stage_flags(vk::ShaderStageFlags::ALL)
+                .build(),
+        );
+    }
+
+    bindings
+}
+
+/// Creates the layout of the bindless set. Every pipeline shares it by handle,
+/// the renderer destroys it once they're gone.
+pub fn create_bindless_descriptor_set_layout(device: &Device) -> DescriptorSetLayoutDesc {
     let raw_device = &device.raw;
 
-    let set_binding_flags = vec![
-        vk::DescriptorBindingFlags::PARTIALLY_BOUND,
-        vk::DescriptorBindingFlags::PARTIALLY_BOUND,
-    ];
+    let bindings = bindless_descriptor_set_layout_bindings(device);
+
+    let set_binding_flags = vec![vk::DescriptorBindingFlags::PARTIALLY_BOUND; bindings.len()];
 
     let mut binding_flags_create_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
         .binding_flags(&set_binding_flags)
         .build();
 
-    let descriptor_set_layout = unsafe {
+    let layout = unsafe {
         raw_device
             .create_descriptor_set_layout(
                 &vk::DescriptorSetLayoutCreateInfo::builder()
-                    .bindings(&[
-                        // Spheres
-                        vk::DescriptorSetLayoutBinding::builder()
-                            .binding(0)
-                            .descriptor_count(1)
-                            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                            .stage_flags(vk::ShaderStageFlags::ALL)
-                            .build(),
-                        // Vertices
-                        vk::DescriptorSetLayoutBinding::builder()
-                            .binding(1)
-                            .descriptor_count(1)
-                            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                            .stage_flags(vk::ShaderStageFlags::ALL)
-                            .build(),
-                    ])
+                    .bindings(&bindings)
                     .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
                     .push_next(&mut binding_flags_create_info)
                     .build(),
@@ -42,19 +147,30 @@ pub fn create_bindless_descriptor_set_layout(device: &Device) -> vk::DescriptorS
             .unwrap()
     };
 
-    descriptor_set_layout
+    DescriptorSetLayoutDesc { layout, bindings }
 }
 
-pub fn create_bindless_descriptor_set(device: &Device) -> vk::DescriptorSet {
+/// Allocates the bindless set from a pool of its own, which owns it.
+pub fn create_bindless_descriptor_set(
+    device: &Device,
+    layout: vk::DescriptorSetLayout,
+) -> (vk::DescriptorPool, vk::DescriptorSet) {
     let raw_device = &device.raw;
 
-    let descriptor_set_layout = create_bindless_descriptor_set_layout(device);
+    let mut pool_sizes = vec![vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::STORAGE_BUFFER,
+        descriptor_count: 14,
+    }];
+
+    if device.acceleration_structure_enabled {
+        pool_sizes.push(vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            descriptor_count: 1,
+        });
+    }
 
     let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
-        .pool_sizes(&[vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::STORAGE_BUFFER,
-            descriptor_count: 2,
-        }])
+        .pool_sizes(&pool_sizes)
         .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
         .max_sets(1);
 
@@ -69,11 +185,11 @@ pub fn create_bindless_descriptor_set(device: &Device) -> vk::DescriptorSet {
             .allocate_descriptor_sets(
                 &vk::DescriptorSetAllocateInfo::builder()
                     .descriptor_pool(descriptor_pool)
-                    .set_layouts(std::slice::from_ref(&descriptor_set_layout))
+                    .set_layouts(std::slice::from_ref(&layout))
                     .build(),
             )
             .unwrap()[0]
     };
 
-    set
+    (descriptor_pool, set)
 }
@@ -0,0 +1,317 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::math::{Aabb, Ray, Vec3};
+
+use super::scene::{PrimitiveId, Scene, SceneHit};
+
+/// Centroid bins per axis when searching for a split.
+const BIN_COUNT: usize = 16;
+/// Leaves with more primitives than this are split even if SAH says otherwise.
+const MAX_LEAF_SIZE: u32 = 8;
+/// Cost of visiting a node relative to intersecting a primitive.
+const TRAVERSAL_COST: f32 = 1.0;
+/// Deepest tree the shaders can traverse, see `BVH_STACK_SIZE` in `bvh.glsl`.
+pub const MAX_DEPTH: usize = 64;
+
+/// A node of the flattened tree, laid out as in `assets/shaders/include/bvh.glsl`.
+/// Interior nodes have `count == 0` and their children at `left_or_first` and
+/// `left_or_first + 1`. Leaves hold `primitives[left_or_first..][..count]`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct BvhNode {
+    pub aabb_min: [f32; 3],
+    pub left_or_first: u32,
+    pub aabb_max: [f32; 3],
+    pub count: u32,
+}
+
+/// `PrimitiveId` for the shaders.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Zeroable, Pod)]
+pub struct BvhPrimitive {
+    /// `BvhPrimitive::SPHERE` for spheres, the mesh instance for triangles.
+    pub instance: u32,
+    /// The sphere, or the triangle within the instance's mesh.
+    pub index: u32,
+}
+
+impl BvhPrimitive {
+    pub const SPHERE: u32 = u32::MAX;
+}
+
+impl From<PrimitiveId> for BvhPrimitive {
+    fn from(primitive: PrimitiveId) -> Self {
+        match primitive {
+            PrimitiveId::Sphere(sphere) => Self {
+                instance: Self::SPHERE,
+                index: sphere,
+            },
+            PrimitiveId::Triangle { instance, triangle } => Self {
+                instance,
+                index: triangle,
+            },
+        }
+    }
+}
+
+impl From<BvhPrimitive> for PrimitiveId {
+    fn from(primitive: BvhPrimitive) -> Self {
+        if primitive.instance == BvhPrimitive::SPHERE {
+            PrimitiveId::Sphere(primitive.index)
+        } else {
+            PrimitiveId::Triangle {
+                instance: primitive.instance,
+                triangle: primitive.index,
+            }
+        }
+    }
+}
+
+/// A bounding volume hierarchy over the spheres and mesh triangles of a
+/// `Scene`, built with the surface area heuristic over binned centroids.
+/// The node 0 is the root.
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    pub nodes: Vec<BvhNode>,
+    pub primitives: Vec<BvhPrimitive>,
+}
+
+impl BvhNode {
+    pub fn bounds(&self) -> Aabb {
+        Aabb {
+            min: self.aabb_min.into(),
+            max: self.aabb_max.into(),
+        }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+struct BuildPrimitive {
+    id: BvhPrimitive,
+    bounds: Aabb,
+    centroid: Vec3,
+}
+
+#[derive(Clone, Copy)]
+struct Bin {
+    bounds: Aabb,
+    count: u32,
+}
+
+struct Split {
+    axis: usize,
+    /// Primitives in bins below this go left.
+    bin: usize,
+    /// Children's primitive counts weighted by their surface areas.
+    cost: f32,
+}
+
+impl Bvh {
+    pub fn build(scene: &Scene) -> Self {
+        let mut build_primitives: Vec<BuildPrimitive> = scene
+            .primitives()
+            .map(|primitive| {
+                let bounds = scene.primitive_bounds(primitive);
+                BuildPrimitive {
+                    id: primitive.into(),
+                    bounds,
+                    centroid: bounds.center(),
+                }
+            })
+            .collect();
+
+        let mut nodes = vec![BvhNode {
+            left_or_first: 0,
+            count: build_primitives.len() as u32,
+            ..Default::default()
+        }];
+
+        // (node, depth) pairs left to split.
+        let mut stack = vec![(0, 1)];
+        while let Some((node_idx, depth)) = stack.pop() {
+            let node = &mut nodes[node_idx];
+            let first = node.left_or_first as usize;
+            let count = node.count;
+            let range = &mut build_primitives[first..first + count as usize];
+
+            let bounds = range
+                .iter()
+                .fold(Aabb::EMPTY, |aabb, p| aabb.union(p.bounds));
+            node.aabb_min = bounds.min.into();
+            node.aabb_max = bounds.max.into();
+
+            if count <= 1 || depth >= MAX_DEPTH {
+                continue;
+            }
+
+            let split = match find_split(range) {
+                Some(split) => split,
+                None => continue,
+            };
+
+            let area = bounds.surface_area();
+            let split_cost = TRAVERSAL_COST * area + split.cost;
+            if split_cost >= count as f32 * area && count <= MAX_LEAF_SIZE {
+                continue;
+            }
+
+            let left_count = partition(range, &split);
+            if left_count == 0 || left_count == count as usize {
+                continue;
+            }
+
+            let left_idx = nodes.len();
+            nodes[node_idx].left_or_first = left_idx as u32;
+            nodes[node_idx].count = 0;
+
+            nodes.push(BvhNode {
+                left_or_first: first as u32,
+                count: left_count as u32,
+                ..Default::default()
+            });
+            nodes.push(BvhNode {
+                left_or_first: (first + left_count) as u32,
+                count: count - left_count as u32,
+                ..Default::default()
+            });
+
+            stack.push((left_idx, depth + 1));
+            stack.push((left_idx + 1, depth + 1));
+        }
+
+        Self {
+            nodes,
+            primitives: build_primitives.into_iter().map(|p| p.id).collect(),
+        }
+    }
+
+    /// The closest hit between `t_min` and `t_max`, the CPU side of `raycast()`
+    /// in `raycast.glsl`.
+    pub fn intersect(&self, scene: &Scene, ray: &Ray, t_min: f32, t_max: f32) -> Option<SceneHit> {
+        if self.primitives.is_empty() {
+            return None;
+        }
+
+        let mut closest: Option<SceneHit> = None;
+        let mut stack = Vec::with_capacity(MAX_DEPTH);
+        stack.push(0);
+
+        while let Some(node_idx) = stack.pop() {
+            let node = &self.nodes[node_idx as usize];
+            let t_max = closest.map_or(t_max, |hit| hit.t);
+
+            if node.bounds().intersect(ray, t_max).is_none() {
+                continue;
+            }
+
+            if node.is_leaf() {
+                let first = node.left_or_first as usize;
+                for &primitive in &self.primitives[first..first + node.count as usize] {
+                    let primitive = primitive.into();
+                    let t_max = closest.map_or(t_max, |hit| hit.t);
+                    if let Some(t) = scene.intersect_primitive(primitive, ray, t_min, t_max) {
+                        closest = Some(SceneHit { t, primitive });
+                    }
+                }
+            } else {
+                stack.push(node.left_or_first + 1);
+                stack.push(node.left_or_first);
+            }
+        }
+
+        closest
+    }
+}
+
+/// The cheapest split of `primitives` by the surface area heuristic.
+fn find_split(primitives: &[BuildPrimitive]) -> Option<Split> {
+    let centroid_bounds = primitives
+        .iter()
+        .fold(Aabb::EMPTY, |aabb, p| aabb.grow(p.centroid));
+    let extent = centroid_bounds.extent();
+
+    let mut best: Option<Split> = None;
+
+    for axis in 0..3 {
+        // All centroids coincide along this axis.
+        if extent[axis] <= 0.0 {
+            continue;
+        }
+
+        let mut bins = [Bin {
+            bounds: Aabb::EMPTY,
+            count: 0,
+        }; BIN_COUNT];
+
+        for p in primitives {
+            let bin = &mut bins[bin_index(p.centroid, &centroid_bounds, axis)];
+            bin.bounds = bin.bounds.union(p.bounds);
+            bin.count += 1;
+        }
+
+        // Area and count of everything left of each split, then right of it.
+        let mut left_area = [0.0; BIN_COUNT];
+        let mut left_count = [0; BIN_COUNT];
+        let mut bounds = Aabb::EMPTY;
+        let mut count = 0;
+        for split in 1..BIN_COUNT {
+            bounds = bounds.union(bins[split - 1].bounds);
+            count += bins[split - 1].count;
+            left_area[split] = bounds.surface_area();
+            left_count[split] = count;
+        }
+
+        let mut bounds = Aabb::EMPTY;
+        let mut count = 0;
+        for split in (1..BIN_COUNT).rev() {
+            bounds = bounds.union(bins[split].bounds);
+            count += bins[split].count;
+
+            if left_count[split] == 0 || count == 0 {
+                continue;
+            }
+
+            let cost =
+                left_count[split] as f32 * left_area[split] + count as f32 * bounds.surface_area();
+
+            let better = match &best {
+                Some(best) => cost < best.cost,
+                None => true,
+            };
+            if better {
+                best = Some(Split {
+                    axis,
+                    bin: split,
+                    cost,
+                });
+            }
+        }
+    }
+
+    best
+}
+
+fn bin_index(centroid: Vec3, centroid_bounds: &Aabb, axis: usize) -> usize {
+    let relative = (centroid[axis] - centroid_bounds.min[axis]) / centroid_bounds.extent()[axis];
+    ((relative * BIN_COUNT as f32) as usize).min(BIN_COUNT - 1)
+}
+
+/// Moves the primitives going left to the front, returns how many there are.
+fn partition(primitives: &mut [BuildPrimitive], split: &Split) -> usize {
+    let centroid_bounds = primitives
+        .iter()
+        .fold(Aabb::EMPTY, |aabb, p| aabb.grow(p.centroid));
+
+    let mut left = 0;
+    for i in 0..primitives.len() {
+        if bin_index(primitives[i].centroid, &centroid_bounds, split.axis) < split.bin {
+            primitives.swap(i, left);
+            left += 1;
+        }
+    }
+
+    left
+}
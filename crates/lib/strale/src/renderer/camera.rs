@@ -0,0 +1,122 @@
+use crate::math::{Ray, Vec3};
+
+use super::sampler::sample_disk;
+
+/// A thin lens camera, or a pinhole one without an aperture. World space is
+/// right-handed with +Y up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub position: Vec3,
+    /// Rotation around +Y in radians, 0 looks down +X.
+    pub yaw: f32,
+    /// Rotation above the horizon in radians.
+    pub pitch: f32,
+    /// In degrees.
+    pub vertical_fov: f32,
+    /// Radius of the lens, 0 keeps everything in focus.
+    pub aperture_radius: f32,
+    /// Distance along `forward()` to the plane in focus.
+    pub focus_distance: f32,
+    /// The scene time the shutter opens and closes at, rays are spread evenly
+    /// over it. Equal for no motion blur.
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+}
+
+/// The view vectors the shaders generate rays from.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CameraRays {
+    pub origin: Vec3,
+    pub lower_left_corner: Vec3,
+    pub horizontal: Vec3,
+    pub vertical: Vec3,
+    /// The lens radius along the right and up vectors.
+    pub lens_horizontal: Vec3,
+    pub lens_vertical: Vec3,
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::looking_at(Vec3::new(25.0, 4.0, 3.0), Vec3::ZERO, 20.0)
+    }
+}
+
+impl Camera {
+    /// Focused on `target`, without an aperture, at time 0.
+    pub fn looking_at(position: Vec3, target: Vec3, vertical_fov: f32) -> Self {
+        let mut camera = Self {
+            position,
+            yaw: 0.0,
+            pitch: 0.0,
+            vertical_fov,
+            aperture_radius: 0.0,
+            focus_distance: (target - position).length(),
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        };
+        camera.look_at(target);
+        camera
+    }
+
+    pub fn look_at(&mut self, target: Vec3) {
+        let direction = (target - self.position).normalize();
+        self.pitch = direction.y.clamp(-1.0, 1.0).asin();
+        self.yaw = direction.z.atan2(direction.x);
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        Vec3::new(
+            self.pitch.cos() * self.yaw.cos(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.sin(),
+        )
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.forward().cross(Vec3::Y).normalize()
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.right().cross(self.forward())
+    }
+
+    /// `aspect_ratio` is width over height of the image rendered.
+    pub fn rays(&self, aspect_ratio: f32) -> CameraRays {
+        let viewport_height = 2.0 * (self.vertical_fov.to_radians() / 2.0).tan();
+        let viewport_width = aspect_ratio * viewport_height;
+
+        // The viewport lies on the focus plane, where the rays through a pixel
+        // from every point of the lens meet.
+        let horizontal = self.focus_distance * viewport_width * self.right();
+        let vertical = self.focus_distance * viewport_height * self.up();
+
+        CameraRays {
+            origin: self.position,
+            lower_left_corner: self.position - horizontal / 2.0 - vertical / 2.0
+                + self.focus_distance * self.forward(),
+            horizontal,
+            vertical,
+            lens_horizontal: self.aperture_radius * self.right(),
+            lens_vertical: self.aperture_radius * self.up(),
+            shutter_open: self.shutter_open,
+            shutter_close: self.shutter_close,
+        }
+    }
+}
+
+impl CameraRays {
+    /// The ray through `uv` on the viewport, from the bottom left, leaving the
+    /// lens at the point `lens` in [0, 1)² maps to, `time` of the way through
+    /// the shutter interval. Like `cameraRay()` in `camera.glsl`.
+    pub fn ray(&self, uv: [f32; 2], lens: [f32; 2], time: f32) -> Ray {
+        let [x, y] = sample_disk(lens);
+        let origin = self.origin + x * self.lens_horizontal + y * self.lens_vertical;
+        let target = self.lower_left_corner + uv[0] * self.horizontal + uv[1] * self.vertical;
+        Ray {
+            time: self.shutter_open + (self.shutter_close - self.shutter_open) * time,
+            ..Ray::new(origin, (target - origin).normalize())
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::ensure;
+
+use crate::math::Vec3;
+
+use super::environment::luminance;
+
+// `assets/shaders/include/denoise.glsl` ported to the CPU, change both together.
+// `tests/golden.rs` checks the two agree.
+
+const SIGMA_LUMINANCE: f32 = 4.0;
+const SIGMA_NORMAL: f32 = 128.0;
+const SIGMA_DEPTH: f32 = 1.0;
+/// Depth differences below this fraction of the depth are ignored.
+const DEPTH_TOLERANCE: f32 = 0.01;
+/// Frames the moments need before they are trusted over the neighbours.
+const MIN_TEMPORAL_FRAMES: u32 = 4;
+/// The B3 spline, from the center out.
+const KERNEL: [f32; 3] = [3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+/// Keeps black surfaces from dividing by zero when demodulating.
+const MIN_ALBEDO: f32 = 0.001;
+
+/// The depth of rays that escaped the scene, `MAX_FLOAT` in `common.glsl`.
+pub const MISS_DEPTH: f32 = 99_999.99;
+
+/// Runtime controls of the denoiser. Unlike `RenderSettings`, changing them
+/// doesn't rebuild pipelines or restart accumulation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DenoiserSettings {
+    /// When disabled, the path tracer's output is shown unfiltered.
+    pub enabled: bool,
+    /// Filter passes, each reaching twice as far as the one before. At least 1.
+    pub iterations: u32,
+}
+
+impl Default for DenoiserSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            iterations: 5,
+        }
+    }
+}
+
+/// A pixel of the first-hit AOVs the tracers average alongside their color,
+/// see `gbuffer.glsl`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GBufferTexel {
+    pub normal: Vec3,
+    /// The distance to the first hit, `MISS_DEPTH` for rays that escaped.
+    pub depth: f32,
+    /// 1 where there is no texture detail to keep out of the filter.
+    pub albedo: Vec3,
+    /// The mean over frames of the squared luminance of each frame's
+    /// demodulated color.
+    pub moment: f32,
+}
+
+/// A tracer's output, row by row.
+pub struct DenoiserInput<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub color: &'a [Vec3],
+    pub gbuffer: &'a [GBufferTexel],
+    /// Frames averaged into `color`, at least 1.
+    pub frames: u32,
+}
+
+/// The demodulated color of a pixel and the variance of its luminance.
+#[derive(Clone, Copy)]
+struct Filtered {
+    color: Vec3,
+    variance: f32,
+}
+
+fn demodulate(color: Vec3, albedo: Vec3) -> Vec3 {
+    let albedo = albedo.max(Vec3::splat(MIN_ALBEDO));
+    Vec3::new(color.x / albedo.x, color.y / albedo.y, color.z / albedo.z)
+}
+
+impl DenoiserInput<'_> {
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let inside = (0..self.width as i32).contains(&x) && (0..self.height as i32).contains(&y);
+        inside.then(|| y as usize * self.width + x as usize)
+    }
+
+    fn demodulated(&self, i: usize) -> Vec3 {
+        demodulate(self.color[i], self.gbuffer[i].albedo)
+    }
+
+    /// Like `estimateVariance()` in `denoise.glsl`.
+    fn estimate_variance(&self, x: i32, y: i32) -> Filtered {
+        let i = self.index(x, y).unwrap();
+        let color = self.demodulated(i);
+        let l = luminance(color);
+        let frames = self.frames.max(1);
+
+        if frames >= MIN_TEMPORAL_FRAMES {
+            let variance = (self.gbuffer[i].moment - l * l).max(0.0) / frames as f32;
+            return Filtered { color, variance };
+        }
+
+        let (mut sum, mut sum_squared, mut count) = (0.0, 0.0, 0.0);
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if let Some(q) = self.index(x + dx, y + dy) {
+                    let lq = luminance(self.demodulated(q));
+                    sum += lq;
+                    sum_squared += lq * lq;
+                    count += 1.0;
+                }
+            }
+        }
+
+        let mean = sum / count;
+        Filtered {
+            color,
+            variance: (sum_squared / count - mean * mean).max(0.0),
+        }
+    }
+
+    /// Like `blurredVariance()` in `denoise.glsl`.
+    fn blurred_variance(&self, filtered: &[Filtered], x: i32, y: i32) -> f32 {
+        const WEIGHTS: [f32; 2] = [0.5, 0.25];
+        let (mut sum, mut total) = (0.0, 0.0);
+        for dy in -1..=1i32 {
+            for dx in -1..=1i32 {
+                if let Some(q) = self.index(x + dx, y + dy) {
+                    let w =
+                        WEIGHTS[dx.unsigned_abs() as usize] * WEIGHTS[dy.unsigned_abs() as usize];
+                    sum += w * filtered[q].variance;
+                    total += w;
+                }
+            }
+        }
+        sum / total
+    }
+
+    /// Like `depthGradient()` in `denoise.glsl`.
+    fn depth_gradient(&self, x: i32, y: i32, depth: f32) -> [f32; 2] {
+        let difference = |forward: Option<usize>, backward: Option<usize>| {
+            let forward = forward.map(|q| self.gbuffer[q].depth - depth);
+            let backward = backward.map(|q| depth - self.gbuffer[q].depth);
+            match (forward, backward) {
+                (Some(f), Some(b)) => {
+                    if f.abs() < b.abs() {
+                        f
+                    } else {
+                        b
+                    }
+                }
+                (Some(d), None) | (None, Some(d)) => d,
+                (None, None) => 0.0,
+            }
+        };
+
+        [
+            difference(self.index(x + 1, y), self.index(x - 1, y)),
+            difference(self.index(x, y + 1), self.index(x, y - 1)),
+        ]
+    }
+
+    /// Like `filterIteration()` in `denoise.glsl`.
+    fn atrous_iteration(&self, filtered: &[Filtered], step_size: i32) -> Vec<Filtered> {
+        let mut result = Vec::with_capacity(filtered.len());
+
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                let p = self.index(x, y).unwrap();
+                let center = filtered[p];
+                let texel = self.gbuffer[p];
+                let gradient = self.depth_gradient(x, y, texel.depth);
+                let l = luminance(center.color);
+                let sigma_l = SIGMA_LUMINANCE * self.blurred_variance(filtered, x, y).sqrt();
+
+                // The center always counts in full, even where its normal is
+                // degenerate.
+                let mut weight_sum = KERNEL[0] * KERNEL[0];
+                let mut sum = center.color * weight_sum;
+                let mut variance = center.variance * weight_sum * weight_sum;
+
+                for dy in -2..=2i32 {
+                    for dx in -2..=2i32 {
+                        let offset = [dx * step_size, dy * step_size];
+                        let q = match self.index(x + offset[0], y + offset[1]) {
+                            Some(q) if q != p => q,
+                            _ => continue,
+                        };
+
+                        let tap = filtered[q];
+                        let other = self.gbuffer[q];
+
+                        let normal_weight =
+                            texel.normal.dot(other.normal).max(0.0).powf(SIGMA_NORMAL);
+                        let depth_scale = SIGMA_DEPTH
+                            * (gradient[0] * offset[0] as f32 + gradient[1] * offset[1] as f32)
+                                .abs()
+                            + DEPTH_TOLERANCE * texel.depth;
+                        let depth_weight =
+                            (-(texel.depth - other.depth).abs() / depth_scale.max(1e-6)).exp();
+                        let luminance_weight =
+                            (-(l - luminance(tap.color)).abs() / (sigma_l + 1e-10)).exp();
+
+                        let w = KERNEL[dx.unsigned_abs() as usize]
+                            * KERNEL[dy.unsigned_abs() as usize]
+                            * normal_weight
+                            * depth_weight
+                            * luminance_weight;
+                        sum += tap.color * w;
+                        variance += tap.variance * w * w;
+                        weight_sum += w;
+                    }
+                }
+
+                result.push(Filtered {
+                    color: sum / weight_sum,
+                    variance: variance / (weight_sum * weight_sum),
+                });
+            }
+        }
+
+        result
+    }
+}
+
+/// Filters `input` like the GPU denoiser does with `iterations` passes.
+pub fn denoise(input: &DenoiserInput, iterations: u32) -> anyhow::Result<Vec<Vec3>> {
+    let pixels = input.width * input.height;
+    ensure!(pixels > 0, "The image is empty");
+    ensure!(
+        input.color.len() == pixels && input.gbuffer.len() == pixels,
+        "Expected {} pixels of color and G-buffer, got {} and {}",
+        pixels,
+        input.color.len(),
+        input.gbuffer.len()
+    );
+
+    let mut filtered: Vec<Filtered> = (0..input.height as i32)
+        .flat_map(|y| (0..input.width as i32).map(move |x| (x, y)))
+        .map(|(x, y)| input.estimate_variance(x, y))
+        .collect();
+
+    for iteration in 0..iterations.max(1) {
+        filtered = input.atrous_iteration(&filtered, 1 << iteration);
+    }
+
+    Ok(filtered
+        .iter()
+        .zip(input.gbuffer)
+        .map(|(filtered, texel)| filtered.color * texel.albedo.max(Vec3::splat(MIN_ALBEDO)))
+        .collect())
+}
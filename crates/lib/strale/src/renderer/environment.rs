@@ -0,0 +1,219 @@
+use std::{
+    f32::consts::{FRAC_1_PI, PI},
+    path::Path,
+};
+
+use anyhow::{ensure, Context};
+
+use crate::math::Vec3;
+
+/// Light arriving from infinitely far away, stored as an equirectangular image.
+/// The top row looks up along +Y, the middle column along -Z.
+#[derive(Clone, Debug, Default)]
+pub struct Environment {
+    pub width: u32,
+    pub height: u32,
+    /// Linear RGB radiance, row by row from the top.
+    pub pixels: Vec<Vec3>,
+}
+
+/// How the environment is applied, changeable every frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnvironmentControls {
+    /// Radians around +Y, counter-clockwise seen from above.
+    pub rotation: f32,
+    /// Scales the radiance of every pixel.
+    pub intensity: f32,
+}
+
+impl Default for EnvironmentControls {
+    fn default() -> Self {
+        Self {
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+}
+
+/// A 2D distribution over the pixels of an `Environment`, proportional to their
+/// luminance and the solid angle they cover, for importance sampling.
+#[derive(Clone, Debug, Default)]
+pub struct EnvironmentDistribution {
+    pub width: u32,
+    pub height: u32,
+    /// `height + 1` entries picking the row, from 0 to 1.
+    pub marginal_cdf: Vec<f32>,
+    /// `width + 1` entries per row picking the column within it.
+    pub conditional_cdf: Vec<f32>,
+}
+
+impl Environment {
+    pub fn new(width: u32, height: u32, pixels: Vec<Vec3>) -> anyhow::Result<Self> {
+        ensure!(width > 0 && height > 0, "The environment map is empty");
+        ensure!(
+            pixels.len() == (width * height) as usize,
+            "Expected {} pixels for a {}x{} environment map, got {}",
+            width * height,
+            width,
+            height,
+            pixels.len()
+        );
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Loads an equirectangular light probe, usually a Radiance `.hdr` file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let image = image::open(path)
+            .with_context(|| format!("Failed to load environment map {}", path.display()))?
+            .into_rgb32f();
+
+        let (width, height) = image.dimensions();
+        let pixels = image.pixels().map(|p| Vec3::from(p.0)).collect();
+
+        Self::new(width, height, pixels)
+    }
+
+    /// The pixel looking along `direction`, which doesn't need to be normalized.
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let (x, y) = pixel(self.width, self.height, direction_to_uv(direction));
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+impl EnvironmentDistribution {
+    pub fn new(environment: &Environment) -> Self {
+        let width = environment.width as usize;
+        let height = environment.height as usize;
+
+        let mut marginal_cdf = Vec::with_capacity(height + 1);
+        let mut conditional_cdf = Vec::with_capacity(height * (width + 1));
+        let mut marginal_sum = 0.0;
+        marginal_cdf.push(0.0);
+
+        for (y, row) in environment.pixels.chunks_exact(width).enumerate() {
+            // Rows near the poles cover less solid angle.
+            let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+
+            let start = conditional_cdf.len();
+            let mut sum = 0.0;
+            conditional_cdf.push(0.0);
+            for p in row {
+                sum += luminance(*p).max(0.0) * sin_theta;
+                conditional_cdf.push(sum);
+            }
+            normalize_cdf(&mut conditional_cdf[start..]);
+
+            marginal_sum += sum;
+            marginal_cdf.push(marginal_sum);
+        }
+        normalize_cdf(&mut marginal_cdf);
+
+        Self {
+            width: environment.width,
+            height: environment.height,
+            marginal_cdf,
+            conditional_cdf,
+        }
+    }
+
+    fn conditional(&self, y: u32) -> &[f32] {
+        let stride = self.width as usize + 1;
+        &self.conditional_cdf[y as usize * stride..(y as usize + 1) * stride]
+    }
+
+    /// A direction picked with `u` in [0, 1)², like `sampleEnvironment()` in
+    /// `environment.glsl`, and its density per unit solid angle.
+    pub fn sample(&self, u: [f32; 2]) -> (Vec3, f32) {
+        let y = sample_cdf(&self.marginal_cdf, u[1]);
+        let row = self.conditional(y);
+        let x = sample_cdf(row, u[0]);
+
+        // Uniform within the pixel.
+        let du = (u[0] - row[x as usize]) / (row[x as usize + 1] - row[x as usize]);
+        let dv = (u[1] - self.marginal_cdf[y as usize])
+            / (self.marginal_cdf[y as usize + 1] - self.marginal_cdf[y as usize]);
+        let uv = [
+            (x as f32 + du.clamp(0.0, 1.0)) / self.width as f32,
+            (y as f32 + dv.clamp(0.0, 1.0)) / self.height as f32,
+        ];
+
+        let direction = uv_to_direction(uv);
+        (direction, self.pdf(direction))
+    }
+
+    /// The density `sample` picks `direction` with.
+    pub fn pdf(&self, direction: Vec3) -> f32 {
+        let uv = direction_to_uv(direction);
+        let (x, y) = pixel(self.width, self.height, uv);
+        let row = self.conditional(y);
+
+        let pdf_uv = (self.marginal_cdf[y as usize + 1] - self.marginal_cdf[y as usize])
+            * self.height as f32
+            * (row[x as usize + 1] - row[x as usize])
+            * self.width as f32;
+
+        // From the unit square to the sphere.
+        let sin_theta = (PI * uv[1]).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        pdf_uv / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+/// Like `directionToUv()` in `environment.glsl`.
+pub fn direction_to_uv(direction: Vec3) -> [f32; 2] {
+    let d = direction.normalize();
+    [
+        0.5 + d.x.atan2(-d.z) * 0.5 * FRAC_1_PI,
+        d.y.clamp(-1.0, 1.0).acos() * FRAC_1_PI,
+    ]
+}
+
+/// Like `uvToDirection()` in `environment.glsl`.
+pub fn uv_to_direction(uv: [f32; 2]) -> Vec3 {
+    let phi = 2.0 * PI * (uv[0] - 0.5);
+    let theta = PI * uv[1];
+    Vec3::new(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
+
+fn pixel(width: u32, height: u32, uv: [f32; 2]) -> (u32, u32) {
+    let x = ((uv[0] * width as f32) as u32).min(width - 1);
+    let y = ((uv[1] * height as f32) as u32).min(height - 1);
+    (x, y)
+}
+
+pub(crate) fn luminance(c: Vec3) -> f32 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+/// Scales a running sum to end at 1. All zero sums become uniform.
+fn normalize_cdf(cdf: &mut [f32]) {
+    let n = cdf.len() - 1;
+    let total = cdf[n];
+    for (i, value) in cdf.iter_mut().enumerate() {
+        *value = if total > 0.0 {
+            *value / total
+        } else {
+            i as f32 / n as f32
+        };
+    }
+    cdf[n] = 1.0;
+}
+
+/// The bucket `u` falls into, skipping empty ones. Like `sampleCdf()` in
+/// `environment.glsl`.
+fn sample_cdf(cdf: &[f32], u: f32) -> u32 {
+    // The last entry at most u, so u is in [cdf[i], cdf[i + 1]).
+    let i = cdf.partition_point(|&value| value <= u);
+    (i.clamp(1, cdf.len() - 1) - 1) as u32
+}
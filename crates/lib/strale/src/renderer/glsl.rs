@@ -0,0 +1,122 @@
+//! GLSL to SPIR-V compilation, shared by the build script and shader hot reload.
+
+use std::{fs, path::Path};
+
+/// Shaders that are also compiled with `RAY_QUERY` defined. Their variants
+/// trace shadow rays with `VK_KHR_ray_query`, so the plain shaders stay free of
+/// the capability and run on devices without it.
+pub const RAY_QUERY_SHADERS: &[&str] = &["triangle.frag", "wavefront_shadow.comp"];
+
+/// Shaders that are also compiled with `MOTION_BLUR` defined. Their variants
+/// pass every ray's time to the intersection shader with
+/// `VK_NV_ray_tracing_motion_blur`.
+pub const MOTION_BLUR_SHADERS: &[&str] = &["raytrace.rgen", "raytrace.rint"];
+
+const RAY_QUERY_SUFFIX: &str = "+ray_query";
+const MOTION_BLUR_SUFFIX: &str = "+motion_blur";
+
+/// Name of the `RAY_QUERY` variant of the shader `name`.
+pub fn ray_query_variant(name: &str) -> String {
+    format!("{}{}", name, RAY_QUERY_SUFFIX)
+}
+
+/// Name of the `MOTION_BLUR` variant of the shader `name`.
+pub fn motion_blur_variant(name: &str) -> String {
+    format!("{}{}", name, MOTION_BLUR_SUFFIX)
+}
+
+/// The names of `source` and of each of its variants.
+pub fn variants(source: &str) -> Vec<String> {
+    let mut variants = vec![source.to_owned()];
+    if RAY_QUERY_SHADERS.contains(&source) {
+        variants.push(ray_query_variant(source));
+    }
+    if MOTION_BLUR_SHADERS.contains(&source) {
+        variants.push(motion_blur_variant(source));
+    }
+    variants
+}
+
+/// The source file a shader or variant name is compiled from, and the macros it
+/// is compiled with.
+pub fn variant_source(name: &str) -> (&str, &'static [&'static str]) {
+    if let Some(source) = name.strip_suffix(RAY_QUERY_SUFFIX) {
+        return (source, &["RAY_QUERY"]);
+    }
+    if let Some(source) = name.strip_suffix(MOTION_BLUR_SUFFIX) {
+        return (source, &["MOTION_BLUR"]);
+    }
+    (name, &[])
+}
+
+/// Shader kind from the source extension, following glslang's conventions.
+pub fn shader_kind(path: &Path) -> Option<shaderc::ShaderKind> {
+    let kind = match path.extension()?.to_str()? {
+        "vert" => shaderc::ShaderKind::Vertex,
+        "frag" => shaderc::ShaderKind::Fragment,
+        "comp" => shaderc::ShaderKind::Compute,
+        "rgen" => shaderc::ShaderKind::RayGeneration,
+        "rint" => shaderc::ShaderKind::Intersection,
+        "rchit" => shaderc::ShaderKind::ClosestHit,
+        "rahit" => shaderc::ShaderKind::AnyHit,
+        "rmiss" => shaderc::ShaderKind::Miss,
+        _ => return None,
+    };
+
+    Some(kind)
+}
+
+/// Compiles the shader at `path` with `defines` defined. `#include "file"`
+/// resolves relative to the including file and `#include <file>` relative to
+/// `shader_dir`.
+///
+/// Errors are glslang's diagnostics in `file:line: error: ...` form.
+pub fn compile(
+    compiler: &shaderc::Compiler,
+    path: &Path,
+    shader_dir: &Path,
+    defines: &[&str],
+) -> Result<shaderc::CompilationArtifact, String> {
+    let kind =
+        shader_kind(path).ok_or_else(|| format!("{}: unknown shader stage", path.display()))?;
+
+    let source = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    let mut options = shaderc::CompileOptions::new()
+        .ok_or_else(|| "Failed to create shader compile options".to_owned())?;
+    // Ray tracing stages need SPIR-V 1.4, which Vulkan 1.2 guarantees.
+    options.set_target_env(
+        shaderc::TargetEnv::Vulkan,
+        shaderc::EnvVersion::Vulkan1_2 as u32,
+    );
+    for define in defines {
+        options.add_macro_definition(define, None);
+    }
+    options.set_include_callback(|requested, include_type, requesting, _depth| {
+        let include_path = match include_type {
+            shaderc::IncludeType::Relative => Path::new(requesting)
+                .parent()
+                .unwrap_or(shader_dir)
+                .join(requested),
+            shaderc::IncludeType::Standard => shader_dir.join(requested),
+        };
+
+        let content = fs::read_to_string(&include_path)
+            .map_err(|err| format!("{}: {}", include_path.display(), err))?;
+
+        Ok(shaderc::ResolvedInclude {
+            resolved_name: include_path.to_string_lossy().into_owned(),
+            content,
+        })
+    });
+
+    compiler
+        .compile_into_spirv(
+            &source,
+            kind,
+            &path.to_string_lossy(),
+            "main",
+            Some(&options),
+        )
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
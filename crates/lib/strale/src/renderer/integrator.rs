@@ -0,0 +1,413 @@
+use std::f32::consts::PI;
+
+use crate::math::{Ray, Vec3};
+
+use super::{
+    environment::{Environment, EnvironmentDistribution},
+    light::LightList,
+    material::Material,
+    sampler::{sample_ball, sample_sphere, Sampler, LIGHT_DIMENSION, SCATTER_DIMENSION},
+    scene::{sphere_uv, PrimitiveId, Scene},
+};
+
+// The surface and environment lighting of `assets/shaders/include/integrator.glsl`
+// ported to the CPU, so its multiple importance sampling can be tested there.
+// Change both together. Media aren't ported, paths only meet surfaces, and the
+// environment isn't rotated or scaled.
+
+/// Veach's power heuristic with beta = 2, like `powerHeuristic()`.
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/// Where a ray hit a surface, `Hit` in `ray.glsl`.
+#[derive(Clone, Copy, Debug)]
+pub struct SurfaceHit {
+    pub t: f32,
+    pub point: Vec3,
+    /// Faces against the ray.
+    pub normal: Vec3,
+    /// Whether the ray hit the outside of the surface.
+    pub front_face: bool,
+    /// Spherical coordinates on spheres, barycentric coordinates on triangles.
+    pub uv: [f32; 2],
+    pub material: Material,
+    /// The light the surface is part of, if it emits.
+    pub light: Option<u32>,
+}
+
+/// Light arriving at a point from a sampled direction, if nothing is in the way.
+#[derive(Clone, Copy, Debug)]
+pub struct IncidentLight {
+    pub radiance: Vec3,
+    pub direction: Vec3,
+    /// How far the light is, stopping short of it.
+    pub distance: f32,
+    /// The density of the direction.
+    pub pdf: f32,
+}
+
+/// A light sampled from a point, and the light it brings if nothing is in the
+/// way. `ShadowRay` in `integrator.glsl`.
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowRay {
+    pub ray: Ray,
+    pub distance: f32,
+    pub radiance: Vec3,
+}
+
+/// Everything a path carries from one bounce to the next, `PathState`.
+#[derive(Clone, Debug)]
+pub struct PathState {
+    pub ray: Ray,
+    pub throughput: Vec3,
+    /// The density the last diffuse bounce picked `ray` with, zero for camera
+    /// rays and after specular bounces.
+    pub bsdf_pdf: f32,
+    pub radiance: Vec3,
+    pub sampler: Sampler,
+}
+
+impl PathState {
+    /// A path leaving the camera along `ray`, like `startPath()`.
+    pub fn new(ray: Ray, sampler: Sampler) -> Self {
+        Self {
+            ray,
+            throughput: Vec3::ONE,
+            bsdf_pdf: 0.0,
+            radiance: Vec3::ZERO,
+            sampler,
+        }
+    }
+}
+
+/// The scene as the integrator's shaders see it.
+pub struct Integrator<'a> {
+    pub scene: &'a Scene,
+    pub lights: &'a LightList,
+    /// Without an environment map the sky is a gradient that isn't sampled.
+    pub environment: Option<(&'a Environment, &'a EnvironmentDistribution)>,
+    /// `RenderSettings::max_bounces`.
+    pub max_bounces: u32,
+}
+
+impl<'a> Integrator<'a> {
+    /// The closest surface along `ray` up to `t_max`, like `raycast()`.
+    pub fn raycast(&self, ray: &Ray, t_max: f32) -> Option<SurfaceHit> {
+        let hit = self.scene.intersect(ray, 0.00001, t_max)?;
+        let point = ray.at(hit.t);
+
+        let (normal, uv, material) = match hit.primitive {
+            PrimitiveId::Sphere(sphere) => {
+                let sphere = &self.scene.spheres[sphere as usize];
+                let normal = (point - sphere.center_at(ray.time)) / sphere.radius;
+                (normal, sphere_uv(normal), sphere.material)
+            }
+            PrimitiveId::Triangle { instance, triangle } => {
+                let [v0, v1, v2] = self.scene.world_triangle(instance, triangle);
+                let area = (v1 - v0).cross(v2 - v0);
+                let uv = [
+                    (point - v0).cross(v2 - v0).dot(area) / area.dot(area),
+                    (v1 - v0).cross(point - v0).dot(area) / area.dot(area),
+                ];
+                let material = self.scene.instances[instance as usize].material;
+                (area.normalize(), uv, material)
+            }
+        };
+
+        let front_face = ray.direction.dot(normal) < 0.0;
+        Some(SurfaceHit {
+            t: hit.t,
+            point,
+            normal: if front_face { normal } else { -normal },
+            front_face,
+            uv,
+            material: self.scene.materials[material as usize],
+            light: self.lights.light_of(hit.primitive),
+        })
+    }
+
+    /// The chance of direct lighting sampling the environment map rather than
+    /// the light list, like `environmentProbability()`.
+    pub fn environment_probability(&self) -> f32 {
+        match self.environment {
+            None => 0.0,
+            Some(_) if !self.lights.lights.is_empty() => 0.5,
+            Some(_) => 1.0,
+        }
+    }
+
+    /// The light arriving from `direction`, like `environmentRadiance()`.
+    pub fn environment_radiance(&self, direction: Vec3) -> Vec3 {
+        match self.environment {
+            Some((environment, _)) => environment.radiance(direction),
+            None => {
+                let t = 0.5 * (direction.normalize().y + 1.0);
+                Vec3::ONE * (1.0 - t) + Vec3::new(0.5, 0.7, 1.0) * t
+            }
+        }
+    }
+
+    /// Picks a light or the environment and a direction from `origin` towards
+    /// it, like `sampleIncidentLight()`. `None` if sampling failed.
+    pub fn sample_incident_light(
+        &self,
+        origin: Vec3,
+        sampler: &mut Sampler,
+    ) -> Option<IncidentLight> {
+        let pick = sampler.sample_1d();
+        let u = sampler.sample_2d();
+        let environment_chance = self.environment_probability();
+
+        if pick < environment_chance {
+            let (_, distribution) = self.environment?;
+            let (direction, pdf) = distribution.sample(u);
+            return Some(IncidentLight {
+                radiance: self.environment_radiance(direction),
+                direction,
+                distance: f32::MAX,
+                pdf: pdf * environment_chance,
+            });
+        }
+
+        // Reuse pick to pick the light.
+        let pick = (pick - environment_chance) / (1.0 - environment_chance);
+        let (light, sample) = self.lights.sample(origin, [pick, u[0], u[1]])?;
+
+        let material = self.lights.lights[light as usize].material();
+        let radiance = match self.scene.materials[material as usize] {
+            Material::Emissive { radiance } => radiance,
+            _ => Vec3::ZERO,
+        };
+
+        Some(IncidentLight {
+            radiance,
+            direction: sample.direction,
+            // Stop short of the light so it doesn't shadow itself.
+            distance: sample.distance * 0.999,
+            pdf: sample.pdf * (1.0 - environment_chance),
+        })
+    }
+
+    /// The light `shadow` brings past surfaces, like `traceShadowRay()`.
+    pub fn trace_shadow_ray(&self, shadow: &ShadowRay) -> Vec3 {
+        if shadow.radiance == Vec3::ZERO || self.raycast(&shadow.ray, shadow.distance).is_some() {
+            return Vec3::ZERO;
+        }
+        shadow.radiance
+    }
+
+    /// A shadow ray from the diffuse `hit` with `albedo` towards a light or the
+    /// environment, weighted against scattering finding the same light. Like
+    /// `sampleDirectLight()`.
+    fn sample_direct_light(
+        &self,
+        hit: &SurfaceHit,
+        time: f32,
+        albedo: Vec3,
+        sampler: &mut Sampler,
+    ) -> Option<ShadowRay> {
+        let origin = hit.point + hit.normal * 0.001;
+        let light = self.sample_incident_light(origin, sampler)?;
+
+        let cosine = light.direction.dot(hit.normal);
+        if cosine <= 0.0 || light.pdf <= 0.0 {
+            return None;
+        }
+
+        let brdf = albedo / PI;
+        let bsdf_pdf = cosine / PI;
+
+        Some(ShadowRay {
+            ray: Ray {
+                origin,
+                direction: light.direction,
+                time,
+            },
+            distance: light.distance,
+            radiance: brdf
+                * cosine
+                * light.radiance
+                * (power_heuristic(light.pdf, bsdf_pdf) / light.pdf),
+        })
+    }
+
+    /// The environment's light reaching `path` as it escapes the scene, like
+    /// `escapedLight()`.
+    pub fn escaped_light(&self, path: &PathState) -> Vec3 {
+        let mut weight = 1.0;
+        let environment_chance = self.environment_probability();
+        if let Some((_, distribution)) = self.environment {
+            if path.bsdf_pdf > 0.0 && environment_chance > 0.0 {
+                weight = power_heuristic(
+                    path.bsdf_pdf,
+                    distribution.pdf(path.ray.direction) * environment_chance,
+                );
+            }
+        }
+        path.throughput * self.environment_radiance(path.ray.direction) * weight
+    }
+
+    /// Adds the light `hit` emits and scatters `path` off it on `bounce`, like
+    /// `shadeSurface()`. Returns whether the path continues, and the shadow
+    /// ray towards a light it sampled.
+    pub fn shade_surface(
+        &self,
+        path: &mut PathState,
+        bounce: u32,
+        hit: &SurfaceHit,
+    ) -> (bool, Option<ShadowRay>) {
+        let (albedo, emission) = match hit.material {
+            Material::Lambertian { albedo } | Material::Metal { albedo, .. } => (
+                albedo.evaluate(&self.scene.images, hit.uv, hit.point),
+                Vec3::ZERO,
+            ),
+            Material::Dielectric { .. } => (Vec3::ONE, Vec3::ZERO),
+            Material::Emissive { radiance } => (Vec3::ZERO, radiance),
+        };
+
+        // The previous hit already sampled this light directly.
+        let mut weight = 1.0;
+        if let Some(light) = hit.light {
+            if path.bsdf_pdf > 0.0 {
+                let pdf = self.lights.pdf(light, path.ray.origin, hit.point)
+                    * (1.0 - self.environment_probability());
+                weight = power_heuristic(path.bsdf_pdf, pdf);
+            }
+        }
+        path.radiance += path.throughput * emission * weight;
+
+        let diffuse = matches!(hit.material, Material::Lambertian { .. });
+
+        // Light found by the next hit only counts if there is one.
+        let mut shadow = None;
+        if diffuse && bounce + 1 < self.max_bounces {
+            path.sampler.seek_dimension(bounce, LIGHT_DIMENSION);
+            shadow = self
+                .sample_direct_light(hit, path.ray.time, albedo, &mut path.sampler)
+                .map(|shadow| ShadowRay {
+                    radiance: shadow.radiance * path.throughput,
+                    ..shadow
+                });
+        }
+
+        path.sampler.seek_dimension(bounce, SCATTER_DIMENSION);
+        if !scatter(hit, &mut path.ray, &mut path.sampler) {
+            return (false, shadow);
+        }
+        path.throughput = path.throughput * albedo;
+        path.bsdf_pdf = if diffuse {
+            path.ray.direction.dot(hit.normal).max(0.0) / PI
+        } else {
+            0.0
+        };
+        (true, shadow)
+    }
+
+    /// The light arriving along `ray`, like `rayColor()`.
+    pub fn ray_color(&self, ray: Ray, sampler: Sampler) -> Vec3 {
+        let mut path = PathState::new(ray, sampler);
+
+        for bounce in 0..self.max_bounces {
+            let hit = match self.raycast(&path.ray, f32::MAX) {
+                Some(hit) => hit,
+                None => {
+                    path.radiance += self.escaped_light(&path);
+                    break;
+                }
+            };
+
+            let (scattered, shadow) = self.shade_surface(&mut path, bounce, &hit);
+            if let Some(shadow) = shadow {
+                path.radiance += self.trace_shadow_ray(&shadow);
+            }
+            if !scattered {
+                break;
+            }
+        }
+
+        path.radiance
+    }
+}
+
+/// Continues `ray` from `hit`, like `scatter()` in `material.glsl` with metal
+/// enabled. Returns false if the path ends there.
+fn scatter(hit: &SurfaceHit, ray: &mut Ray, sampler: &mut Sampler) -> bool {
+    let unit_direction = ray.direction.normalize();
+
+    // Drawn up front so every material takes the same dimensions.
+    let u = sampler.sample_2d();
+    let v = sampler.sample_1d();
+
+    let direction = match hit.material {
+        Material::Emissive { .. } => return false,
+        Material::Lambertian { .. } => {
+            let direction = hit.normal + sample_sphere(u);
+            // The random vector can cancel out the normal.
+            if direction.dot(direction) < 1e-8 {
+                hit.normal
+            } else {
+                direction
+            }
+        }
+        Material::Metal { fuzz, .. } => {
+            let direction =
+                reflect(unit_direction, hit.normal) + sample_ball([u[0], u[1], v]) * fuzz;
+            // Fuzz pushed the ray below the surface.
+            if direction.dot(hit.normal) <= 0.0 {
+                return false;
+            }
+            direction
+        }
+        Material::Dielectric { ior } => {
+            let refraction_ratio = if hit.front_face { 1.0 / ior } else { ior };
+            let cos_theta = (-unit_direction).dot(hit.normal).min(1.0);
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+            let cannot_refract = refraction_ratio * sin_theta > 1.0;
+            if cannot_refract || reflectance(cos_theta, refraction_ratio) > v {
+                reflect(unit_direction, hit.normal)
+            } else {
+                refract(unit_direction, hit.normal, refraction_ratio)
+            }
+        }
+    };
+
+    // Offset to the side the ray leaves from, refracted rays go below the surface.
+    let side = if direction.dot(hit.normal) > 0.0 {
+        1.0
+    } else {
+        -1.0
+    };
+    ray.origin = hit.point + hit.normal * (side * 0.001);
+    ray.direction = direction.normalize();
+    true
+}
+
+/// Schlick's approximation of the Fresnel reflectance.
+fn reflectance(cosine: f32, refraction_ratio: f32) -> f32 {
+    let r0 = (1.0 - refraction_ratio) / (1.0 + refraction_ratio);
+    let r0 = r0 * r0;
+    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+}
+
+/// GLSL's `reflect()`.
+fn reflect(incident: Vec3, normal: Vec3) -> Vec3 {
+    incident - normal * (2.0 * normal.dot(incident))
+}
+
+/// GLSL's `refract()`.
+fn refract(incident: Vec3, normal: Vec3, eta: f32) -> Vec3 {
+    let cosine = normal.dot(incident);
+    let k = 1.0 - eta * eta * (1.0 - cosine * cosine);
+    if k < 0.0 {
+        return Vec3::ZERO;
+    }
+    incident * eta - normal * (eta * cosine + k.sqrt())
+}
@@ -0,0 +1,308 @@
+use std::f32::consts::PI;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::math::Vec3;
+
+use super::{
+    material::Material,
+    scene::{MaterialId, PrimitiveId, Scene},
+};
+
+/// Marks spheres and mesh instances without lights on the GPU, see `light.glsl`.
+pub const NO_LIGHT: u32 = u32::MAX;
+
+/// Emissive geometry the path tracer samples directly. Triangle lights emit
+/// from both faces, like every other triangle is shaded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Light {
+    Sphere {
+        center: Vec3,
+        radius: f32,
+        material: MaterialId,
+    },
+    /// A world space triangle of an emissive mesh instance.
+    Triangle {
+        vertices: [Vec3; 3],
+        material: MaterialId,
+    },
+}
+
+/// A direction towards a light, from `Light::sample`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightSample {
+    /// Normalized.
+    pub direction: Vec3,
+    /// To the light's surface along `direction`.
+    pub distance: f32,
+    /// Probability density of `direction`, per unit solid angle.
+    pub pdf: f32,
+}
+
+/// `Light` as laid out in `assets/shaders/include/light.glsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct GpuLight {
+    /// The sphere's center, or the triangle's first vertex.
+    pub p0: [f32; 3],
+    pub kind: u32,
+    /// The sphere's radius in `x`, or the triangle's second vertex.
+    pub p1: [f32; 3],
+    pub material: u32,
+    pub p2: [f32; 3],
+    pub _pad: u32,
+}
+
+impl Light {
+    // Must match the LIGHT_* constants in light.glsl.
+    const SPHERE: u32 = 0;
+    const TRIANGLE: u32 = 1;
+
+    pub fn material(&self) -> MaterialId {
+        match *self {
+            Light::Sphere { material, .. } | Light::Triangle { material, .. } => material,
+        }
+    }
+
+    /// Samples a direction from `origin` towards the light with `u` in [0, 1)².
+    /// Spheres are sampled uniformly over the cone they subtend, triangles
+    /// uniformly over their area. `None` if `origin` is inside the sphere or
+    /// the triangle is seen edge on.
+    pub fn sample(&self, origin: Vec3, u: [f32; 2]) -> Option<LightSample> {
+        match *self {
+            Light::Sphere { center, radius, .. } => {
+                let to_center = center - origin;
+                let distance_squared = to_center.dot(to_center);
+                if distance_squared <= radius * radius {
+                    return None;
+                }
+
+                let w = to_center / distance_squared.sqrt();
+                let one_minus_cos_max = one_minus_cos_cone(distance_squared, radius);
+
+                let cos_theta = 1.0 - u[0] * one_minus_cos_max;
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * u[1];
+                let (t, b) = orthonormal_basis(w);
+                let direction =
+                    (t * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + w * cos_theta)
+                        .normalize();
+
+                // The near intersection, the direction is inside the cone.
+                let oc = origin - center;
+                let half_b = oc.dot(direction);
+                let c = distance_squared - radius * radius;
+                let discriminant = (half_b * half_b - c).max(0.0);
+                let distance = -half_b - discriminant.sqrt();
+
+                Some(LightSample {
+                    direction,
+                    distance,
+                    pdf: 1.0 / (2.0 * PI * one_minus_cos_max),
+                })
+            }
+            Light::Triangle { vertices, .. } => {
+                let [v0, v1, v2] = vertices;
+                let su = u[0].sqrt();
+                let b0 = 1.0 - su;
+                let b1 = u[1] * su;
+                let point = v0 * b0 + v1 * b1 + v2 * (1.0 - b0 - b1);
+
+                let to_point = point - origin;
+                let distance = to_point.length();
+                if distance <= 0.0 {
+                    return None;
+                }
+                let direction = to_point / distance;
+
+                let pdf = triangle_pdf(vertices, direction, distance)?;
+                Some(LightSample {
+                    direction,
+                    distance,
+                    pdf,
+                })
+            }
+        }
+    }
+
+    /// The density `sample` from `origin` would have picked the direction to
+    /// `point` on the light with.
+    pub fn pdf(&self, origin: Vec3, point: Vec3) -> f32 {
+        match *self {
+            Light::Sphere { center, radius, .. } => {
+                let to_center = center - origin;
+                let distance_squared = to_center.dot(to_center);
+                if distance_squared <= radius * radius {
+                    return 0.0;
+                }
+
+                1.0 / (2.0 * PI * one_minus_cos_cone(distance_squared, radius))
+            }
+            Light::Triangle { vertices, .. } => {
+                let to_point = point - origin;
+                let distance = to_point.length();
+                if distance <= 0.0 {
+                    return 0.0;
+                }
+
+                triangle_pdf(vertices, to_point / distance, distance).unwrap_or(0.0)
+            }
+        }
+    }
+
+    pub fn gpu_data(&self) -> GpuLight {
+        match *self {
+            Light::Sphere {
+                center,
+                radius,
+                material,
+            } => GpuLight {
+                p0: center.into(),
+                kind: Self::SPHERE,
+                p1: [radius, 0.0, 0.0],
+                material,
+                ..Default::default()
+            },
+            Light::Triangle { vertices, material } => GpuLight {
+                p0: vertices[0].into(),
+                kind: Self::TRIANGLE,
+                p1: vertices[1].into(),
+                material,
+                p2: vertices[2].into(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Every light in a `Scene`, and which geometry they came from. Built from the
+/// spheres and mesh instances with `Material::Emissive` materials. Moving
+/// spheres are left out, they're only found by rays hitting them.
+#[derive(Clone, Debug, Default)]
+pub struct LightList {
+    pub lights: Vec<Light>,
+    /// The light of each sphere in `Scene::spheres`.
+    pub sphere_lights: Vec<Option<u32>>,
+    /// The light of the first triangle of each instance in `Scene::instances`,
+    /// the lights of the others follow in order.
+    pub instance_lights: Vec<Option<u32>>,
+}
+
+impl LightList {
+    pub fn new(scene: &Scene) -> Self {
+        let emits = |material: MaterialId| {
+            matches!(
+                scene.materials[material as usize],
+                Material::Emissive { radiance } if radiance != Vec3::ZERO
+            )
+        };
+
+        let mut lights = Vec::new();
+
+        let sphere_lights = scene
+            .spheres
+            .iter()
+            .map(|sphere| {
+                (emits(sphere.material) && sphere.end_center.is_none()).then(|| {
+                    lights.push(Light::Sphere {
+                        center: sphere.center,
+                        radius: sphere.radius,
+                        material: sphere.material,
+                    });
+                    lights.len() as u32 - 1
+                })
+            })
+            .collect();
+
+        let instance_lights = (0..scene.instances.len() as u32)
+            .map(|instance_idx| {
+                let instance = &scene.instances[instance_idx as usize];
+                let triangle_count = scene.meshes[instance.mesh].triangle_count() as u32;
+                if !emits(instance.material) || triangle_count == 0 {
+                    return None;
+                }
+
+                let first = lights.len() as u32;
+                lights.extend((0..triangle_count).map(|triangle| Light::Triangle {
+                    vertices: scene.world_triangle(instance_idx, triangle),
+                    material: instance.material,
+                }));
+                Some(first)
+            })
+            .collect();
+
+        Self {
+            lights,
+            sphere_lights,
+            instance_lights,
+        }
+    }
+
+    /// The light that `primitive` is part of, if it emits.
+    pub fn light_of(&self, primitive: PrimitiveId) -> Option<u32> {
+        match primitive {
+            PrimitiveId::Sphere(sphere) => self.sphere_lights[sphere as usize],
+            PrimitiveId::Triangle { instance, triangle } => {
+                self.instance_lights[instance as usize].map(|first| first + triangle)
+            }
+        }
+    }
+
+    /// Picks a light uniformly with `u[0]` and samples it with the rest, like
+    /// `sampleLight()` in `light.glsl`. The density includes picking the light.
+    pub fn sample(&self, origin: Vec3, u: [f32; 3]) -> Option<(u32, LightSample)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let count = self.lights.len();
+        let light = ((u[0] * count as f32) as usize).min(count - 1);
+        let mut sample = self.lights[light].sample(origin, [u[1], u[2]])?;
+        sample.pdf /= count as f32;
+
+        Some((light as u32, sample))
+    }
+
+    /// The density `sample` would have picked the direction from `origin` to
+    /// `point` on `light` with.
+    pub fn pdf(&self, light: u32, origin: Vec3, point: Vec3) -> f32 {
+        self.lights[light as usize].pdf(origin, point) / self.lights.len() as f32
+    }
+}
+
+/// One minus the cosine of the half-angle of the cone a sphere subtends,
+/// computed without cancellation so far away spheres keep a valid density.
+fn one_minus_cos_cone(distance_squared: f32, radius: f32) -> f32 {
+    let sin_squared_max = radius * radius / distance_squared;
+    let cos_max = (1.0 - sin_squared_max).max(0.0).sqrt();
+    sin_squared_max / (1.0 + cos_max)
+}
+
+/// Converts the uniform area density of a triangle to solid angle.
+fn triangle_pdf(vertices: [Vec3; 3], direction: Vec3, distance: f32) -> Option<f32> {
+    let [v0, v1, v2] = vertices;
+    let normal = (v1 - v0).cross(v2 - v0);
+    let double_area = normal.length();
+    if double_area <= 0.0 {
+        return None;
+    }
+
+    let cosine = normal.dot(direction).abs() / double_area;
+    if cosine < 1e-6 {
+        return None;
+    }
+
+    Some(distance * distance / (0.5 * double_area * cosine))
+}
+
+/// Two unit vectors perpendicular to `n` and each other.
+pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    // Duff et al., "Building an Orthonormal Basis, Revisited".
+    let sign = if n.z >= 0.0 { 1.0 } else { -1.0 };
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
@@ -0,0 +1,73 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::math::Vec3;
+
+use super::texture::Texture;
+
+/// How light scatters off a surface. Geometry references materials by their
+/// index in the material buffer, bound at binding 4 of the bindless set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Material {
+    /// Ideal diffuse reflection.
+    Lambertian { albedo: Texture },
+    /// Mirror reflection, blurred by `fuzz` from 0 (perfect mirror) to 1.
+    Metal { albedo: Texture, fuzz: f32 },
+    /// Clear glass, water etc. Refracts or reflects according to the Schlick
+    /// approximation of the Fresnel equations.
+    Dielectric { ior: f32 },
+    /// A light source, it doesn't scatter anything.
+    Emissive { radiance: Vec3 },
+}
+
+/// `Material` as laid out in `assets/shaders/include/material.glsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct GpuMaterial {
+    pub albedo: [f32; 3],
+    pub kind: u32,
+    pub emission: [f32; 3],
+    /// Fuzz for metals, the index of refraction for dielectrics.
+    pub param: f32,
+    /// The odd cubes of a checker.
+    pub odd_albedo: [f32; 3],
+    /// How `albedo` is textured, see `Texture`.
+    pub texture: u32,
+    pub texture_scale: f32,
+    /// Index into the image buffer.
+    pub image: u32,
+    pub octaves: u32,
+    pub _pad: u32,
+}
+
+impl Material {
+    // Must match the MATERIAL_* constants in material.glsl.
+    const LAMBERTIAN: u32 = 0;
+    const METAL: u32 = 1;
+    const DIELECTRIC: u32 = 2;
+    const EMISSIVE: u32 = 3;
+
+    pub fn gpu_data(&self) -> GpuMaterial {
+        match *self {
+            Material::Lambertian { albedo } => GpuMaterial {
+                kind: Self::LAMBERTIAN,
+                ..albedo.gpu_data()
+            },
+            Material::Metal { albedo, fuzz } => GpuMaterial {
+                kind: Self::METAL,
+                param: fuzz.clamp(0.0, 1.0),
+                ..albedo.gpu_data()
+            },
+            Material::Dielectric { ior } => GpuMaterial {
+                albedo: [1.0; 3],
+                kind: Self::DIELECTRIC,
+                param: ior,
+                ..Default::default()
+            },
+            Material::Emissive { radiance } => GpuMaterial {
+                kind: Self::EMISSIVE,
+                emission: radiance.into(),
+                ..Default::default()
+            },
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::f32::consts::PI;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::math::{Ray, Vec3};
+
+use super::{light::orthonormal_basis, sampler::pcg_hash, scene::Scene};
+
+// `assets/shaders/include/medium.glsl` ported to the CPU, change both together.
+
+/// How a medium redirects the light it scatters.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PhaseFunction {
+    /// Equally in every direction.
+    #[default]
+    Isotropic,
+    /// Forward for `g` above 0, backward below, between -1 and 1.
+    HenyeyGreenstein { g: f32 },
+}
+
+/// Smoke, fog or anything else that scatters and absorbs light throughout its
+/// volume, with the same density everywhere.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Medium {
+    /// Collisions per unit of distance.
+    pub density: f32,
+    /// The fraction of collisions that scatter rather than absorb, per channel.
+    pub albedo: Vec3,
+    pub phase: PhaseFunction,
+}
+
+/// A medium filling a sphere. Surfaces can be inside it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Volume {
+    pub center: Vec3,
+    pub radius: f32,
+    pub medium: Medium,
+}
+
+/// A medium filling everything below `top`, so rays leaving the scene upwards
+/// still see the sky.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fog {
+    pub medium: Medium,
+    pub top: f32,
+}
+
+/// Where a ray collided with a medium, from `sample_collision`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collision {
+    pub t: f32,
+    pub medium: Medium,
+}
+
+/// `Medium` as laid out in `medium.glsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct GpuMedium {
+    pub albedo: [f32; 3],
+    pub density: f32,
+    /// The Henyey-Greenstein `g`, 0 is isotropic.
+    pub anisotropy: f32,
+    pub _pad: [u32; 3],
+}
+
+/// `Volume` as laid out in `medium.glsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct GpuVolume {
+    pub center: [f32; 3],
+    pub radius: f32,
+    pub medium: GpuMedium,
+}
+
+impl PhaseFunction {
+    fn anisotropy(&self) -> f32 {
+        match *self {
+            PhaseFunction::Isotropic => 0.0,
+            PhaseFunction::HenyeyGreenstein { g } => g.clamp(-0.99, 0.99),
+        }
+    }
+
+    /// The density of scattering into a direction at an angle with `cosine`
+    /// to the one the light travelled in, per unit solid angle.
+    pub fn pdf(&self, cosine: f32) -> f32 {
+        henyey_greenstein(self.anisotropy(), cosine)
+    }
+
+    /// Samples a new direction for light travelling along the normalized
+    /// `direction` with `u` in [0, 1)².
+    pub fn sample(&self, direction: Vec3, u: [f32; 2]) -> Vec3 {
+        let cos_theta = sample_henyey_greenstein(self.anisotropy(), u[0]);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u[1];
+        let (t, b) = orthonormal_basis(direction);
+        (t * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + direction * cos_theta)
+            .normalize()
+    }
+}
+
+impl Medium {
+    pub fn gpu_data(&self) -> GpuMedium {
+        GpuMedium {
+            albedo: self.albedo.into(),
+            density: self.density.max(0.0),
+            anisotropy: self.phase.anisotropy(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Volume {
+    /// The part of `ray` between 0 and `t_max` inside the sphere.
+    pub fn interval(&self, ray: &Ray, t_max: f32) -> Option<(f32, f32)> {
+        let oc = ray.origin - self.center;
+        let a = ray.direction.dot(ray.direction);
+        let half_b = oc.dot(ray.direction);
+        let c = oc.dot(oc) - self.radius * self.radius;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrtd = discriminant.sqrt();
+        clip((-half_b - sqrtd) / a, (-half_b + sqrtd) / a, t_max)
+    }
+
+    pub fn gpu_data(&self) -> GpuVolume {
+        GpuVolume {
+            center: self.center.into(),
+            radius: self.radius,
+            medium: self.medium.gpu_data(),
+        }
+    }
+}
+
+impl Fog {
+    /// The part of `ray` between 0 and `t_max` below the top of the fog.
+    pub fn interval(&self, ray: &Ray, t_max: f32) -> Option<(f32, f32)> {
+        let below = ray.origin.y < self.top;
+        if ray.direction.y == 0.0 {
+            return if below { clip(0.0, t_max, t_max) } else { None };
+        }
+
+        let t = (self.top - ray.origin.y) / ray.direction.y;
+        match (below, ray.direction.y > 0.0) {
+            (true, true) => clip(0.0, t, t_max),
+            (true, false) => clip(0.0, t_max, t_max),
+            (false, true) => None,
+            (false, false) => clip(t, t_max, t_max),
+        }
+    }
+}
+
+/// Clips `start..end` to `0..t_max`, `None` if nothing is left.
+fn clip(start: f32, end: f32, t_max: f32) -> Option<(f32, f32)> {
+    let (start, end) = (start.max(0.0), end.min(t_max));
+    (start < end).then_some((start, end))
+}
+
+/// The fog, then every volume, with the parts of `ray` up to `t_max` inside
+/// them.
+fn media_along(scene: &Scene, ray: &Ray, t_max: f32) -> Vec<(Medium, (f32, f32))> {
+    let fog = scene
+        .fog
+        .and_then(|fog| Some((fog.medium, fog.interval(ray, t_max)?)));
+    let volumes = scene
+        .volumes
+        .iter()
+        .filter_map(|volume| Some((volume.medium, volume.interval(ray, t_max)?)));
+
+    fog.into_iter().chain(volumes).collect()
+}
+
+/// A random number for the medium `index`, counting the fog and the volumes
+/// that follow it. The first is `u`, the others are hashed from it.
+fn medium_random(u: f32, index: u32) -> f32 {
+    if index == 0 {
+        u
+    } else {
+        (pcg_hash(u.to_bits().wrapping_add(index)) >> 8) as f32 * (1.0 / 16_777_216.0)
+    }
+}
+
+/// The first collision of `ray` with the fog or a volume before `t_max`, or
+/// `None` if it passes through. Every medium samples its own exponentially
+/// distributed distance with `u` in [0, 1), the closest one wins. Distances
+/// are only in world units if `ray.direction` is normalized. Like
+/// `sampleCollision()` in `medium.glsl`.
+pub fn sample_collision(scene: &Scene, ray: &Ray, t_max: f32, u: f32) -> Option<Collision> {
+    let mut closest: Option<Collision> = None;
+
+    // Without fog the first volume gets `u` itself.
+    let first_volume = matches!(scene.fog, Some(fog) if fog.medium.density > 0.0) as u32;
+    let fog = scene
+        .fog
+        .map(|fog| (0, fog.medium, fog.interval(ray, t_max)));
+    let volumes = scene.volumes.iter().enumerate().map(|(i, volume)| {
+        let index = first_volume + i as u32;
+        (index, volume.medium, volume.interval(ray, t_max))
+    });
+
+    for (index, medium, interval) in fog.into_iter().chain(volumes) {
+        let (start, end) = match interval {
+            Some(interval) if medium.density > 0.0 => interval,
+            _ => continue,
+        };
+
+        let t = start - (1.0 - medium_random(u, index)).ln() / medium.density;
+        let end = closest.map_or(end, |closest| end.min(closest.t));
+        if t < end {
+            closest = Some(Collision { t, medium });
+        }
+    }
+
+    closest
+}
+
+/// The fraction of light that makes it along `ray` from 0 to `t_max` through
+/// the fog and the volumes, ignoring surfaces.
+pub fn transmittance(scene: &Scene, ray: &Ray, t_max: f32) -> f32 {
+    let optical_depth: f32 = media_along(scene, ray, t_max)
+        .iter()
+        .map(|(medium, (start, end))| medium.density.max(0.0) * (end - start))
+        .sum();
+    (-optical_depth).exp()
+}
+
+fn henyey_greenstein(g: f32, cosine: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cosine;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.max(1e-12).sqrt())
+}
+
+/// Inverts the Henyey-Greenstein CDF for the cosine of the scattering angle.
+fn sample_henyey_greenstein(g: f32, u: f32) -> f32 {
+    if g.abs() < 1e-3 {
+        return 1.0 - 2.0 * u;
+    }
+
+    let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+    ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+}
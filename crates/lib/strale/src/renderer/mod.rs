@@ -1,137 +1,539 @@
+pub mod acceleration_structure;
 mod bindless_descriptor_set;
-mod renderers;
+pub mod bvh;
+pub mod camera;
+pub mod denoiser;
+pub mod environment;
+pub mod glsl;
+pub mod integrator;
+pub mod light;
+pub mod material;
+pub mod medium;
+pub mod render_graph;
+pub mod render_pass;
+pub mod render_settings;
+pub mod renderers;
+pub mod sampler;
+pub mod scene;
+pub mod shader_compiler;
+mod shader_watcher;
+pub mod texture;
 pub mod utils;
 mod vertex;
 pub mod vulkan;
 
 use std::sync::Arc;
 
-use ash::vk::{self, Rect2D};
+use anyhow::Context;
+use ash::vk;
+use vk_sync::AccessType;
 
 use self::{
-    bindless_descriptor_set::create_bindless_descriptor_set,
-    renderers::triangles::TrianglesPipeline,
-    vertex::{Sphere, Vertex},
+    acceleration_structure::SceneAccelerationStructure,
+    bindless_descriptor_set::{
+        create_bindless_descriptor_set, create_bindless_descriptor_set_layout,
+    },
+    camera::Camera,
+    denoiser::DenoiserSettings,
+    environment::EnvironmentControls,
+    render_graph::{RenderGraph, TransientResourceCache},
+    render_pass::{FrameConstants, FrameContext, GpuFrameConstants, RenderPass, SetupContext},
+    render_settings::{DebugView, RenderSettings, Tracer},
+    renderers::{
+        permutations::PermutationCache, ray_tracing::RayTracingPipeline,
+        triangles::TrianglesPipeline, wavefront::WavefrontPipeline,
+    },
+    scene::{Scene, SceneBuffers},
+    shader_compiler::shader_dir,
+    shader_watcher::ShaderWatcher,
     vulkan::{
         backend::Backend,
         buffer::{Buffer, BufferDesc},
         device::Device,
-        swapchain::Swapchain,
+        ray_tracing::AccelerationStructure,
+        shader::DescriptorSetLayoutDesc,
+        swapchain::{Swapchain, SwapchainDesc},
     },
 };
 
 pub struct Renderer {
     device: Arc<Device>,
-    triangles_pipeline: TrianglesPipeline,
+    settings: RenderSettings,
+    triangles_pipelines: PermutationCache<TrianglesPipeline>,
+    ray_tracing_pipelines: PermutationCache<RayTracingPipeline>,
+    wavefront_pipelines: PermutationCache<WavefrontPipeline>,
+    /// The tracer picked with `set_tracer`. Starts with the ray tracing pipeline
+    /// if the device supports it and it could be built.
+    tracer: Tracer,
+    /// Set after a hot reload, so new permutations don't go back to the embedded SPIR-V.
+    shaders_from_source: bool,
+    /// Referenced from the bindless set, `None` without ray tracing support.
+    acceleration_structure: Option<SceneAccelerationStructure>,
+    /// Some of the scene's spheres move while the shutter is open.
+    moving_spheres: bool,
+    scene_buffers: SceneBuffers,
+    /// Shared by every pipeline as set 0, destroyed after them on drop.
+    bindless_descriptor_set_layout: DescriptorSetLayoutDesc,
+    bindless_descriptor_pool: vk::DescriptorPool,
+    bindless_descriptor_set: vk::DescriptorSet,
+    swapchain_desc: SwapchainDesc,
+    transient_resources: TransientResourceCache,
+    /// Application passes, recorded after the path tracer in this order.
+    render_passes: Vec<Box<dyn RenderPass>>,
+    frame_index: u64,
+    camera: Camera,
+    environment: EnvironmentControls,
+    denoiser: DenoiserSettings,
+    /// `FrameConstants` for the shaders, rewritten at the start of every frame.
+    frame_constants_buffer: Buffer,
+    /// Frames averaged into the current pipeline's accumulation target.
+    accumulated_frames: u32,
+    /// `None` if the watcher couldn't be started, hot reload is then disabled.
+    shader_watcher: Option<ShaderWatcher>,
 }
 
 impl Renderer {
-    pub fn new(backend: &Backend) -> anyhow::Result<Renderer> {
-        let bindless_descriptor_set = create_bindless_descriptor_set(backend.device.as_ref());
+    /// Uploads `scene`, which can't be changed afterwards.
+    pub fn new(
+        backend: &Backend,
+        settings: RenderSettings,
+        scene: &Scene,
+    ) -> anyhow::Result<Renderer> {
+        let bindless_descriptor_set_layout =
+            create_bindless_descriptor_set_layout(backend.device.as_ref());
+        let (bindless_descriptor_pool, bindless_descriptor_set) = create_bindless_descriptor_set(
+            backend.device.as_ref(),
+            bindless_descriptor_set_layout.layout,
+        );
 
-        let vertices = [
-            Vertex {
-                position: [-1.0, 1.0, 0.0, 1.0],
-            },
-            Vertex {
-                position: [1.0, 1.0, 0.0, 1.0],
-            },
-            Vertex {
-                position: [0.0, -1.0, 0.0, 1.0],
-            },
-        ];
-
-        let vertex_buffer_size = vertices.len() * std::mem::size_of::<Vertex>();
-
-        let vertex_buffer = unsafe {
-            backend.device.create_buffer(
-                BufferDesc {
-                    size: vertex_buffer_size,
-                    usage: vk::BufferUsageFlags::STORAGE_BUFFER
-                        | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-                        | vk::BufferUsageFlags::INDEX_BUFFER
-                        | vk::BufferUsageFlags::TRANSFER_DST,
-                    memory_location: gpu_allocator::MemoryLocation::GpuOnly,
-                },
-                "vertex buffer",
-                Some(std::slice::from_raw_parts(
-                    vertices.as_ptr() as *const u8,
-                    vertex_buffer_size,
-                )),
-            )
-        };
+        let scene_buffers = SceneBuffers::new(&backend.device, scene)?;
+
+        for (binding, buffer) in [
+            (0, &scene_buffers.vertices),
+            (1, &scene_buffers.spheres),
+            (4, &scene_buffers.materials),
+            (5, &scene_buffers.indices),
+            (6, &scene_buffers.mesh_instances),
+            (7, &scene_buffers.bvh_nodes),
+            (8, &scene_buffers.bvh_primitives),
+            (9, &scene_buffers.lights),
+            (10, &scene_buffers.environment),
+            (11, &scene_buffers.environment_cdf),
+            (12, &scene_buffers.images),
+            (13, &scene_buffers.texels),
+            (14, &scene_buffers.media),
+        ] {
+            Self::write_descriptor_set_buffer(
+                &backend.device,
+                bindless_descriptor_set,
+                binding,
+                buffer,
+            );
+        }
 
-        let spheres = [
-            Sphere {
-                position: [0.0, -1000.0, 0.0],
-                radius: 1000.0,
-                material: 0 as f32,
-                albedo: [0.5, 0.5, 0.5],
-            },
-            Sphere {
-                position: [2.0, 0.2, 0.0],
-                radius: 0.2,
-                material: 0 as f32,
-                albedo: [0.5, 0.5, 0.5],
+        let frame_constants_buffer = backend.device.create_buffer(
+            BufferDesc {
+                size: std::mem::size_of::<GpuFrameConstants>(),
+                usage: vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                memory_location: gpu_allocator::MemoryLocation::GpuOnly,
             },
-            Sphere {
-                position: [0.0, 1.2, 1.0],
-                radius: 1.0,
-                material: 0 as f32,
-                albedo: [0.5, 0.5, 0.5],
-            },
-            Sphere {
-                position: [1.0, 0.2, 1.0],
-                radius: 0.2,
-                material: 0 as f32,
-                albedo: [0.5, 0.5, 0.5],
-            },
-        ];
-
-        let sphere_buffer = unsafe {
-            backend.device.create_buffer(
-                BufferDesc {
-                    size: 1024 * 1024 * 1024,
-                    usage: vk::BufferUsageFlags::TRANSFER_DST
-                        | vk::BufferUsageFlags::STORAGE_BUFFER
-                        | vk::BufferUsageFlags::INDEX_BUFFER
-                        | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
-                    memory_location: gpu_allocator::MemoryLocation::GpuOnly,
-                },
-                "sphere buffer",
-                Some(std::slice::from_raw_parts(
-                    spheres.as_ptr() as *const u8,
-                    spheres.len() * std::mem::size_of::<Sphere>(),
-                )),
-            )
-        };
+            "frame constants",
+            None,
+        );
 
         Self::write_descriptor_set_buffer(
-            &backend.device.clone(),
+            &backend.device,
             bindless_descriptor_set,
-            0,
-            &vertex_buffer,
+            3,
+            &frame_constants_buffer,
         );
-        Self::write_descriptor_set_buffer(
-            &backend.device.clone(),
+
+        let acceleration_structure = if backend.device.acceleration_structure_enabled {
+            match SceneAccelerationStructure::new(&backend.device, scene, &scene_buffers) {
+                Ok(acceleration_structure) => {
+                    Self::write_descriptor_set_acceleration_structure(
+                        &backend.device,
+                        bindless_descriptor_set,
+                        2,
+                        &acceleration_structure.tlas,
+                    );
+
+                    Some(acceleration_structure)
+                }
+                Err(err) => {
+                    log::warn!(
+                        "Falling back to the fragment shader tracer, failed to build acceleration structures: {:#}",
+                        err
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let shader_watcher = match ShaderWatcher::new(&shader_dir()) {
+            Ok(watcher) => Some(watcher),
+            Err(err) => {
+                log::warn!("Shader hot reload disabled: {:#}", err);
+                None
+            }
+        };
+
+        let mut renderer = Renderer {
+            device: backend.device.clone(),
+            settings,
+            triangles_pipelines: PermutationCache::new(),
+            ray_tracing_pipelines: PermutationCache::new(),
+            wavefront_pipelines: PermutationCache::new(),
+            tracer: if backend.device.ray_tracing_enabled && acceleration_structure.is_some() {
+                Tracer::RayTracing
+            } else {
+                Tracer::Fragment
+            },
+            shaders_from_source: false,
+            acceleration_structure,
+            moving_spheres: scene
+                .spheres
+                .iter()
+                .any(|sphere| sphere.end_center.is_some()),
+            scene_buffers,
+            bindless_descriptor_set_layout,
+            bindless_descriptor_pool,
             bindless_descriptor_set,
-            1,
-            &sphere_buffer,
-        );
+            swapchain_desc: backend.swapchain.desc,
+            transient_resources: TransientResourceCache::new(),
+            render_passes: Vec::new(),
+            frame_index: 0,
+            camera: Camera::default(),
+            environment: EnvironmentControls::default(),
+            denoiser: DenoiserSettings::default(),
+            frame_constants_buffer,
+            accumulated_frames: 0,
+            shader_watcher,
+        };
+        renderer.settings.ray_queries &= renderer.ray_queries_available();
 
-        let mut triangles_pipeline = TrianglesPipeline::create_pipeline(
-            &backend.device,
-            backend.swapchain.desc,
-            spheres.len(),
-        );
-        triangles_pipeline
+        if renderer.tracer == Tracer::RayTracing && renderer.active_tracer() == Tracer::Fragment {
+            log::info!(
+                "Moving spheres are rendered by the fragment shader tracer, the device doesn't support ray tracing motion blur"
+            );
+        }
+
+        if renderer.tracer == Tracer::RayTracing {
+            if let Err(err) = renderer.prepare_pipelines() {
+                log::warn!(
+                    "Falling back to the fragment shader tracer, failed to create the ray tracing pipeline: {:#}",
+                    err
+                );
+                renderer.tracer = Tracer::Fragment;
+            }
+        }
+
+        renderer.prepare_pipelines()?;
+
+        Ok(renderer)
+    }
+
+    pub fn settings(&self) -> RenderSettings {
+        self.settings
+    }
+
+    /// Switches to new quality settings. The pipeline permutation for them is built
+    /// right away, if that fails the previous settings stay in use.
+    pub fn set_settings(&mut self, mut settings: RenderSettings) -> anyhow::Result<()> {
+        settings.ray_queries &= self.ray_queries_available();
+        let previous = std::mem::replace(&mut self.settings, settings);
+
+        if let Err(err) = self.prepare_pipelines() {
+            self.settings = previous;
+            return Err(err);
+        }
+
+        if self.settings != previous {
+            self.reset_accumulation();
+        }
+
+        Ok(())
+    }
+
+    pub fn tracer(&self) -> Tracer {
+        self.tracer
+    }
+
+    /// Switches to another tracer. Its pipeline is built right away, if that fails
+    /// the previous tracer stays in use.
+    pub fn set_tracer(&mut self, tracer: Tracer) -> anyhow::Result<()> {
+        if tracer == Tracer::RayTracing
+            && !(self.device.ray_tracing_enabled && self.acceleration_structure.is_some())
+        {
+            anyhow::bail!("Hardware ray tracing isn't available on this device");
+        }
+
+        let previous = std::mem::replace(&mut self.tracer, tracer);
+
+        if let Err(err) = self.prepare_pipelines() {
+            self.tracer = previous;
+            return Err(err);
+        }
+
+        if self.tracer != previous {
+            self.reset_accumulation();
+        }
+
+        Ok(())
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    /// Restarts accumulation if the camera moved.
+    pub fn set_camera(&mut self, camera: Camera) {
+        if camera != self.camera {
+            self.camera = camera;
+            self.reset_accumulation();
+        }
+    }
+
+    pub fn environment(&self) -> EnvironmentControls {
+        self.environment
+    }
+
+    /// Restarts accumulation if the environment changed.
+    pub fn set_environment(&mut self, environment: EnvironmentControls) {
+        if environment != self.environment {
+            self.environment = environment;
+            self.reset_accumulation();
+        }
+    }
+
+    pub fn denoiser(&self) -> DenoiserSettings {
+        self.denoiser
+    }
+
+    /// Takes effect with the next frame, accumulation carries on.
+    pub fn set_denoiser(&mut self, denoiser: DenoiserSettings) {
+        self.denoiser = denoiser;
+    }
+
+    /// Throws away the accumulated image, e.g. because the scene changed. Camera,
+    /// environment, settings and shader changes already do this.
+    pub fn reset_accumulation(&mut self) {
+        self.accumulated_frames = 0;
+    }
+
+    /// Samples per pixel in the image presented by the last `draw`.
+    pub fn accumulated_samples(&self) -> u32 {
+        self.accumulated_frames * self.settings.samples_per_pixel
+    }
+
+    /// Registers a pass to run after the path tracer and after every pass added
+    /// before it. Its `setup` hook runs right away.
+    pub fn add_render_pass(&mut self, mut pass: impl RenderPass + 'static) -> anyhow::Result<()> {
+        pass.setup(&self.setup_context())?;
+        self.render_passes.push(Box::new(pass));
+
+        Ok(())
+    }
+
+    fn setup_context(&self) -> SetupContext<'_> {
+        SetupContext {
+            device: &self.device,
+            bindless_descriptor_set_layout: &self.bindless_descriptor_set_layout,
+            bindless_descriptor_set: self.bindless_descriptor_set,
+            swapchain_desc: self.swapchain_desc,
+        }
+    }
+
+    /// Recreates everything that depends on the swapchain dimensions.
+    fn resize(&mut self, swapchain_desc: SwapchainDesc) -> anyhow::Result<()> {
+        unsafe { self.device.raw.device_wait_idle().unwrap() };
+
+        self.swapchain_desc = swapchain_desc;
+
+        for old in self.triangles_pipelines.drain() {
+            old.destroy(&self.device);
+        }
+        for old in self.ray_tracing_pipelines.drain() {
+            old.destroy(&self.device);
+        }
+        for old in self.wavefront_pipelines.drain() {
+            old.destroy(&self.device);
+        }
+        self.reset_accumulation();
+        std::mem::take(&mut self.transient_resources).destroy(&self.device);
+
+        self.prepare_pipelines()
+            .context("Failed to recreate the pipelines after a resize")?;
+
+        let ctx = SetupContext {
+            device: &self.device,
+            bindless_descriptor_set_layout: &self.bindless_descriptor_set_layout,
+            bindless_descriptor_set: self.bindless_descriptor_set,
+            swapchain_desc,
+        };
+        for pass in &mut self.render_passes {
+            pass.resize(&ctx)
+                .context("Failed to resize a render pass")?;
+        }
+
+        Ok(())
+    }
+
+    /// Ray queries trace against the scene's TLAS, which the device may support
+    /// but have failed to build.
+    fn ray_queries_available(&self) -> bool {
+        self.device.ray_query_enabled && self.acceleration_structure.is_some()
+    }
+
+    /// The tracer that renders with the current settings. Counting BVH traversal
+    /// steps needs one that walks the BVH itself, and without
+    /// `VK_NV_ray_tracing_motion_blur` the intersection shader can't tell when
+    /// a ray was traced. So the ray tracing pipeline hands that view, and scenes
+    /// with moving spheres, to the fragment shader tracer.
+    fn active_tracer(&self) -> Tracer {
+        match self.tracer {
+            Tracer::RayTracing if self.settings.debug_view == DebugView::BvhCost => {
+                Tracer::Fragment
+            }
+            Tracer::RayTracing
+                if self.moving_spheres && !self.device.ray_tracing_motion_blur_enabled =>
+            {
+                Tracer::Fragment
+            }
+            tracer => tracer,
+        }
+    }
+
+    /// Makes sure the pipeline permutation for the current settings exists.
+    fn prepare_pipelines(&mut self) -> anyhow::Result<()> {
+        let constants = self.settings.specialization_constants();
+
+        match self.active_tracer() {
+            Tracer::Fragment => {
+                if self
+                    .triangles_pipelines
+                    .get(TrianglesPipeline::SOURCES, &constants)
+                    .is_none()
+                {
+                    let pipeline = self.create_triangles_pipeline(self.shaders_from_source)?;
+                    self.triangles_pipelines.insert(
+                        TrianglesPipeline::SOURCES,
+                        &constants,
+                        pipeline,
+                    );
+                }
+            }
+            Tracer::RayTracing => {
+                if self
+                    .ray_tracing_pipelines
+                    .get(RayTracingPipeline::SOURCES, &constants)
+                    .is_none()
+                {
+                    let pipeline = self.create_ray_tracing_pipeline(self.shaders_from_source)?;
+                    self.ray_tracing_pipelines.insert(
+                        RayTracingPipeline::SOURCES,
+                        &constants,
+                        pipeline,
+                    );
+                }
+            }
+            Tracer::Wavefront => {
+                if self
+                    .wavefront_pipelines
+                    .get(WavefrontPipeline::SOURCES, &constants)
+                    .is_none()
+                {
+                    let pipeline = self.create_wavefront_pipeline(self.shaders_from_source)?;
+                    self.wavefront_pipelines.insert(
+                        WavefrontPipeline::SOURCES,
+                        &constants,
+                        pipeline,
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn create_triangles_pipeline(&self, from_source: bool) -> anyhow::Result<TrianglesPipeline> {
+        let mut pipeline = if from_source {
+            TrianglesPipeline::create_pipeline_from_source(
+                &self.device,
+                self.swapchain_desc,
+                &self.bindless_descriptor_set_layout,
+                &self.scene_buffers,
+                self.settings,
+            )?
+        } else {
+            TrianglesPipeline::create_pipeline(
+                &self.device,
+                self.swapchain_desc,
+                &self.bindless_descriptor_set_layout,
+                &self.scene_buffers,
+                self.settings,
+            )?
+        };
+
+        pipeline
             .inner
-            .add_descriptor_set(0, bindless_descriptor_set);
+            .add_descriptor_set(0, self.bindless_descriptor_set);
 
-        Ok(Renderer {
-            device: backend.device.clone(),
-            triangles_pipeline,
-        })
+        Ok(pipeline)
+    }
+
+    fn create_ray_tracing_pipeline(&self, from_source: bool) -> anyhow::Result<RayTracingPipeline> {
+        let constants = self.settings.specialization_constants();
+
+        let mut pipeline = if from_source {
+            RayTracingPipeline::create_pipeline_from_source(
+                &self.device,
+                self.swapchain_desc,
+                &self.bindless_descriptor_set_layout,
+                &constants,
+                self.device.ray_tracing_motion_blur_enabled,
+            )?
+        } else {
+            RayTracingPipeline::create_pipeline(
+                &self.device,
+                self.swapchain_desc,
+                &self.bindless_descriptor_set_layout,
+                &constants,
+                self.device.ray_tracing_motion_blur_enabled,
+            )?
+        };
+
+        pipeline
+            .inner
+            .add_descriptor_set(0, self.bindless_descriptor_set);
+
+        Ok(pipeline)
+    }
+
+    fn create_wavefront_pipeline(&self, from_source: bool) -> anyhow::Result<WavefrontPipeline> {
+        let mut pipeline = if from_source {
+            WavefrontPipeline::create_pipeline_from_source(
+                &self.device,
+                self.swapchain_desc,
+                &self.bindless_descriptor_set_layout,
+                &self.scene_buffers,
+                self.settings,
+            )?
+        } else {
+            WavefrontPipeline::create_pipeline(
+                &self.device,
+                self.swapchain_desc,
+                &self.bindless_descriptor_set_layout,
+                &self.scene_buffers,
+                self.settings,
+            )?
+        };
+
+        pipeline.add_descriptor_set(0, self.bindless_descriptor_set);
+
+        Ok(pipeline)
     }
 
     fn write_descriptor_set_buffer(
@@ -159,7 +561,209 @@ impl Renderer {
         }
     }
 
-    pub fn draw(&mut self, swapchain: &mut Swapchain) {
+    fn write_descriptor_set_acceleration_structure(
+        device: &Device,
+        set: vk::DescriptorSet,
+        dst_binding: u32,
+        acceleration_structure: &AccelerationStructure,
+    ) {
+        let mut acceleration_structure_info =
+            vk::WriteDescriptorSetAccelerationStructureKHR::builder()
+                .acceleration_structures(std::slice::from_ref(&acceleration_structure.raw))
+                .build();
+
+        let mut write_descriptor_set = vk::WriteDescriptorSet::builder()
+            .dst_set(set)
+            .descriptor_type(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
+            .dst_binding(dst_binding)
+            .push_next(&mut acceleration_structure_info)
+            .build();
+        // The builder only derives the count from buffer and image infos.
+        write_descriptor_set.descriptor_count = 1;
+
+        unsafe {
+            device
+                .raw
+                .update_descriptor_sets(std::slice::from_ref(&write_descriptor_set), &[])
+        }
+    }
+
+    /// Recompiles pipelines whose GLSL sources changed since the last frame. A
+    /// pipeline that fails to compile keeps running with its previous shaders.
+    ///
+    /// Only the permutation for the current settings of the active tracer is
+    /// rebuilt. The others, and the other tracers' pipelines that use the
+    /// changed files, are dropped and recreated from source when they're next
+    /// used.
+    fn reload_shaders(&mut self) {
+        let changed = match &self.shader_watcher {
+            Some(watcher) => watcher.changed_files(),
+            None => return,
+        };
+
+        // Includes aren't tracked per shader, so an edited include reloads everything.
+        let include_changed = changed.iter().any(|file| file.ends_with(".glsl"));
+        let affects = |sources: &[&str]| {
+            include_changed || sources.iter().any(|source| changed.contains(*source))
+        };
+
+        let constants = self.settings.specialization_constants();
+
+        let active = self.active_tracer();
+
+        if active == Tracer::Fragment && affects(TrianglesPipeline::SOURCES) {
+            match self.create_triangles_pipeline(true) {
+                Ok(pipeline) => {
+                    unsafe { self.device.raw.device_wait_idle().unwrap() };
+                    for old in self.triangles_pipelines.drain() {
+                        old.destroy(&self.device);
+                    }
+                    self.triangles_pipelines.insert(
+                        TrianglesPipeline::SOURCES,
+                        &constants,
+                        pipeline,
+                    );
+                    self.shaders_from_source = true;
+                    self.reset_accumulation();
+
+                    log::info!("Reloaded the triangles pipeline");
+                }
+                Err(err) => log::error!("{:#}", err),
+            }
+        }
+
+        if active == Tracer::RayTracing && affects(RayTracingPipeline::SOURCES) {
+            match self.create_ray_tracing_pipeline(true) {
+                Ok(pipeline) => {
+                    unsafe { self.device.raw.device_wait_idle().unwrap() };
+                    for old in self.ray_tracing_pipelines.drain() {
+                        old.destroy(&self.device);
+                    }
+                    self.ray_tracing_pipelines.insert(
+                        RayTracingPipeline::SOURCES,
+                        &constants,
+                        pipeline,
+                    );
+                    self.shaders_from_source = true;
+                    self.reset_accumulation();
+
+                    log::info!("Reloaded the ray tracing pipeline");
+                }
+                Err(err) => log::error!("{:#}", err),
+            }
+        }
+
+        if active == Tracer::Wavefront && affects(WavefrontPipeline::SOURCES) {
+            match self.create_wavefront_pipeline(true) {
+                Ok(pipeline) => {
+                    unsafe { self.device.raw.device_wait_idle().unwrap() };
+                    for old in self.wavefront_pipelines.drain() {
+                        old.destroy(&self.device);
+                    }
+                    self.wavefront_pipelines.insert(
+                        WavefrontPipeline::SOURCES,
+                        &constants,
+                        pipeline,
+                    );
+                    self.shaders_from_source = true;
+                    self.reset_accumulation();
+
+                    log::info!("Reloaded the wavefront pipeline");
+                }
+                Err(err) => log::error!("{:#}", err),
+            }
+        }
+
+        let stale_triangles = active != Tracer::Fragment && affects(TrianglesPipeline::SOURCES);
+        let stale_ray_tracing =
+            active != Tracer::RayTracing && affects(RayTracingPipeline::SOURCES);
+        let stale_wavefront = active != Tracer::Wavefront && affects(WavefrontPipeline::SOURCES);
+        if stale_triangles || stale_ray_tracing || stale_wavefront {
+            unsafe { self.device.raw.device_wait_idle().unwrap() };
+            if stale_triangles {
+                for old in self.triangles_pipelines.drain() {
+                    old.destroy(&self.device);
+                }
+            }
+            if stale_ray_tracing {
+                for old in self.ray_tracing_pipelines.drain() {
+                    old.destroy(&self.device);
+                }
+            }
+            if stale_wavefront {
+                for old in self.wavefront_pipelines.drain() {
+                    old.destroy(&self.device);
+                }
+            }
+            self.shaders_from_source = true;
+        }
+    }
+
+    /// Renders a frame to `swapchain`, after adapting to its size if it was
+    /// recreated, see `Backend::resize`.
+    pub fn draw(&mut self, swapchain: &mut Swapchain) -> anyhow::Result<()> {
+        self.draw_frame(swapchain, None)
+    }
+
+    /// Renders a frame like `draw` and returns what it presented, e.g. to save
+    /// an offline render. Waits for the GPU to finish the frame.
+    pub fn capture(&mut self, swapchain: &mut Swapchain) -> anyhow::Result<image::RgbaImage> {
+        let usage = swapchain.images[0].desc.usage;
+        anyhow::ensure!(
+            usage.contains(vk::ImageUsageFlags::TRANSFER_SRC),
+            "The surface doesn't support copying from swapchain images"
+        );
+
+        // Readback has to pick the channels by the format.
+        let bgra = match swapchain.desc.format {
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => true,
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => false,
+            format => anyhow::bail!("Can't capture swapchain images in {:?}", format),
+        };
+
+        let dims = swapchain.desc.dims;
+        let readback = self.device.create_buffer(
+            BufferDesc {
+                size: dims.width as usize * dims.height as usize * 4,
+                usage: vk::BufferUsageFlags::TRANSFER_DST,
+                memory_location: gpu_allocator::MemoryLocation::GpuToCpu,
+            },
+            "capture readback",
+            None,
+        );
+
+        let result = self.draw_frame(swapchain, Some(&readback)).map(|()| {
+            let mut pixels = readback
+                .allocation
+                .mapped_slice()
+                .expect("memory not host visible")
+                .to_vec();
+            if bgra {
+                for pixel in pixels.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+            image::RgbaImage::from_raw(dims.width, dims.height, pixels)
+                .expect("readback holds a whole image")
+        });
+
+        self.device.destroy_buffer(readback);
+        result
+    }
+
+    /// Renders a frame to `swapchain`. With `readback`, also copies the frame to
+    /// it and waits until the copy is done.
+    fn draw_frame(
+        &mut self,
+        swapchain: &mut Swapchain,
+        readback: Option<&Buffer>,
+    ) -> anyhow::Result<()> {
+        if swapchain.desc.dims != self.swapchain_desc.dims {
+            self.resize(swapchain.desc)?;
+        }
+
+        self.reload_shaders();
+
         let current_frame = self.device.begin_frame();
 
         unsafe {
@@ -192,104 +796,108 @@ impl Renderer {
         {
             let main_cb = &current_frame.main_command_buffer;
 
-            vk_sync::cmd::pipeline_barrier(
-                &self.device.raw,
-                main_cb.raw,
-                None,
-                &[],
-                &[vk_sync::ImageBarrier {
-                    discard_contents: false,
-                    image: swapchain_image.image.raw,
-                    previous_accesses: &[vk_sync::AccessType::Nothing],
-                    next_accesses: &[vk_sync::AccessType::ColorAttachmentWrite],
-                    next_layout: vk_sync::ImageLayout::Optimal,
-                    previous_layout: vk_sync::ImageLayout::Optimal,
-                    range: vk::ImageSubresourceRange {
-                        aspect_mask: vk::ImageAspectFlags::COLOR,
-                        base_mip_level: 0,
-                        level_count: vk::REMAINING_MIP_LEVELS,
-                        base_array_layer: 0,
-                        layer_count: vk::REMAINING_ARRAY_LAYERS,
-                    },
-                    dst_queue_family_index: self.device.universal_queue.family.index,
-                    src_queue_family_index: self.device.universal_queue.family.index,
-                }],
-            );
-
-            // DO SCREEN RENDER STUFF
-
-            // Do CB stuff
-            let color_attachment_info = vk::RenderingAttachmentInfo::builder()
-                .clear_value(vk::ClearValue {
-                    color: vk::ClearColorValue {
-                        float32: [0.0, 0.0, 1.0, 0.0],
-                    },
-                })
-                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                .image_view(swapchain_image.image.view)
-                .load_op(vk::AttachmentLoadOp::CLEAR)
-                .store_op(vk::AttachmentStoreOp::STORE);
-
-            let render_info = vk::RenderingInfoKHR::builder()
-                .color_attachments(std::slice::from_ref(&color_attachment_info))
-                .layer_count(1)
-                .render_area(
-                    vk::Rect2D::builder()
-                        .extent(swapchain.desc.dims)
-                        .offset(vk::Offset2D { x: 0, y: 0 })
-                        .build(),
-                );
-
-            let viewports = &[vk::Viewport {
-                width: swapchain.desc.dims.width as f32,
-                height: -(swapchain.desc.dims.height as f32),
-                y: swapchain.desc.dims.height as f32,
-                ..Default::default()
-            }];
-
-            let scissors = &[Rect2D::builder().extent(swapchain.desc.dims).build()];
-
-            vk_sync::cmd::pipeline_barrier(
-                &self.device.raw,
-                main_cb.raw,
-                None,
-                &[],
-                &[vk_sync::ImageBarrier {
-                    discard_contents: false,
-                    image: swapchain_image.image.raw,
-                    previous_accesses: &[vk_sync::AccessType::ColorAttachmentWrite],
-                    previous_layout: vk_sync::ImageLayout::Optimal,
-                    next_accesses: &[vk_sync::AccessType::Present],
-                    next_layout: vk_sync::ImageLayout::Optimal,
-                    range: vk::ImageSubresourceRange {
-                        aspect_mask: vk::ImageAspectFlags::COLOR,
-                        base_mip_level: 0,
-                        level_count: vk::REMAINING_MIP_LEVELS,
-                        base_array_layer: 0,
-                        layer_count: vk::REMAINING_ARRAY_LAYERS,
-                    },
-                    dst_queue_family_index: self.device.universal_queue.family.index,
-                    src_queue_family_index: self.device.universal_queue.family.index,
-                }],
-            );
-
-            unsafe {
-                self.device
-                    .raw
-                    .cmd_begin_rendering(main_cb.raw, &render_info);
+            let constants = self.settings.specialization_constants();
+
+            let mut graph = RenderGraph::new();
+            let swapchain_handle = graph.import_image(&swapchain_image.image, AccessType::Nothing);
+            // Every pass that reads the constants uses this access.
+            let frame_constants_handle =
+                graph.import_buffer(&self.frame_constants_buffer, AccessType::AnyShaderReadOther);
+
+            let frame = FrameContext {
+                device: &self.device,
+                bindless_descriptor_set: self.bindless_descriptor_set,
+                constants: FrameConstants {
+                    time: self.device.first_frame.elapsed().as_secs_f32(),
+                    frame_index: self.frame_index,
+                    dims: swapchain.desc.dims,
+                    camera: self.camera,
+                    accumulated_frames: self.accumulated_frames,
+                    environment: self.environment,
+                },
+                frame_constants: frame_constants_handle,
+                target: swapchain_handle,
+            };
+
+            let gpu_frame_constants = frame.constants.gpu_data();
+            graph
+                .add_pass("upload frame constants")
+                .write_buffer(frame_constants_handle, AccessType::TransferWrite)
+                .render(move |ctx| unsafe {
+                    ctx.device.raw.cmd_update_buffer(
+                        ctx.cb.raw,
+                        ctx.buffer(frame_constants_handle).raw,
+                        0,
+                        bytemuck::bytes_of(&gpu_frame_constants),
+                    );
+                });
+
+            // Debug views show what the tracer sees, not what the denoiser makes of it.
+            let mut denoiser = self.denoiser;
+            denoiser.enabled &= self.settings.debug_view == DebugView::None;
+
+            match self.active_tracer() {
+                Tracer::Fragment => self
+                    .triangles_pipelines
+                    .get(TrianglesPipeline::SOURCES, &constants)
+                    .expect("triangles pipeline for the current settings")
+                    .add_passes(&mut graph, &frame, denoiser),
+                Tracer::RayTracing => self
+                    .ray_tracing_pipelines
+                    .get(RayTracingPipeline::SOURCES, &constants)
+                    .expect("ray tracing pipeline for the current settings")
+                    .add_passes(&mut graph, &frame, denoiser),
+                Tracer::Wavefront => self
+                    .wavefront_pipelines
+                    .get(WavefrontPipeline::SOURCES, &constants)
+                    .expect("wavefront pipeline for the current settings")
+                    .add_passes(&mut graph, &frame, denoiser),
+            }
 
-                self.device.raw.cmd_set_viewport(main_cb.raw, 0, viewports);
-                self.device.raw.cmd_set_scissor(main_cb.raw, 0, scissors);
+            for pass in &self.render_passes {
+                pass.record(&mut graph, &frame);
+            }
 
-                self.triangles_pipeline
-                    .inner
-                    .bind_pipeline(&self.device, main_cb.raw);
+            if let Some(readback) = readback {
+                let readback_handle = graph.import_buffer(readback, AccessType::Nothing);
+                let dims = swapchain.desc.dims;
+                graph
+                    .add_pass("copy to readback")
+                    .read_image(swapchain_handle, AccessType::TransferRead)
+                    .write_buffer(readback_handle, AccessType::TransferWrite)
+                    .render(move |ctx| unsafe {
+                        ctx.device.raw.cmd_copy_image_to_buffer(
+                            ctx.cb.raw,
+                            ctx.image(swapchain_handle).raw,
+                            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                            ctx.buffer(readback_handle).raw,
+                            &[vk::BufferImageCopy::builder()
+                                .image_subresource(vk::ImageSubresourceLayers {
+                                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                                    mip_level: 0,
+                                    base_array_layer: 0,
+                                    layer_count: 1,
+                                })
+                                .image_extent(vk::Extent3D {
+                                    width: dims.width,
+                                    height: dims.height,
+                                    depth: 1,
+                                })
+                                .build()],
+                        );
+                    });
+                graph.export_buffer(readback_handle, AccessType::HostRead);
+            }
 
-                self.triangles_pipeline
-                    .render(&self.device.clone(), main_cb);
+            graph.export_image(swapchain_handle, AccessType::Present);
+            graph.export_buffer(frame_constants_handle, AccessType::AnyShaderReadOther);
+            graph.execute(&self.device, main_cb, &mut self.transient_resources);
 
-                self.device.raw.cmd_end_rendering(main_cb.raw);
+            // The graph's first barrier on the swapchain image has no source stage
+            // to chain the acquire semaphore to, so the wait has to cover all of them.
+            let wait_stage = vk::PipelineStageFlags::ALL_COMMANDS;
 
+            unsafe {
                 self.device.raw.end_command_buffer(main_cb.raw).unwrap();
 
                 let submit_info = [vk::SubmitInfo::builder()
@@ -298,7 +906,7 @@ impl Renderer {
                     .signal_semaphores(std::slice::from_ref(
                         &swapchain_image.rendering_finished_semaphore,
                     ))
-                    .wait_dst_stage_mask(&[vk::PipelineStageFlags::FRAGMENT_SHADER])
+                    .wait_dst_stage_mask(std::slice::from_ref(&wait_stage))
                     .build()];
 
                 self.device
@@ -314,11 +922,60 @@ impl Renderer {
                         main_cb.submit_done_fence,
                     )
                     .expect("queue submit failed");
+
+                if readback.is_some() {
+                    self.device
+                        .raw
+                        .wait_for_fences(
+                            std::slice::from_ref(&main_cb.submit_done_fence),
+                            true,
+                            u64::MAX,
+                        )
+                        .expect("wait for the frame to read back");
+                }
             }
 
             swapchain.present_image(swapchain_image);
         }
 
         self.device.finish_frame(current_frame);
+        self.frame_index += 1;
+        self.accumulated_frames = self.accumulated_frames.saturating_add(1);
+
+        Ok(())
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        unsafe { self.device.raw.device_wait_idle().unwrap() };
+
+        for mut pass in std::mem::take(&mut self.render_passes) {
+            pass.destroy(&self.setup_context());
+        }
+
+        for old in self.triangles_pipelines.drain() {
+            old.destroy(&self.device);
+        }
+        for old in self.ray_tracing_pipelines.drain() {
+            old.destroy(&self.device);
+        }
+        for old in self.wavefront_pipelines.drain() {
+            old.destroy(&self.device);
+        }
+        std::mem::take(&mut self.transient_resources).destroy(&self.device);
+        if let Some(acceleration_structure) = self.acceleration_structure.take() {
+            acceleration_structure.destroy(&self.device);
+        }
+
+        // The pipelines are gone, so nothing refers to the bindless layout anymore.
+        unsafe {
+            self.device
+                .raw
+                .destroy_descriptor_pool(self.bindless_descriptor_pool, None);
+            self.device
+                .raw
+                .destroy_descriptor_set_layout(self.bindless_descriptor_set_layout.layout, None);
+        }
     }
 }
@@ -0,0 +1,561 @@
+use std::sync::Arc;
+
+use ash::vk;
+use vk_sync::AccessType;
+
+use super::vulkan::{
+    buffer::{Buffer, BufferDesc},
+    device::{CommandBuffer, Device},
+    image::{Image, ImageDesc},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ImageHandle(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BufferHandle(usize);
+
+enum ResourceSource<'a, T, Desc> {
+    /// Owned outside the graph, `AccessType` is how it was last used.
+    Imported(&'a T, AccessType),
+    /// Taken from the `TransientResourceCache` when the graph executes.
+    Transient(Desc),
+}
+
+struct GraphResource<'a, T, Desc> {
+    source: ResourceSource<'a, T, Desc>,
+    /// Access to transition to after the last pass, e.g. `Present`.
+    export: Option<AccessType>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ResourceRef {
+    Image(usize),
+    Buffer(usize),
+}
+
+struct ResourceUse {
+    resource: ResourceRef,
+    access: AccessType,
+    /// Writes that aren't preceded by a read don't need the previous contents.
+    write: bool,
+}
+
+type RecordFn<'a> = Box<dyn FnOnce(&PassContext) + 'a>;
+
+struct Pass<'a> {
+    name: String,
+    uses: Vec<ResourceUse>,
+    record: Option<RecordFn<'a>>,
+}
+
+/// A frame's worth of passes. Passes declare which resources they read and write,
+/// and the graph records the barriers and layout transitions between them.
+///
+/// Passes run in the order they're added. That is always a valid dependency order,
+/// since a pass can only use resources that were imported, created or written
+/// before it was added.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    images: Vec<GraphResource<'a, Image, ImageDesc>>,
+    buffers: Vec<GraphResource<'a, Buffer, BufferDesc>>,
+    passes: Vec<Pass<'a>>,
+}
+
+/// What a pass gets to record with.
+pub struct PassContext<'g> {
+    pub device: &'g Arc<Device>,
+    pub cb: &'g CommandBuffer,
+    images: Vec<&'g Image>,
+    buffers: Vec<&'g Buffer>,
+}
+
+impl<'g> PassContext<'g> {
+    pub fn image(&self, handle: ImageHandle) -> &'g Image {
+        self.images[handle.0]
+    }
+
+    pub fn buffer(&self, handle: BufferHandle) -> &'g Buffer {
+        self.buffers[handle.0]
+    }
+}
+
+pub struct PassBuilder<'rg, 'a> {
+    graph: &'rg mut RenderGraph<'a>,
+    pass_idx: usize,
+}
+
+impl<'rg, 'a> PassBuilder<'rg, 'a> {
+    fn add_use(&mut self, resource: ResourceRef, access: AccessType, write: bool) {
+        self.graph.passes[self.pass_idx].uses.push(ResourceUse {
+            resource,
+            access,
+            write,
+        });
+    }
+
+    pub fn read_image(mut self, handle: ImageHandle, access: AccessType) -> Self {
+        self.add_use(ResourceRef::Image(handle.0), access, false);
+        self
+    }
+
+    pub fn write_image(mut self, handle: ImageHandle, access: AccessType) -> Self {
+        self.add_use(ResourceRef::Image(handle.0), access, true);
+        self
+    }
+
+    pub fn read_buffer(mut self, handle: BufferHandle, access: AccessType) -> Self {
+        self.add_use(ResourceRef::Buffer(handle.0), access, false);
+        self
+    }
+
+    pub fn write_buffer(mut self, handle: BufferHandle, access: AccessType) -> Self {
+        self.add_use(ResourceRef::Buffer(handle.0), access, true);
+        self
+    }
+
+    /// Sets the function recording the pass. It runs once all the declared
+    /// resources are in the requested state.
+    pub fn render(self, record: impl FnOnce(&PassContext) + 'a) {
+        self.graph.passes[self.pass_idx].record = Some(Box::new(record));
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn import_image(&mut self, image: &'a Image, last_access: AccessType) -> ImageHandle {
+        self.images.push(GraphResource {
+            source: ResourceSource::Imported(image, last_access),
+            export: None,
+        });
+        ImageHandle(self.images.len() - 1)
+    }
+
+    pub fn import_buffer(&mut self, buffer: &'a Buffer, last_access: AccessType) -> BufferHandle {
+        self.buffers.push(GraphResource {
+            source: ResourceSource::Imported(buffer, last_access),
+            export: None,
+        });
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    /// An image that only lives for the duration of the graph.
+    pub fn create_image(&mut self, desc: ImageDesc) -> ImageHandle {
+        self.images.push(GraphResource {
+            source: ResourceSource::Transient(desc),
+            export: None,
+        });
+        ImageHandle(self.images.len() - 1)
+    }
+
+    /// A buffer that only lives for the duration of the graph.
+    pub fn create_buffer(&mut self, desc: BufferDesc) -> BufferHandle {
+        self.buffers.push(GraphResource {
+            source: ResourceSource::Transient(desc),
+            export: None,
+        });
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    /// Transitions the image to `access` once all passes are done.
+    pub fn export_image(&mut self, handle: ImageHandle, access: AccessType) {
+        self.images[handle.0].export = Some(access);
+    }
+
+    /// Transitions the buffer to `access` once all passes are done.
+    pub fn export_buffer(&mut self, handle: BufferHandle, access: AccessType) {
+        self.buffers[handle.0].export = Some(access);
+    }
+
+    pub fn add_pass<'rg>(&'rg mut self, name: &str) -> PassBuilder<'rg, 'a> {
+        self.passes.push(Pass {
+            name: name.to_owned(),
+            uses: Vec::new(),
+            record: None,
+        });
+
+        PassBuilder {
+            pass_idx: self.passes.len() - 1,
+            graph: self,
+        }
+    }
+
+    /// Records every pass into `cb`, with transient resources taken from and
+    /// returned to `transient_resources`.
+    pub fn execute(
+        self,
+        device: &Arc<Device>,
+        cb: &CommandBuffer,
+        transient_resources: &mut TransientResourceCache,
+    ) {
+        let mut image_states = Vec::with_capacity(self.images.len());
+        let mut transient_images = Vec::new();
+
+        for (idx, image) in self.images.iter().enumerate() {
+            match &image.source {
+                ResourceSource::Imported(_, access) => image_states.push(vec![*access]),
+                ResourceSource::Transient(desc) => {
+                    let (transient, access) = transient_resources.take_image(device, *desc);
+                    transient_images.push((idx, transient));
+                    image_states.push(vec![access]);
+                }
+            }
+        }
+
+        let mut buffer_states = Vec::with_capacity(self.buffers.len());
+        let mut transient_buffers = Vec::new();
+
+        for (idx, buffer) in self.buffers.iter().enumerate() {
+            match &buffer.source {
+                ResourceSource::Imported(_, access) => buffer_states.push(vec![*access]),
+                ResourceSource::Transient(desc) => {
+                    let (transient, access) = transient_resources.take_buffer(device, *desc);
+                    transient_buffers.push((idx, transient));
+                    buffer_states.push(vec![access]);
+                }
+            }
+        }
+
+        {
+            let images: Vec<&Image> = (0..self.images.len())
+                .map(|idx| match &self.images[idx].source {
+                    ResourceSource::Imported(image, _) => *image,
+                    ResourceSource::Transient(_) => {
+                        &transient_images.iter().find(|(i, _)| *i == idx).unwrap().1
+                    }
+                })
+                .collect();
+
+            let buffers: Vec<&Buffer> = (0..self.buffers.len())
+                .map(|idx| match &self.buffers[idx].source {
+                    ResourceSource::Imported(buffer, _) => *buffer,
+                    ResourceSource::Transient(_) => {
+                        &transient_buffers.iter().find(|(i, _)| *i == idx).unwrap().1
+                    }
+                })
+                .collect();
+
+            // Whether an image has been used by an earlier pass. The first use
+            // discards the contents unless the pass reads them.
+            let mut image_used = vec![false; images.len()];
+
+            for pass in self.passes {
+                let mut transitions = Vec::new();
+
+                for resource in dedup_resources(&pass.uses) {
+                    let next_accesses: Vec<AccessType> = pass
+                        .uses
+                        .iter()
+                        .filter(|u| u.resource == resource)
+                        .map(|u| u.access)
+                        .collect();
+                    let write_only = pass
+                        .uses
+                        .iter()
+                        .filter(|u| u.resource == resource)
+                        .all(|u| u.write);
+
+                    transitions.push((resource, next_accesses, write_only));
+                }
+
+                let mut image_barriers = Vec::new();
+                let mut buffer_barriers = Vec::new();
+                let previous_image_states = image_states.clone();
+                let previous_buffer_states = buffer_states.clone();
+
+                // Reads after reads in the same layout need no barrier. Their
+                // accesses are added to the state instead, so the next write
+                // waits for all of them.
+                for (resource, next_accesses, write_only) in &transitions {
+                    match *resource {
+                        ResourceRef::Image(idx) => {
+                            let discard_contents = *write_only && !image_used[idx];
+                            image_used[idx] = true;
+
+                            let previous = &previous_image_states[idx];
+                            if same_read_layout(previous.iter().chain(next_accesses)) {
+                                add_reads(&mut image_states[idx], next_accesses);
+                                continue;
+                            }
+
+                            image_barriers.push(image_barrier(
+                                device,
+                                images[idx],
+                                previous,
+                                next_accesses,
+                                discard_contents,
+                            ));
+                            image_states[idx] = next_accesses.clone();
+                        }
+                        ResourceRef::Buffer(idx) => {
+                            let previous = &previous_buffer_states[idx];
+                            if previous
+                                .iter()
+                                .chain(next_accesses)
+                                .all(|access| read_layout(*access).is_some())
+                            {
+                                add_reads(&mut buffer_states[idx], next_accesses);
+                                continue;
+                            }
+
+                            buffer_barriers.push(buffer_barrier(
+                                device,
+                                buffers[idx],
+                                previous,
+                                next_accesses,
+                            ));
+                            buffer_states[idx] = next_accesses.clone();
+                        }
+                    }
+                }
+
+                if !image_barriers.is_empty() || !buffer_barriers.is_empty() {
+                    vk_sync::cmd::pipeline_barrier(
+                        &device.raw,
+                        cb.raw,
+                        None,
+                        &buffer_barriers,
+                        &image_barriers,
+                    );
+                }
+
+                match pass.record {
+                    Some(record) => record(&PassContext {
+                        device,
+                        cb,
+                        images: images.clone(),
+                        buffers: buffers.clone(),
+                    }),
+                    None => log::warn!("Render graph pass {} has nothing to record", pass.name),
+                }
+            }
+
+            let mut image_barriers = Vec::new();
+            let mut buffer_barriers = Vec::new();
+
+            for (idx, image) in self.images.iter().enumerate() {
+                if let Some(export) = &image.export {
+                    image_barriers.push(image_barrier(
+                        device,
+                        images[idx],
+                        &image_states[idx],
+                        std::slice::from_ref(export),
+                        false,
+                    ));
+                }
+            }
+
+            for (idx, buffer) in self.buffers.iter().enumerate() {
+                if let Some(export) = &buffer.export {
+                    buffer_barriers.push(buffer_barrier(
+                        device,
+                        buffers[idx],
+                        &buffer_states[idx],
+                        std::slice::from_ref(export),
+                    ));
+                }
+            }
+
+            if !image_barriers.is_empty() || !buffer_barriers.is_empty() {
+                vk_sync::cmd::pipeline_barrier(
+                    &device.raw,
+                    cb.raw,
+                    None,
+                    &buffer_barriers,
+                    &image_barriers,
+                );
+            }
+        }
+
+        for (idx, image) in transient_images {
+            let access = self.images[idx]
+                .export
+                .unwrap_or_else(|| *image_states[idx].last().unwrap());
+            transient_resources.return_image(image, access);
+        }
+
+        for (idx, buffer) in transient_buffers {
+            let access = self.buffers[idx]
+                .export
+                .unwrap_or_else(|| *buffer_states[idx].last().unwrap());
+            transient_resources.return_buffer(buffer, access);
+        }
+    }
+}
+
+/// The resources a pass uses, each once, in the order they were declared.
+fn dedup_resources(uses: &[ResourceUse]) -> Vec<ResourceRef> {
+    let mut resources = Vec::new();
+    for u in uses {
+        if !resources.contains(&u.resource) {
+            resources.push(u.resource);
+        }
+    }
+    resources
+}
+
+/// The layout an image is in for `access` if it only reads, `None` if it
+/// writes. Buffer-only reads have no layout and get `UNDEFINED`.
+fn read_layout(access: AccessType) -> Option<vk::ImageLayout> {
+    use AccessType::*;
+
+    match access {
+        CommandBufferReadNVX
+        | IndirectBuffer
+        | IndexBuffer
+        | VertexBuffer
+        | VertexShaderReadUniformBuffer
+        | FragmentShaderReadUniformBuffer
+        | ComputeShaderReadUniformBuffer
+        | AnyShaderReadUniformBuffer
+        | AnyShaderReadUniformBufferOrVertexBuffer
+        | RayTracingShaderReadAccelerationStructure
+        | AccelerationStructureBuildRead => Some(vk::ImageLayout::UNDEFINED),
+        VertexShaderReadSampledImageOrUniformTexelBuffer
+        | FragmentShaderReadSampledImageOrUniformTexelBuffer
+        | FragmentShaderReadColorInputAttachment
+        | ComputeShaderReadSampledImageOrUniformTexelBuffer
+        | AnyShaderReadSampledImageOrUniformTexelBuffer
+        | RayTracingShaderReadSampledImageOrUniformTexelBuffer
+        | RayTracingShaderReadColorInputAttachment => {
+            Some(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        }
+        FragmentShaderReadDepthStencilInputAttachment
+        | DepthStencilAttachmentRead
+        | RayTracingShaderReadDepthStencilInputAttachment => {
+            Some(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+        }
+        VertexShaderReadOther
+        | FragmentShaderReadOther
+        | ComputeShaderReadOther
+        | AnyShaderReadOther
+        | RayTracingShaderReadOther
+        | HostRead => Some(vk::ImageLayout::GENERAL),
+        ColorAttachmentRead => Some(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+        TransferRead => Some(vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+        Present => Some(vk::ImageLayout::PRESENT_SRC_KHR),
+        _ => None,
+    }
+}
+
+/// Whether every access only reads, all in the same image layout.
+fn same_read_layout<'b>(mut accesses: impl Iterator<Item = &'b AccessType>) -> bool {
+    let first = match accesses.next().and_then(|access| read_layout(*access)) {
+        Some(layout) => layout,
+        None => return false,
+    };
+    accesses.all(|access| read_layout(*access) == Some(first))
+}
+
+/// Adds the reads in `next_accesses` that `state` doesn't have yet.
+fn add_reads(state: &mut Vec<AccessType>, next_accesses: &[AccessType]) {
+    for access in next_accesses {
+        if !state.contains(access) {
+            state.push(*access);
+        }
+    }
+}
+
+fn image_barrier<'b>(
+    device: &Device,
+    image: &Image,
+    previous_accesses: &'b [AccessType],
+    next_accesses: &'b [AccessType],
+    discard_contents: bool,
+) -> vk_sync::ImageBarrier<'b> {
+    vk_sync::ImageBarrier {
+        previous_accesses,
+        next_accesses,
+        previous_layout: vk_sync::ImageLayout::Optimal,
+        next_layout: vk_sync::ImageLayout::Optimal,
+        discard_contents,
+        src_queue_family_index: device.universal_queue.family.index,
+        dst_queue_family_index: device.universal_queue.family.index,
+        image: image.raw,
+        range: vk::ImageSubresourceRange {
+            aspect_mask: image.desc.aspect_mask(),
+            base_mip_level: 0,
+            level_count: vk::REMAINING_MIP_LEVELS,
+            base_array_layer: 0,
+            layer_count: vk::REMAINING_ARRAY_LAYERS,
+        },
+    }
+}
+
+fn buffer_barrier<'b>(
+    device: &Device,
+    buffer: &Buffer,
+    previous_accesses: &'b [AccessType],
+    next_accesses: &'b [AccessType],
+) -> vk_sync::BufferBarrier<'b> {
+    vk_sync::BufferBarrier {
+        previous_accesses,
+        next_accesses,
+        src_queue_family_index: device.universal_queue.family.index,
+        dst_queue_family_index: device.universal_queue.family.index,
+        buffer: buffer.raw,
+        offset: 0,
+        size: buffer.desc.size,
+    }
+}
+
+/// Images and buffers that render graphs create, kept across frames so they're
+/// only allocated once. The last access is kept with each resource, so reusing
+/// it waits for the previous frame to be done with it.
+#[derive(Default)]
+pub struct TransientResourceCache {
+    images: Vec<(Image, AccessType)>,
+    buffers: Vec<(Buffer, AccessType)>,
+}
+
+impl TransientResourceCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn take_image(&mut self, device: &Device, desc: ImageDesc) -> (Image, AccessType) {
+        match self.images.iter().position(|(image, _)| image.desc == desc) {
+            Some(idx) => self.images.swap_remove(idx),
+            None => (
+                device.create_image(desc, "render graph image"),
+                AccessType::Nothing,
+            ),
+        }
+    }
+
+    fn return_image(&mut self, image: Image, access: AccessType) {
+        self.images.push((image, access));
+    }
+
+    fn take_buffer(&mut self, device: &Device, desc: BufferDesc) -> (Buffer, AccessType) {
+        match self
+            .buffers
+            .iter()
+            .position(|(buffer, _)| buffer.desc == desc)
+        {
+            Some(idx) => self.buffers.swap_remove(idx),
+            None => (
+                device.create_buffer(desc, "render graph buffer", None),
+                AccessType::Nothing,
+            ),
+        }
+    }
+
+    fn return_buffer(&mut self, buffer: Buffer, access: AccessType) {
+        self.buffers.push((buffer, access));
+    }
+
+    /// Frees every cached resource. The GPU must be done with them.
+    pub fn destroy(self, device: &Device) {
+        for (image, _) in self.images {
+            device.destroy_image(image);
+        }
+
+        for (buffer, _) in self.buffers {
+            device.destroy_buffer(buffer);
+        }
+    }
+}
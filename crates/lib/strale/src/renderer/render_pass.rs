@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+
+use super::{
+    camera::Camera,
+    environment::EnvironmentControls,
+    render_graph::{BufferHandle, ImageHandle, RenderGraph},
+    vulkan::{device::Device, shader::DescriptorSetLayoutDesc, swapchain::SwapchainDesc},
+};
+
+/// Values shared by every pass in a frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameConstants {
+    /// Seconds since the device was created.
+    pub time: f32,
+    pub frame_index: u64,
+    pub dims: vk::Extent2D,
+    pub camera: Camera,
+    /// Frames averaged into the accumulation target before this one.
+    pub accumulated_frames: u32,
+    pub environment: EnvironmentControls,
+}
+
+/// `FrameConstants` as laid out in `assets/shaders/include/frame_constants.glsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct GpuFrameConstants {
+    pub camera_origin: [f32; 3],
+    pub time: f32,
+    pub camera_lower_left_corner: [f32; 3],
+    pub accumulated_frames: u32,
+    pub camera_horizontal: [f32; 3],
+    pub frame_index: u32,
+    pub camera_vertical: [f32; 3],
+    pub _pad0: u32,
+    pub camera_lens_horizontal: [f32; 3],
+    pub environment_rotation: f32,
+    pub camera_lens_vertical: [f32; 3],
+    pub environment_intensity: f32,
+    pub camera_shutter_open: f32,
+    pub camera_shutter_close: f32,
+    pub _pad1: [u32; 2],
+}
+
+impl FrameConstants {
+    pub fn gpu_data(&self) -> GpuFrameConstants {
+        let rays = self
+            .camera
+            .rays(self.dims.width as f32 / self.dims.height as f32);
+
+        GpuFrameConstants {
+            camera_origin: rays.origin.into(),
+            time: self.time,
+            camera_lower_left_corner: rays.lower_left_corner.into(),
+            accumulated_frames: self.accumulated_frames,
+            camera_horizontal: rays.horizontal.into(),
+            // Only used to decorrelate noise, so wrapping is fine.
+            frame_index: self.frame_index as u32,
+            camera_vertical: rays.vertical.into(),
+            camera_lens_horizontal: rays.lens_horizontal.into(),
+            environment_rotation: self.environment.rotation,
+            camera_lens_vertical: rays.lens_vertical.into(),
+            environment_intensity: self.environment.intensity,
+            camera_shutter_open: rays.shutter_open,
+            camera_shutter_close: rays.shutter_close,
+            ..Default::default()
+        }
+    }
+}
+
+/// What a pass gets when it's set up or the swapchain is resized.
+pub struct SetupContext<'a> {
+    pub device: &'a Arc<Device>,
+    /// For pipelines that bind the bindless set as set 0, like the tracers do.
+    pub bindless_descriptor_set_layout: &'a DescriptorSetLayoutDesc,
+    pub bindless_descriptor_set: vk::DescriptorSet,
+    pub swapchain_desc: SwapchainDesc,
+}
+
+/// What a pass gets every frame. Command buffer access happens in the render
+/// graph passes it adds, through `render_graph::PassContext`.
+pub struct FrameContext<'a> {
+    pub device: &'a Arc<Device>,
+    pub bindless_descriptor_set: vk::DescriptorSet,
+    pub constants: FrameConstants,
+    /// `constants` uploaded as a storage buffer, bound at binding 3 of the
+    /// bindless set. Read it with `AccessType::AnyShaderReadOther`.
+    pub frame_constants: BufferHandle,
+    /// The swapchain image, presented once every pass has run.
+    pub target: ImageHandle,
+}
+
+/// Work that applications add to every frame, e.g. overlays and debug views.
+/// Registered passes run after the path tracer, in the order they were added
+/// with `Renderer::add_render_pass`.
+pub trait RenderPass {
+    /// Called once, when the pass is registered.
+    fn setup(&mut self, _ctx: &SetupContext) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called when the swapchain dimensions change, before the next `record`.
+    fn resize(&mut self, _ctx: &SetupContext) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Adds the pass's work to the frame's render graph.
+    fn record<'a>(&'a self, graph: &mut RenderGraph<'a>, frame: &FrameContext);
+
+    /// Called when the renderer is dropped, once the GPU is idle. Frees what
+    /// `setup` and `resize` created.
+    fn destroy(&mut self, _ctx: &SetupContext) {}
+}
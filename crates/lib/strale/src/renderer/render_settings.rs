@@ -0,0 +1,114 @@
+use super::{sampler::SamplerKind, vulkan::shader::SpecializationConstants};
+
+/// What the tracers show instead of the image, the `DEBUG_VIEW` specialization
+/// constant. See `assets/shaders/include/debug_view.glsl`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugView {
+    /// The path traced image.
+    #[default]
+    None = 0,
+    /// The shading normal of the first hit, mapped from [-1, 1] to [0, 1].
+    Normal = 1,
+    /// The textured albedo of the first hit.
+    Albedo = 2,
+    /// A color per material index.
+    Material = 3,
+    /// The distance to the first hit as a heatmap.
+    Distance = 4,
+    /// How often paths scattered as a heatmap, up to the maximum bounces.
+    Bounces = 5,
+    /// The variance of the luminance of each pixel's samples as a heatmap, on
+    /// a log scale. Needs at least 2 samples per pixel.
+    Variance = 6,
+    /// BVH nodes a camera ray visits as a heatmap. The hardware ray tracing
+    /// pipeline can't count them, so it hands this view to the fragment shader
+    /// tracer.
+    BvhCost = 7,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 8] = [
+        DebugView::None,
+        DebugView::Normal,
+        DebugView::Albedo,
+        DebugView::Material,
+        DebugView::Distance,
+        DebugView::Bounces,
+        DebugView::Variance,
+        DebugView::BvhCost,
+    ];
+
+    /// The view after this one, back to `None` after the last.
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// Which implementation of the path tracer renders the image. They trace the
+/// same paths, so they can be compared for speed and correctness.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tracer {
+    /// One fragment shader invocation traces every path of a pixel, against the
+    /// scene's BVH. See `triangle.frag`.
+    #[default]
+    Fragment,
+    /// Like `Fragment`, but with the ray tracing pipeline and hardware
+    /// acceleration structures. Needs device support.
+    RayTracing,
+    /// Compute passes that generate, extend, shade and shadow all paths a
+    /// bounce at a time, handing them on through queues. Uses the BVH like
+    /// `Fragment`, and a few hundred bytes of path state per pixel. See
+    /// `renderers/wavefront.rs`.
+    Wavefront,
+}
+
+impl Tracer {
+    pub const ALL: [Tracer; 3] = [Tracer::Fragment, Tracer::RayTracing, Tracer::Wavefront];
+
+    /// The tracer after this one, back to `Fragment` after the last.
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// Path tracer quality settings, baked into the shaders as specialization constants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RenderSettings {
+    pub samples_per_pixel: u32,
+    pub max_bounces: u32,
+    /// When disabled, metal materials are shaded as diffuse.
+    pub metal_materials: bool,
+    pub sampler: SamplerKind,
+    pub debug_view: DebugView,
+    /// Traces the shadow rays of the fragment shader and wavefront tracers with
+    /// ray queries instead of walking the BVH. The renderer turns it off on
+    /// devices without `VK_KHR_ray_query`.
+    pub ray_queries: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            samples_per_pixel: 8,
+            max_bounces: 4,
+            metal_materials: true,
+            sampler: SamplerKind::default(),
+            debug_view: DebugView::default(),
+            ray_queries: true,
+        }
+    }
+}
+
+impl RenderSettings {
+    /// The ids match the `constant_id`s in `assets/shaders/include/settings.glsl`.
+    pub fn specialization_constants(&self) -> SpecializationConstants {
+        SpecializationConstants::new()
+            .u32(0, self.samples_per_pixel)
+            .u32(1, self.max_bounces)
+            .bool(2, self.metal_materials)
+            .u32(3, self.sampler as u32)
+            .u32(4, self.debug_view as u32)
+            .bool(5, self.ray_queries)
+    }
+}
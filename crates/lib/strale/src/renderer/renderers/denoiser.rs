@@ -0,0 +1,197 @@
+use std::{borrow::Cow, sync::Arc};
+
+use anyhow::Context;
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use vk_sync::AccessType;
+
+use crate::renderer::{
+    denoiser::DenoiserSettings,
+    render_graph::{ImageHandle, RenderGraph},
+    render_pass::FrameContext,
+    vulkan::{
+        device::Device,
+        image::{Image, ImageDesc},
+    },
+};
+
+use super::{
+    gbuffer::GBuffer,
+    pipeline::{create_compute_pipeline, ComputePipelineDesc, Pipeline},
+    storage_image::{create_storage_image_set, initialize_storage_image},
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct DenoisePushConstant {
+    iteration: u32,
+    iterations: u32,
+    frames: u32,
+}
+
+/// The edge-avoiding à-trous filter in `denoise.comp`. Reads a tracer's color
+/// and `GBuffer`, and filters into images of its own.
+pub struct Denoiser {
+    pub inner: Pipeline,
+    /// The iterations write these in turn, see `denoise.comp`.
+    pub filtered: [Image; 2],
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+impl Denoiser {
+    /// GLSL source under `assets/shaders` that the pipeline is built from.
+    pub const SOURCE: &'static str = "denoise.comp";
+
+    /// How `filtered` is left at the end of every frame.
+    const FRAME_END_ACCESS: AccessType = AccessType::ComputeShaderWrite;
+
+    /// Creates a denoiser for `color`, which has to stay alive as long as it.
+    pub fn new(
+        device: &Arc<Device>,
+        shader: impl Into<Cow<'static, [u8]>>,
+        color: &Image,
+        gbuffer: &GBuffer,
+    ) -> anyhow::Result<Self> {
+        let mut inner = create_compute_pipeline(
+            device,
+            &ComputePipelineDesc::builder()
+                .compute_shader(shader)
+                .push_constants::<DenoisePushConstant>(),
+        )?;
+
+        let set_layout = inner
+            .descriptor_set_layout(0)
+            .context("The denoiser doesn't use descriptor set 0")?;
+
+        let desc = ImageDesc {
+            extent: color.desc.extent,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC,
+        };
+        let filtered = [
+            device.create_image(desc, "denoiser ping"),
+            device.create_image(desc, "denoiser pong"),
+        ];
+
+        // The bindings of `denoise.comp`.
+        let set = create_storage_image_set(
+            device,
+            set_layout,
+            &[
+                &gbuffer.normal_depth,
+                &gbuffer.albedo,
+                color,
+                &filtered[0],
+                &filtered[1],
+            ],
+        );
+        let (descriptor_pool, descriptor_set) = match set {
+            Ok(set) => set,
+            Err(err) => {
+                let [ping, pong] = filtered;
+                device.destroy_image(ping);
+                device.destroy_image(pong);
+                return Err(err);
+            }
+        };
+        for image in &filtered {
+            initialize_storage_image(device, image, Self::FRAME_END_ACCESS);
+        }
+
+        inner.add_descriptor_set(0, descriptor_set);
+
+        Ok(Self {
+            inner,
+            filtered,
+            descriptor_pool,
+            descriptor_set,
+        })
+    }
+
+    /// Frees the resources the denoiser owns. The GPU must be done with them.
+    pub fn destroy(self, device: &Device) {
+        unsafe {
+            device
+                .raw
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+
+        let [ping, pong] = self.filtered;
+        device.destroy_image(ping);
+        device.destroy_image(pong);
+    }
+
+    /// Adds passes filtering `color`, the image the denoiser was created for,
+    /// with the first hits in `gbuffer`, and one scaling the result onto the
+    /// frame's target. Every frame the tracer wrote into `color` so far counts
+    /// towards its variance.
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        frame: &FrameContext,
+        settings: DenoiserSettings,
+        color: ImageHandle,
+        gbuffer: [ImageHandle; 2],
+    ) {
+        let [normal_depth, albedo] = gbuffer;
+        let filtered = [
+            graph.import_image(&self.filtered[0], Self::FRAME_END_ACCESS),
+            graph.import_image(&self.filtered[1], Self::FRAME_END_ACCESS),
+        ];
+        let iterations = settings.iterations.max(1);
+        let frames = frame.constants.accumulated_frames.saturating_add(1);
+        let extent = self.filtered[0].desc.extent;
+
+        for iteration in 0..=iterations {
+            let output = filtered[iteration as usize % 2];
+            let pass = if iteration == 0 {
+                graph
+                    .add_pass("estimate denoiser variance")
+                    .read_image(color, AccessType::ComputeShaderReadOther)
+            } else {
+                graph
+                    .add_pass(&format!("denoise iteration {}", iteration))
+                    .read_image(
+                        filtered[(iteration as usize + 1) % 2],
+                        AccessType::ComputeShaderReadOther,
+                    )
+                    .read_image(normal_depth, AccessType::ComputeShaderReadOther)
+            };
+
+            pass.read_image(albedo, AccessType::ComputeShaderReadOther)
+                .write_image(output, AccessType::ComputeShaderWrite)
+                .render(move |ctx| {
+                    self.inner.bind_pipeline(ctx.device, ctx.cb.raw);
+                    self.inner.push_constants(
+                        ctx.cb.raw,
+                        &DenoisePushConstant {
+                            iteration,
+                            iterations,
+                            frames,
+                        },
+                    );
+                    ctx.cb.dispatch_threads(
+                        ctx.device,
+                        [extent.width, extent.height, 1],
+                        self.inner.group_size,
+                    );
+                });
+        }
+
+        let denoised = filtered[iterations as usize % 2];
+        let target = frame.target;
+        graph
+            .add_pass("blit denoised output")
+            .read_image(denoised, AccessType::TransferRead)
+            .write_image(target, AccessType::TransferWrite)
+            .render(move |ctx| {
+                ctx.cb
+                    .blit_image(ctx.device, ctx.image(denoised), ctx.image(target));
+            });
+
+        for handle in filtered {
+            graph.export_image(handle, Self::FRAME_END_ACCESS);
+        }
+    }
+}
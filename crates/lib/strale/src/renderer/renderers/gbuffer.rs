@@ -0,0 +1,77 @@
+use anyhow::Context;
+use ash::vk;
+use vk_sync::AccessType;
+
+use crate::renderer::vulkan::{
+    device::Device,
+    image::{Image, ImageDesc},
+};
+
+use super::{
+    pipeline::Pipeline,
+    storage_image::{create_storage_image_set, initialize_storage_image},
+};
+
+/// First-hit AOVs a tracer averages alongside its color for the denoiser,
+/// bound to bindings 0 and 1 of one of its descriptor sets. See `gbuffer.glsl`.
+pub struct GBuffer {
+    /// The normal in xyz and the distance to the first hit in w.
+    pub normal_depth: Image,
+    /// The albedo in rgb and the second moment of the luminance in a.
+    pub albedo: Image,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    /// How both images are left at the end of every frame, like
+    /// `StorageImage::frame_end_access`.
+    pub frame_end_access: AccessType,
+}
+
+impl GBuffer {
+    /// Creates the images and binds them to `set_idx` of `pipeline`.
+    pub fn new(
+        device: &Device,
+        pipeline: &mut Pipeline,
+        set_idx: u32,
+        extent: vk::Extent2D,
+        frame_end_access: AccessType,
+    ) -> anyhow::Result<Self> {
+        let set_layout = pipeline
+            .descriptor_set_layout(set_idx)
+            .with_context(|| format!("Shaders don't use descriptor set {}", set_idx))?;
+
+        let desc = ImageDesc {
+            extent,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            usage: vk::ImageUsageFlags::STORAGE,
+        };
+        let normal_depth = device.create_image(desc, "gbuffer normal and depth");
+        let albedo = device.create_image(desc, "gbuffer albedo");
+
+        let (descriptor_pool, descriptor_set) =
+            create_storage_image_set(device, set_layout, &[&normal_depth, &albedo])?;
+        initialize_storage_image(device, &normal_depth, frame_end_access);
+        initialize_storage_image(device, &albedo, frame_end_access);
+
+        pipeline.add_descriptor_set(set_idx, descriptor_set);
+
+        Ok(Self {
+            normal_depth,
+            albedo,
+            descriptor_pool,
+            descriptor_set,
+            frame_end_access,
+        })
+    }
+
+    /// Frees the images and their descriptor set. The GPU must be done with them.
+    pub fn destroy(self, device: &Device) {
+        unsafe {
+            device
+                .raw
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+
+        device.destroy_image(self.normal_depth);
+        device.destroy_image(self.albedo);
+    }
+}
@@ -1,2 +1,8 @@
+pub mod denoiser;
+pub mod gbuffer;
+pub mod permutations;
 pub mod pipeline;
+pub mod ray_tracing;
+pub mod storage_image;
 pub mod triangles;
+pub mod wavefront;
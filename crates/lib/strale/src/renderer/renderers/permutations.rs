@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use crate::renderer::vulkan::shader::SpecializationConstants;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PermutationKey {
+    /// The GLSL sources the pipeline is built from, see e.g. `TrianglesPipeline::SOURCES`.
+    pub shaders: &'static [&'static str],
+    pub constants: SpecializationConstants,
+}
+
+/// Pipelines built from the same shaders with different specialization constants.
+/// Permutations are kept around after use, so switching back to earlier settings
+/// doesn't recreate the pipeline.
+pub struct PermutationCache<T> {
+    permutations: HashMap<PermutationKey, T>,
+}
+
+impl<T> Default for PermutationCache<T> {
+    fn default() -> Self {
+        Self {
+            permutations: HashMap::new(),
+        }
+    }
+}
+
+impl<T> PermutationCache<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(
+        &self,
+        shaders: &'static [&'static str],
+        constants: &SpecializationConstants,
+    ) -> Option<&T> {
+        self.permutations.get(&PermutationKey {
+            shaders,
+            constants: constants.clone(),
+        })
+    }
+
+    /// Adds a permutation, replacing any previous one with the same key.
+    pub fn insert(
+        &mut self,
+        shaders: &'static [&'static str],
+        constants: &SpecializationConstants,
+        permutation: T,
+    ) -> Option<T> {
+        self.permutations.insert(
+            PermutationKey {
+                shaders,
+                constants: constants.clone(),
+            },
+            permutation,
+        )
+    }
+
+    /// Removes every permutation, e.g. because its shaders are out of date.
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.permutations
+            .drain()
+            .map(|(_, permutation)| permutation)
+    }
+}
@@ -1,32 +1,71 @@
+use std::{borrow::Cow, ffi::CString, sync::Arc};
+
+use anyhow::Context;
 use ash::vk;
+use bytemuck::Pod;
 
-use crate::renderer::vulkan::device::Device;
+use crate::renderer::vulkan::{
+    device::Device,
+    ray_tracing::{ShaderBindingTable, ShaderBindingTableDesc},
+    shader::{DescriptorSetLayoutDesc, PipelineLayout, ShaderModule, SpecializationConstants},
+};
 
-#[derive(Default)]
 pub struct Pipeline {
+    device: Arc<Device>,
     pub bindings: Vec<(u32, vk::DescriptorSet)>,
     pub layout: vk::PipelineLayout,
     pub raw: vk::Pipeline,
-    pub descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub descriptor_set_layouts: Vec<(u32, vk::DescriptorSetLayout)>,
+    pub push_constant_stages: vk::ShaderStageFlags,
+    pub bind_point: vk::PipelineBindPoint,
+    /// Workgroup size of compute pipelines.
+    pub group_size: [u32; 3],
 }
 
 impl Pipeline {
+    fn new(
+        device: &Arc<Device>,
+        layout: PipelineLayout,
+        bind_point: vk::PipelineBindPoint,
+    ) -> Self {
+        Self {
+            device: device.clone(),
+            bindings: Vec::new(),
+            layout: layout.raw,
+            raw: vk::Pipeline::null(),
+            descriptor_set_layouts: layout.descriptor_set_layouts,
+            push_constant_stages: layout
+                .push_constants
+                .map(|range| range.stage_flags)
+                .unwrap_or_default(),
+            bind_point,
+            group_size: [1, 1, 1],
+        }
+    }
+
+    /// Layout of a descriptor set generated from reflection, for allocating
+    /// sets that are not provided externally.
+    pub fn descriptor_set_layout(&self, set_idx: u32) -> Option<vk::DescriptorSetLayout> {
+        self.descriptor_set_layouts
+            .iter()
+            .find(|(idx, _)| *idx == set_idx)
+            .map(|(_, layout)| *layout)
+    }
+
     pub fn add_descriptor_set(&mut self, set_idx: u32, descriptor_set: vk::DescriptorSet) {
         self.bindings.push((set_idx, descriptor_set));
     }
 
     pub fn bind_pipeline(&self, device: &Device, cb: vk::CommandBuffer) {
         unsafe {
-            device
-                .raw
-                .cmd_bind_pipeline(cb, vk::PipelineBindPoint::GRAPHICS, self.raw);
+            device.raw.cmd_bind_pipeline(cb, self.bind_point, self.raw);
         }
 
         for (set_idx, descriptor_set) in &self.bindings {
             unsafe {
                 device.raw.cmd_bind_descriptor_sets(
                     cb,
-                    vk::PipelineBindPoint::GRAPHICS,
+                    self.bind_point,
                     self.layout,
                     *set_idx,
                     &[*descriptor_set],
@@ -35,4 +74,714 @@ impl Pipeline {
             }
         }
     }
+
+    pub fn push_constants<T: Pod>(&self, cb: vk::CommandBuffer, constants: &T) {
+        unsafe {
+            self.device.raw.cmd_push_constants(
+                cb,
+                self.layout,
+                self.push_constant_stages,
+                0,
+                bytemuck::bytes_of(constants),
+            );
+        }
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.raw.destroy_pipeline(self.raw, None);
+            self.device.raw.destroy_pipeline_layout(self.layout, None);
+
+            for (_, layout) in &self.descriptor_set_layouts {
+                self.device.raw.destroy_descriptor_set_layout(*layout, None);
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ShaderStageDesc {
+    pub stage: vk::ShaderStageFlags,
+    pub spirv: Cow<'static, [u8]>,
+    pub entry_point: String,
+}
+
+#[derive(Clone)]
+pub struct GraphicsPipelineDesc {
+    pub stages: Vec<ShaderStageDesc>,
+    pub vertex_bindings: Vec<vk::VertexInputBindingDescription>,
+    pub vertex_attributes: Vec<vk::VertexInputAttributeDescription>,
+    pub topology: vk::PrimitiveTopology,
+    pub polygon_mode: vk::PolygonMode,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_compare_op: vk::CompareOp,
+    pub color_formats: Vec<vk::Format>,
+    /// One blend state per entry in `color_formats`.
+    pub blend_states: Vec<vk::PipelineColorBlendAttachmentState>,
+    pub depth_format: vk::Format,
+    pub descriptor_sets: Vec<(u32, DescriptorSetLayoutDesc)>,
+    pub push_constants_size: usize,
+    /// Applied to every shader stage of the pipeline.
+    pub specialization: SpecializationConstants,
+}
+
+impl Default for GraphicsPipelineDesc {
+    fn default() -> Self {
+        Self {
+            stages: Vec::new(),
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            depth_test: false,
+            depth_write: false,
+            depth_compare_op: vk::CompareOp::GREATER_OR_EQUAL,
+            color_formats: Vec::new(),
+            blend_states: Vec::new(),
+            depth_format: vk::Format::UNDEFINED,
+            descriptor_sets: Vec::new(),
+            push_constants_size: 0,
+            specialization: SpecializationConstants::default(),
+        }
+    }
+}
+
+impl GraphicsPipelineDesc {
+    pub fn builder() -> Self {
+        Self::default()
+    }
+
+    pub fn shader_stage(
+        mut self,
+        stage: vk::ShaderStageFlags,
+        spirv: impl Into<Cow<'static, [u8]>>,
+        entry_point: &str,
+    ) -> Self {
+        self.stages.push(ShaderStageDesc {
+            stage,
+            spirv: spirv.into(),
+            entry_point: entry_point.to_owned(),
+        });
+        self
+    }
+
+    pub fn vertex_shader(self, spirv: impl Into<Cow<'static, [u8]>>) -> Self {
+        self.shader_stage(vk::ShaderStageFlags::VERTEX, spirv, "main")
+    }
+
+    pub fn fragment_shader(self, spirv: impl Into<Cow<'static, [u8]>>) -> Self {
+        self.shader_stage(vk::ShaderStageFlags::FRAGMENT, spirv, "main")
+    }
+
+    pub fn vertex_input(
+        mut self,
+        bindings: &[vk::VertexInputBindingDescription],
+        attributes: &[vk::VertexInputAttributeDescription],
+    ) -> Self {
+        self.vertex_bindings = bindings.to_vec();
+        self.vertex_attributes = attributes.to_vec();
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags, front_face: vk::FrontFace) -> Self {
+        self.cull_mode = cull_mode;
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn depth_state(mut self, test: bool, write: bool, compare_op: vk::CompareOp) -> Self {
+        self.depth_test = test;
+        self.depth_write = write;
+        self.depth_compare_op = compare_op;
+        self
+    }
+
+    /// Adds a color attachment with blending disabled.
+    pub fn color_attachment(self, format: vk::Format) -> Self {
+        self.color_attachment_blended(
+            format,
+            vk::PipelineColorBlendAttachmentState {
+                blend_enable: 0,
+                color_write_mask: vk::ColorComponentFlags::RGBA,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn color_attachment_blended(
+        mut self,
+        format: vk::Format,
+        blend_state: vk::PipelineColorBlendAttachmentState,
+    ) -> Self {
+        self.color_formats.push(format);
+        self.blend_states.push(blend_state);
+        self
+    }
+
+    pub fn depth_format(mut self, format: vk::Format) -> Self {
+        self.depth_format = format;
+        self
+    }
+
+    pub fn descriptor_set(mut self, set_idx: u32, layout: DescriptorSetLayoutDesc) -> Self {
+        self.descriptor_sets.push((set_idx, layout));
+        self
+    }
+
+    pub fn push_constants<T: Pod>(mut self) -> Self {
+        self.push_constants_size = std::mem::size_of::<T>();
+        self
+    }
+
+    pub fn specialization(mut self, constants: SpecializationConstants) -> Self {
+        self.specialization = constants;
+        self
+    }
+}
+
+/// Creates a module per stage. If one fails, the ones before it are destroyed.
+fn create_shader_modules(
+    device: &Device,
+    stages: &[ShaderStageDesc],
+) -> anyhow::Result<Vec<ShaderModule>> {
+    let mut modules = Vec::with_capacity(stages.len());
+    for stage in stages {
+        match device.create_shader_module(&stage.spirv, stage.stage) {
+            Ok(module) => modules.push(module),
+            Err(err) => {
+                destroy_shader_modules(device, modules);
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(modules)
+}
+
+fn destroy_shader_modules(device: &Device, modules: Vec<ShaderModule>) {
+    for module in modules {
+        unsafe { device.raw.destroy_shader_module(module.raw, None) };
+    }
+}
+
+/// The entry point name as a C string. Checked before any Vulkan object is
+/// created, so an invalid name has nothing to clean up.
+fn entry_point_name(stage: &ShaderStageDesc) -> anyhow::Result<CString> {
+    CString::new(stage.entry_point.as_str()).with_context(|| {
+        format!(
+            "Entry point {:?} of the {:?} shader contains a NUL byte",
+            stage.entry_point, stage.stage
+        )
+    })
+}
+
+fn create_layout(
+    device: &Device,
+    modules: &[ShaderModule],
+    descriptor_sets: &[(u32, DescriptorSetLayoutDesc)],
+    push_constants_size: usize,
+) -> anyhow::Result<PipelineLayout> {
+    let reflections: Vec<_> = modules.iter().map(|module| &module.reflection).collect();
+
+    device.create_pipeline_layout(&reflections, descriptor_sets, push_constants_size)
+}
+
+pub fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    desc: &GraphicsPipelineDesc,
+) -> anyhow::Result<Pipeline> {
+    let entry_points = desc
+        .stages
+        .iter()
+        .map(entry_point_name)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let modules = create_shader_modules(device, &desc.stages)?;
+
+    let layout = match create_layout(
+        device,
+        &modules,
+        &desc.descriptor_sets,
+        desc.push_constants_size,
+    ) {
+        Ok(layout) => layout,
+        Err(err) => {
+            destroy_shader_modules(device, modules);
+            return Err(err);
+        }
+    };
+
+    let specialization_entries = desc.specialization.map_entries();
+    let specialization_data = desc.specialization.data();
+    let specialization_info = vk::SpecializationInfo::builder()
+        .map_entries(&specialization_entries)
+        .data(&specialization_data);
+
+    let stages: Vec<vk::PipelineShaderStageCreateInfo> = modules
+        .iter()
+        .zip(&entry_points)
+        .map(|(module, entry_point)| {
+            vk::PipelineShaderStageCreateInfo::builder()
+                .name(entry_point)
+                .specialization_info(&specialization_info)
+                .stage(module.stage)
+                .module(module.raw)
+                .build()
+        })
+        .collect();
+
+    let mut rendering_info = vk::PipelineRenderingCreateInfo::builder()
+        .color_attachment_formats(&desc.color_formats)
+        .depth_attachment_format(desc.depth_format);
+
+    let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&desc.vertex_bindings)
+        .vertex_attribute_descriptions(&desc.vertex_attributes);
+
+    let input_assembly_state_info =
+        vk::PipelineInputAssemblyStateCreateInfo::builder().topology(desc.topology);
+
+    // Viewport and scissor are dynamic and set when recording.
+    let viewport_state_info = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterization_state_info = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(desc.polygon_mode)
+        .cull_mode(desc.cull_mode)
+        .front_face(desc.front_face)
+        .line_width(1.0);
+
+    let multisample_state_info = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let depth_stencil_state_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(desc.depth_test)
+        .depth_write_enable(desc.depth_write)
+        .depth_compare_op(desc.depth_compare_op)
+        .max_depth_bounds(1.0);
+
+    let color_blend_state =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&desc.blend_states);
+
+    let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::builder()
+        .dynamic_states(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]);
+
+    let create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .push_next(&mut rendering_info)
+        .stages(&stages)
+        .vertex_input_state(&vertex_input_state_info)
+        .input_assembly_state(&input_assembly_state_info)
+        .viewport_state(&viewport_state_info)
+        .rasterization_state(&rasterization_state_info)
+        .multisample_state(&multisample_state_info)
+        .depth_stencil_state(&depth_stencil_state_info)
+        .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state_info)
+        .layout(layout.raw)
+        .build();
+
+    let raw = unsafe {
+        device
+            .raw
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+    };
+
+    destroy_shader_modules(device, modules);
+
+    // Owns the layout from here on, so it is cleaned up if pipeline creation fails.
+    let mut pipeline = Pipeline::new(device, layout, vk::PipelineBindPoint::GRAPHICS);
+    pipeline.raw =
+        raw.map_err(|(_, err)| anyhow::anyhow!("Failed to create graphics pipeline: {}", err))?[0];
+
+    Ok(pipeline)
+}
+
+#[derive(Clone)]
+pub struct ComputePipelineDesc {
+    pub shader: Option<ShaderStageDesc>,
+    pub descriptor_sets: Vec<(u32, DescriptorSetLayoutDesc)>,
+    pub push_constants_size: usize,
+    /// Applied to every shader stage of the pipeline.
+    pub specialization: SpecializationConstants,
+}
+
+impl ComputePipelineDesc {
+    pub fn builder() -> Self {
+        Self {
+            shader: None,
+            descriptor_sets: Vec::new(),
+            push_constants_size: 0,
+            specialization: SpecializationConstants::default(),
+        }
+    }
+
+    pub fn compute_shader(mut self, spirv: impl Into<Cow<'static, [u8]>>) -> Self {
+        self.shader = Some(ShaderStageDesc {
+            stage: vk::ShaderStageFlags::COMPUTE,
+            spirv: spirv.into(),
+            entry_point: "main".to_owned(),
+        });
+        self
+    }
+
+    pub fn descriptor_set(mut self, set_idx: u32, layout: DescriptorSetLayoutDesc) -> Self {
+        self.descriptor_sets.push((set_idx, layout));
+        self
+    }
+
+    pub fn push_constants<T: Pod>(mut self) -> Self {
+        self.push_constants_size = std::mem::size_of::<T>();
+        self
+    }
+
+    pub fn specialization(mut self, constants: SpecializationConstants) -> Self {
+        self.specialization = constants;
+        self
+    }
+}
+
+pub fn create_compute_pipeline(
+    device: &Arc<Device>,
+    desc: &ComputePipelineDesc,
+) -> anyhow::Result<Pipeline> {
+    let shader = match &desc.shader {
+        Some(shader) => shader,
+        None => anyhow::bail!("Compute pipeline has no shader"),
+    };
+
+    let entry_point = entry_point_name(shader)?;
+    let module = device.create_shader_module(&shader.spirv, shader.stage)?;
+    let group_size = module.reflection.group_size.unwrap_or([1, 1, 1]);
+
+    let layout = match create_layout(
+        device,
+        std::slice::from_ref(&module),
+        &desc.descriptor_sets,
+        desc.push_constants_size,
+    ) {
+        Ok(layout) => layout,
+        Err(err) => {
+            destroy_shader_modules(device, vec![module]);
+            return Err(err);
+        }
+    };
+
+    let specialization_entries = desc.specialization.map_entries();
+    let specialization_data = desc.specialization.data();
+    let specialization_info = vk::SpecializationInfo::builder()
+        .map_entries(&specialization_entries)
+        .data(&specialization_data);
+
+    let create_info = vk::ComputePipelineCreateInfo::builder()
+        .stage(
+            vk::PipelineShaderStageCreateInfo::builder()
+                .name(&entry_point)
+                .specialization_info(&specialization_info)
+                .stage(vk::ShaderStageFlags::COMPUTE)
+                .module(module.raw)
+                .build(),
+        )
+        .layout(layout.raw)
+        .build();
+
+    let raw = unsafe {
+        device
+            .raw
+            .create_compute_pipelines(vk::PipelineCache::null(), &[create_info], None)
+    };
+
+    destroy_shader_modules(device, vec![module]);
+
+    let mut pipeline = Pipeline::new(device, layout, vk::PipelineBindPoint::COMPUTE);
+    pipeline.group_size = group_size;
+    pipeline.raw =
+        raw.map_err(|(_, err)| anyhow::anyhow!("Failed to create compute pipeline: {}", err))?[0];
+
+    Ok(pipeline)
+}
+
+#[derive(Clone, Default)]
+pub struct HitGroupDesc {
+    pub closest_hit: Option<ShaderStageDesc>,
+    pub any_hit: Option<ShaderStageDesc>,
+    /// Procedural hit groups have an intersection shader, triangle hit groups don't.
+    pub intersection: Option<ShaderStageDesc>,
+}
+
+#[derive(Clone)]
+pub struct RayTracingPipelineDesc {
+    pub raygen: Option<ShaderStageDesc>,
+    pub miss: Vec<ShaderStageDesc>,
+    pub hit_groups: Vec<HitGroupDesc>,
+    pub max_recursion_depth: u32,
+    pub descriptor_sets: Vec<(u32, DescriptorSetLayoutDesc)>,
+    pub push_constants_size: usize,
+    /// Applied to every shader stage of the pipeline.
+    pub specialization: SpecializationConstants,
+    /// Lets the shaders trace rays at a point in time with
+    /// `traceRayMotionNV`, see `Device::ray_tracing_motion_blur_enabled`.
+    pub motion_blur: bool,
+}
+
+fn main_stage(stage: vk::ShaderStageFlags, spirv: Cow<'static, [u8]>) -> ShaderStageDesc {
+    ShaderStageDesc {
+        stage,
+        spirv,
+        entry_point: "main".to_owned(),
+    }
+}
+
+impl RayTracingPipelineDesc {
+    pub fn builder() -> Self {
+        Self {
+            raygen: None,
+            miss: Vec::new(),
+            hit_groups: Vec::new(),
+            max_recursion_depth: 1,
+            descriptor_sets: Vec::new(),
+            push_constants_size: 0,
+            specialization: SpecializationConstants::default(),
+            motion_blur: false,
+        }
+    }
+
+    pub fn raygen_shader(mut self, spirv: impl Into<Cow<'static, [u8]>>) -> Self {
+        self.raygen = Some(main_stage(vk::ShaderStageFlags::RAYGEN_KHR, spirv.into()));
+        self
+    }
+
+    pub fn miss_shader(mut self, spirv: impl Into<Cow<'static, [u8]>>) -> Self {
+        self.miss
+            .push(main_stage(vk::ShaderStageFlags::MISS_KHR, spirv.into()));
+        self
+    }
+
+    pub fn triangle_hit_group(mut self, closest_hit: impl Into<Cow<'static, [u8]>>) -> Self {
+        self.hit_groups.push(HitGroupDesc {
+            closest_hit: Some(main_stage(
+                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                closest_hit.into(),
+            )),
+            ..Default::default()
+        });
+        self
+    }
+
+    pub fn procedural_hit_group(
+        mut self,
+        intersection: impl Into<Cow<'static, [u8]>>,
+        closest_hit: impl Into<Cow<'static, [u8]>>,
+    ) -> Self {
+        self.hit_groups.push(HitGroupDesc {
+            closest_hit: Some(main_stage(
+                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                closest_hit.into(),
+            )),
+            any_hit: None,
+            intersection: Some(main_stage(
+                vk::ShaderStageFlags::INTERSECTION_KHR,
+                intersection.into(),
+            )),
+        });
+        self
+    }
+
+    pub fn max_recursion_depth(mut self, depth: u32) -> Self {
+        self.max_recursion_depth = depth;
+        self
+    }
+
+    pub fn descriptor_set(mut self, set_idx: u32, layout: DescriptorSetLayoutDesc) -> Self {
+        self.descriptor_sets.push((set_idx, layout));
+        self
+    }
+
+    pub fn push_constants<T: Pod>(mut self) -> Self {
+        self.push_constants_size = std::mem::size_of::<T>();
+        self
+    }
+
+    pub fn specialization(mut self, constants: SpecializationConstants) -> Self {
+        self.specialization = constants;
+        self
+    }
+
+    pub fn motion_blur(mut self, enabled: bool) -> Self {
+        self.motion_blur = enabled;
+        self
+    }
+
+    pub fn group_count(&self) -> u32 {
+        (1 + self.miss.len() + self.hit_groups.len()) as u32
+    }
+
+    /// Groups are laid out as raygen, then miss shaders, then hit groups, in the
+    /// order they were added.
+    pub fn shader_binding_table_desc(&self) -> ShaderBindingTableDesc {
+        let miss_start = 1;
+        let hit_start = miss_start + self.miss.len() as u32;
+
+        ShaderBindingTableDesc {
+            raygen_group: 0,
+            miss_groups: (miss_start..hit_start).collect(),
+            hit_groups: (hit_start..self.group_count()).collect(),
+        }
+    }
+}
+
+pub fn create_ray_tracing_pipeline(
+    device: &Arc<Device>,
+    desc: &RayTracingPipelineDesc,
+) -> anyhow::Result<(Pipeline, ShaderBindingTable)> {
+    let raygen = match &desc.raygen {
+        Some(raygen) => raygen,
+        None => anyhow::bail!("Ray tracing pipeline has no raygen shader"),
+    };
+
+    let mut stage_descs = vec![raygen.clone()];
+    stage_descs.extend(desc.miss.iter().cloned());
+
+    let mut groups = vec![vk::RayTracingShaderGroupCreateInfoKHR::builder()
+        .ty(vk::RayTracingShaderGroupTypeKHR::GENERAL)
+        .general_shader(0)
+        .closest_hit_shader(vk::SHADER_UNUSED_KHR)
+        .any_hit_shader(vk::SHADER_UNUSED_KHR)
+        .intersection_shader(vk::SHADER_UNUSED_KHR)
+        .build()];
+
+    for miss_idx in 0..desc.miss.len() {
+        groups.push(
+            vk::RayTracingShaderGroupCreateInfoKHR::builder()
+                .ty(vk::RayTracingShaderGroupTypeKHR::GENERAL)
+                .general_shader(1 + miss_idx as u32)
+                .closest_hit_shader(vk::SHADER_UNUSED_KHR)
+                .any_hit_shader(vk::SHADER_UNUSED_KHR)
+                .intersection_shader(vk::SHADER_UNUSED_KHR)
+                .build(),
+        );
+    }
+
+    for hit_group in &desc.hit_groups {
+        let mut push_stage = |stage: &Option<ShaderStageDesc>| match stage {
+            Some(stage) => {
+                stage_descs.push(stage.clone());
+                stage_descs.len() as u32 - 1
+            }
+            None => vk::SHADER_UNUSED_KHR,
+        };
+
+        let closest_hit = push_stage(&hit_group.closest_hit);
+        let any_hit = push_stage(&hit_group.any_hit);
+        let intersection = push_stage(&hit_group.intersection);
+
+        let ty = if hit_group.intersection.is_some() {
+            vk::RayTracingShaderGroupTypeKHR::PROCEDURAL_HIT_GROUP
+        } else {
+            vk::RayTracingShaderGroupTypeKHR::TRIANGLES_HIT_GROUP
+        };
+
+        groups.push(
+            vk::RayTracingShaderGroupCreateInfoKHR::builder()
+                .ty(ty)
+                .general_shader(vk::SHADER_UNUSED_KHR)
+                .closest_hit_shader(closest_hit)
+                .any_hit_shader(any_hit)
+                .intersection_shader(intersection)
+                .build(),
+        );
+    }
+
+    let entry_points = stage_descs
+        .iter()
+        .map(entry_point_name)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let modules = create_shader_modules(device, &stage_descs)?;
+
+    let layout = match create_layout(
+        device,
+        &modules,
+        &desc.descriptor_sets,
+        desc.push_constants_size,
+    ) {
+        Ok(layout) => layout,
+        Err(err) => {
+            destroy_shader_modules(device, modules);
+            return Err(err);
+        }
+    };
+
+    let specialization_entries = desc.specialization.map_entries();
+    let specialization_data = desc.specialization.data();
+    let specialization_info = vk::SpecializationInfo::builder()
+        .map_entries(&specialization_entries)
+        .data(&specialization_data);
+
+    let stages: Vec<vk::PipelineShaderStageCreateInfo> = modules
+        .iter()
+        .zip(&entry_points)
+        .map(|(module, entry_point)| {
+            vk::PipelineShaderStageCreateInfo::builder()
+                .name(entry_point)
+                .specialization_info(&specialization_info)
+                .stage(module.stage)
+                .module(module.raw)
+                .build()
+        })
+        .collect();
+
+    let flags = if desc.motion_blur {
+        vk::PipelineCreateFlags::RAY_TRACING_ALLOW_MOTION_NV
+    } else {
+        vk::PipelineCreateFlags::empty()
+    };
+
+    let create_info = vk::RayTracingPipelineCreateInfoKHR::builder()
+        .flags(flags)
+        .stages(&stages)
+        .groups(&groups)
+        .max_pipeline_ray_recursion_depth(desc.max_recursion_depth)
+        .layout(layout.raw)
+        .build();
+
+    let raw = unsafe {
+        device
+            .ray_tracing_pipeline_ext
+            .create_ray_tracing_pipelines(
+                vk::DeferredOperationKHR::null(),
+                vk::PipelineCache::null(),
+                &[create_info],
+                None,
+            )
+    };
+
+    destroy_shader_modules(device, modules);
+
+    let mut pipeline = Pipeline::new(device, layout, vk::PipelineBindPoint::RAY_TRACING_KHR);
+    pipeline.raw =
+        raw.map_err(|err| anyhow::anyhow!("Failed to create ray tracing pipeline: {}", err))?[0];
+
+    let sbt = device.create_shader_binding_table(
+        pipeline.raw,
+        desc.group_count(),
+        &desc.shader_binding_table_desc(),
+    )?;
+
+    Ok((pipeline, sbt))
 }
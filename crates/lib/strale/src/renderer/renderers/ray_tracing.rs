@@ -0,0 +1,210 @@
+use std::{borrow::Cow, sync::Arc};
+
+use ash::vk;
+use vk_sync::AccessType;
+
+use crate::renderer::{
+    denoiser::DenoiserSettings,
+    glsl::motion_blur_variant,
+    render_graph::RenderGraph,
+    render_pass::FrameContext,
+    shader_compiler::{compile_shader, embedded_shader},
+    vulkan::{
+        device::Device,
+        image::ImageDesc,
+        ray_tracing::ShaderBindingTable,
+        shader::{DescriptorSetLayoutDesc, SpecializationConstants},
+        swapchain::SwapchainDesc,
+    },
+};
+
+use super::{
+    denoiser::Denoiser,
+    gbuffer::GBuffer,
+    pipeline::{create_ray_tracing_pipeline, Pipeline, RayTracingPipelineDesc},
+    storage_image::StorageImage,
+};
+
+pub struct RayTracingPipeline {
+    pub inner: Pipeline,
+    pub sbt: ShaderBindingTable,
+    /// Running average of every frame since accumulation was last reset.
+    pub output: StorageImage,
+    /// First-hit AOVs, averaged like `output`.
+    pub gbuffer: GBuffer,
+    pub denoiser: Denoiser,
+}
+
+impl RayTracingPipeline {
+    /// GLSL sources under `assets/shaders` that the pipeline is built from.
+    pub const SOURCES: &'static [&'static str] = &[
+        "raytrace.rgen",
+        "raytrace.rmiss",
+        "raytrace.rint",
+        "raytrace.rchit",
+        "raytrace_triangle.rchit",
+        Denoiser::SOURCE,
+    ];
+
+    /// With `motion_blur`, which needs `Device::ray_tracing_motion_blur_enabled`,
+    /// spheres are intersected at each ray's time. Without it, spheres are
+    /// where they are at time 0.
+    pub fn create_pipeline(
+        device: &Arc<Device>,
+        desc: SwapchainDesc,
+        bindless_layout: &DescriptorSetLayoutDesc,
+        constants: &SpecializationConstants,
+        motion_blur: bool,
+    ) -> anyhow::Result<RayTracingPipeline> {
+        Self::create_with_shaders(
+            device,
+            desc,
+            bindless_layout,
+            constants,
+            motion_blur,
+            |name| Ok(embedded_shader(name)?.into()),
+        )
+    }
+
+    /// Compiles the GLSL sources at runtime instead of using the embedded SPIR-V.
+    pub fn create_pipeline_from_source(
+        device: &Arc<Device>,
+        desc: SwapchainDesc,
+        bindless_layout: &DescriptorSetLayoutDesc,
+        constants: &SpecializationConstants,
+        motion_blur: bool,
+    ) -> anyhow::Result<RayTracingPipeline> {
+        Self::create_with_shaders(
+            device,
+            desc,
+            bindless_layout,
+            constants,
+            motion_blur,
+            |name| Ok(compile_shader(name)?.into()),
+        )
+    }
+
+    fn create_with_shaders(
+        device: &Arc<Device>,
+        desc: SwapchainDesc,
+        bindless_layout: &DescriptorSetLayoutDesc,
+        constants: &SpecializationConstants,
+        motion_blur: bool,
+        load_shader: impl Fn(&str) -> anyhow::Result<Cow<'static, [u8]>>,
+    ) -> anyhow::Result<RayTracingPipeline> {
+        let load_timed_shader = |name: &str| {
+            if motion_blur {
+                load_shader(&motion_blur_variant(name))
+            } else {
+                load_shader(name)
+            }
+        };
+
+        let (mut inner, sbt) = create_ray_tracing_pipeline(
+            device,
+            &RayTracingPipelineDesc::builder()
+                .raygen_shader(load_timed_shader("raytrace.rgen")?)
+                .miss_shader(load_shader("raytrace.rmiss")?)
+                // Hit group order must match the instance offsets in SceneAccelerationStructure.
+                .procedural_hit_group(
+                    load_timed_shader("raytrace.rint")?,
+                    load_shader("raytrace.rchit")?,
+                )
+                .triangle_hit_group(load_shader("raytrace_triangle.rchit")?)
+                .descriptor_set(0, bindless_layout.clone())
+                .specialization(constants.clone())
+                .motion_blur(motion_blur),
+        )?;
+
+        let output = StorageImage::new(
+            device,
+            &mut inner,
+            1,
+            ImageDesc {
+                extent: desc.dims,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC,
+            },
+            // The blit to the swapchain is the last use in a frame.
+            AccessType::TransferRead,
+            "ray tracing output",
+        )?;
+
+        let gbuffer = GBuffer::new(device, &mut inner, 3, desc.dims, AccessType::AnyShaderWrite)?;
+
+        let denoiser = Denoiser::new(
+            device,
+            load_shader(Denoiser::SOURCE)?,
+            &output.image,
+            &gbuffer,
+        )?;
+
+        Ok(RayTracingPipeline {
+            inner,
+            sbt,
+            output,
+            gbuffer,
+            denoiser,
+        })
+    }
+
+    /// Frees the resources the pipeline owns. The GPU must be done with them.
+    pub fn destroy(self, device: &Device) {
+        self.output.destroy(device);
+        self.gbuffer.destroy(device);
+        self.denoiser.destroy(device);
+        device.destroy_buffer(self.sbt.buffer);
+    }
+
+    /// Adds a pass accumulating into the output image, and ones scaling that onto the
+    /// frame's target, through the denoiser if `denoiser` is enabled.
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        frame: &FrameContext,
+        denoiser: DenoiserSettings,
+    ) {
+        let output = graph.import_image(&self.output.image, self.output.frame_end_access);
+        let gbuffer = [
+            graph.import_image(&self.gbuffer.normal_depth, self.gbuffer.frame_end_access),
+            graph.import_image(&self.gbuffer.albedo, self.gbuffer.frame_end_access),
+        ];
+        let target = frame.target;
+
+        graph
+            .add_pass("trace rays")
+            .read_buffer(frame.frame_constants, AccessType::AnyShaderReadOther)
+            .read_image(output, AccessType::AnyShaderReadOther)
+            .write_image(output, AccessType::AnyShaderWrite)
+            .read_image(gbuffer[0], AccessType::AnyShaderReadOther)
+            .read_image(gbuffer[1], AccessType::AnyShaderReadOther)
+            .write_image(gbuffer[0], AccessType::AnyShaderWrite)
+            .write_image(gbuffer[1], AccessType::AnyShaderWrite)
+            .render(move |ctx| {
+                self.inner.bind_pipeline(ctx.device, ctx.cb.raw);
+                let extent = self.output.image.desc.extent;
+                ctx.cb
+                    .trace_rays(ctx.device, &self.sbt, [extent.width, extent.height, 1]);
+            });
+
+        if denoiser.enabled {
+            self.denoiser
+                .add_passes(graph, frame, denoiser, output, gbuffer);
+
+            graph.export_image(output, self.output.frame_end_access);
+            for handle in gbuffer {
+                graph.export_image(handle, self.gbuffer.frame_end_access);
+            }
+            return;
+        }
+
+        graph
+            .add_pass("blit ray tracing output")
+            .read_image(output, AccessType::TransferRead)
+            .write_image(target, AccessType::TransferWrite)
+            .render(move |ctx| {
+                ctx.cb
+                    .blit_image(ctx.device, ctx.image(output), ctx.image(target));
+            });
+    }
+}
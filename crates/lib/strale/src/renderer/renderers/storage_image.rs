@@ -0,0 +1,158 @@
+use anyhow::Context;
+use ash::vk;
+use vk_sync::AccessType;
+
+use crate::renderer::vulkan::{
+    device::Device,
+    image::{Image, ImageDesc},
+};
+
+use super::pipeline::Pipeline;
+
+/// An image a pipeline's shaders read and write through binding 0 of its own
+/// descriptor set, e.g. the path tracer's accumulation target.
+pub struct StorageImage {
+    pub image: Image,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    /// How the image is left at the end of every frame. It starts out in this
+    /// state too, so render graphs can always import it with this access.
+    pub frame_end_access: AccessType,
+}
+
+impl StorageImage {
+    /// Creates the image and binds it to `set_idx` of `pipeline`.
+    pub fn new(
+        device: &Device,
+        pipeline: &mut Pipeline,
+        set_idx: u32,
+        desc: ImageDesc,
+        frame_end_access: AccessType,
+        name: &str,
+    ) -> anyhow::Result<Self> {
+        let set_layout = pipeline
+            .descriptor_set_layout(set_idx)
+            .with_context(|| format!("Shaders don't use descriptor set {}", set_idx))?;
+
+        let image = device.create_image(desc, name);
+        let (descriptor_pool, descriptor_set) =
+            create_storage_image_set(device, set_layout, &[&image])?;
+        initialize_storage_image(device, &image, frame_end_access);
+
+        pipeline.add_descriptor_set(set_idx, descriptor_set);
+
+        Ok(Self {
+            image,
+            descriptor_pool,
+            descriptor_set,
+            frame_end_access,
+        })
+    }
+
+    /// Frees the image and its descriptor set. The GPU must be done with them.
+    pub fn destroy(self, device: &Device) {
+        unsafe {
+            device
+                .raw
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+
+        device.destroy_image(self.image);
+    }
+}
+
+/// Allocates a descriptor set of `layout` with `images` bound as storage images
+/// at bindings 0, 1 and so on. Freeing the pool frees the set.
+pub fn create_storage_image_set(
+    device: &Device,
+    layout: vk::DescriptorSetLayout,
+    images: &[&Image],
+) -> anyhow::Result<(vk::DescriptorPool, vk::DescriptorSet)> {
+    let descriptor_pool = unsafe {
+        device.raw.create_descriptor_pool(
+            &vk::DescriptorPoolCreateInfo::builder()
+                .pool_sizes(&[vk::DescriptorPoolSize {
+                    ty: vk::DescriptorType::STORAGE_IMAGE,
+                    descriptor_count: images.len() as u32,
+                }])
+                .max_sets(1),
+            None,
+        )?
+    };
+
+    let descriptor_set = unsafe {
+        let sets = device.raw.allocate_descriptor_sets(
+            &vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(std::slice::from_ref(&layout)),
+        );
+        match sets {
+            Ok(sets) => sets[0],
+            Err(err) => {
+                device.raw.destroy_descriptor_pool(descriptor_pool, None);
+                return Err(err.into());
+            }
+        }
+    };
+
+    let image_infos: Vec<vk::DescriptorImageInfo> = images
+        .iter()
+        .map(|image| {
+            vk::DescriptorImageInfo::builder()
+                .image_view(image.view)
+                .image_layout(vk::ImageLayout::GENERAL)
+                .build()
+        })
+        .collect();
+
+    let image_descriptor_writes: Vec<vk::WriteDescriptorSet> = image_infos
+        .iter()
+        .enumerate()
+        .map(|(binding, image_info)| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(binding as u32)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(std::slice::from_ref(image_info))
+                .build()
+        })
+        .collect();
+
+    unsafe {
+        device
+            .raw
+            .update_descriptor_sets(&image_descriptor_writes, &[]);
+    }
+
+    Ok((descriptor_pool, descriptor_set))
+}
+
+/// Moves a new image into `frame_end_access`, so render graphs can import it
+/// with that access from the first frame on.
+pub fn initialize_storage_image(device: &Device, image: &Image, frame_end_access: AccessType) {
+    device.with_setup_cb(|cb| {
+        vk_sync::cmd::pipeline_barrier(
+            &device.raw,
+            cb,
+            None,
+            &[],
+            &[vk_sync::ImageBarrier {
+                discard_contents: true,
+                image: image.raw,
+                previous_accesses: &[AccessType::Nothing],
+                next_accesses: &[frame_end_access],
+                previous_layout: vk_sync::ImageLayout::Optimal,
+                next_layout: vk_sync::ImageLayout::Optimal,
+                range: vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: vk::REMAINING_MIP_LEVELS,
+                    base_array_layer: 0,
+                    layer_count: vk::REMAINING_ARRAY_LAYERS,
+                },
+                src_queue_family_index: device.universal_queue.family.index,
+                dst_queue_family_index: device.universal_queue.family.index,
+            }],
+        );
+    });
+}
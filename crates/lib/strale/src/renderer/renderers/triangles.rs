@@ -1,176 +1,146 @@
-use std::{ffi::CStr, io::Cursor, sync::Arc};
+use std::{borrow::Cow, sync::Arc};
 
-use ash::{
-    util::read_spv,
-    vk::{self, GraphicsPipelineCreateInfo, PipelineViewportStateCreateInfo, Rect2D},
-};
+use ash::vk;
 use bytemuck::{Pod, Zeroable};
+use vk_sync::AccessType;
 
 use crate::renderer::{
-    bindless_descriptor_set::create_bindless_descriptor_set_layout,
+    denoiser::DenoiserSettings,
+    glsl::ray_query_variant,
+    render_graph::RenderGraph,
+    render_pass::FrameContext,
+    render_settings::RenderSettings,
+    scene::SceneBuffers,
+    shader_compiler::{compile_shader, embedded_shader},
     vulkan::{
         device::{CommandBuffer, Device},
+        image::ImageDesc,
+        shader::DescriptorSetLayoutDesc,
         swapchain::SwapchainDesc,
     },
 };
 
-use super::pipeline::Pipeline;
+use super::{
+    denoiser::Denoiser,
+    gbuffer::GBuffer,
+    pipeline::{create_graphics_pipeline, GraphicsPipelineDesc, Pipeline},
+    storage_image::StorageImage,
+};
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
 pub struct TrianglesPushConstant {
-    time: f32,
-    num_spheres: u32,
+    num_bvh_primitives: u32,
 }
 
 pub struct TrianglesPipeline {
     pub inner: Pipeline,
-    pub num_spheres: u32,
+    pub num_bvh_primitives: u32,
+    /// Running average of every frame since accumulation was last reset.
+    pub accumulation: StorageImage,
+    /// First-hit AOVs, averaged like `accumulation`.
+    pub gbuffer: GBuffer,
+    pub denoiser: Denoiser,
 }
 
 impl TrianglesPipeline {
+    /// GLSL sources under `assets/shaders` that the pipeline is built from.
+    pub const SOURCES: &'static [&'static str] =
+        &["triangle.vert", "triangle.frag", Denoiser::SOURCE];
+
     pub fn create_pipeline(
-        device: &Device,
+        device: &Arc<Device>,
         desc: SwapchainDesc,
-        num_spheres: usize,
-    ) -> TrianglesPipeline {
-        let mut vertex_spv_file =
-            Cursor::new(&include_bytes!("../../../../../../assets/shaders/triangle.vert.spv")[..]);
-        let mut frag_spv_file =
-            Cursor::new(&include_bytes!("../../../../../../assets/shaders/triangle.frag.spv")[..]);
-
-        let vertex_code =
-            read_spv(&mut vertex_spv_file).expect("Failed to read vertex shader spv file");
-        let vertex_shader_info = vk::ShaderModuleCreateInfo::builder().code(&vertex_code);
-
-        let frag_code =
-            read_spv(&mut frag_spv_file).expect("Failed to read fragment shader spv file");
-        let frag_shader_info = vk::ShaderModuleCreateInfo::builder().code(&frag_code);
-
-        let vertex_shader_module = unsafe {
-            device
-                .raw
-                .create_shader_module(&vertex_shader_info, None)
-                .expect("Vertex shader module error")
-        };
-
-        let fragment_shader_module = unsafe {
-            device
-                .raw
-                .create_shader_module(&frag_shader_info, None)
-                .expect("Fragment shader module error")
-        };
-
-        let descriptor_set_layouts = &[create_bindless_descriptor_set_layout(device)];
-
-        let mut pipeline = vk::PipelineRenderingCreateInfo::builder()
-            .color_attachment_formats(&[vk::Format::B8G8R8A8_UNORM]);
-
-        let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
-            blend_enable: 0,
-            src_color_blend_factor: vk::BlendFactor::SRC_COLOR,
-            dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_DST_COLOR,
-            color_blend_op: vk::BlendOp::ADD,
-            src_alpha_blend_factor: vk::BlendFactor::ZERO,
-            dst_alpha_blend_factor: vk::BlendFactor::ZERO,
-            alpha_blend_op: vk::BlendOp::ADD,
-            color_write_mask: vk::ColorComponentFlags::RGBA,
-        }];
-        let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
-            .logic_op(vk::LogicOp::CLEAR)
-            .attachments(&color_blend_attachment_states);
-
-        let layout_create_info = vk::PipelineLayoutCreateInfo::builder()
-            .set_layouts(descriptor_set_layouts)
-            .push_constant_ranges(&[vk::PushConstantRange::builder()
-                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
-                .offset(0)
-                .size(std::mem::size_of::<TrianglesPushConstant>() as u32)
-                .build()])
-            .build();
-
-        let pipeline_layout = unsafe {
-            device
-                .raw
-                .create_pipeline_layout(&layout_create_info, None)
-                .unwrap()
-        };
-
-        let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::builder()
-            .dynamic_states(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT])
-            .build();
-
-        let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo {
-            vertex_attribute_description_count: 0,
-            p_vertex_attribute_descriptions: std::ptr::null(),
-            vertex_binding_description_count: 0,
-            p_vertex_binding_descriptions: std::ptr::null(),
-            ..Default::default()
-        };
+        bindless_layout: &DescriptorSetLayoutDesc,
+        scene: &SceneBuffers,
+        settings: RenderSettings,
+    ) -> anyhow::Result<TrianglesPipeline> {
+        Self::create_with_shaders(device, desc, bindless_layout, scene, settings, |name| {
+            Ok(embedded_shader(name)?.into())
+        })
+    }
 
-        let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo {
-            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-            ..Default::default()
-        };
+    /// Compiles the GLSL sources at runtime instead of using the embedded SPIR-V.
+    pub fn create_pipeline_from_source(
+        device: &Arc<Device>,
+        desc: SwapchainDesc,
+        bindless_layout: &DescriptorSetLayoutDesc,
+        scene: &SceneBuffers,
+        settings: RenderSettings,
+    ) -> anyhow::Result<TrianglesPipeline> {
+        Self::create_with_shaders(device, desc, bindless_layout, scene, settings, |name| {
+            Ok(compile_shader(name)?.into())
+        })
+    }
 
-        let viewports = &[vk::Viewport {
-            width: desc.dims.width as f32,
-            height: -(desc.dims.height as f32),
-            y: desc.dims.height as f32,
-            ..Default::default()
-        }];
-
-        let scissors = &[Rect2D::builder().extent(desc.dims).build()];
-
-        let graphics = GraphicsPipelineCreateInfo::builder()
-            .push_next(&mut pipeline)
-            .vertex_input_state(&vertex_input_state_info)
-            .input_assembly_state(&vertex_input_assembly_state_info)
-            .viewport_state(
-                &PipelineViewportStateCreateInfo::builder()
-                    .viewports(viewports)
-                    .scissors(scissors)
-                    .build(),
-            )
-            .color_blend_state(&color_blend_state)
-            .layout(pipeline_layout)
-            .stages(&[
-                vk::PipelineShaderStageCreateInfo::builder()
-                    .name(unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") })
-                    .stage(vk::ShaderStageFlags::VERTEX)
-                    .module(vertex_shader_module)
-                    .build(),
-                vk::PipelineShaderStageCreateInfo::builder()
-                    .name(unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") })
-                    .stage(vk::ShaderStageFlags::FRAGMENT)
-                    .module(fragment_shader_module)
-                    .build(),
-            ])
-            .rasterization_state(
-                &vk::PipelineRasterizationStateCreateInfo::builder()
-                    .cull_mode(vk::CullModeFlags::BACK)
-                    .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-                    .line_width(1.0),
-            )
-            .dynamic_state(&dynamic_state_info)
-            .build();
-
-        let pipeline = unsafe {
-            device
-                .raw
-                .create_graphics_pipelines(vk::PipelineCache::null(), &[graphics], None)
-                .unwrap()[0]
+    fn create_with_shaders(
+        device: &Arc<Device>,
+        desc: SwapchainDesc,
+        bindless_layout: &DescriptorSetLayoutDesc,
+        scene: &SceneBuffers,
+        settings: RenderSettings,
+        load_shader: impl Fn(&str) -> anyhow::Result<Cow<'static, [u8]>>,
+    ) -> anyhow::Result<TrianglesPipeline> {
+        let fragment_shader = if settings.ray_queries {
+            ray_query_variant("triangle.frag")
+        } else {
+            "triangle.frag".to_owned()
         };
 
-        TrianglesPipeline {
-            inner: Pipeline {
-                layout: pipeline_layout,
-                raw: pipeline,
-                descriptor_set_layouts: descriptor_set_layouts.to_vec(),
-                ..Default::default()
+        let mut inner = create_graphics_pipeline(
+            device,
+            &GraphicsPipelineDesc::builder()
+                .vertex_shader(load_shader("triangle.vert")?)
+                .fragment_shader(load_shader(&fragment_shader)?)
+                .cull_mode(vk::CullModeFlags::BACK, vk::FrontFace::COUNTER_CLOCKWISE)
+                .color_attachment(desc.format)
+                .descriptor_set(0, bindless_layout.clone())
+                .push_constants::<TrianglesPushConstant>()
+                .specialization(settings.specialization_constants()),
+        )?;
+
+        let accumulation = StorageImage::new(
+            device,
+            &mut inner,
+            1,
+            ImageDesc {
+                extent: desc.dims,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                usage: vk::ImageUsageFlags::STORAGE,
             },
-            num_spheres: num_spheres as u32,
-        }
+            AccessType::FragmentShaderWrite,
+            "accumulation",
+        )?;
+
+        let gbuffer = GBuffer::new(
+            device,
+            &mut inner,
+            2,
+            desc.dims,
+            AccessType::FragmentShaderWrite,
+        )?;
+
+        let denoiser = Denoiser::new(
+            device,
+            load_shader(Denoiser::SOURCE)?,
+            &accumulation.image,
+            &gbuffer,
+        )?;
+
+        Ok(TrianglesPipeline {
+            inner,
+            num_bvh_primitives: scene.num_bvh_primitives,
+            accumulation,
+            gbuffer,
+            denoiser,
+        })
+    }
+
+    /// Frees the resources the pipeline owns. The GPU must be done with them.
+    pub fn destroy(self, device: &Device) {
+        self.accumulation.destroy(device);
+        self.gbuffer.destroy(device);
+        self.denoiser.destroy(device);
     }
 
     pub fn render(&self, device: &Arc<Device>, cb: &CommandBuffer) {
@@ -179,21 +149,99 @@ impl TrianglesPipeline {
                 .raw
                 .cmd_bind_pipeline(cb.raw, vk::PipelineBindPoint::GRAPHICS, self.inner.raw);
 
-            device.raw.cmd_push_constants(
+            self.inner.push_constants(
                 cb.raw,
-                self.inner.layout,
-                vk::ShaderStageFlags::FRAGMENT,
-                0,
-                std::slice::from_raw_parts(
-                    [TrianglesPushConstant {
-                        time: device.first_frame.elapsed().as_secs_f32(),
-                        num_spheres: self.num_spheres,
-                    }]
-                    .as_ptr() as *const u8,
-                    std::mem::size_of::<TrianglesPushConstant>(),
-                ),
+                &TrianglesPushConstant {
+                    num_bvh_primitives: self.num_bvh_primitives,
+                },
             );
+
             device.raw.cmd_draw(cb.raw, 3, 1, 0, 0);
         };
     }
+
+    /// Adds a pass accumulating into the accumulation image and writing the
+    /// average to the frame's target, and the denoiser's passes overwriting it
+    /// with the filtered average if `denoiser` is enabled.
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        frame: &FrameContext,
+        denoiser: DenoiserSettings,
+    ) {
+        let accumulation =
+            graph.import_image(&self.accumulation.image, self.accumulation.frame_end_access);
+        let gbuffer = [
+            graph.import_image(&self.gbuffer.normal_depth, self.gbuffer.frame_end_access),
+            graph.import_image(&self.gbuffer.albedo, self.gbuffer.frame_end_access),
+        ];
+        let target = frame.target;
+
+        graph
+            .add_pass("triangles")
+            .read_buffer(frame.frame_constants, AccessType::AnyShaderReadOther)
+            .read_image(accumulation, AccessType::FragmentShaderReadOther)
+            .write_image(accumulation, AccessType::FragmentShaderWrite)
+            .read_image(gbuffer[0], AccessType::FragmentShaderReadOther)
+            .read_image(gbuffer[1], AccessType::FragmentShaderReadOther)
+            .write_image(gbuffer[0], AccessType::FragmentShaderWrite)
+            .write_image(gbuffer[1], AccessType::FragmentShaderWrite)
+            .write_image(target, AccessType::ColorAttachmentWrite)
+            .render(move |ctx| {
+                let target = ctx.image(target);
+                let dims = target.desc.extent;
+
+                let color_attachment_info = vk::RenderingAttachmentInfo::builder()
+                    .clear_value(vk::ClearValue {
+                        color: vk::ClearColorValue {
+                            float32: [0.0, 0.0, 1.0, 0.0],
+                        },
+                    })
+                    .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .image_view(target.view)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::STORE);
+
+                let render_info = vk::RenderingInfoKHR::builder()
+                    .color_attachments(std::slice::from_ref(&color_attachment_info))
+                    .layer_count(1)
+                    .render_area(
+                        vk::Rect2D::builder()
+                            .extent(dims)
+                            .offset(vk::Offset2D { x: 0, y: 0 })
+                            .build(),
+                    );
+
+                let viewports = &[vk::Viewport {
+                    width: dims.width as f32,
+                    height: -(dims.height as f32),
+                    y: dims.height as f32,
+                    ..Default::default()
+                }];
+
+                let scissors = &[vk::Rect2D::builder().extent(dims).build()];
+
+                unsafe {
+                    ctx.device.raw.cmd_begin_rendering(ctx.cb.raw, &render_info);
+
+                    ctx.device.raw.cmd_set_viewport(ctx.cb.raw, 0, viewports);
+                    ctx.device.raw.cmd_set_scissor(ctx.cb.raw, 0, scissors);
+
+                    self.inner.bind_pipeline(ctx.device, ctx.cb.raw);
+                    self.render(ctx.device, ctx.cb);
+
+                    ctx.device.raw.cmd_end_rendering(ctx.cb.raw);
+                }
+            });
+
+        if denoiser.enabled {
+            self.denoiser
+                .add_passes(graph, frame, denoiser, accumulation, gbuffer);
+
+            graph.export_image(accumulation, self.accumulation.frame_end_access);
+            for handle in gbuffer {
+                graph.export_image(handle, self.gbuffer.frame_end_access);
+            }
+        }
+    }
 }
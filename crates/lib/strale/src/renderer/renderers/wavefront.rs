@@ -0,0 +1,758 @@
+use std::{borrow::Cow, sync::Arc};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use vk_sync::AccessType;
+
+use crate::renderer::{
+    denoiser::DenoiserSettings,
+    glsl::ray_query_variant,
+    render_graph::{BufferHandle, ImageHandle, PassContext, RenderGraph},
+    render_pass::FrameContext,
+    render_settings::RenderSettings,
+    scene::SceneBuffers,
+    shader_compiler::{compile_shader, embedded_shader},
+    vulkan::{
+        buffer::{Buffer, BufferDesc},
+        device::Device,
+        image::ImageDesc,
+        shader::{DescriptorSetLayoutDesc, SpecializationConstants},
+        swapchain::SwapchainDesc,
+    },
+};
+
+use super::{
+    denoiser::Denoiser,
+    gbuffer::GBuffer,
+    pipeline::{create_compute_pipeline, ComputePipelineDesc, Pipeline},
+    storage_image::StorageImage,
+};
+
+/// The queues of `wavefront.glsl`. Paths waiting for their next hit, surface
+/// hits by material kind from `QUEUE_SHADE` on, collisions with media and
+/// shadow rays.
+const QUEUE_EXTEND: u32 = 0;
+const QUEUE_SHADE: u32 = 1;
+const QUEUE_MEDIUM: u32 = 5;
+const QUEUE_SHADOW: u32 = 6;
+const QUEUE_COUNT: u32 = 7;
+
+/// The stages `wavefront_queue.comp` prepares the queues for.
+const STAGE_GENERATE: u32 = 0;
+const STAGE_EXTEND: u32 = 1;
+const STAGE_SHADE: u32 = 2;
+const STAGE_SHADOW: u32 = 3;
+
+/// `SHADE_QUEUE` in `wavefront_shade.comp`.
+const SHADE_QUEUE_CONSTANT_ID: u32 = 100;
+
+/// Bytes per path of the structs in `wavefront.glsl`: `PathState`, `PathHit`,
+/// `ShadowRay` and `PixelSamples`.
+const PATH_STATE_SIZE: usize = 112;
+const PATH_HIT_SIZE: usize = 112;
+const SHADOW_RAY_SIZE: usize = 48;
+const PIXEL_SAMPLES_SIZE: usize = 48;
+
+/// Most paths traced at once. Larger images are traced in waves of this many
+/// pixels, so the path buffers don't grow with the resolution.
+const WAVE_SIZE: u32 = 1 << 18;
+
+/// How the buffers are left at the end of every frame.
+const FRAME_END_ACCESS: AccessType = AccessType::ComputeShaderWrite;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct WavefrontPushConstant {
+    num_bvh_primitives: u32,
+    width: u32,
+    height: u32,
+    sample_index: u32,
+    bounce: u32,
+    stage: u32,
+    wave_offset: u32,
+    wave_size: u32,
+}
+
+/// Where the indirect dispatch over `queue` is in the queue buffer.
+fn queue_dispatch_offset(queue: u32) -> u64 {
+    (queue as usize * std::mem::size_of::<vk::DispatchIndirectCommand>()) as u64
+}
+
+/// The path tracer split into compute passes, so paths that hit the same kind
+/// of material are shaded together instead of diverging in one big loop. The
+/// image is traced in waves of up to `WAVE_SIZE` pixels, each tracing one path
+/// at a time. The stages hand paths on through queues in storage buffers and
+/// are dispatched indirectly over their length. See `wavefront.glsl`.
+pub struct WavefrontPipeline {
+    /// Starts a path from the camera for every pixel of a wave.
+    pub generate: Pipeline,
+    /// Finds the next hit of the queued paths, and queues them by what they hit.
+    pub extend: Pipeline,
+    /// One per queue from `QUEUE_SHADE` to `QUEUE_MEDIUM`, specialized to it.
+    pub shade: Vec<Pipeline>,
+    /// Traces the shadow rays of direct lighting.
+    pub shadow: Pipeline,
+    /// Empties queues and writes their indirect dispatches between stages.
+    pub queue: Pipeline,
+    /// Averages finished samples into `accumulation` and `gbuffer`.
+    pub accumulate: Pipeline,
+    pub num_bvh_primitives: u32,
+    pub samples_per_pixel: u32,
+    pub max_bounces: u32,
+    /// Paths the buffers hold, the smaller of `WAVE_SIZE` and the pixel count.
+    pub wave_size: u32,
+    /// The indirect dispatches and lengths of the queues.
+    pub queues: Buffer,
+    pub queue_entries: Buffer,
+    pub paths: Buffer,
+    pub path_hits: Buffer,
+    pub shadow_rays: Buffer,
+    pub pixels: Buffer,
+    /// Binds the buffers to set 1 of every stage.
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    /// Running average of every frame since accumulation was last reset.
+    pub accumulation: StorageImage,
+    /// First-hit AOVs, averaged like `accumulation`.
+    pub gbuffer: GBuffer,
+    pub denoiser: Denoiser,
+}
+
+/// Frees what `WavefrontPipeline::create_with_shaders` has created when a
+/// later step fails. Null handles and `None` are what isn't created yet.
+struct ResourceGuard<'a> {
+    device: &'a Device,
+    set_layout: vk::DescriptorSetLayout,
+    buffers: Option<[Buffer; 6]>,
+    descriptor_pool: vk::DescriptorPool,
+    accumulation: Option<StorageImage>,
+    gbuffer: Option<GBuffer>,
+}
+
+impl ResourceGuard<'_> {
+    /// Hands the resources over to the finished pipeline instead of freeing
+    /// them.
+    fn release(mut self) -> ([Buffer; 6], StorageImage, GBuffer) {
+        self.set_layout = vk::DescriptorSetLayout::null();
+        self.descriptor_pool = vk::DescriptorPool::null();
+        (
+            self.buffers.take().expect("buffers were created"),
+            self.accumulation.take().expect("accumulation was created"),
+            self.gbuffer.take().expect("G-buffer was created"),
+        )
+    }
+}
+
+impl Drop for ResourceGuard<'_> {
+    fn drop(&mut self) {
+        // Destroying null handles does nothing.
+        unsafe {
+            self.device
+                .raw
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            self.device
+                .raw
+                .destroy_descriptor_set_layout(self.set_layout, None);
+        }
+
+        for buffer in self.buffers.take().into_iter().flatten() {
+            self.device.destroy_buffer(buffer);
+        }
+        if let Some(accumulation) = self.accumulation.take() {
+            accumulation.destroy(self.device);
+        }
+        if let Some(gbuffer) = self.gbuffer.take() {
+            gbuffer.destroy(self.device);
+        }
+    }
+}
+
+/// The graph's handles to the buffers every stage reads and writes.
+#[derive(Clone, Copy)]
+struct StageBuffers {
+    frame_constants: BufferHandle,
+    queues: BufferHandle,
+    others: [BufferHandle; 5],
+}
+
+impl WavefrontPipeline {
+    /// GLSL sources under `assets/shaders` that the pipeline is built from.
+    pub const SOURCES: &'static [&'static str] = &[
+        "wavefront_generate.comp",
+        "wavefront_extend.comp",
+        "wavefront_shade.comp",
+        "wavefront_shadow.comp",
+        "wavefront_queue.comp",
+        "wavefront_accumulate.comp",
+        Denoiser::SOURCE,
+    ];
+
+    pub fn create_pipeline(
+        device: &Arc<Device>,
+        desc: SwapchainDesc,
+        bindless_layout: &DescriptorSetLayoutDesc,
+        scene: &SceneBuffers,
+        settings: RenderSettings,
+    ) -> anyhow::Result<WavefrontPipeline> {
+        Self::create_with_shaders(device, desc, bindless_layout, scene, settings, |name| {
+            Ok(embedded_shader(name)?.into())
+        })
+    }
+
+    /// Compiles the GLSL sources at runtime instead of using the embedded SPIR-V.
+    pub fn create_pipeline_from_source(
+        device: &Arc<Device>,
+        desc: SwapchainDesc,
+        bindless_layout: &DescriptorSetLayoutDesc,
+        scene: &SceneBuffers,
+        settings: RenderSettings,
+    ) -> anyhow::Result<WavefrontPipeline> {
+        Self::create_with_shaders(device, desc, bindless_layout, scene, settings, |name| {
+            Ok(compile_shader(name)?.into())
+        })
+    }
+
+    fn create_with_shaders(
+        device: &Arc<Device>,
+        desc: SwapchainDesc,
+        bindless_layout: &DescriptorSetLayoutDesc,
+        scene: &SceneBuffers,
+        settings: RenderSettings,
+        load_shader: impl Fn(&str) -> anyhow::Result<Cow<'static, [u8]>>,
+    ) -> anyhow::Result<WavefrontPipeline> {
+        // Every shader is loaded before anything is created, so one that fails
+        // to compile leaves nothing to clean up.
+        let shadow_shader = if settings.ray_queries {
+            ray_query_variant("wavefront_shadow.comp")
+        } else {
+            "wavefront_shadow.comp".to_owned()
+        };
+        let generate_spirv = load_shader("wavefront_generate.comp")?;
+        let extend_spirv = load_shader("wavefront_extend.comp")?;
+        let shade_spirv = load_shader("wavefront_shade.comp")?;
+        let shadow_spirv = load_shader(&shadow_shader)?;
+        let queue_spirv = load_shader("wavefront_queue.comp")?;
+        let accumulate_spirv = load_shader("wavefront_accumulate.comp")?;
+        let denoiser_spirv = load_shader(Denoiser::SOURCE)?;
+
+        // Frees what was created so far if a later step fails.
+        let mut guard = ResourceGuard {
+            device,
+            set_layout: vk::DescriptorSetLayout::null(),
+            buffers: None,
+            descriptor_pool: vk::DescriptorPool::null(),
+            accumulation: None,
+            gbuffer: None,
+        };
+
+        let constants = settings.specialization_constants();
+        let set_layout = wavefront_descriptor_set_layout_desc(device)?;
+        guard.set_layout = set_layout.layout;
+
+        let create_stage = |spirv: &Cow<'static, [u8]>, constants: SpecializationConstants| {
+            create_compute_pipeline(
+                device,
+                &ComputePipelineDesc::builder()
+                    .compute_shader(spirv.clone())
+                    .descriptor_set(0, bindless_layout.clone())
+                    .descriptor_set(1, set_layout.clone())
+                    .push_constants::<WavefrontPushConstant>()
+                    .specialization(constants),
+            )
+        };
+
+        let mut generate = create_stage(&generate_spirv, constants.clone())?;
+        let mut extend = create_stage(&extend_spirv, constants.clone())?;
+        let mut shade = (QUEUE_SHADE..=QUEUE_MEDIUM)
+            .map(|queue| {
+                create_stage(
+                    &shade_spirv,
+                    constants.clone().u32(SHADE_QUEUE_CONSTANT_ID, queue),
+                )
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut shadow = create_stage(&shadow_spirv, constants.clone())?;
+        let mut queue = create_stage(&queue_spirv, constants.clone())?;
+        let mut accumulate = create_stage(&accumulate_spirv, constants)?;
+
+        let wave_size = (desc.dims.width * desc.dims.height).min(WAVE_SIZE);
+        let path_count = wave_size as usize;
+        let create_buffer = |size: usize, usage: vk::BufferUsageFlags, name: &str| {
+            device.create_buffer(
+                BufferDesc {
+                    size,
+                    usage: vk::BufferUsageFlags::STORAGE_BUFFER | usage,
+                    memory_location: gpu_allocator::MemoryLocation::GpuOnly,
+                },
+                name,
+                None,
+            )
+        };
+
+        // The bindings of `wavefront.glsl`.
+        let buffers = guard.buffers.insert([
+            create_buffer(
+                QUEUE_COUNT as usize
+                    * (std::mem::size_of::<vk::DispatchIndirectCommand>()
+                        + std::mem::size_of::<u32>()),
+                vk::BufferUsageFlags::INDIRECT_BUFFER,
+                "wavefront queues",
+            ),
+            create_buffer(
+                QUEUE_COUNT as usize * path_count * std::mem::size_of::<u32>(),
+                vk::BufferUsageFlags::empty(),
+                "wavefront queue entries",
+            ),
+            create_buffer(
+                path_count * PATH_STATE_SIZE,
+                vk::BufferUsageFlags::empty(),
+                "wavefront paths",
+            ),
+            create_buffer(
+                path_count * PATH_HIT_SIZE,
+                vk::BufferUsageFlags::empty(),
+                "wavefront path hits",
+            ),
+            create_buffer(
+                path_count * SHADOW_RAY_SIZE,
+                vk::BufferUsageFlags::empty(),
+                "wavefront shadow rays",
+            ),
+            create_buffer(
+                path_count * PIXEL_SAMPLES_SIZE,
+                vk::BufferUsageFlags::empty(),
+                "wavefront pixels",
+            ),
+        ]);
+
+        let (descriptor_pool, descriptor_set) =
+            create_buffer_set(device, set_layout.layout, &buffers.each_ref())?;
+        guard.descriptor_pool = descriptor_pool;
+        for pipeline in [
+            &mut generate,
+            &mut extend,
+            &mut shadow,
+            &mut queue,
+            &mut accumulate,
+        ]
+        .into_iter()
+        .chain(&mut shade)
+        {
+            pipeline.add_descriptor_set(1, descriptor_set);
+        }
+
+        let accumulation = guard.accumulation.insert(StorageImage::new(
+            device,
+            &mut accumulate,
+            3,
+            ImageDesc {
+                extent: desc.dims,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC,
+            },
+            // The blit to the swapchain is the last use in a frame.
+            AccessType::TransferRead,
+            "wavefront accumulation",
+        )?);
+
+        let gbuffer = guard.gbuffer.insert(GBuffer::new(
+            device,
+            &mut accumulate,
+            2,
+            desc.dims,
+            AccessType::ComputeShaderWrite,
+        )?);
+
+        let denoiser = Denoiser::new(device, denoiser_spirv, &accumulation.image, gbuffer)?;
+
+        let ([queues, queue_entries, paths, path_hits, shadow_rays, pixels], accumulation, gbuffer) =
+            guard.release();
+
+        Ok(WavefrontPipeline {
+            generate,
+            extend,
+            shade,
+            shadow,
+            queue,
+            accumulate,
+            num_bvh_primitives: scene.num_bvh_primitives,
+            samples_per_pixel: settings.samples_per_pixel,
+            max_bounces: settings.max_bounces,
+            wave_size,
+            queues,
+            queue_entries,
+            paths,
+            path_hits,
+            shadow_rays,
+            pixels,
+            descriptor_set_layout: set_layout.layout,
+            descriptor_pool,
+            descriptor_set,
+            accumulation,
+            gbuffer,
+            denoiser,
+        })
+    }
+
+    /// Binds `descriptor_set` to `set_idx` of every stage.
+    pub fn add_descriptor_set(&mut self, set_idx: u32, descriptor_set: vk::DescriptorSet) {
+        for pipeline in [
+            &mut self.generate,
+            &mut self.extend,
+            &mut self.shadow,
+            &mut self.queue,
+            &mut self.accumulate,
+        ]
+        .into_iter()
+        .chain(&mut self.shade)
+        {
+            pipeline.add_descriptor_set(set_idx, descriptor_set);
+        }
+    }
+
+    /// Frees the resources the pipeline owns. The GPU must be done with them.
+    pub fn destroy(self, device: &Device) {
+        unsafe {
+            device
+                .raw
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            device
+                .raw
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+
+        for buffer in [
+            self.queues,
+            self.queue_entries,
+            self.paths,
+            self.path_hits,
+            self.shadow_rays,
+            self.pixels,
+        ] {
+            device.destroy_buffer(buffer);
+        }
+
+        self.accumulation.destroy(device);
+        self.gbuffer.destroy(device);
+        self.denoiser.destroy(device);
+    }
+
+    /// How many waves of `wave_size` pixels cover the image.
+    fn wave_count(&self) -> u32 {
+        let extent = self.accumulation.image.desc.extent;
+        (extent.width * extent.height).div_ceil(self.wave_size)
+    }
+
+    fn push_constant(
+        &self,
+        wave: u32,
+        sample_index: u32,
+        bounce: u32,
+        stage: u32,
+    ) -> WavefrontPushConstant {
+        let extent = self.accumulation.image.desc.extent;
+        let wave_offset = wave * self.wave_size;
+        WavefrontPushConstant {
+            num_bvh_primitives: self.num_bvh_primitives,
+            width: extent.width,
+            height: extent.height,
+            sample_index,
+            bounce,
+            stage,
+            wave_offset,
+            // The last wave takes the pixels that are left.
+            wave_size: self
+                .wave_size
+                .min(extent.width * extent.height - wave_offset),
+        }
+    }
+
+    /// Adds a pass that reads and writes every buffer and `images`, and reads
+    /// the queue buffer's indirect dispatches too if `indirect` is set.
+    fn add_stage<'a>(
+        graph: &mut RenderGraph<'a>,
+        name: &str,
+        buffers: StageBuffers,
+        images: &[ImageHandle],
+        indirect: bool,
+        record: impl FnOnce(&PassContext) + 'a,
+    ) {
+        let mut pass = graph
+            .add_pass(name)
+            .read_buffer(buffers.frame_constants, AccessType::AnyShaderReadOther);
+        if indirect {
+            pass = pass.read_buffer(buffers.queues, AccessType::IndirectBuffer);
+        }
+        for buffer in std::iter::once(buffers.queues).chain(buffers.others) {
+            pass = pass
+                .read_buffer(buffer, AccessType::ComputeShaderReadOther)
+                .write_buffer(buffer, AccessType::ComputeShaderWrite);
+        }
+        for image in images {
+            pass = pass
+                .read_image(*image, AccessType::ComputeShaderReadOther)
+                .write_image(*image, AccessType::ComputeShaderWrite);
+        }
+        pass.render(record);
+    }
+
+    /// Adds a pass preparing the queues for `stage`.
+    fn add_queue_pass<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        buffers: StageBuffers,
+        push: WavefrontPushConstant,
+    ) {
+        Self::add_stage(
+            graph,
+            "prepare wavefront queues",
+            buffers,
+            &[],
+            false,
+            move |ctx| {
+                self.queue.bind_pipeline(ctx.device, ctx.cb.raw);
+                self.queue.push_constants(ctx.cb.raw, &push);
+                ctx.cb
+                    .dispatch_threads(ctx.device, [QUEUE_COUNT, 1, 1], self.queue.group_size);
+            },
+        );
+    }
+
+    /// Adds the passes tracing a sample per pixel `samples_per_pixel` times,
+    /// wave by wave, and averaging them into the accumulation image, and ones scaling that onto
+    /// the frame's target, through the denoiser if `denoiser` is enabled.
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        frame: &FrameContext,
+        denoiser: DenoiserSettings,
+    ) {
+        let buffers = StageBuffers {
+            frame_constants: frame.frame_constants,
+            queues: graph.import_buffer(&self.queues, FRAME_END_ACCESS),
+            others: [
+                graph.import_buffer(&self.queue_entries, FRAME_END_ACCESS),
+                graph.import_buffer(&self.paths, FRAME_END_ACCESS),
+                graph.import_buffer(&self.path_hits, FRAME_END_ACCESS),
+                graph.import_buffer(&self.shadow_rays, FRAME_END_ACCESS),
+                graph.import_buffer(&self.pixels, FRAME_END_ACCESS),
+            ],
+        };
+        let accumulation =
+            graph.import_image(&self.accumulation.image, self.accumulation.frame_end_access);
+        let gbuffer = [
+            graph.import_image(&self.gbuffer.normal_depth, self.gbuffer.frame_end_access),
+            graph.import_image(&self.gbuffer.albedo, self.gbuffer.frame_end_access),
+        ];
+        let queues = buffers.queues;
+
+        // A wave traces all its samples before the next one reuses the buffers.
+        for wave in 0..self.wave_count() {
+            for sample in 0..self.samples_per_pixel {
+                let push = self.push_constant(wave, sample, 0, STAGE_GENERATE);
+                self.add_queue_pass(graph, buffers, push);
+                Self::add_stage(
+                    graph,
+                    "wavefront generate",
+                    buffers,
+                    &[],
+                    false,
+                    move |ctx| {
+                        self.generate.bind_pipeline(ctx.device, ctx.cb.raw);
+                        self.generate.push_constants(ctx.cb.raw, &push);
+                        ctx.cb.dispatch_threads(
+                            ctx.device,
+                            [push.wave_size, 1, 1],
+                            self.generate.group_size,
+                        );
+                    },
+                );
+
+                for bounce in 0..self.max_bounces {
+                    let push = self.push_constant(wave, sample, bounce, STAGE_EXTEND);
+                    self.add_queue_pass(graph, buffers, push);
+                    Self::add_stage(graph, "wavefront extend", buffers, &[], true, move |ctx| {
+                        self.extend.bind_pipeline(ctx.device, ctx.cb.raw);
+                        self.extend.push_constants(ctx.cb.raw, &push);
+                        ctx.cb.dispatch_indirect(
+                            ctx.device,
+                            ctx.buffer(queues),
+                            queue_dispatch_offset(QUEUE_EXTEND),
+                        );
+                    });
+
+                    // The queues are disjoint, so the materials are shaded
+                    // without barriers between them.
+                    let push = self.push_constant(wave, sample, bounce, STAGE_SHADE);
+                    self.add_queue_pass(graph, buffers, push);
+                    Self::add_stage(graph, "wavefront shade", buffers, &[], true, move |ctx| {
+                        for (shade, queue) in self.shade.iter().zip(QUEUE_SHADE..) {
+                            shade.bind_pipeline(ctx.device, ctx.cb.raw);
+                            shade.push_constants(ctx.cb.raw, &push);
+                            ctx.cb.dispatch_indirect(
+                                ctx.device,
+                                ctx.buffer(queues),
+                                queue_dispatch_offset(queue),
+                            );
+                        }
+                    });
+
+                    // The last bounce doesn't sample direct lighting, the path
+                    // has no next hit to find the same light.
+                    if bounce + 1 == self.max_bounces {
+                        continue;
+                    }
+
+                    let push = self.push_constant(wave, sample, bounce, STAGE_SHADOW);
+                    self.add_queue_pass(graph, buffers, push);
+                    Self::add_stage(graph, "wavefront shadow", buffers, &[], true, move |ctx| {
+                        self.shadow.bind_pipeline(ctx.device, ctx.cb.raw);
+                        self.shadow.push_constants(ctx.cb.raw, &push);
+                        ctx.cb.dispatch_indirect(
+                            ctx.device,
+                            ctx.buffer(queues),
+                            queue_dispatch_offset(QUEUE_SHADOW),
+                        );
+                    });
+                }
+
+                let push = self.push_constant(wave, sample, 0, STAGE_GENERATE);
+                Self::add_stage(
+                    graph,
+                    "wavefront accumulate",
+                    buffers,
+                    &[accumulation, gbuffer[0], gbuffer[1]],
+                    false,
+                    move |ctx| {
+                        self.accumulate.bind_pipeline(ctx.device, ctx.cb.raw);
+                        self.accumulate.push_constants(ctx.cb.raw, &push);
+                        ctx.cb.dispatch_threads(
+                            ctx.device,
+                            [push.wave_size, 1, 1],
+                            self.accumulate.group_size,
+                        );
+                    },
+                );
+            }
+        }
+
+        graph.export_buffer(queues, FRAME_END_ACCESS);
+        for buffer in buffers.others {
+            graph.export_buffer(buffer, FRAME_END_ACCESS);
+        }
+        for handle in gbuffer {
+            graph.export_image(handle, self.gbuffer.frame_end_access);
+        }
+
+        if denoiser.enabled {
+            self.denoiser
+                .add_passes(graph, frame, denoiser, accumulation, gbuffer);
+
+            graph.export_image(accumulation, self.accumulation.frame_end_access);
+            return;
+        }
+
+        let target = frame.target;
+        graph
+            .add_pass("blit wavefront output")
+            .read_image(accumulation, AccessType::TransferRead)
+            .write_image(target, AccessType::TransferWrite)
+            .render(move |ctx| {
+                ctx.cb
+                    .blit_image(ctx.device, ctx.image(accumulation), ctx.image(target));
+            });
+    }
+}
+
+/// The layout of set 1 in `wavefront.glsl`, shared by every stage so they can
+/// bind the same set.
+fn wavefront_descriptor_set_layout_desc(
+    device: &Device,
+) -> anyhow::Result<DescriptorSetLayoutDesc> {
+    let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..6)
+        .map(|binding| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build()
+        })
+        .collect();
+
+    let layout = unsafe {
+        device.raw.create_descriptor_set_layout(
+            &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+            None,
+        )?
+    };
+
+    Ok(DescriptorSetLayoutDesc { layout, bindings })
+}
+
+/// Allocates a descriptor set of `layout` with `buffers` bound as storage
+/// buffers at bindings 0, 1 and so on, like `create_storage_image_set`.
+fn create_buffer_set(
+    device: &Device,
+    layout: vk::DescriptorSetLayout,
+    buffers: &[&Buffer],
+) -> anyhow::Result<(vk::DescriptorPool, vk::DescriptorSet)> {
+    let descriptor_pool = unsafe {
+        device.raw.create_descriptor_pool(
+            &vk::DescriptorPoolCreateInfo::builder()
+                .pool_sizes(&[vk::DescriptorPoolSize {
+                    ty: vk::DescriptorType::STORAGE_BUFFER,
+                    descriptor_count: buffers.len() as u32,
+                }])
+                .max_sets(1),
+            None,
+        )?
+    };
+
+    let descriptor_set = unsafe {
+        let sets = device.raw.allocate_descriptor_sets(
+            &vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(std::slice::from_ref(&layout)),
+        );
+        match sets {
+            Ok(sets) => sets[0],
+            Err(err) => {
+                device.raw.destroy_descriptor_pool(descriptor_pool, None);
+                return Err(err.into());
+            }
+        }
+    };
+
+    let buffer_infos: Vec<vk::DescriptorBufferInfo> = buffers
+        .iter()
+        .map(|buffer| {
+            vk::DescriptorBufferInfo::builder()
+                .buffer(buffer.raw)
+                .range(vk::WHOLE_SIZE)
+                .build()
+        })
+        .collect();
+
+    let buffer_descriptor_writes: Vec<vk::WriteDescriptorSet> = buffer_infos
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer_info)| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(binding as u32)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(buffer_info))
+                .build()
+        })
+        .collect();
+
+    unsafe {
+        device
+            .raw
+            .update_descriptor_sets(&buffer_descriptor_writes, &[]);
+    }
+
+    Ok((descriptor_pool, descriptor_set))
+}
@@ -0,0 +1,181 @@
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use crate::math::Vec3;
+
+// `assets/shaders/include/sampler.glsl` ported line by line, with the same
+// 32 bit integer arithmetic, so the random numbers the shaders draw can be
+// checked on the CPU. Change both together, `tests/golden.rs` checks they
+// agree.
+
+/// Dimensions drawn before the first bounce: the jitter within the pixel, the
+/// point on the lens and the time within the shutter interval.
+pub const CAMERA_DIMENSIONS: u32 = 3;
+/// Dimensions every bounce draws.
+pub const BOUNCE_DIMENSIONS: u32 = 5;
+/// The first dimension of a bounce picks a light, the second the direction to it.
+pub const LIGHT_DIMENSION: u32 = 0;
+/// The third is the scattered direction, the fourth the choice between
+/// reflection and refraction or the length of a metal's fuzz.
+pub const SCATTER_DIMENSION: u32 = 2;
+/// The fifth is the distance to a collision with a medium.
+pub const MEDIUM_DIMENSION: u32 = 4;
+
+/// The sequence a `Sampler` draws from, the `SAMPLER` specialization constant.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// Independent numbers from a PCG generator.
+    #[default]
+    Pcg = 0,
+    /// Owen scrambled Sobol points, stratified over the samples of a pixel.
+    Sobol = 1,
+}
+
+/// The random numbers of one path, like `Sampler` in `sampler.glsl`.
+#[derive(Clone, Copy, Debug)]
+pub struct Sampler {
+    kind: SamplerKind,
+    /// Decorrelates pixels.
+    seed: u32,
+    /// The point of the Sobol sequence.
+    index: u32,
+    /// Each draw takes the next dimension of the point.
+    dimension: u32,
+    /// PCG state.
+    state: u32,
+}
+
+impl Sampler {
+    /// `sample_index` counts the samples of `pixel` since accumulation
+    /// started, `frame_index` keeps independent samples changing between
+    /// frames even when accumulation restarts.
+    pub fn new(kind: SamplerKind, pixel: [u32; 2], frame_index: u32, sample_index: u32) -> Self {
+        let seed = pcg_hash(pixel[0].wrapping_add(pcg_hash(pixel[1])));
+        Self {
+            kind,
+            seed,
+            index: sample_index,
+            dimension: 0,
+            state: pcg_hash(seed ^ pcg_hash(frame_index ^ pcg_hash(sample_index))),
+        }
+    }
+
+    /// Continues from dimension `offset` of `bounce`, so the same decisions
+    /// of every path use the same dimensions.
+    pub fn seek_dimension(&mut self, bounce: u32, offset: u32) {
+        self.dimension = CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS + offset;
+    }
+
+    /// A number in [0, 1).
+    pub fn sample_1d(&mut self) -> f32 {
+        self.sample_2d()[0]
+    }
+
+    /// A point in [0, 1)².
+    pub fn sample_2d(&mut self) -> [f32; 2] {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        match self.kind {
+            SamplerKind::Sobol => sobol_2d(self.index, pcg_hash(self.seed ^ pcg_hash(dimension))),
+            SamplerKind::Pcg => {
+                let x = to_float(pcg_next(&mut self.state));
+                let y = to_float(pcg_next(&mut self.state));
+                [x, y]
+            }
+        }
+    }
+}
+
+/// Uniform on the unit sphere.
+pub fn sample_sphere(u: [f32; 2]) -> Vec3 {
+    let z = 1.0 - 2.0 * u[0];
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u[1];
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Uniform in the unit ball.
+pub fn sample_ball(u: [f32; 3]) -> Vec3 {
+    sample_sphere([u[0], u[1]]) * u[2].cbrt()
+}
+
+/// Uniform on the unit disk, with Shirley and Chiu's concentric mapping.
+pub fn sample_disk(u: [f32; 2]) -> [f32; 2] {
+    let x = 2.0 * u[0] - 1.0;
+    let y = 2.0 * u[1] - 1.0;
+    if x == 0.0 && y == 0.0 {
+        return [0.0, 0.0];
+    }
+
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, FRAC_PI_4 * (y / x))
+    } else {
+        (y, FRAC_PI_2 - FRAC_PI_4 * (x / y))
+    };
+    [r * theta.cos(), r * theta.sin()]
+}
+
+/// The output permutation of PCG-RXS-M-XS.
+fn pcg_permute(state: u32) -> u32 {
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277_803_737);
+    (word >> 22) ^ word
+}
+
+fn pcg_step(state: u32) -> u32 {
+    state.wrapping_mul(747_796_405).wrapping_add(2_891_336_453)
+}
+
+/// Jarzynski and Olano, "Hash Functions for GPU Rendering".
+pub fn pcg_hash(v: u32) -> u32 {
+    pcg_permute(pcg_step(v))
+}
+
+fn pcg_next(state: &mut u32) -> u32 {
+    *state = pcg_step(*state);
+    pcg_permute(*state)
+}
+
+/// The top 24 bits as a float in [0, 1).
+fn to_float(x: u32) -> f32 {
+    (x >> 8) as f32 * (1.0 / 16_777_216.0)
+}
+
+/// The second dimension of the Sobol sequence. The first is the bit reversed
+/// index.
+fn sobol_1(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut v = 0x8000_0000u32;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        v ^= v >> 1;
+        index >>= 1;
+    }
+    result
+}
+
+/// Scrambles the bits of `x`, each depending only on the ones below it.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+/// Burley, "Practical Hash-based Owen Scrambling".
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// Point `index` of a shuffled, Owen scrambled 2D Sobol sequence. The first
+/// 2^k points of every seed stay a (0, k, 2)-net.
+pub fn sobol_2d(index: u32, seed: u32) -> [f32; 2] {
+    let index = nested_uniform_scramble(index, seed);
+    let x = nested_uniform_scramble(index.reverse_bits(), pcg_hash(seed));
+    let y = nested_uniform_scramble(sobol_1(index), pcg_hash(seed.wrapping_add(1)));
+    [to_float(x), to_float(y)]
+}
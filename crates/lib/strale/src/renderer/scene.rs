@@ -0,0 +1,758 @@
+use std::{f32::consts::PI, path::Path};
+
+use anyhow::{ensure, Context};
+use ash::vk;
+use bytemuck::Pod;
+use gpu_allocator::MemoryLocation;
+
+use crate::math::{Aabb, Affine3, Ray, Vec3};
+
+use super::{
+    bvh::Bvh,
+    environment::{Environment, EnvironmentDistribution},
+    light::{LightList, NO_LIGHT},
+    material::{GpuMaterial, Material},
+    medium::{Fog, GpuMedium, Medium, Volume},
+    texture::{GpuImage, Image, ImageId, Texture},
+    vertex::{GpuMeshInstance, GpuSphere, Vertex},
+    vulkan::{
+        buffer::{Buffer, BufferDesc},
+        device::Device,
+    },
+};
+
+/// Index into `Scene::materials`.
+pub type MaterialId = u32;
+/// Index into `Scene::meshes`.
+pub type MeshId = usize;
+
+/// Scene time runs from 0 to 1 over an animation, `Camera`'s shutter picks the
+/// part of it a frame shows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sphere {
+    /// Where the sphere is at time 0.
+    pub center: Vec3,
+    pub radius: f32,
+    pub material: MaterialId,
+    /// Where the sphere is at time 1, if it moves. It moves in a straight line
+    /// at constant speed from `center`.
+    pub end_center: Option<Vec3>,
+}
+
+/// An indexed triangle list. Triangles are shaded from both sides.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    /// Three per triangle.
+    pub indices: Vec<u32>,
+}
+
+/// A mesh placed in the scene. Several instances can share one mesh.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshInstance {
+    pub mesh: MeshId,
+    pub transform: Affine3,
+    pub material: MaterialId,
+}
+
+/// A single sphere or triangle of a `Scene`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PrimitiveId {
+    /// Index into `Scene::spheres`.
+    Sphere(u32),
+    /// A triangle of the mesh of `Scene::instances[instance]`.
+    Triangle { instance: u32, triangle: u32 },
+}
+
+/// The closest hit along a ray, see `Scene::intersect`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SceneHit {
+    pub t: f32,
+    pub primitive: PrimitiveId,
+}
+
+/// Everything the path tracer renders.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    pub materials: Vec<Material>,
+    pub spheres: Vec<Sphere>,
+    pub meshes: Vec<Mesh>,
+    pub instances: Vec<MeshInstance>,
+    /// The images `Texture::Image` refers to.
+    pub images: Vec<Image>,
+    /// Smoke and the like, found by rays travelling through them rather than
+    /// by intersecting them. Every ray tests every volume.
+    pub volumes: Vec<Volume>,
+    pub fog: Option<Fog>,
+    /// Lights rays that leave the scene, a sky gradient if `None`.
+    pub environment: Option<Environment>,
+}
+
+impl Sphere {
+    /// Like `sphereCenter()` in `scene.glsl`.
+    pub fn center_at(&self, time: f32) -> Vec3 {
+        match self.end_center {
+            Some(end_center) => self.center + (end_center - self.center) * time,
+            None => self.center,
+        }
+    }
+
+    /// Covers the sphere over its whole motion.
+    pub fn bounds(&self) -> Aabb {
+        let radius = Vec3::splat(self.radius);
+        let end_center = self.end_center.unwrap_or(self.center);
+        Aabb {
+            min: self.center.min(end_center) - radius,
+            max: self.center.max(end_center) + radius,
+        }
+    }
+
+    /// The closest `t` between `t_min` and `t_max` where `ray` hits the sphere
+    /// at the ray's time, like `hit()` in `raycast.glsl`.
+    pub fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<f32> {
+        let oc = ray.origin - self.center_at(ray.time);
+        let a = ray.direction.dot(ray.direction);
+        let half_b = oc.dot(ray.direction);
+        let c = oc.dot(oc) - self.radius * self.radius;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrtd = discriminant.sqrt();
+        [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a]
+            .into_iter()
+            .find(|t| (t_min..=t_max).contains(t))
+    }
+}
+
+/// Spherical coordinates of a point on the unit sphere, `u` around +Y starting
+/// at -X, `v` from the bottom to the top. Like `sphereUv()` in `scene.glsl`.
+pub fn sphere_uv(normal: Vec3) -> [f32; 2] {
+    let theta = (-normal.y).clamp(-1.0, 1.0).acos();
+    let phi = (-normal.z).atan2(normal.x) + PI;
+    [phi / (2.0 * PI), theta / PI]
+}
+
+/// Moller-Trumbore like `intersectTriangle()` in `mesh.glsl`, hits both faces.
+pub fn intersect_triangle(ray: &Ray, triangle: [Vec3; 3], t_min: f32, t_max: f32) -> Option<f32> {
+    let [v0, v1, v2] = triangle;
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let p = ray.direction.cross(edge2);
+    let det = edge1.dot(p);
+
+    // Parallel to the triangle.
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = ray.origin - v0;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(edge1);
+    let v = ray.direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(q) * inv_det;
+    (t_min..=t_max).contains(&t).then_some(t)
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Vec3>, indices: Vec<u32>) -> Self {
+        Self { positions, indices }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// The corners of triangle `index`.
+    pub fn triangle(&self, index: usize) -> [Vec3; 3] {
+        let corner = |i: usize| self.positions[self.indices[3 * index + i] as usize];
+        [corner(0), corner(1), corner(2)]
+    }
+
+    /// A cube from -1 to 1 on every axis.
+    pub fn cube() -> Self {
+        let positions = (0..8)
+            .map(|corner| {
+                let coord = |bit: u32| if corner & (1 << bit) != 0 { 1.0 } else { -1.0 };
+                Vec3::new(coord(0), coord(1), coord(2))
+            })
+            .collect();
+
+        // Two triangles per face, corners are numbered by their bits as xyz.
+        #[rustfmt::skip]
+        let indices = vec![
+            0, 2, 3, 0, 3, 1, // -z
+            4, 5, 7, 4, 7, 6, // +z
+            0, 4, 6, 0, 6, 2, // -x
+            1, 3, 7, 1, 7, 5, // +x
+            0, 1, 5, 0, 5, 4, // -y
+            2, 6, 7, 2, 7, 3, // +y
+        ];
+
+        Self { positions, indices }
+    }
+
+    /// Loads the vertex positions and faces of a Wavefront OBJ file. Every
+    /// object and group in the file ends up in the same mesh.
+    pub fn load_obj(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        Self::parse_obj(&source).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Like `load_obj`, from the file's contents.
+    pub fn parse_obj(source: &str) -> anyhow::Result<Self> {
+        let mut mesh = Self::default();
+
+        for (line_idx, line) in source.lines().enumerate() {
+            let mut tokens = line.split_whitespace();
+
+            let result = match tokens.next() {
+                Some("v") => parse_obj_vertex(tokens).map(|v| mesh.positions.push(v)),
+                Some("f") => parse_obj_face(tokens, mesh.positions.len())
+                    .map(|face| triangulate_face(&face, &mut mesh.indices)),
+                // Normals, texture coordinates, groups, materials etc.
+                _ => Ok(()),
+            };
+
+            result.with_context(|| format!("Line {}: {}", line_idx + 1, line))?;
+        }
+
+        Ok(mesh)
+    }
+}
+
+fn parse_obj_vertex<'a>(mut tokens: impl Iterator<Item = &'a str>) -> anyhow::Result<Vec3> {
+    let mut coord = || -> anyhow::Result<f32> {
+        Ok(tokens
+            .next()
+            .context("Expected three coordinates")?
+            .parse()?)
+    };
+
+    Ok(Vec3::new(coord()?, coord()?, coord()?))
+}
+
+/// Returns the zero-based vertex indices of a face. OBJ indices start at 1,
+/// negative ones count back from the last vertex.
+fn parse_obj_face<'a>(
+    tokens: impl Iterator<Item = &'a str>,
+    vertex_count: usize,
+) -> anyhow::Result<Vec<u32>> {
+    let face = tokens
+        .map(|token| {
+            // `v/vt/vn`, only the position is used.
+            let index: i64 = token.split('/').next().unwrap_or_default().parse()?;
+            let index = if index < 0 {
+                vertex_count as i64 + index
+            } else {
+                index - 1
+            };
+
+            ensure!(
+                (0..vertex_count as i64).contains(&index),
+                "Vertex {} doesn't exist",
+                token
+            );
+            Ok(index as u32)
+        })
+        .collect::<anyhow::Result<Vec<u32>>>()?;
+
+    ensure!(face.len() >= 3, "A face needs at least three vertices");
+    Ok(face)
+}
+
+/// Splits a convex polygon into a triangle fan.
+fn triangulate_face(face: &[u32], indices: &mut Vec<u32>) {
+    for i in 1..face.len() - 1 {
+        indices.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+    }
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        self.materials.push(material);
+        (self.materials.len() - 1) as MaterialId
+    }
+
+    pub fn add_image(&mut self, image: Image) -> ImageId {
+        self.images.push(image);
+        (self.images.len() - 1) as ImageId
+    }
+
+    pub fn add_sphere(&mut self, center: Vec3, radius: f32, material: MaterialId) {
+        self.spheres.push(Sphere {
+            center,
+            radius,
+            material,
+            end_center: None,
+        });
+    }
+
+    /// A sphere moving from `center` at time 0 to `end_center` at time 1.
+    pub fn add_moving_sphere(
+        &mut self,
+        center: Vec3,
+        end_center: Vec3,
+        radius: f32,
+        material: MaterialId,
+    ) {
+        self.spheres.push(Sphere {
+            center,
+            radius,
+            material,
+            end_center: Some(end_center),
+        });
+    }
+
+    /// Fills a sphere with `medium`.
+    pub fn add_volume(&mut self, center: Vec3, radius: f32, medium: Medium) {
+        self.volumes.push(Volume {
+            center,
+            radius,
+            medium,
+        });
+    }
+
+    pub fn add_mesh(&mut self, mesh: Mesh) -> MeshId {
+        self.meshes.push(mesh);
+        self.meshes.len() - 1
+    }
+
+    pub fn add_instance(&mut self, mesh: MeshId, transform: Affine3, material: MaterialId) {
+        self.instances.push(MeshInstance {
+            mesh,
+            transform,
+            material,
+        });
+    }
+
+    /// Every sphere, then every triangle of every instance.
+    pub fn primitives(&self) -> impl Iterator<Item = PrimitiveId> + '_ {
+        let spheres = (0..self.spheres.len() as u32).map(PrimitiveId::Sphere);
+        let triangles =
+            self.instances
+                .iter()
+                .enumerate()
+                .flat_map(move |(instance_idx, instance)| {
+                    (0..self.meshes[instance.mesh].triangle_count() as u32).map(move |triangle| {
+                        PrimitiveId::Triangle {
+                            instance: instance_idx as u32,
+                            triangle,
+                        }
+                    })
+                });
+
+        spheres.chain(triangles)
+    }
+
+    /// World space bounds.
+    pub fn primitive_bounds(&self, primitive: PrimitiveId) -> Aabb {
+        match primitive {
+            PrimitiveId::Sphere(sphere) => self.spheres[sphere as usize].bounds(),
+            PrimitiveId::Triangle { instance, triangle } => {
+                Aabb::from_points(self.world_triangle(instance, triangle))
+            }
+        }
+    }
+
+    /// The corners of a triangle of a mesh instance, in world space.
+    pub fn world_triangle(&self, instance: u32, triangle: u32) -> [Vec3; 3] {
+        let instance = &self.instances[instance as usize];
+        let corners = self.meshes[instance.mesh].triangle(triangle as usize);
+        corners.map(|p| instance.transform.transform_point(p))
+    }
+
+    /// Where `ray` hits `primitive` between `t_min` and `t_max`.
+    pub fn intersect_primitive(
+        &self,
+        primitive: PrimitiveId,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<f32> {
+        match primitive {
+            PrimitiveId::Sphere(sphere) => {
+                self.spheres[sphere as usize].intersect(ray, t_min, t_max)
+            }
+            PrimitiveId::Triangle { instance, triangle } => {
+                let instance = &self.instances[instance as usize];
+                let corners = self.meshes[instance.mesh].triangle(triangle as usize);
+
+                // Object space keeps t, the direction isn't renormalized.
+                let object_from_world = instance.transform.inverse();
+                let object_ray = Ray::new(
+                    object_from_world.transform_point(ray.origin),
+                    object_from_world.transform_vector(ray.direction),
+                );
+
+                intersect_triangle(&object_ray, corners, t_min, t_max)
+            }
+        }
+    }
+
+    /// The closest hit between `t_min` and `t_max`, testing every primitive.
+    /// Slow, meant as a reference for `Bvh::intersect`.
+    pub fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<SceneHit> {
+        let mut closest: Option<SceneHit> = None;
+
+        for primitive in self.primitives() {
+            let t_max = closest.map_or(t_max, |hit| hit.t);
+            if let Some(t) = self.intersect_primitive(primitive, ray, t_min, t_max) {
+                closest = Some(SceneHit { t, primitive });
+            }
+        }
+
+        closest
+    }
+
+    /// Checks that every index in the scene points at something.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let check_material = |material: MaterialId| {
+            ensure!(
+                (material as usize) < self.materials.len(),
+                "Material {} doesn't exist",
+                material
+            );
+            Ok(())
+        };
+
+        for material in &self.materials {
+            if let Material::Lambertian {
+                albedo: Texture::Image(image),
+            }
+            | Material::Metal {
+                albedo: Texture::Image(image),
+                ..
+            } = *material
+            {
+                ensure!(
+                    (image as usize) < self.images.len(),
+                    "Image {} doesn't exist",
+                    image
+                );
+            }
+        }
+
+        for sphere in &self.spheres {
+            check_material(sphere.material)?;
+        }
+
+        for (mesh_idx, mesh) in self.meshes.iter().enumerate() {
+            ensure!(
+                mesh.indices.len() % 3 == 0,
+                "Mesh {} has an incomplete triangle",
+                mesh_idx
+            );
+            ensure!(
+                mesh.indices
+                    .iter()
+                    .all(|&index| (index as usize) < mesh.positions.len()),
+                "Mesh {} indexes vertices that don't exist",
+                mesh_idx
+            );
+        }
+
+        for instance in &self.instances {
+            check_material(instance.material)?;
+            ensure!(
+                instance.mesh < self.meshes.len(),
+                "Mesh {} doesn't exist",
+                instance.mesh
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Where a mesh's data starts in `SceneBuffers::vertices` and `SceneBuffers::indices`.
+#[derive(Clone, Copy, Debug)]
+pub struct MeshRange {
+    pub base_vertex: u32,
+    pub vertex_count: u32,
+    pub first_index: u32,
+    pub triangle_count: u32,
+}
+
+/// A `Scene` uploaded for the shaders. The renderer binds the buffers to the
+/// bindless set, see `assets/shaders/include/scene.glsl`, `mesh.glsl`, `bvh.glsl`,
+/// `light.glsl`, `environment.glsl`, `texture.glsl` and `medium.glsl`.
+pub struct SceneBuffers {
+    /// The vertices of every mesh, one after the other.
+    pub vertices: Buffer,
+    /// The indices of every mesh, relative to the mesh's first vertex.
+    pub indices: Buffer,
+    pub spheres: Buffer,
+    pub materials: Buffer,
+    pub mesh_instances: Buffer,
+    pub bvh_nodes: Buffer,
+    pub bvh_primitives: Buffer,
+    /// The light count followed by the `LightList` lights.
+    pub lights: Buffer,
+    /// The environment's size followed by its pixels, zero sized without one.
+    pub environment: Buffer,
+    /// The `EnvironmentDistribution` CDFs, marginal first.
+    pub environment_cdf: Buffer,
+    /// Where each image's texels start.
+    pub images: Buffer,
+    /// The pixels of every image, one after the other.
+    pub texels: Buffer,
+    /// The volume count and the fog followed by the volumes.
+    pub media: Buffer,
+    pub mesh_ranges: Vec<MeshRange>,
+    pub num_spheres: u32,
+    pub num_mesh_instances: u32,
+    /// Primitives under the BVH root, zero for an empty scene.
+    pub num_bvh_primitives: u32,
+    pub num_lights: u32,
+    pub num_volumes: u32,
+}
+
+impl SceneBuffers {
+    pub fn new(device: &Device, scene: &Scene) -> anyhow::Result<Self> {
+        scene.validate()?;
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut mesh_ranges = Vec::with_capacity(scene.meshes.len());
+
+        for mesh in &scene.meshes {
+            mesh_ranges.push(MeshRange {
+                base_vertex: vertices.len() as u32,
+                vertex_count: mesh.positions.len() as u32,
+                first_index: indices.len() as u32,
+                triangle_count: mesh.triangle_count() as u32,
+            });
+
+            vertices.extend(mesh.positions.iter().map(|p| Vertex {
+                position: [p.x, p.y, p.z, 1.0],
+            }));
+            indices.extend_from_slice(&mesh.indices);
+        }
+
+        let light_list = LightList::new(scene);
+
+        let spheres: Vec<GpuSphere> = scene
+            .spheres
+            .iter()
+            .zip(&light_list.sphere_lights)
+            .map(|(sphere, light)| GpuSphere {
+                position: sphere.center.into(),
+                radius: sphere.radius,
+                end_position: sphere.end_center.unwrap_or(sphere.center).into(),
+                material: sphere.material,
+                light: light.unwrap_or(NO_LIGHT),
+                ..Default::default()
+            })
+            .collect();
+
+        let materials: Vec<GpuMaterial> = scene.materials.iter().map(Material::gpu_data).collect();
+
+        let mesh_instances: Vec<GpuMeshInstance> = scene
+            .instances
+            .iter()
+            .zip(&light_list.instance_lights)
+            .map(|(instance, first_light)| {
+                let range = mesh_ranges[instance.mesh];
+                GpuMeshInstance {
+                    world_from_object: instance.transform.to_rows(),
+                    object_from_world: instance.transform.inverse().to_rows(),
+                    first_index: range.first_index,
+                    triangle_count: range.triangle_count,
+                    base_vertex: range.base_vertex,
+                    material: instance.material,
+                    first_light: first_light.unwrap_or(NO_LIGHT),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        let bvh = Bvh::build(scene);
+
+        // Like the lights, the size goes in front of the pixels.
+        let mut environment = Vec::new();
+        let mut environment_cdf = Vec::new();
+        match &scene.environment {
+            Some(env) => {
+                environment.extend_from_slice(bytemuck::bytes_of(&[env.width, env.height, 0, 0]));
+                for p in &env.pixels {
+                    environment.extend_from_slice(bytemuck::bytes_of(&[p.x, p.y, p.z, 0.0]));
+                }
+
+                let distribution = EnvironmentDistribution::new(env);
+                environment_cdf.extend_from_slice(&distribution.marginal_cdf);
+                environment_cdf.extend_from_slice(&distribution.conditional_cdf);
+            }
+            None => environment.extend_from_slice(bytemuck::bytes_of(&[0u32; 4])),
+        }
+
+        let mut images = Vec::with_capacity(scene.images.len());
+        let mut texels = Vec::new();
+        for image in &scene.images {
+            images.push(GpuImage {
+                width: image.width,
+                height: image.height,
+                first_texel: texels.len() as u32,
+                ..Default::default()
+            });
+            texels.extend(image.pixels.iter().map(|p| [p.x, p.y, p.z, 0.0]));
+        }
+
+        // Without fog the shaders find a medium of zero density.
+        let (fog, fog_top) = match scene.fog {
+            Some(fog) => (fog.medium.gpu_data(), fog.top),
+            None => (GpuMedium::default(), 0.0),
+        };
+        let mut media =
+            bytemuck::bytes_of(&[scene.volumes.len() as u32, fog_top.to_bits(), 0, 0]).to_vec();
+        media.extend_from_slice(bytemuck::bytes_of(&fog));
+        for volume in &scene.volumes {
+            media.extend_from_slice(bytemuck::bytes_of(&volume.gpu_data()));
+        }
+
+        // Shaders read the count from the front of the buffer, padded to the
+        // alignment of the lights.
+        let mut lights = bytemuck::bytes_of(&[light_list.lights.len() as u32, 0, 0, 0]).to_vec();
+        for light in &light_list.lights {
+            lights.extend_from_slice(bytemuck::bytes_of(&light.gpu_data()));
+        }
+
+        // Acceleration structures are built straight from the mesh buffers.
+        let geometry_usage = if device.acceleration_structure_enabled {
+            vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+        } else {
+            vk::BufferUsageFlags::empty()
+        };
+
+        Ok(Self {
+            vertices: create_storage_buffer(device, &vertices, geometry_usage, "vertex buffer"),
+            indices: create_storage_buffer(device, &indices, geometry_usage, "index buffer"),
+            spheres: create_storage_buffer(
+                device,
+                &spheres,
+                vk::BufferUsageFlags::empty(),
+                "sphere buffer",
+            ),
+            materials: create_storage_buffer(
+                device,
+                &materials,
+                vk::BufferUsageFlags::empty(),
+                "material buffer",
+            ),
+            mesh_instances: create_storage_buffer(
+                device,
+                &mesh_instances,
+                vk::BufferUsageFlags::empty(),
+                "mesh instance buffer",
+            ),
+            bvh_nodes: create_storage_buffer(
+                device,
+                &bvh.nodes,
+                vk::BufferUsageFlags::empty(),
+                "bvh node buffer",
+            ),
+            bvh_primitives: create_storage_buffer(
+                device,
+                &bvh.primitives,
+                vk::BufferUsageFlags::empty(),
+                "bvh primitive buffer",
+            ),
+            lights: device.create_buffer(
+                BufferDesc {
+                    size: lights.len(),
+                    usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+                    memory_location: MemoryLocation::GpuOnly,
+                },
+                "light buffer",
+                Some(&lights),
+            ),
+            environment: device.create_buffer(
+                BufferDesc {
+                    size: environment.len(),
+                    usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+                    memory_location: MemoryLocation::GpuOnly,
+                },
+                "environment buffer",
+                Some(&environment),
+            ),
+            environment_cdf: create_storage_buffer(
+                device,
+                &environment_cdf,
+                vk::BufferUsageFlags::empty(),
+                "environment cdf buffer",
+            ),
+            images: create_storage_buffer(
+                device,
+                &images,
+                vk::BufferUsageFlags::empty(),
+                "image buffer",
+            ),
+            texels: create_storage_buffer(
+                device,
+                &texels,
+                vk::BufferUsageFlags::empty(),
+                "texel buffer",
+            ),
+            media: device.create_buffer(
+                BufferDesc {
+                    size: media.len(),
+                    usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+                    memory_location: MemoryLocation::GpuOnly,
+                },
+                "medium buffer",
+                Some(&media),
+            ),
+            mesh_ranges,
+            num_spheres: spheres.len() as u32,
+            num_mesh_instances: mesh_instances.len() as u32,
+            num_bvh_primitives: bvh.primitives.len() as u32,
+            num_lights: light_list.lights.len() as u32,
+            num_volumes: scene.volumes.len() as u32,
+        })
+    }
+}
+
+fn create_storage_buffer<T: Pod>(
+    device: &Device,
+    data: &[T],
+    usage: vk::BufferUsageFlags,
+    name: &str,
+) -> Buffer {
+    // Vulkan doesn't allow empty buffers. The shaders never read past the
+    // counts, so a single zeroed element stands in.
+    let placeholder = [T::zeroed()];
+    let data = if data.is_empty() {
+        &placeholder[..]
+    } else {
+        data
+    };
+
+    device.create_buffer(
+        BufferDesc {
+            size: std::mem::size_of_val(data),
+            usage: vk::BufferUsageFlags::STORAGE_BUFFER | usage,
+            memory_location: MemoryLocation::GpuOnly,
+        },
+        name,
+        Some(bytemuck::cast_slice(data)),
+    )
+}
@@ -0,0 +1,46 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use super::glsl;
+
+include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
+
+/// Directory holding the GLSL sources: `STRALE_SHADER_DIR` if set, otherwise
+/// `assets/shaders` in the source tree the crate was built from. Only hot reload
+/// reads it, the pipelines start from the SPIR-V embedded at build time.
+pub fn shader_dir() -> PathBuf {
+    match std::env::var_os("STRALE_SHADER_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("../../../assets/shaders"),
+    }
+}
+
+pub fn shader_path(name: &str) -> PathBuf {
+    shader_dir().join(name)
+}
+
+/// SPIR-V the build script compiled from `assets/shaders/<name>`.
+pub fn embedded_shader(name: &str) -> anyhow::Result<&'static [u8]> {
+    EMBEDDED_SHADERS
+        .iter()
+        .find(|(shader_name, _)| *shader_name == name)
+        .map(|(_, spirv)| *spirv)
+        .with_context(|| format!("No embedded shader named {}", name))
+}
+
+/// Compiles a GLSL file under `assets/shaders`, or a variant of one, to SPIR-V
+/// at runtime. Compiler errors keep glslang's `file:line: error: ...` format.
+pub fn compile_shader(name: &str) -> anyhow::Result<Vec<u8>> {
+    let compiler = shaderc::Compiler::new().context("Failed to create shader compiler")?;
+
+    let (source, defines) = glsl::variant_source(name);
+    let artifact = glsl::compile(&compiler, &shader_path(source), &shader_dir(), defines)
+        .map_err(anyhow::Error::msg)?;
+
+    if artifact.get_num_warnings() > 0 {
+        log::warn!("{}", artifact.get_warning_messages());
+    }
+
+    Ok(artifact.as_binary_u8().to_vec())
+}
@@ -0,0 +1,59 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::mpsc::{channel, Receiver},
+};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Watches `assets/shaders` for modified GLSL sources.
+pub struct ShaderWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: &Path) -> anyhow::Result<Self> {
+        let (tx, events) = channel();
+
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+
+        log::info!("Watching {} for shader changes", dir.display());
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// File names of the sources changed since the last call. Editors often write
+    /// a file several times per save, so changes are deduplicated.
+    pub fn changed_files(&self) -> HashSet<String> {
+        let mut changed = HashSet::new();
+
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    log::warn!("Shader watcher error: {}", err);
+                    continue;
+                }
+            };
+
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+
+            changed.extend(
+                event
+                    .paths
+                    .iter()
+                    .filter_map(|path| path.file_name()?.to_str())
+                    .map(str::to_owned),
+            );
+        }
+
+        changed
+    }
+}
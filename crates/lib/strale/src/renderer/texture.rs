@@ -0,0 +1,318 @@
+use std::path::Path;
+
+use anyhow::{ensure, Context};
+use bytemuck::{Pod, Zeroable};
+use image::DynamicImage;
+
+use crate::math::Vec3;
+
+use super::{material::GpuMaterial, sampler::pcg_hash};
+
+// The patterns are ported line by line from `assets/shaders/include/texture.glsl`
+// so they can be checked on the CPU. Change both together.
+
+/// Index into `Scene::images`.
+pub type ImageId = u32;
+
+/// Where the albedo of a material comes from. Images are wrapped around the
+/// surface by its UV coordinates, the procedural patterns are solid textures
+/// evaluated at the world space hit point.
+///
+/// Images are not bound as sampled images. Their texels are packed into one
+/// storage buffer next to the rest of the scene in the bindless set, and
+/// `texture.glsl` filters them by hand. That keeps the set a fixed list of
+/// buffers that every tracer already binds, uploads images like any other
+/// scene buffer, and lets `Image::sample` reproduce the shader exactly for
+/// tests. The price is no mipmaps and 16 bytes per texel, which is fine for
+/// lookdev textures but worth revisiting for large scenes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Texture {
+    Solid(Vec3),
+    Image(ImageId),
+    /// Cubes of `scale` per unit alternating between two colors.
+    Checker {
+        even: Vec3,
+        odd: Vec3,
+        scale: f32,
+    },
+    /// Perlin noise of `scale` cells per unit, from black to `color`.
+    Noise {
+        color: Vec3,
+        scale: f32,
+    },
+    /// `octaves` of Perlin noise, each twice as fine and half as strong.
+    Turbulence {
+        color: Vec3,
+        scale: f32,
+        octaves: u32,
+    },
+    /// Sine waves of `color` along Z, `scale` radians per unit, distorted by
+    /// turbulence.
+    Marble {
+        color: Vec3,
+        scale: f32,
+        octaves: u32,
+    },
+}
+
+/// Linear RGB pixels, row by row from the top.
+#[derive(Clone, Debug, Default)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Vec3>,
+}
+
+/// Where an `Image` is in `SceneBuffers::texels`, as laid out in `texture.glsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct GpuImage {
+    pub width: u32,
+    pub height: u32,
+    pub first_texel: u32,
+    pub _pad: u32,
+}
+
+impl From<Vec3> for Texture {
+    fn from(color: Vec3) -> Self {
+        Texture::Solid(color)
+    }
+}
+
+impl Texture {
+    // Must match the TEXTURE_* constants in texture.glsl.
+    const SOLID: u32 = 0;
+    const IMAGE: u32 = 1;
+    const CHECKER: u32 = 2;
+    const NOISE: u32 = 3;
+    const TURBULENCE: u32 = 4;
+    const MARBLE: u32 = 5;
+
+    /// The albedo at `uv` on the surface, at `point` in world space, like
+    /// `materialAlbedo()` in `material.glsl`.
+    pub fn evaluate(&self, images: &[Image], uv: [f32; 2], point: Vec3) -> Vec3 {
+        match *self {
+            Texture::Solid(color) => color,
+            Texture::Image(image) => images[image as usize].sample(uv),
+            Texture::Checker { even, odd, scale } => {
+                let p = point * scale;
+                let sum = p.x.floor() + p.y.floor() + p.z.floor();
+                if sum.rem_euclid(2.0) < 1.0 {
+                    even
+                } else {
+                    odd
+                }
+            }
+            Texture::Noise { color, scale } => color * (0.5 * (1.0 + perlin_noise(point * scale))),
+            Texture::Turbulence {
+                color,
+                scale,
+                octaves,
+            } => color * turbulence(point * scale, octaves).min(1.0),
+            Texture::Marble {
+                color,
+                scale,
+                octaves,
+            } => {
+                color * (0.5 * (1.0 + (scale * point.z + 10.0 * turbulence(point, octaves)).sin()))
+            }
+        }
+    }
+
+    /// The albedo and texture fields of a `GpuMaterial`.
+    pub fn gpu_data(&self) -> GpuMaterial {
+        match *self {
+            Texture::Solid(color) => GpuMaterial {
+                albedo: color.into(),
+                texture: Self::SOLID,
+                ..Default::default()
+            },
+            Texture::Image(image) => GpuMaterial {
+                albedo: [1.0; 3],
+                texture: Self::IMAGE,
+                image,
+                ..Default::default()
+            },
+            Texture::Checker { even, odd, scale } => GpuMaterial {
+                albedo: even.into(),
+                odd_albedo: odd.into(),
+                texture: Self::CHECKER,
+                texture_scale: scale,
+                ..Default::default()
+            },
+            Texture::Noise { color, scale } => GpuMaterial {
+                albedo: color.into(),
+                texture: Self::NOISE,
+                texture_scale: scale,
+                ..Default::default()
+            },
+            Texture::Turbulence {
+                color,
+                scale,
+                octaves,
+            } => GpuMaterial {
+                albedo: color.into(),
+                texture: Self::TURBULENCE,
+                texture_scale: scale,
+                octaves,
+                ..Default::default()
+            },
+            Texture::Marble {
+                color,
+                scale,
+                octaves,
+            } => GpuMaterial {
+                albedo: color.into(),
+                texture: Self::MARBLE,
+                texture_scale: scale,
+                octaves,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, pixels: Vec<Vec3>) -> anyhow::Result<Self> {
+        ensure!(width > 0 && height > 0, "The image is empty");
+        ensure!(
+            pixels.len() == (width * height) as usize,
+            "Expected {} pixels for a {}x{} image, got {}",
+            width * height,
+            width,
+            height,
+            pixels.len()
+        );
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Loads a PNG, JPEG or Radiance `.hdr` file. 8 bit images are taken to be
+    /// sRGB encoded and converted to linear.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let image = image::open(path)
+            .with_context(|| format!("Failed to load image {}", path.display()))?;
+
+        let (width, height, pixels) = match image {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                let image = image.into_rgb32f();
+                let pixels = image.pixels().map(|p| Vec3::from(p.0)).collect();
+                (image.width(), image.height(), pixels)
+            }
+            _ => {
+                let image = image.into_rgb8();
+                let pixels = image
+                    .pixels()
+                    .map(|p| Vec3::from(p.0.map(|c| srgb_to_linear(c as f32 / 255.0))))
+                    .collect();
+                (image.width(), image.height(), pixels)
+            }
+        };
+
+        Self::new(width, height, pixels)
+    }
+
+    /// Bilinearly filtered, repeating outside [0, 1]². V runs up from the
+    /// bottom row, like `sampleImage()` in `texture.glsl`.
+    pub fn sample(&self, uv: [f32; 2]) -> Vec3 {
+        let x = uv[0] * self.width as f32 - 0.5;
+        let y = (1.0 - uv[1]) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let texel = |dx: i64, dy: i64| {
+            let tx = (x0 as i64 + dx).rem_euclid(self.width as i64);
+            let ty = (y0 as i64 + dy).rem_euclid(self.height as i64);
+            self.pixels[(ty * self.width as i64 + tx) as usize]
+        };
+
+        let top = texel(0, 0) * (1.0 - fx) + texel(1, 0) * fx;
+        let bottom = texel(0, 1) * (1.0 - fx) + texel(1, 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// The edges of a cube, Perlin's "Improving Noise" gradients.
+const GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+/// The gradient at a lattice point, hashed instead of looked up in a
+/// permutation table so the shaders don't need one.
+fn gradient(cell: [i32; 3]) -> Vec3 {
+    let hash = pcg_hash((cell[0] as u32).wrapping_add(pcg_hash(
+        (cell[1] as u32).wrapping_add(pcg_hash(cell[2] as u32)),
+    )));
+    Vec3::from(GRADIENTS[(hash % 12) as usize])
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Gradient noise in roughly [-1, 1], zero at every integer point.
+pub fn perlin_noise(p: Vec3) -> f32 {
+    let cell = [p.x.floor(), p.y.floor(), p.z.floor()];
+    let f = p - Vec3::from(cell);
+    let cell = cell.map(|c| c as i32);
+
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let offset = Vec3::new(dx as f32, dy as f32, dz as f32);
+        gradient([cell[0] + dx, cell[1] + dy, cell[2] + dz]).dot(f - offset)
+    };
+
+    let (u, v, w) = (fade(f.x), fade(f.y), fade(f.z));
+    lerp(
+        lerp(
+            lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+            lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+            v,
+        ),
+        lerp(
+            lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+            lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+            v,
+        ),
+        w,
+    )
+}
+
+/// The sum of `octaves` of absolute noise, at least one.
+pub fn turbulence(p: Vec3, octaves: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut p = p;
+    let mut weight = 1.0;
+    for _ in 0..octaves.max(1) {
+        sum += weight * perlin_noise(p).abs();
+        weight *= 0.5;
+        p = p * 2.0;
+    }
+    sum
+}
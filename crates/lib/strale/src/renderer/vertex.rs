@@ -8,9 +8,32 @@ pub struct Vertex {
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
-pub struct Sphere {
+pub struct GpuSphere {
     pub position: [f32; 3],
     pub radius: f32,
-    pub albedo: [f32; 3],
-    pub material: f32,
+    /// The position at time 1, the same as `position` unless the sphere moves.
+    pub end_position: [f32; 3],
+    /// Index into the material buffer.
+    pub material: u32,
+    /// Index into the light buffer, `light::NO_LIGHT` unless the sphere emits.
+    pub light: u32,
+    pub _pad: [u32; 3],
+}
+
+/// `MeshInstance` as laid out in `assets/shaders/include/mesh.glsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct GpuMeshInstance {
+    /// Row-major 3x4.
+    pub world_from_object: [[f32; 4]; 3],
+    pub object_from_world: [[f32; 4]; 3],
+    /// Where the mesh's triangles start in the index buffer.
+    pub first_index: u32,
+    pub triangle_count: u32,
+    /// Added to the mesh's indices to index the vertex buffer.
+    pub base_vertex: u32,
+    pub material: u32,
+    /// The light of the instance's first triangle, `light::NO_LIGHT` unless it emits.
+    pub first_light: u32,
+    pub _pad: [u32; 3],
 }
@@ -61,7 +61,9 @@ impl Backend {
                     height: window.inner_size().height,
                     width: window.inner_size().width,
                 },
+                format: vk::Format::B8G8R8A8_UNORM,
             },
+            None,
         )?;
 
         Ok(Self {
@@ -70,4 +72,27 @@ impl Backend {
             swapchain,
         })
     }
+
+    /// Recreates the swapchain at the window's new size, call it on
+    /// `WindowEvent::Resized`. The renderer adapts in its next `draw`.
+    pub fn resize(&mut self, window: &Window) -> anyhow::Result<()> {
+        // The old swapchain's images may still be in flight.
+        unsafe { self.device.raw.device_wait_idle() }?;
+
+        let swapchain = Swapchain::new(
+            &self.device,
+            &self.surface,
+            SwapchainDesc {
+                dims: vk::Extent2D {
+                    height: window.inner_size().height,
+                    width: window.inner_size().width,
+                },
+                ..self.swapchain.desc
+            },
+            Some(&self.swapchain),
+        )?;
+        self.swapchain = swapchain;
+
+        Ok(())
+    }
 }
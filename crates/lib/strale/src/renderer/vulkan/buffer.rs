@@ -6,7 +6,7 @@ use gpu_allocator::{
 
 use super::device::Device;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct BufferDesc {
     pub size: usize,
     pub usage: vk::BufferUsageFlags,
@@ -83,64 +83,102 @@ impl Device {
         );
 
         if let Some(initial_data) = initial_data {
-            let empty_desc = BufferDesc {
-                size: desc.size,
-                usage: vk::BufferUsageFlags::TRANSFER_SRC,
-                memory_location: MemoryLocation::CpuToGpu,
-            };
-
-            let mut empty_buffer = Self::internal_create_buffer(
-                self,
-                empty_desc,
-                &mut self.global_allocator.lock().unwrap(),
-                "empty buffer",
+            self.upload_buffer(&buffer, initial_data);
+        }
+
+        buffer
+    }
+
+    /// Copies `data` to the start of `buffer` through a staging buffer.
+    pub fn upload_buffer(&self, buffer: &Buffer, data: &[u8]) {
+        let empty_desc = BufferDesc {
+            size: data.len(),
+            usage: vk::BufferUsageFlags::TRANSFER_SRC,
+            memory_location: MemoryLocation::CpuToGpu,
+        };
+
+        let mut empty_buffer = Self::internal_create_buffer(
+            self,
+            empty_desc,
+            &mut self.global_allocator.lock().unwrap(),
+            "empty buffer",
+        );
+
+        empty_buffer
+            .allocation
+            .mapped_slice_mut()
+            .expect("memory not host visible")[0..data.len()]
+            .copy_from_slice(data);
+
+        self.with_setup_cb(|cb| unsafe {
+            self.raw.cmd_copy_buffer(
+                cb,
+                empty_buffer.raw,
+                buffer.raw,
+                &[vk::BufferCopy {
+                    src_offset: 0,
+                    dst_offset: 0,
+                    size: data.len() as u64,
+                }],
             );
+        });
+
+        self.destroy_buffer(empty_buffer);
+    }
 
-            empty_buffer
-                .allocation
-                .mapped_slice_mut()
-                .expect("memory not host visible")[0..initial_data.len()]
-                .copy_from_slice(initial_data);
+    pub fn destroy_buffer(&self, buffer: Buffer) {
+        unsafe {
+            self.raw.destroy_buffer(buffer.raw, None);
+        }
 
-            let cb = self.setup_cb.lock().unwrap();
+        self.global_allocator
+            .lock()
+            .unwrap()
+            .free(buffer.allocation)
+            .expect("Failed to free buffer memory");
+    }
 
-            unsafe {
-                self.raw
-                    .begin_command_buffer(
-                        cb.raw,
-                        &vk::CommandBufferBeginInfo::builder()
-                            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
-                    )
-                    .unwrap();
+    /// Records `callback` into the setup command buffer, submits it and waits for it to finish.
+    pub fn with_setup_cb(&self, callback: impl FnOnce(vk::CommandBuffer)) {
+        let cb = self.setup_cb.lock().unwrap();
 
-                self.raw.cmd_copy_buffer(
+        unsafe {
+            self.raw
+                .begin_command_buffer(
                     cb.raw,
-                    empty_buffer.raw,
-                    buffer.raw,
-                    &[vk::BufferCopy {
-                        src_offset: 0,
-                        dst_offset: 0,
-                        size: desc.size as u64,
-                    }],
-                );
-
-                self.raw.end_command_buffer(cb.raw).unwrap();
-
-                let submit_info =
-                    vk::SubmitInfo::builder().command_buffers(std::slice::from_ref(&cb.raw));
-
-                self.raw
-                    .queue_submit(
-                        self.universal_queue.raw,
-                        &[submit_info.build()],
-                        vk::Fence::null(),
-                    )
-                    .expect("queue submit failed.");
-
-                self.raw.device_wait_idle().unwrap();
-            };
+                    &vk::CommandBufferBeginInfo::builder()
+                        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+                )
+                .unwrap();
         }
 
-        buffer
+        callback(cb.raw);
+
+        unsafe {
+            self.raw.end_command_buffer(cb.raw).unwrap();
+
+            let submit_info =
+                vk::SubmitInfo::builder().command_buffers(std::slice::from_ref(&cb.raw));
+
+            self.raw
+                .queue_submit(
+                    self.universal_queue.raw,
+                    &[submit_info.build()],
+                    vk::Fence::null(),
+                )
+                .expect("queue submit failed.");
+
+            self.raw.device_wait_idle().unwrap();
+        }
+    }
+}
+
+impl Buffer {
+    pub fn device_address(&self, device: &Device) -> vk::DeviceAddress {
+        unsafe {
+            device
+                .raw
+                .get_buffer_device_address(&vk::BufferDeviceAddressInfo::builder().buffer(self.raw))
+        }
     }
 }
@@ -17,8 +17,11 @@ use gpu_allocator::{
 use log::debug;
 
 use super::{
+    buffer::Buffer,
+    image::Image,
     instance::Instance,
     physical_device::{PhysicalDevice, QueueFamily},
+    ray_tracing::ShaderBindingTable,
 };
 
 pub struct Queue {
@@ -87,6 +90,96 @@ impl CommandBuffer {
             submit_done_fence,
         })
     }
+
+    pub fn dispatch(&self, device: &Device, group_count: [u32; 3]) {
+        unsafe {
+            device
+                .raw
+                .cmd_dispatch(self.raw, group_count[0], group_count[1], group_count[2]);
+        }
+    }
+
+    /// Dispatches enough workgroups of `group_size` to cover `threads`.
+    pub fn dispatch_threads(&self, device: &Device, threads: [u32; 3], group_size: [u32; 3]) {
+        self.dispatch(
+            device,
+            [
+                threads[0].div_ceil(group_size[0]),
+                threads[1].div_ceil(group_size[1]),
+                threads[2].div_ceil(group_size[2]),
+            ],
+        );
+    }
+
+    /// `buffer` holds a `vk::DispatchIndirectCommand` at `offset` and needs
+    /// `INDIRECT_BUFFER` usage.
+    pub fn dispatch_indirect(&self, device: &Device, buffer: &Buffer, offset: u64) {
+        unsafe {
+            device
+                .raw
+                .cmd_dispatch_indirect(self.raw, buffer.raw, offset);
+        }
+    }
+
+    /// Launches one ray generation invocation per element of `dims`.
+    pub fn trace_rays(&self, device: &Device, sbt: &ShaderBindingTable, dims: [u32; 3]) {
+        unsafe {
+            device.ray_tracing_pipeline_ext.cmd_trace_rays(
+                self.raw,
+                &sbt.raygen_region,
+                &sbt.miss_region,
+                &sbt.hit_region,
+                &sbt.callable_region,
+                dims[0],
+                dims[1],
+                dims[2],
+            );
+        }
+    }
+
+    /// Scales all of `src` onto all of `dst`, which need to be in the transfer
+    /// source and destination layouts.
+    pub fn blit_image(&self, device: &Device, src: &Image, dst: &Image) {
+        let src_extent = src.desc.extent;
+        let dst_extent = dst.desc.extent;
+        let subresource = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        unsafe {
+            device.raw.cmd_blit_image(
+                self.raw,
+                src.raw,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst.raw,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[vk::ImageBlit {
+                    src_subresource: subresource,
+                    src_offsets: [
+                        vk::Offset3D::default(),
+                        vk::Offset3D {
+                            x: src_extent.width as i32,
+                            y: src_extent.height as i32,
+                            z: 1,
+                        },
+                    ],
+                    dst_subresource: subresource,
+                    dst_offsets: [
+                        vk::Offset3D::default(),
+                        vk::Offset3D {
+                            x: dst_extent.width as i32,
+                            y: dst_extent.height as i32,
+                            z: 1,
+                        },
+                    ],
+                }],
+                vk::Filter::LINEAR,
+            );
+        }
+    }
 }
 
 pub struct Device {
@@ -99,6 +192,16 @@ pub struct Device {
     pub acceleration_structure_ext: khr::AccelerationStructure,
     pub ray_tracing_pipeline_ext: khr::RayTracingPipeline,
     pub ray_tracing_pipeline_properties: vk::PhysicalDeviceRayTracingPipelinePropertiesKHR,
+    /// `VK_KHR_acceleration_structure` is enabled, so the scene's acceleration
+    /// structure can be built for ray tracing pipelines and ray queries.
+    pub acceleration_structure_enabled: bool,
+    pub ray_tracing_enabled: bool,
+    /// `VK_KHR_ray_query` is enabled, so any shader stage can trace against
+    /// the acceleration structure in the bindless set.
+    pub ray_query_enabled: bool,
+    /// `VK_NV_ray_tracing_motion_blur` is enabled, so ray tracing pipelines can
+    /// trace rays at a point in time.
+    pub ray_tracing_motion_blur_enabled: bool,
     frames: [Mutex<Arc<DeviceFrame>>; 2],
     pub first_frame: Instant,
 }
@@ -133,17 +236,24 @@ impl Device {
             vk::KhrUniformBufferStandardLayoutFn::name().as_ptr(),
         ];
 
-        let ray_tracing_extensions = [
+        let acceleration_structure_extensions = [
             vk::KhrVulkanMemoryModelFn::name().as_ptr(), // used in ray tracing shaders
-            vk::KhrPipelineLibraryFn::name().as_ptr(),   // rt dep
-            vk::KhrDeferredHostOperationsFn::name().as_ptr(), // rt dep
-            vk::KhrBufferDeviceAddressFn::name().as_ptr(), // rt dep
+            vk::KhrDeferredHostOperationsFn::name().as_ptr(), // as dep
+            vk::KhrBufferDeviceAddressFn::name().as_ptr(), // as dep
             vk::KhrAccelerationStructureFn::name().as_ptr(),
+        ];
+
+        let ray_tracing_extensions = [
+            vk::KhrPipelineLibraryFn::name().as_ptr(), // rt dep
             vk::KhrRayTracingPipelineFn::name().as_ptr(),
         ];
 
-        let ray_tracing_enabled = unsafe {
-            ray_tracing_extensions.iter().all(|ext| {
+        let ray_query_extensions = [vk::KhrRayQueryFn::name().as_ptr()];
+
+        let motion_blur_extensions = [vk::NvRayTracingMotionBlurFn::name().as_ptr()];
+
+        let all_supported = |extensions: &[*const c_char]| unsafe {
+            extensions.iter().all(|ext| {
                 let ext = std::ffi::CStr::from_ptr(*ext).to_string_lossy();
 
                 let supported = supported_extensions.contains(ext.as_ref());
@@ -156,12 +266,42 @@ impl Device {
             })
         };
 
+        let acceleration_structure_enabled = all_supported(&acceleration_structure_extensions);
+
+        if acceleration_structure_enabled {
+            log::info!("Acceleration structure extensions are supported");
+
+            device_extension_names.extend(acceleration_structure_extensions.iter());
+        }
+
+        // Ray tracing pipelines and ray queries each trace against the same
+        // acceleration structures, but don't depend on each other.
+        let ray_tracing_enabled =
+            acceleration_structure_enabled && all_supported(&ray_tracing_extensions);
+
         if ray_tracing_enabled {
             log::info!("All ray tracing extensions are supported");
 
             device_extension_names.extend(ray_tracing_extensions.iter());
         }
 
+        let ray_query_enabled =
+            acceleration_structure_enabled && all_supported(&ray_query_extensions);
+
+        if ray_query_enabled {
+            log::info!("Ray query extension is supported");
+
+            device_extension_names.extend(ray_query_extensions.iter());
+        }
+
+        let motion_blur_supported = ray_tracing_enabled && all_supported(&motion_blur_extensions);
+
+        if motion_blur_supported {
+            log::info!("Ray tracing motion blur extension is supported");
+
+            device_extension_names.extend(motion_blur_extensions.iter());
+        }
+
         unsafe {
             for &ext in &device_extension_names {
                 let ext = std::ffi::CStr::from_ptr(ext).to_string_lossy();
@@ -197,12 +337,17 @@ impl Device {
         let mut vulkan_memory_model = vk::PhysicalDeviceVulkanMemoryModelFeaturesKHR::default();
         let mut get_buffer_device_address_features =
             ash::vk::PhysicalDeviceBufferDeviceAddressFeatures::default();
-        let _acceleration_structure_features =
+        let mut acceleration_structure_features =
             ash::vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default();
 
-        let _ray_tracing_pipeline_features =
+        let mut ray_tracing_pipeline_features =
             ash::vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default();
 
+        let mut ray_query_features = ash::vk::PhysicalDeviceRayQueryFeaturesKHR::default();
+
+        let mut motion_blur_features =
+            ash::vk::PhysicalDeviceRayTracingMotionBlurFeaturesNV::default();
+
         let mut features13 = vk::PhysicalDeviceVulkan13Features::builder()
             .dynamic_rendering(true)
             .build();
@@ -212,8 +357,26 @@ impl Device {
             .push_next(&mut imageless_framebuffer)
             .push_next(&mut shader_float16_int8)
             .push_next(&mut vulkan_memory_model)
-            .push_next(&mut get_buffer_device_address_features)
-            .build();
+            .push_next(&mut get_buffer_device_address_features);
+
+        // Feature structs of extensions that aren't enabled must stay out of the chain.
+        if acceleration_structure_enabled {
+            features2 = features2.push_next(&mut acceleration_structure_features);
+        }
+
+        if ray_tracing_enabled {
+            features2 = features2.push_next(&mut ray_tracing_pipeline_features);
+        }
+
+        if ray_query_enabled {
+            features2 = features2.push_next(&mut ray_query_features);
+        }
+
+        if motion_blur_supported {
+            features2 = features2.push_next(&mut motion_blur_features);
+        }
+
+        let mut features2 = features2.build();
 
         unsafe {
             (physical_device
@@ -223,6 +386,10 @@ impl Device {
                 .get_physical_device_features2)(physical_device.raw, &mut features2);
         }
 
+        // The extension alone doesn't promise the feature.
+        let ray_tracing_motion_blur_enabled =
+            motion_blur_supported && motion_blur_features.ray_tracing_motion_blur == vk::TRUE;
+
         let device_create_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(&universal_queue_info)
             .enabled_extension_names(&device_extension_names)
@@ -293,8 +460,11 @@ impl Device {
                 setup_cb: Mutex::new(setup_cb),
                 acceleration_structure_ext,
                 ray_tracing_pipeline_ext,
-                // ray_query_ext,
                 ray_tracing_pipeline_properties,
+                acceleration_structure_enabled,
+                ray_tracing_enabled,
+                ray_query_enabled,
+                ray_tracing_motion_blur_enabled,
                 frames: [Mutex::new(Arc::new(frame0)), Mutex::new(Arc::new(frame1))],
                 first_frame: Instant::now(),
             }))
@@ -1,17 +1,59 @@
 use std::sync::Arc;
 
-
 use ash::vk::{self};
+use gpu_allocator::{
+    vulkan::{Allocation, AllocationCreateDesc},
+    MemoryLocation,
+};
 
 use super::device::Device;
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ImageDesc {
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub usage: vk::ImageUsageFlags,
+}
+
+impl ImageDesc {
+    /// The aspects of the format, for barriers and views over the whole image.
+    pub fn aspect_mask(&self) -> vk::ImageAspectFlags {
+        match self.format {
+            vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+                vk::ImageAspectFlags::DEPTH
+            }
+            vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+            vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT => {
+                vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+            }
+            _ => vk::ImageAspectFlags::COLOR,
+        }
+    }
+}
+
 pub struct Image {
     pub raw: vk::Image,
     pub view: vk::ImageView,
+    pub desc: ImageDesc,
+    /// `None` for images owned by the swapchain.
+    pub allocation: Option<Allocation>,
 }
 
 impl Image {
-    pub fn new(device: Arc<Device>, image: vk::Image) -> Self {
+    pub fn new(device: Arc<Device>, image: vk::Image, desc: ImageDesc) -> Self {
+        let view = Self::create_view(&device, image, desc);
+
+        Self {
+            raw: image,
+            view,
+            desc,
+            allocation: None,
+        }
+    }
+
+    fn create_view(device: &Device, image: vk::Image, desc: ImageDesc) -> vk::ImageView {
         let create_info = vk::ImageViewCreateInfo::builder()
             .image(image)
             .components(vk::ComponentMapping {
@@ -21,16 +63,87 @@ impl Image {
                 a: vk::ComponentSwizzle::A,
             })
             .view_type(vk::ImageViewType::TYPE_2D)
-            .format(vk::Format::B8G8R8A8_UNORM)
+            .format(desc.format)
             .subresource_range(vk::ImageSubresourceRange {
-                aspect_mask: vk::ImageAspectFlags::COLOR,
+                aspect_mask: desc.aspect_mask(),
                 level_count: 1,
                 layer_count: 1,
                 ..Default::default()
             });
 
-        let view = unsafe { device.raw.create_image_view(&create_info, None).unwrap() };
+        unsafe { device.raw.create_image_view(&create_info, None).unwrap() }
+    }
+}
+
+impl Device {
+    pub fn create_image(&self, desc: ImageDesc, name: impl Into<String>) -> Image {
+        let create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(desc.format)
+            .extent(vk::Extent3D {
+                width: desc.extent.width,
+                height: desc.extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(desc.usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let image = unsafe {
+            self.raw
+                .create_image(&create_info, None)
+                .expect("Failed to create image")
+        };
+
+        let requirements = unsafe { self.raw.get_image_memory_requirements(image) };
+
+        let allocation = self
+            .global_allocator
+            .lock()
+            .unwrap()
+            .allocate(&AllocationCreateDesc {
+                name: &name.into(),
+                requirements,
+                location: MemoryLocation::GpuOnly,
+                linear: false,
+            })
+            .expect("Failed to allocate image");
+
+        unsafe {
+            self.raw
+                .bind_image_memory(image, allocation.memory(), allocation.offset())
+                .expect("couldnt bind image memory");
+        }
+
+        let view = Image::create_view(self, image, desc);
+
+        Image {
+            raw: image,
+            view,
+            desc,
+            allocation: Some(allocation),
+        }
+    }
+
+    pub fn destroy_image(&self, image: Image) {
+        unsafe {
+            self.raw.destroy_image_view(image.view, None);
+        }
+
+        if let Some(allocation) = image.allocation {
+            unsafe {
+                self.raw.destroy_image(image.raw, None);
+            }
 
-        Self { raw: image, view }
+            self.global_allocator
+                .lock()
+                .unwrap()
+                .free(allocation)
+                .expect("Failed to free image memory");
+        }
     }
 }
@@ -1,8 +1,10 @@
 pub mod backend;
 pub mod buffer;
 pub mod device;
-mod image;
+pub mod image;
 pub mod instance;
 pub mod physical_device;
+pub mod ray_tracing;
+pub mod shader;
 pub mod surface;
 pub mod swapchain;
@@ -0,0 +1,385 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+
+use super::{
+    buffer::{Buffer, BufferDesc},
+    device::Device,
+};
+
+pub struct AccelerationStructure {
+    pub raw: vk::AccelerationStructureKHR,
+    pub buffer: Buffer,
+    pub device_address: vk::DeviceAddress,
+}
+
+impl AccelerationStructure {
+    /// Frees the acceleration structure and its buffer. The GPU must be done
+    /// with them.
+    pub fn destroy(self, device: &Device) {
+        unsafe {
+            device
+                .acceleration_structure_ext
+                .destroy_acceleration_structure(self.raw, None);
+        }
+
+        device.destroy_buffer(self.buffer);
+    }
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    (value + alignment - 1) & !(alignment - 1)
+}
+
+impl Device {
+    /// Builds a bottom level acceleration structure over procedural geometry.
+    /// `aabb_buffer` holds one `vk::AabbPositionsKHR` per primitive, and
+    /// `gl_PrimitiveID` in the intersection shader indexes into it.
+    pub fn create_blas_from_aabbs(
+        &self,
+        aabb_buffer: &Buffer,
+        aabb_count: u32,
+    ) -> anyhow::Result<AccelerationStructure> {
+        let geometry = vk::AccelerationStructureGeometryKHR::builder()
+            .geometry_type(vk::GeometryTypeKHR::AABBS)
+            .geometry(vk::AccelerationStructureGeometryDataKHR {
+                aabbs: vk::AccelerationStructureGeometryAabbsDataKHR::builder()
+                    .data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: aabb_buffer.device_address(self),
+                    })
+                    .stride(std::mem::size_of::<vk::AabbPositionsKHR>() as u64)
+                    .build(),
+            })
+            .flags(vk::GeometryFlagsKHR::OPAQUE)
+            .build();
+
+        self.build_acceleration_structure(
+            vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+            geometry,
+            aabb_count,
+            "blas",
+        )
+    }
+
+    /// Builds a bottom level acceleration structure over an indexed triangle list.
+    /// Vertices are `vec3` positions `vertex_stride` bytes apart, indices are `u32`.
+    /// The offsets are in bytes.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_blas_from_triangles(
+        &self,
+        vertex_buffer: &Buffer,
+        vertex_offset: u64,
+        vertex_stride: u64,
+        vertex_count: u32,
+        index_buffer: &Buffer,
+        index_offset: u64,
+        triangle_count: u32,
+    ) -> anyhow::Result<AccelerationStructure> {
+        let geometry = vk::AccelerationStructureGeometryKHR::builder()
+            .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
+            .geometry(vk::AccelerationStructureGeometryDataKHR {
+                triangles: vk::AccelerationStructureGeometryTrianglesDataKHR::builder()
+                    .vertex_format(vk::Format::R32G32B32_SFLOAT)
+                    .vertex_data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: vertex_buffer.device_address(self) + vertex_offset,
+                    })
+                    .vertex_stride(vertex_stride)
+                    .max_vertex(vertex_count.saturating_sub(1))
+                    .index_type(vk::IndexType::UINT32)
+                    .index_data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: index_buffer.device_address(self) + index_offset,
+                    })
+                    .build(),
+            })
+            .flags(vk::GeometryFlagsKHR::OPAQUE)
+            .build();
+
+        self.build_acceleration_structure(
+            vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+            geometry,
+            triangle_count,
+            "blas",
+        )
+    }
+
+    pub fn create_tlas(
+        &self,
+        instances: &[vk::AccelerationStructureInstanceKHR],
+    ) -> anyhow::Result<AccelerationStructure> {
+        let instance_buffer = self.create_buffer(
+            BufferDesc {
+                size: std::mem::size_of_val(instances),
+                usage: vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                memory_location: MemoryLocation::GpuOnly,
+            },
+            "tlas instances",
+            Some(unsafe {
+                std::slice::from_raw_parts(
+                    instances.as_ptr() as *const u8,
+                    std::mem::size_of_val(instances),
+                )
+            }),
+        );
+
+        let geometry = vk::AccelerationStructureGeometryKHR::builder()
+            .geometry_type(vk::GeometryTypeKHR::INSTANCES)
+            .geometry(vk::AccelerationStructureGeometryDataKHR {
+                instances: vk::AccelerationStructureGeometryInstancesDataKHR::builder()
+                    .array_of_pointers(false)
+                    .data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: instance_buffer.device_address(self),
+                    })
+                    .build(),
+            })
+            .build();
+
+        let tlas = self.build_acceleration_structure(
+            vk::AccelerationStructureTypeKHR::TOP_LEVEL,
+            geometry,
+            instances.len() as u32,
+            "tlas",
+        );
+
+        self.destroy_buffer(instance_buffer);
+
+        tlas
+    }
+
+    fn build_acceleration_structure(
+        &self,
+        ty: vk::AccelerationStructureTypeKHR,
+        geometry: vk::AccelerationStructureGeometryKHR,
+        primitive_count: u32,
+        name: &str,
+    ) -> anyhow::Result<AccelerationStructure> {
+        let mut build_info = vk::AccelerationStructureBuildGeometryInfoKHR::builder()
+            .ty(ty)
+            .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .geometries(std::slice::from_ref(&geometry))
+            .build();
+
+        let sizes = unsafe {
+            self.acceleration_structure_ext
+                .get_acceleration_structure_build_sizes(
+                    vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                    &build_info,
+                    &[primitive_count],
+                )
+        };
+
+        let buffer = self.create_buffer(
+            BufferDesc {
+                size: sizes.acceleration_structure_size as usize,
+                usage: vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                memory_location: MemoryLocation::GpuOnly,
+            },
+            name,
+            None,
+        );
+
+        let raw = unsafe {
+            self.acceleration_structure_ext
+                .create_acceleration_structure(
+                    &vk::AccelerationStructureCreateInfoKHR::builder()
+                        .ty(ty)
+                        .buffer(buffer.raw)
+                        .size(sizes.acceleration_structure_size),
+                    None,
+                )
+        };
+        let raw = match raw {
+            Ok(raw) => raw,
+            Err(err) => {
+                self.destroy_buffer(buffer);
+                return Err(err.into());
+            }
+        };
+
+        let scratch_alignment =
+            self.physical_device
+                .ray_tracing_properties
+                .min_acceleration_structure_scratch_offset_alignment as u64;
+
+        let scratch_buffer = self.create_buffer(
+            BufferDesc {
+                size: (sizes.build_scratch_size + scratch_alignment) as usize,
+                usage: vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                memory_location: MemoryLocation::GpuOnly,
+            },
+            "acceleration structure scratch",
+            None,
+        );
+
+        build_info.dst_acceleration_structure = raw;
+        build_info.scratch_data = vk::DeviceOrHostAddressKHR {
+            device_address: align_up(scratch_buffer.device_address(self), scratch_alignment),
+        };
+
+        let build_range = vk::AccelerationStructureBuildRangeInfoKHR::builder()
+            .primitive_count(primitive_count)
+            .build();
+
+        self.with_setup_cb(|cb| unsafe {
+            self.acceleration_structure_ext
+                .cmd_build_acceleration_structures(
+                    cb,
+                    std::slice::from_ref(&build_info),
+                    &[std::slice::from_ref(&build_range)],
+                );
+        });
+
+        self.destroy_buffer(scratch_buffer);
+
+        let device_address = unsafe {
+            self.acceleration_structure_ext
+                .get_acceleration_structure_device_address(
+                    &vk::AccelerationStructureDeviceAddressInfoKHR::builder()
+                        .acceleration_structure(raw),
+                )
+        };
+
+        Ok(AccelerationStructure {
+            raw,
+            buffer,
+            device_address,
+        })
+    }
+}
+
+/// Which pipeline shader groups go into each region of a shader binding table.
+#[derive(Clone, Default)]
+pub struct ShaderBindingTableDesc {
+    pub raygen_group: u32,
+    pub miss_groups: Vec<u32>,
+    pub hit_groups: Vec<u32>,
+}
+
+impl ShaderBindingTableDesc {
+    pub fn builder() -> Self {
+        Self::default()
+    }
+
+    pub fn raygen(mut self, group: u32) -> Self {
+        self.raygen_group = group;
+        self
+    }
+
+    pub fn miss(mut self, group: u32) -> Self {
+        self.miss_groups.push(group);
+        self
+    }
+
+    pub fn hit_group(mut self, group: u32) -> Self {
+        self.hit_groups.push(group);
+        self
+    }
+}
+
+pub struct ShaderBindingTable {
+    pub buffer: Buffer,
+    pub raygen_region: vk::StridedDeviceAddressRegionKHR,
+    pub miss_region: vk::StridedDeviceAddressRegionKHR,
+    pub hit_region: vk::StridedDeviceAddressRegionKHR,
+    pub callable_region: vk::StridedDeviceAddressRegionKHR,
+}
+
+impl Device {
+    pub fn create_shader_binding_table(
+        &self,
+        pipeline: vk::Pipeline,
+        group_count: u32,
+        desc: &ShaderBindingTableDesc,
+    ) -> anyhow::Result<ShaderBindingTable> {
+        let properties = &self.ray_tracing_pipeline_properties;
+        let handle_size = properties.shader_group_handle_size as u64;
+        let handle_stride = align_up(handle_size, properties.shader_group_handle_alignment as u64);
+        let base_alignment = properties.shader_group_base_alignment as u64;
+
+        let handles = unsafe {
+            self.ray_tracing_pipeline_ext
+                .get_ray_tracing_shader_group_handles(
+                    pipeline,
+                    0,
+                    group_count,
+                    (group_count as u64 * handle_size) as usize,
+                )?
+        };
+
+        let regions = [
+            std::slice::from_ref(&desc.raygen_group),
+            desc.miss_groups.as_slice(),
+            desc.hit_groups.as_slice(),
+        ];
+
+        // Every region starts at a multiple of the base alignment.
+        let mut offsets = [0u64; 3];
+        let mut size = 0;
+        for (offset, groups) in offsets.iter_mut().zip(regions) {
+            *offset = size;
+            size = align_up(size + groups.len() as u64 * handle_stride, base_alignment);
+        }
+
+        let mut data = vec![0u8; size as usize];
+        for (offset, groups) in offsets.iter().zip(regions) {
+            for (i, group) in groups.iter().enumerate() {
+                if *group >= group_count {
+                    anyhow::bail!(
+                        "Shader group {} out of range, pipeline has {} groups",
+                        group,
+                        group_count
+                    );
+                }
+
+                let src = (*group as u64 * handle_size) as usize;
+                let dst = (offset + i as u64 * handle_stride) as usize;
+                data[dst..dst + handle_size as usize]
+                    .copy_from_slice(&handles[src..src + handle_size as usize]);
+            }
+        }
+
+        let buffer = self.create_buffer(
+            BufferDesc {
+                // Over-allocate so the table can be aligned within the buffer.
+                size: (size + base_alignment) as usize,
+                usage: vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR
+                    | vk::BufferUsageFlags::TRANSFER_DST
+                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                memory_location: MemoryLocation::GpuOnly,
+            },
+            "shader binding table",
+            None,
+        );
+
+        let base_address = buffer.device_address(self);
+        let aligned_address = align_up(base_address, base_alignment);
+        let padding = (aligned_address - base_address) as usize;
+
+        let mut padded_data = vec![0u8; padding];
+        padded_data.extend_from_slice(&data);
+        self.upload_buffer(&buffer, &padded_data);
+
+        let region = |offset: u64, count: usize, stride: u64| {
+            if count == 0 {
+                return vk::StridedDeviceAddressRegionKHR::default();
+            }
+
+            vk::StridedDeviceAddressRegionKHR {
+                device_address: aligned_address + offset,
+                stride,
+                size: count as u64 * stride,
+            }
+        };
+
+        Ok(ShaderBindingTable {
+            // The raygen region's stride must equal its size.
+            raygen_region: region(offsets[0], 1, handle_stride),
+            miss_region: region(offsets[1], desc.miss_groups.len(), handle_stride),
+            hit_region: region(offsets[2], desc.hit_groups.len(), handle_stride),
+            callable_region: vk::StridedDeviceAddressRegionKHR::default(),
+            buffer,
+        })
+    }
+}
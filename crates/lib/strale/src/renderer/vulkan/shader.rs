@@ -0,0 +1,418 @@
+use std::{collections::BTreeMap, io::Cursor};
+
+use anyhow::Context;
+use ash::{util::read_spv, vk};
+use rspirv_reflect::{BindingCount, Reflection};
+
+use super::device::Device;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReflectedBinding {
+    pub ty: vk::DescriptorType,
+    /// `None` for runtime-sized arrays.
+    pub count: Option<u32>,
+    pub stage_flags: vk::ShaderStageFlags,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub descriptor_sets: BTreeMap<u32, BTreeMap<u32, ReflectedBinding>>,
+    pub push_constants: Option<vk::PushConstantRange>,
+    /// Workgroup size declared with `local_size_*` in compute shaders.
+    pub group_size: Option<[u32; 3]>,
+}
+
+impl ShaderReflection {
+    pub fn new(spirv: &[u8], stage: vk::ShaderStageFlags) -> anyhow::Result<Self> {
+        let reflection = Reflection::new_from_spirv(spirv)
+            .map_err(|err| anyhow::anyhow!("Failed to parse SPIR-V for reflection: {}", err))?;
+
+        let descriptor_sets = reflection
+            .get_descriptor_sets()
+            .map_err(|err| anyhow::anyhow!("Failed to reflect descriptor sets: {}", err))?
+            .into_iter()
+            .map(|(set_idx, bindings)| {
+                let bindings = bindings
+                    .into_iter()
+                    .map(|(binding_idx, info)| {
+                        let count = match info.binding_count {
+                            BindingCount::One => Some(1),
+                            BindingCount::StaticSized(count) => Some(count as u32),
+                            BindingCount::Unbounded => None,
+                        };
+
+                        (
+                            binding_idx,
+                            ReflectedBinding {
+                                ty: vk::DescriptorType::from_raw(info.ty.0 as i32),
+                                count,
+                                stage_flags: stage,
+                            },
+                        )
+                    })
+                    .collect();
+
+                (set_idx, bindings)
+            })
+            .collect();
+
+        let push_constants = reflection
+            .get_push_constant_range()
+            .map_err(|err| anyhow::anyhow!("Failed to reflect push constants: {}", err))?
+            .map(|range| {
+                vk::PushConstantRange::builder()
+                    .stage_flags(stage)
+                    .offset(range.offset)
+                    .size(range.size)
+                    .build()
+            });
+
+        let group_size = reflection
+            .get_compute_group_size()
+            .map(|(x, y, z)| [x, y, z]);
+
+        Ok(Self {
+            stage,
+            descriptor_sets,
+            push_constants,
+            group_size,
+        })
+    }
+}
+
+/// Values for `layout(constant_id = N)` constants, applied when a pipeline is
+/// created. Every supported type is 32 bits wide, so the raw bits are stored and
+/// the whole set can be used as a cache key.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SpecializationConstants {
+    constants: BTreeMap<u32, u32>,
+}
+
+impl SpecializationConstants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn u32(mut self, id: u32, value: u32) -> Self {
+        self.constants.insert(id, value);
+        self
+    }
+
+    pub fn i32(self, id: u32, value: i32) -> Self {
+        self.u32(id, value as u32)
+    }
+
+    pub fn f32(self, id: u32, value: f32) -> Self {
+        self.u32(id, value.to_bits())
+    }
+
+    pub fn bool(self, id: u32, value: bool) -> Self {
+        self.u32(id, if value { vk::TRUE } else { vk::FALSE })
+    }
+
+    pub fn map_entries(&self) -> Vec<vk::SpecializationMapEntry> {
+        self.constants
+            .keys()
+            .enumerate()
+            .map(|(i, id)| vk::SpecializationMapEntry {
+                constant_id: *id,
+                offset: (i * std::mem::size_of::<u32>()) as u32,
+                size: std::mem::size_of::<u32>(),
+            })
+            .collect()
+    }
+
+    pub fn data(&self) -> Vec<u8> {
+        self.constants
+            .values()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect()
+    }
+}
+
+pub struct ShaderModule {
+    pub raw: vk::ShaderModule,
+    pub stage: vk::ShaderStageFlags,
+    pub reflection: ShaderReflection,
+}
+
+/// A descriptor set layout created outside of pipeline creation, such as the
+/// bindless set. Reflected bindings are validated against `bindings` instead of
+/// generating a new layout for the set.
+#[derive(Clone)]
+pub struct DescriptorSetLayoutDesc {
+    pub layout: vk::DescriptorSetLayout,
+    pub bindings: Vec<vk::DescriptorSetLayoutBinding>,
+}
+
+pub struct PipelineLayout {
+    pub raw: vk::PipelineLayout,
+    /// Layouts generated from reflection with their set index, owned by the pipeline.
+    pub descriptor_set_layouts: Vec<(u32, vk::DescriptorSetLayout)>,
+    pub push_constants: Option<vk::PushConstantRange>,
+}
+
+/// The descriptor sets and push constants of a pipeline, merged from the
+/// reflection of its stages.
+#[derive(Clone, Debug, Default)]
+pub struct PipelineReflection {
+    pub descriptor_sets: BTreeMap<u32, BTreeMap<u32, ReflectedBinding>>,
+    /// Starts at offset zero, like the Rust-side struct.
+    pub push_constants: Option<vk::PushConstantRange>,
+}
+
+impl PipelineReflection {
+    /// Merges the reflection of every stage in a pipeline.
+    ///
+    /// Every binding the shaders expect in a set listed in `external_sets` must
+    /// exist there with a matching type, count and stage visibility.
+    /// `push_constants_size` is the size of the Rust-side push constant struct and
+    /// must match the shaders' block.
+    pub fn new(
+        stages: &[&ShaderReflection],
+        external_sets: &[(u32, DescriptorSetLayoutDesc)],
+        push_constants_size: usize,
+    ) -> anyhow::Result<Self> {
+        let mut descriptor_sets: BTreeMap<u32, BTreeMap<u32, ReflectedBinding>> = BTreeMap::new();
+        let mut push_constants: Option<vk::PushConstantRange> = None;
+
+        for reflection in stages {
+            for (set_idx, bindings) in &reflection.descriptor_sets {
+                let set = descriptor_sets.entry(*set_idx).or_default();
+
+                for (binding_idx, binding) in bindings {
+                    match set.get_mut(binding_idx) {
+                        Some(existing) => {
+                            if existing.ty != binding.ty || existing.count != binding.count {
+                                anyhow::bail!(
+                                    "Shader stages disagree on set {} binding {}: {:?} in {:?}, {:?} in {:?}",
+                                    set_idx,
+                                    binding_idx,
+                                    existing.ty,
+                                    existing.stage_flags,
+                                    binding.ty,
+                                    binding.stage_flags
+                                );
+                            }
+                            existing.stage_flags |= binding.stage_flags;
+                        }
+                        None => {
+                            set.insert(*binding_idx, *binding);
+                        }
+                    }
+                }
+            }
+
+            if let Some(range) = reflection.push_constants {
+                push_constants = Some(match push_constants {
+                    Some(existing) => {
+                        let start = existing.offset.min(range.offset);
+                        let end = (existing.offset + existing.size).max(range.offset + range.size);
+
+                        vk::PushConstantRange {
+                            stage_flags: existing.stage_flags | range.stage_flags,
+                            offset: start,
+                            size: end - start,
+                        }
+                    }
+                    None => range,
+                });
+            }
+        }
+
+        // The Rust-side struct always starts at offset zero, so the range does too.
+        let push_constants = push_constants.map(|range| vk::PushConstantRange {
+            stage_flags: range.stage_flags,
+            offset: 0,
+            size: range.offset + range.size,
+        });
+
+        let shader_push_constants_size =
+            push_constants.map(|range| range.size as usize).unwrap_or(0);
+
+        if shader_push_constants_size != push_constants_size {
+            anyhow::bail!(
+                "Push constant size mismatch: shaders expect {} bytes, got {} bytes",
+                shader_push_constants_size,
+                push_constants_size
+            );
+        }
+
+        for (set_idx, external) in external_sets {
+            let bindings = match descriptor_sets.get(set_idx) {
+                Some(bindings) => bindings,
+                None => continue,
+            };
+
+            for (binding_idx, binding) in bindings {
+                let layout_binding = match external
+                    .bindings
+                    .iter()
+                    .find(|layout_binding| layout_binding.binding == *binding_idx)
+                {
+                    Some(layout_binding) => layout_binding,
+                    None => anyhow::bail!(
+                        "Set {} binding {} is used by {:?} but missing from the descriptor set layout",
+                        set_idx,
+                        binding_idx,
+                        binding.stage_flags
+                    ),
+                };
+
+                if layout_binding.descriptor_type != binding.ty {
+                    anyhow::bail!(
+                        "Set {} binding {} type mismatch: shaders expect {:?}, layout has {:?}",
+                        set_idx,
+                        binding_idx,
+                        binding.ty,
+                        layout_binding.descriptor_type
+                    );
+                }
+
+                if matches!(binding.count, Some(count) if count > layout_binding.descriptor_count) {
+                    anyhow::bail!(
+                        "Set {} binding {} count mismatch: shaders expect {:?}, layout has {}",
+                        set_idx,
+                        binding_idx,
+                        binding.count,
+                        layout_binding.descriptor_count
+                    );
+                }
+
+                if !layout_binding.stage_flags.contains(binding.stage_flags) {
+                    anyhow::bail!(
+                        "Set {} binding {} is not visible to {:?}",
+                        set_idx,
+                        binding_idx,
+                        binding.stage_flags
+                    );
+                }
+            }
+        }
+
+        Ok(Self {
+            descriptor_sets,
+            push_constants,
+        })
+    }
+}
+
+impl Device {
+    pub fn create_shader_module(
+        &self,
+        spirv: &[u8],
+        stage: vk::ShaderStageFlags,
+    ) -> anyhow::Result<ShaderModule> {
+        let code = read_spv(&mut Cursor::new(spirv)).context("Failed to read SPIR-V")?;
+        let reflection = ShaderReflection::new(spirv, stage)?;
+
+        let raw = unsafe {
+            self.raw
+                .create_shader_module(&vk::ShaderModuleCreateInfo::builder().code(&code), None)
+                .context("Failed to create shader module")?
+        };
+
+        Ok(ShaderModule {
+            raw,
+            stage,
+            reflection,
+        })
+    }
+
+    /// Builds a pipeline layout from the reflection of every stage in the pipeline.
+    ///
+    /// Sets listed in `external_sets` are used as-is after checking them against the
+    /// shaders, see `PipelineReflection::new`. All other sets get a layout generated
+    /// from reflection.
+    pub fn create_pipeline_layout(
+        &self,
+        stages: &[&ShaderReflection],
+        external_sets: &[(u32, DescriptorSetLayoutDesc)],
+        push_constants_size: usize,
+    ) -> anyhow::Result<PipelineLayout> {
+        let PipelineReflection {
+            descriptor_sets,
+            push_constants,
+        } = PipelineReflection::new(stages, external_sets, push_constants_size)?;
+
+        let set_count = descriptor_sets
+            .keys()
+            .chain(external_sets.iter().map(|(set_idx, _)| set_idx))
+            .max()
+            .map_or(0, |max_set| max_set + 1);
+
+        let mut owned_set_layouts = Vec::new();
+        let mut set_layouts = Vec::with_capacity(set_count as usize);
+
+        for set_idx in 0..set_count {
+            if let Some((_, external)) = external_sets.iter().find(|(idx, _)| *idx == set_idx) {
+                set_layouts.push(external.layout);
+                continue;
+            }
+
+            // Unused sets still need a layout if a later set index is used.
+            let bindings: Vec<vk::DescriptorSetLayoutBinding> = descriptor_sets
+                .get(&set_idx)
+                .into_iter()
+                .flatten()
+                .map(|(binding_idx, binding)| {
+                    vk::DescriptorSetLayoutBinding::builder()
+                        .binding(*binding_idx)
+                        .descriptor_type(binding.ty)
+                        .descriptor_count(binding.count.unwrap_or(1))
+                        .stage_flags(binding.stage_flags)
+                        .build()
+                })
+                .collect();
+
+            let layout = unsafe {
+                self.raw.create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+            };
+            let layout = match layout {
+                Ok(layout) => layout,
+                Err(err) => {
+                    self.destroy_set_layouts(&owned_set_layouts);
+                    return Err(err.into());
+                }
+            };
+
+            owned_set_layouts.push((set_idx, layout));
+            set_layouts.push(layout);
+        }
+
+        let push_constant_ranges: Vec<vk::PushConstantRange> = push_constants.into_iter().collect();
+
+        let raw = unsafe {
+            self.raw.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&set_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )
+        };
+        let raw = match raw {
+            Ok(raw) => raw,
+            Err(err) => {
+                self.destroy_set_layouts(&owned_set_layouts);
+                return Err(err.into());
+            }
+        };
+
+        Ok(PipelineLayout {
+            raw,
+            descriptor_set_layouts: owned_set_layouts,
+            push_constants,
+        })
+    }
+
+    /// Frees the layouts `create_pipeline_layout` made before it failed.
+    fn destroy_set_layouts(&self, layouts: &[(u32, vk::DescriptorSetLayout)]) {
+        for (_, layout) in layouts {
+            unsafe { self.raw.destroy_descriptor_set_layout(*layout, None) };
+        }
+    }
+}
@@ -6,11 +6,16 @@ use ash::{
     vk::{self, ColorSpaceKHR, SwapchainKHR},
 };
 
-use super::{device::Device, image::Image, surface::Surface};
+use super::{
+    device::Device,
+    image::{Image, ImageDesc},
+    surface::Surface,
+};
 
 #[derive(Clone, Copy, Default)]
 pub struct SwapchainDesc {
     pub dims: vk::Extent2D,
+    pub format: vk::Format,
 }
 
 pub struct SwapchainImage {
@@ -36,10 +41,13 @@ pub struct Swapchain {
 }
 
 impl Swapchain {
+    /// Creates a swapchain for `surface`. When recreating one, e.g. after the
+    /// window was resized, pass the one it replaces as `old`.
     pub fn new(
         device: &Arc<Device>,
         surface: &Arc<Surface>,
         desc: SwapchainDesc,
+        old: Option<&Swapchain>,
     ) -> anyhow::Result<Self> {
         let surface_capabilities = unsafe {
             surface
@@ -59,6 +67,12 @@ impl Swapchain {
             _ => surface_capabilities.current_extent,
         };
 
+        // Transfer destination lets passes that render to their own images blit into the swapchain.
+        // Transfer source, where the surface allows it, lets `Renderer::capture` read frames back.
+        let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | vk::ImageUsageFlags::TRANSFER_DST
+            | (surface_capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC);
+
         let present_mode = vk::PresentModeKHR::IMMEDIATE;
         log::info!("Presentation mode: {:?}", present_mode);
 
@@ -66,15 +80,16 @@ impl Swapchain {
             .surface(surface.raw)
             .min_image_count(desired_image_count)
             .image_color_space(ColorSpaceKHR::SRGB_NONLINEAR)
-            .image_format(vk::Format::B8G8R8A8_UNORM)
+            .image_format(desc.format)
             .image_extent(surface_resolution)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(image_usage)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .pre_transform(surface_capabilities.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
             .present_mode(present_mode)
             .clipped(true)
             .image_array_layers(1)
+            .old_swapchain(old.map_or(SwapchainKHR::null(), |old| old.raw))
             .build();
 
         let fns = khr::Swapchain::new(&device.instance.raw, &device.raw);
@@ -84,7 +99,17 @@ impl Swapchain {
 
         let images: Vec<Arc<Image>> = vk_images
             .into_iter()
-            .map(|vk_image| Arc::new(Image::new(device.clone(), vk_image)))
+            .map(|vk_image| {
+                Arc::new(Image::new(
+                    device.clone(),
+                    vk_image,
+                    ImageDesc {
+                        extent: surface_resolution,
+                        format: desc.format,
+                        usage: image_usage,
+                    },
+                ))
+            })
             .collect();
 
         let acquire_semaphores = (0..images.len())
@@ -117,7 +142,10 @@ impl Swapchain {
             rendering_finished_semaphores,
             images,
             next_semaphore: 0,
-            desc,
+            desc: SwapchainDesc {
+                dims: surface_resolution,
+                ..desc
+            },
         })
     }
 
@@ -188,6 +216,14 @@ impl Swapchain {
 impl Drop for Swapchain {
     fn drop(&mut self) {
         unsafe {
+            for &semaphore in self
+                .acquire_semaphores
+                .iter()
+                .chain(&self.rendering_finished_semaphores)
+            {
+                self.device.raw.destroy_semaphore(semaphore, None);
+            }
+
             self.fns.destroy_swapchain(self.raw, None);
         }
     }
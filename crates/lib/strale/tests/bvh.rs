@@ -0,0 +1,182 @@
+mod common;
+
+use std::{collections::HashSet, f32::consts::TAU};
+
+use strale::{
+    math::{Aabb, Affine3, Ray, Vec3},
+    renderer::{
+        bvh::{Bvh, MAX_DEPTH},
+        material::Material,
+        scene::{Mesh, PrimitiveId, Scene},
+    },
+};
+
+use common::Rng;
+
+fn random_scene(rng: &mut Rng) -> Scene {
+    let mut scene = Scene::new();
+    let material = scene.add_material(Material::Lambertian {
+        albedo: Vec3::splat(0.5).into(),
+    });
+
+    for i in 0..40 {
+        let center = rng.vec3(-10.0, 10.0);
+        let radius = rng.range(0.1, 1.5);
+        if i % 4 == 0 {
+            let end_center = center + rng.vec3(-3.0, 3.0);
+            scene.add_moving_sphere(center, end_center, radius, material);
+        } else {
+            scene.add_sphere(center, radius, material);
+        }
+    }
+
+    // A big sphere overlapping everything else, like a ground plane.
+    scene.add_sphere(Vec3::new(0.0, -1010.0, 0.0), 1000.0, material);
+
+    let mut soup = Mesh::default();
+    for _ in 0..300 {
+        let base = rng.vec3(-2.0, 2.0);
+        for _ in 0..3 {
+            soup.indices.push(soup.positions.len() as u32);
+            soup.positions.push(base + rng.vec3(-0.5, 0.5));
+        }
+    }
+
+    let soup = scene.add_mesh(soup);
+    let cube = scene.add_mesh(Mesh::cube());
+
+    for i in 0..12 {
+        let mesh = if i % 3 == 0 { soup } else { cube };
+        let transform = Affine3::from_translation(rng.vec3(-8.0, 8.0))
+            * Affine3::from_rotation_y(rng.range(0.0, TAU))
+            * Affine3::from_rotation_x(rng.range(0.0, TAU))
+            * Affine3::from_scale(rng.vec3(0.2, 2.0));
+        scene.add_instance(mesh, transform, material);
+    }
+
+    scene
+}
+
+#[test]
+fn bvh_hits_match_brute_force() {
+    let mut rng = Rng(0x9e37_79b9);
+    let scene = random_scene(&mut rng);
+    let bvh = Bvh::build(&scene);
+
+    let mut hits = 0;
+    for _ in 0..5000 {
+        let ray = Ray {
+            time: rng.next_f32(),
+            ..Ray::new(rng.vec3(-15.0, 15.0), rng.unit_vector())
+        };
+
+        let expected = scene.intersect(&ray, 1e-4, f32::MAX);
+        let actual = bvh.intersect(&scene, &ray, 1e-4, f32::MAX);
+
+        match (expected, actual) {
+            (None, None) => {}
+            (Some(expected), Some(actual)) => {
+                hits += 1;
+                assert!(
+                    (expected.t - actual.t).abs() <= 1e-4 * expected.t.max(1.0),
+                    "{:?}: brute force hit {:?}, BVH hit {:?}",
+                    ray,
+                    expected,
+                    actual
+                );
+                // Different primitives are fine only when they're hit at the same distance.
+                if expected.primitive != actual.primitive {
+                    assert_eq!(expected.t, actual.t, "{:?}", ray);
+                }
+            }
+            (expected, actual) => panic!(
+                "{:?}: brute force hit {:?}, BVH hit {:?}",
+                ray, expected, actual
+            ),
+        }
+    }
+
+    // Most rays should hit something, or the comparison says little.
+    assert!(hits > 2500, "only {} of 5000 rays hit", hits);
+}
+
+#[test]
+fn bvh_covers_every_primitive_once() {
+    let mut rng = Rng(0x1234_5678);
+    let scene = random_scene(&mut rng);
+    let bvh = Bvh::build(&scene);
+
+    let expected: HashSet<PrimitiveId> = scene.primitives().collect();
+    assert_eq!(bvh.primitives.len(), expected.len());
+
+    let mut seen = HashSet::new();
+    // (node, bounds of its parent, depth)
+    let mut stack = vec![(0, bvh.nodes[0].bounds(), 1)];
+    while let Some((node_idx, parent_bounds, depth)) = stack.pop() {
+        let node = bvh.nodes[node_idx];
+        let bounds = node.bounds();
+
+        assert!(depth <= MAX_DEPTH, "node {} is too deep", node_idx);
+        assert!(
+            contains(parent_bounds, bounds),
+            "node {} isn't inside its parent",
+            node_idx
+        );
+
+        if node.is_leaf() {
+            let first = node.left_or_first as usize;
+            for &primitive in &bvh.primitives[first..first + node.count as usize] {
+                let primitive = PrimitiveId::from(primitive);
+                assert!(
+                    contains(bounds, scene.primitive_bounds(primitive)),
+                    "{:?} isn't inside its leaf",
+                    primitive
+                );
+                assert!(seen.insert(primitive), "{:?} is in two leaves", primitive);
+            }
+        } else {
+            let left = node.left_or_first as usize;
+            stack.push((left, bounds, depth + 1));
+            stack.push((left + 1, bounds, depth + 1));
+        }
+    }
+
+    assert_eq!(seen, expected);
+}
+
+#[test]
+fn moving_spheres_are_hit_where_they_are_at_the_ray_time() {
+    let mut scene = Scene::new();
+    let material = scene.add_material(Material::Lambertian {
+        albedo: Vec3::splat(0.5).into(),
+    });
+    scene.add_moving_sphere(Vec3::ZERO, Vec3::new(4.0, 0.0, 0.0), 0.5, material);
+    let bvh = Bvh::build(&scene);
+
+    let ray_at = |time| Ray {
+        time,
+        ..Ray::new(Vec3::new(2.0, 0.0, -5.0), Vec3::Z)
+    };
+
+    // Only halfway through does the sphere cross the ray.
+    assert!(bvh.intersect(&scene, &ray_at(0.0), 0.0, f32::MAX).is_none());
+    assert!(bvh.intersect(&scene, &ray_at(1.0), 0.0, f32::MAX).is_none());
+    let hit = bvh
+        .intersect(&scene, &ray_at(0.5), 0.0, f32::MAX)
+        .expect("the sphere is in front of the ray at time 0.5");
+    assert!((hit.t - 4.5).abs() < 1e-4, "hit at {}", hit.t);
+}
+
+#[test]
+fn empty_scene_has_empty_bvh() {
+    let scene = Scene::new();
+    let bvh = Bvh::build(&scene);
+
+    assert!(bvh.primitives.is_empty());
+    let ray = Ray::new(Vec3::ZERO, Vec3::Z);
+    assert!(bvh.intersect(&scene, &ray, 0.0, f32::MAX).is_none());
+}
+
+fn contains(outer: Aabb, inner: Aabb) -> bool {
+    (0..3).all(|axis| outer.min[axis] <= inner.min[axis] && inner.max[axis] <= outer.max[axis])
+}
@@ -0,0 +1,77 @@
+use strale::{
+    math::{Ray, Vec3},
+    renderer::camera::Camera,
+};
+
+const LENS_SAMPLES: [[f32; 2]; 5] = [
+    [0.5, 0.5],
+    [0.0, 0.0],
+    [0.99, 0.1],
+    [0.25, 0.8],
+    [0.7, 0.999],
+];
+
+/// Where `ray` crosses the plane `distance` in front of `camera`.
+fn cross_plane(camera: &Camera, ray: &Ray, distance: f32) -> Vec3 {
+    let forward = camera.forward();
+    let t = (distance - (ray.origin - camera.position).dot(forward)) / ray.direction.dot(forward);
+    ray.at(t)
+}
+
+#[test]
+fn pinhole_rays_leave_from_the_camera() {
+    let camera = Camera::looking_at(Vec3::new(3.0, 1.0, 4.0), Vec3::ZERO, 40.0);
+    let rays = camera.rays(16.0 / 9.0);
+
+    for lens in LENS_SAMPLES {
+        let ray = rays.ray([0.3, 0.6], lens, 0.0);
+        assert_eq!(ray.origin, camera.position);
+        assert_eq!(
+            ray.direction,
+            rays.ray([0.3, 0.6], [0.5, 0.5], 0.0).direction
+        );
+    }
+
+    // The center of the viewport looks at the target.
+    let center = rays.ray([0.5, 0.5], [0.5, 0.5], 0.0);
+    assert!((center.direction - camera.forward()).length() < 1e-5);
+}
+
+#[test]
+fn rays_through_a_pixel_meet_on_the_focus_plane() {
+    let mut camera = Camera::looking_at(Vec3::new(-2.0, 3.0, 5.0), Vec3::new(1.0, 0.0, -1.0), 30.0);
+    camera.aperture_radius = 0.4;
+    camera.focus_distance = 7.0;
+    let rays = camera.rays(1.5);
+
+    for uv in [[0.5, 0.5], [0.1, 0.9], [0.8, 0.3]] {
+        let focus = cross_plane(
+            &camera,
+            &rays.ray(uv, [0.5, 0.5], 0.0),
+            camera.focus_distance,
+        );
+
+        for lens in LENS_SAMPLES {
+            let ray = rays.ray(uv, lens, 0.0);
+
+            // The lens is a disk facing forward.
+            let offset = ray.origin - camera.position;
+            assert!(offset.length() <= camera.aperture_radius + 1e-5);
+            assert!(offset.dot(camera.forward()).abs() < 1e-5);
+
+            let point = cross_plane(&camera, &ray, camera.focus_distance);
+            assert!(
+                (point - focus).length() < 1e-4,
+                "{:?} from {:?} crosses the focus plane at {:?} instead of {:?}",
+                uv,
+                lens,
+                point,
+                focus
+            );
+        }
+
+        // Anything nearer is blurred.
+        let near = |lens| cross_plane(&camera, &rays.ray(uv, lens, 0.0), 2.0);
+        assert!((near([0.0, 0.5]) - near([0.99, 0.5])).length() > 0.1);
+    }
+}
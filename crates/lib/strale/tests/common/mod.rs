@@ -0,0 +1,45 @@
+//! Helpers shared by the integration tests. Every test binary compiles its own
+//! copy and uses only some of them.
+#![allow(dead_code)]
+
+use std::f32::consts::TAU;
+
+use strale::math::Vec3;
+
+/// Chi-square statistics above these have a chance below 0.001 for uniform
+/// numbers, by the degrees of freedom.
+pub const CHI_SQUARE_31: f64 = 61.10;
+pub const CHI_SQUARE_63: f64 = 103.44;
+
+/// xorshift32, good enough for sampling and placing geometry.
+pub struct Rng(pub u32);
+
+impl Rng {
+    pub fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// A point in the box from `min` to `max` on every axis.
+    pub fn vec3(&mut self, min: f32, max: f32) -> Vec3 {
+        Vec3::new(
+            self.range(min, max),
+            self.range(min, max),
+            self.range(min, max),
+        )
+    }
+
+    /// Uniform over the unit sphere.
+    pub fn unit_vector(&mut self) -> Vec3 {
+        let z = 2.0 * self.next_f32() - 1.0;
+        let phi = TAU * self.next_f32();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+}
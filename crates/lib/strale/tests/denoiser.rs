@@ -0,0 +1,201 @@
+mod common;
+
+use strale::{
+    math::Vec3,
+    renderer::denoiser::{denoise, DenoiserInput, GBufferTexel},
+};
+
+use common::Rng;
+
+const WIDTH: usize = 32;
+const HEIGHT: usize = 32;
+
+/// A wall facing the camera, converged for `frames` frames.
+fn wall(albedo: Vec3, color: Vec3) -> GBufferTexel {
+    GBufferTexel {
+        normal: Vec3::Z,
+        depth: 5.0,
+        albedo,
+        moment: (0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z).powi(2),
+    }
+}
+
+fn assert_close(a: Vec3, b: Vec3, tolerance: f32) {
+    assert!((a - b).length() < tolerance, "{:?} != {:?}", a, b);
+}
+
+fn mean_and_variance(values: impl Iterator<Item = f32>) -> (f32, f32) {
+    let values: Vec<f32> = values.collect();
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32;
+    (mean, variance)
+}
+
+#[test]
+fn constant_images_are_unchanged() {
+    let mut rng = Rng(0x1234_5678);
+    let color = vec![Vec3::new(0.4, 0.3, 0.2); WIDTH * HEIGHT];
+    // Even with first hits all over the place.
+    let gbuffer: Vec<GBufferTexel> = (0..WIDTH * HEIGHT)
+        .map(|_| GBufferTexel {
+            normal: Vec3::new(rng.next_f32(), rng.next_f32(), 1.0).normalize(),
+            depth: 1.0 + 10.0 * rng.next_f32(),
+            albedo: Vec3::splat(0.8),
+            moment: 0.2,
+        })
+        .collect();
+
+    for frames in [1, 16] {
+        let input = DenoiserInput {
+            width: WIDTH,
+            height: HEIGHT,
+            color: &color,
+            gbuffer: &gbuffer,
+            frames,
+        };
+        for (denoised, color) in denoise(&input, 5).unwrap().iter().zip(&color) {
+            assert_close(*denoised, *color, 1e-5);
+        }
+    }
+}
+
+#[test]
+fn noise_on_flat_surfaces_is_smoothed() {
+    let mut rng = Rng(0x9e37_79b9);
+    let albedo = Vec3::new(0.8, 0.6, 0.4);
+    let color: Vec<Vec3> = (0..WIDTH * HEIGHT)
+        .map(|_| albedo * (0.5 + rng.next_f32()))
+        .collect();
+    let gbuffer = vec![wall(albedo, Vec3::ZERO); WIDTH * HEIGHT];
+
+    let input = DenoiserInput {
+        width: WIDTH,
+        height: HEIGHT,
+        color: &color,
+        gbuffer: &gbuffer,
+        frames: 1,
+    };
+    let denoised = denoise(&input, 5).unwrap();
+
+    let (mean, variance) = mean_and_variance(color.iter().map(|c| c.x));
+    let (denoised_mean, denoised_variance) = mean_and_variance(denoised.iter().map(|c| c.x));
+    assert!(
+        (denoised_mean - mean).abs() < 0.02,
+        "the mean went from {} to {}",
+        mean,
+        denoised_mean
+    );
+    assert!(
+        denoised_variance < variance * 0.1,
+        "the variance only went from {} to {}",
+        variance,
+        denoised_variance
+    );
+
+    // Still the same color, the filter works on the light reaching the wall.
+    for c in &denoised {
+        assert_close(*c, albedo * (c.x / albedo.x), 1e-4);
+    }
+}
+
+#[test]
+fn geometry_edges_are_preserved() {
+    let mut rng = Rng(0x0bad_cafe);
+    let (bright, dark) = (Vec3::splat(1.0), Vec3::splat(0.1));
+    let left = |x: usize| x < WIDTH / 2;
+
+    let mut color = Vec::new();
+    let mut gbuffer = Vec::new();
+    for _ in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let noise = 0.8 + 0.4 * rng.next_f32();
+            let (base, normal) = if left(x) {
+                (bright, Vec3::X)
+            } else {
+                (dark, Vec3::Z)
+            };
+            color.push(base * noise);
+            gbuffer.push(GBufferTexel {
+                normal,
+                ..wall(Vec3::ONE, Vec3::ZERO)
+            });
+        }
+    }
+
+    let input = DenoiserInput {
+        width: WIDTH,
+        height: HEIGHT,
+        color: &color,
+        gbuffer: &gbuffer,
+        frames: 1,
+    };
+    let denoised = denoise(&input, 5).unwrap();
+
+    for (i, c) in denoised.iter().enumerate() {
+        let expected = if left(i % WIDTH) { bright } else { dark };
+        assert_close(*c, expected, expected.x * 0.2 + 1e-3);
+    }
+}
+
+#[test]
+fn texture_detail_is_kept() {
+    // A checkerboard lit evenly, all the variation is in the albedo.
+    let (mut color, mut gbuffer) = (Vec::new(), Vec::new());
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let albedo = if (x + y) % 2 == 0 {
+                Vec3::new(0.9, 0.1, 0.1)
+            } else {
+                Vec3::splat(0.05)
+            };
+            color.push(albedo);
+            gbuffer.push(wall(albedo, Vec3::ONE));
+        }
+    }
+
+    let input = DenoiserInput {
+        width: WIDTH,
+        height: HEIGHT,
+        color: &color,
+        gbuffer: &gbuffer,
+        frames: 1,
+    };
+    for (denoised, color) in denoise(&input, 5).unwrap().iter().zip(&color) {
+        assert_close(*denoised, *color, 1e-5);
+    }
+}
+
+#[test]
+fn converged_images_are_left_alone() {
+    let mut rng = Rng(0xfeed_f00d);
+    let color: Vec<Vec3> = (0..WIDTH * HEIGHT)
+        .map(|_| Vec3::new(rng.next_f32(), rng.next_f32(), rng.next_f32()))
+        .collect();
+    // Every frame saw the same color, so the moments leave no variance.
+    let gbuffer: Vec<GBufferTexel> = color.iter().map(|c| wall(Vec3::ONE, *c)).collect();
+
+    let input = DenoiserInput {
+        width: WIDTH,
+        height: HEIGHT,
+        color: &color,
+        gbuffer: &gbuffer,
+        frames: 64,
+    };
+    for (denoised, color) in denoise(&input, 5).unwrap().iter().zip(&color) {
+        assert_close(*denoised, *color, 1e-4);
+    }
+}
+
+#[test]
+fn mismatched_buffers_are_rejected() {
+    let color = vec![Vec3::ONE; 4];
+    let gbuffer = vec![wall(Vec3::ONE, Vec3::ONE); 3];
+    let input = DenoiserInput {
+        width: 2,
+        height: 2,
+        color: &color,
+        gbuffer: &gbuffer,
+        frames: 1,
+    };
+    assert!(denoise(&input, 1).is_err());
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use std::f32::consts::PI;
+
+use strale::{
+    math::Vec3,
+    renderer::environment::{
+        direction_to_uv, uv_to_direction, Environment, EnvironmentDistribution,
+    },
+};
+
+use common::Rng;
+
+/// A dim sky with a bright sun, and a black row that can't be sampled.
+fn probe() -> Environment {
+    let (width, height) = (32, 16);
+    let mut pixels = vec![Vec3::new(0.2, 0.3, 0.5); (width * height) as usize];
+    pixels[(4 * width + 20) as usize] = Vec3::splat(500.0);
+    for x in 0..width {
+        pixels[(10 * width + x) as usize] = Vec3::ZERO;
+    }
+
+    Environment::new(width, height, pixels).unwrap()
+}
+
+#[test]
+fn samples_match_pdf() {
+    let environment = probe();
+    let distribution = EnvironmentDistribution::new(&environment);
+    let mut rng = Rng(0x2545_f491);
+
+    let mut in_sun = 0;
+    let samples = 20_000;
+    for _ in 0..samples {
+        let (direction, pdf) = distribution.sample([rng.next_f32(), rng.next_f32()]);
+
+        assert!((direction.length() - 1.0).abs() < 1e-4);
+        assert!(pdf > 0.0, "{:?} can't be sampled", direction);
+        assert_ne!(
+            environment.radiance(direction),
+            Vec3::ZERO,
+            "{:?} is black",
+            direction
+        );
+
+        if environment.radiance(direction) == Vec3::splat(500.0) {
+            in_sun += 1;
+        }
+    }
+
+    // The sun is brighter than the rest of the sky put together.
+    assert!(
+        in_sun > samples / 2,
+        "{} of {} samples hit the sun",
+        in_sun,
+        samples
+    );
+}
+
+#[test]
+fn pdf_integrates_to_one() {
+    let distribution = EnvironmentDistribution::new(&probe());
+
+    // Midpoint rule over the sphere, several points per pixel.
+    let (nu, nv) = (32 * 8, 16 * 8);
+    let mut integral = 0.0;
+    for j in 0..nv {
+        for i in 0..nu {
+            let uv = [(i as f32 + 0.5) / nu as f32, (j as f32 + 0.5) / nv as f32];
+            let solid_angle = (2.0 * PI / nu as f32) * (PI / nv as f32) * (PI * uv[1]).sin();
+            integral += distribution.pdf(uv_to_direction(uv)) as f64 * solid_angle as f64;
+        }
+    }
+
+    assert!(
+        (integral - 1.0).abs() < 0.01,
+        "the pdf integrates to {}",
+        integral
+    );
+}
+
+#[test]
+fn directions_map_to_the_expected_pixels() {
+    // Up is the top row, -Z the middle column.
+    assert!(direction_to_uv(Vec3::Y)[1] < 1e-6);
+    assert!((direction_to_uv(-Vec3::Y)[1] - 1.0).abs() < 1e-6);
+    let forward = direction_to_uv(-Vec3::Z);
+    assert!((forward[0] - 0.5).abs() < 1e-6 && (forward[1] - 0.5).abs() < 1e-6);
+    assert!((direction_to_uv(Vec3::X)[0] - 0.75).abs() < 1e-6);
+}
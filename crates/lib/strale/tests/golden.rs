@@ -0,0 +1,365 @@
+//! Checks the CPU ports of `sampler.glsl` and `denoise.glsl` against what the
+//! shaders themselves compute. `golden/golden.comp` writes their results for
+//! fixed inputs, and `golden/values.txt` keeps them. After changing either
+//! side, rerun the shader with
+//! `cargo test --test golden -- --ignored` on a machine with a Vulkan device.
+
+use std::{fmt::Write, path::Path, sync::Arc};
+
+use anyhow::Context;
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use strale::{
+    math::Vec3,
+    renderer::{
+        denoiser::{denoise, DenoiserInput, GBufferTexel},
+        glsl,
+        render_settings::RenderSettings,
+        renderers::pipeline::{create_compute_pipeline, ComputePipelineDesc},
+        sampler::{pcg_hash, sobol_2d, Sampler, SamplerKind, SCATTER_DIMENSION},
+        vulkan::{
+            buffer::BufferDesc, device::Device, instance::Instance,
+            physical_device::enumerate_physical_devices,
+        },
+    },
+};
+
+const VALUES: &str = include_str!("golden/values.txt");
+
+// The sections of `golden.comp`'s output, in order.
+const HASHES: usize = 16;
+const SOBOL_POINTS: usize = 16;
+const SAMPLERS: usize = 4;
+const SAMPLER_DRAWS: usize = 8;
+const SAMPLE_INDICES: [u32; SAMPLERS] = [0, 1, 17, 1000];
+const SIZE: usize = 8;
+const ITERATIONS: u32 = 3;
+const DENOISE_FRAMES: [u32; 2] = [1, 16];
+
+const HASH_OFFSET: usize = 0;
+const SOBOL_OFFSET: usize = HASH_OFFSET + HASHES;
+const SAMPLER_OFFSET: usize = SOBOL_OFFSET + SOBOL_POINTS * 2;
+const DENOISE_OFFSET: usize = SAMPLER_OFFSET + SAMPLERS * SAMPLER_DRAWS * 2;
+const VALUE_COUNT: usize = DENOISE_OFFSET + DENOISE_FRAMES.len() * SIZE * SIZE * 3;
+
+/// The values under `[name]` in `values.txt`.
+fn section(name: &str) -> Vec<&'static str> {
+    let header = format!("[{}]", name);
+    VALUES
+        .lines()
+        .skip_while(|line| *line != header)
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .collect()
+}
+
+fn floats(name: &str) -> Vec<f32> {
+    section(name)
+        .iter()
+        .map(|value| value.parse().unwrap())
+        .collect()
+}
+
+/// `toFloat()` in `sampler.glsl`.
+fn to_float(x: u32) -> f32 {
+    (x >> 8) as f32 * (1.0 / 16_777_216.0)
+}
+
+#[test]
+fn pcg_hash_matches_the_gpu() {
+    let expected: Vec<u32> = section("pcg_hash")
+        .iter()
+        .map(|value| value.parse().unwrap())
+        .collect();
+    assert_eq!(expected.len(), HASHES);
+
+    for (i, expected) in expected.into_iter().enumerate() {
+        assert_eq!(pcg_hash((i as u32).wrapping_mul(0x9e37_79b9)), expected);
+    }
+}
+
+#[test]
+fn sobol_2d_matches_the_gpu() {
+    let expected = floats("sobol_2d");
+    assert_eq!(expected.len(), SOBOL_POINTS * 2);
+
+    for (i, expected) in expected.chunks(2).enumerate() {
+        assert_eq!(sobol_2d(i as u32, 0x2545_f491), [expected[0], expected[1]]);
+    }
+}
+
+#[test]
+fn samplers_match_the_gpu() {
+    for (kind, name) in [
+        (SamplerKind::Pcg, "pcg_sampler"),
+        (SamplerKind::Sobol, "sobol_sampler"),
+    ] {
+        let expected = floats(name);
+        assert_eq!(expected.len(), SAMPLERS * SAMPLER_DRAWS * 2);
+
+        for (i, expected) in expected.chunks(SAMPLER_DRAWS * 2).enumerate() {
+            let i = i as u32;
+            let mut sampler = Sampler::new(
+                kind,
+                [37 * i, 5 + 11 * i],
+                1 + 3 * i,
+                SAMPLE_INDICES[i as usize],
+            );
+
+            for (draw, expected) in expected.chunks(2).enumerate() {
+                // The last draw skips ahead to a later bounce.
+                if draw == SAMPLER_DRAWS - 1 {
+                    sampler.seek_dimension(2, SCATTER_DIMENSION);
+                }
+
+                assert_eq!(
+                    sampler.sample_2d(),
+                    [expected[0], expected[1]],
+                    "{:?}: draw {} of sampler {}",
+                    kind,
+                    draw,
+                    i
+                );
+            }
+        }
+    }
+}
+
+/// `loadDenoiserInput()` in `golden.comp`: a slanted wall on the right of a
+/// flat one, with noisy colors and texture detail.
+fn denoiser_input() -> (Vec<Vec3>, Vec<GBufferTexel>) {
+    (0..SIZE * SIZE)
+        .map(|i| {
+            let u: Vec<f32> = (0..6)
+                .map(|k| to_float(pcg_hash(6 * i as u32 + k)))
+                .collect();
+
+            let x = i % SIZE;
+            let right = x >= SIZE / 2;
+            let albedo = Vec3::new(0.25 + 0.75 * u[3], 0.5, 0.25 + 0.75 * u[4]);
+            let color = Vec3::new(u[0], u[1], u[2]) * albedo;
+            let demodulated = Vec3::new(color.x / albedo.x, color.y / albedo.y, color.z / albedo.z);
+            let l = 0.2126 * demodulated.x + 0.7152 * demodulated.y + 0.0722 * demodulated.z;
+
+            let texel = GBufferTexel {
+                normal: if right {
+                    Vec3::new(0.6, 0.0, 0.8)
+                } else {
+                    Vec3::Z
+                },
+                depth: if right { 6.0 + 0.25 * x as f32 } else { 4.0 },
+                albedo,
+                moment: l * l + 0.1 * u[5],
+            };
+            (color, texel)
+        })
+        .unzip()
+}
+
+#[test]
+fn denoiser_matches_the_gpu() {
+    let expected = floats("denoise");
+    assert_eq!(expected.len(), DENOISE_FRAMES.len() * SIZE * SIZE * 3);
+
+    let (color, gbuffer) = denoiser_input();
+    for (frames, expected) in DENOISE_FRAMES
+        .into_iter()
+        .zip(expected.chunks(SIZE * SIZE * 3))
+    {
+        let input = DenoiserInput {
+            width: SIZE,
+            height: SIZE,
+            color: &color,
+            gbuffer: &gbuffer,
+            frames,
+        };
+
+        let denoised = denoise(&input, ITERATIONS).unwrap();
+        for (i, (denoised, expected)) in denoised.iter().zip(expected.chunks(3)).enumerate() {
+            let expected = Vec3::new(expected[0], expected[1], expected[2]);
+            // The GPU's exp() and pow() are less precise than the CPU's.
+            assert!(
+                (*denoised - expected).length() < 1e-4 * expected.length().max(1.0),
+                "{} frames: pixel {} is {:?} on the CPU, {:?} on the GPU",
+                frames,
+                i,
+                denoised,
+                expected
+            );
+        }
+    }
+}
+
+/// Runs `golden.comp` with `kind` for the sampler, returning what it wrote.
+fn dispatch(device: &Arc<Device>, spirv: &[u8], kind: SamplerKind) -> anyhow::Result<Vec<u32>> {
+    let settings = RenderSettings {
+        sampler: kind,
+        ..Default::default()
+    };
+    let mut pipeline = create_compute_pipeline(
+        device,
+        &ComputePipelineDesc::builder()
+            .compute_shader(spirv.to_vec())
+            .specialization(settings.specialization_constants()),
+    )?;
+    let set_layout = pipeline
+        .descriptor_set_layout(0)
+        .context("golden.comp doesn't use descriptor set 0")?;
+
+    let buffer = device.create_buffer(
+        BufferDesc {
+            size: VALUE_COUNT * 4,
+            usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+            memory_location: MemoryLocation::GpuToCpu,
+        },
+        "golden values",
+        None,
+    );
+
+    let descriptor_pool = unsafe {
+        device.raw.create_descriptor_pool(
+            &vk::DescriptorPoolCreateInfo::builder()
+                .pool_sizes(&[vk::DescriptorPoolSize {
+                    ty: vk::DescriptorType::STORAGE_BUFFER,
+                    descriptor_count: 1,
+                }])
+                .max_sets(1),
+            None,
+        )?
+    };
+
+    let values = unsafe {
+        let descriptor_set = device.raw.allocate_descriptor_sets(
+            &vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(std::slice::from_ref(&set_layout)),
+        )?[0];
+
+        let buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(buffer.raw)
+            .range(vk::WHOLE_SIZE)
+            .build();
+        device.raw.update_descriptor_sets(
+            &[vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(&buffer_info))
+                .build()],
+            &[],
+        );
+        pipeline.add_descriptor_set(0, descriptor_set);
+
+        device.with_setup_cb(|cb| {
+            pipeline.bind_pipeline(device, cb);
+            device.raw.cmd_dispatch(cb, 1, 1, 1);
+            device.raw.cmd_pipeline_barrier(
+                cb,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::HOST_READ)
+                    .build()],
+                &[],
+                &[],
+            );
+        });
+
+        let mapped = buffer
+            .allocation
+            .mapped_slice()
+            .context("The golden values aren't host visible")?;
+        let values = bytemuck::cast_slice::<u8, u32>(&mapped[..VALUE_COUNT * 4]).to_vec();
+
+        device.raw.destroy_descriptor_pool(descriptor_pool, None);
+        values
+    };
+
+    device.destroy_buffer(buffer);
+    Ok(values)
+}
+
+fn write_section(out: &mut String, name: &str, values: impl Iterator<Item = String>) {
+    writeln!(out, "[{}]", name).unwrap();
+    for value in values {
+        writeln!(out, "{}", value).unwrap();
+    }
+}
+
+/// `pcg` and `sobol` are `golden.comp`'s output with either sampler.
+fn format_values(pcg: &[u32], sobol: &[u32]) -> String {
+    let as_floats = |values: &[u32]| -> Vec<String> {
+        values
+            .iter()
+            .map(|&bits| format!("{:?}", f32::from_bits(bits)))
+            .collect()
+    };
+
+    let mut out = String::from(
+        "# golden.comp's output, `cargo test --test golden -- --ignored` rewrites it.\n",
+    );
+    write_section(
+        &mut out,
+        "pcg_hash",
+        pcg[HASH_OFFSET..SOBOL_OFFSET].iter().map(u32::to_string),
+    );
+    write_section(
+        &mut out,
+        "sobol_2d",
+        as_floats(&pcg[SOBOL_OFFSET..SAMPLER_OFFSET]).into_iter(),
+    );
+    write_section(
+        &mut out,
+        "pcg_sampler",
+        as_floats(&pcg[SAMPLER_OFFSET..DENOISE_OFFSET]).into_iter(),
+    );
+    write_section(
+        &mut out,
+        "sobol_sampler",
+        as_floats(&sobol[SAMPLER_OFFSET..DENOISE_OFFSET]).into_iter(),
+    );
+    write_section(
+        &mut out,
+        "denoise",
+        as_floats(&pcg[DENOISE_OFFSET..VALUE_COUNT]).into_iter(),
+    );
+    out
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, and rewrites golden/values.txt"]
+fn regenerate_golden_values() -> anyhow::Result<()> {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let shader_dir = manifest_dir
+        .join("../../../assets/shaders")
+        .canonicalize()?;
+
+    let compiler = shaderc::Compiler::new().context("Failed to create shader compiler")?;
+    let spirv = glsl::compile(
+        &compiler,
+        &manifest_dir.join("tests/golden/golden.comp"),
+        &shader_dir,
+        &[],
+    )
+    .map_err(anyhow::Error::msg)?;
+
+    let instance = Instance::builder()
+        .required_extensions(Vec::new())
+        .build()?;
+    let physical_device = enumerate_physical_devices(&instance)?
+        .into_iter()
+        .next()
+        .context("No Vulkan device found")?;
+    let device = Device::create(Arc::new(physical_device))?;
+
+    let pcg = dispatch(&device, spirv.as_binary_u8(), SamplerKind::Pcg)?;
+    let sobol = dispatch(&device, spirv.as_binary_u8(), SamplerKind::Sobol)?;
+
+    std::fs::write(
+        manifest_dir.join("tests/golden/values.txt"),
+        format_values(&pcg, &sobol),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,289 @@
+mod common;
+
+use strale::{
+    math::{Affine3, Ray, Vec3},
+    renderer::{
+        environment::{Environment, EnvironmentDistribution},
+        integrator::{Integrator, SurfaceHit},
+        light::{Light, LightList},
+        material::Material,
+        sampler::{Sampler, SamplerKind},
+        scene::{intersect_triangle, Mesh, Scene, Sphere},
+        texture::Texture,
+    },
+};
+
+use common::Rng;
+
+/// Bounces per path, like `RenderSettings::max_bounces`.
+const MAX_BOUNCES: u32 = 4;
+
+/// A diffuse sphere on a diffuse ground, lit by a small spherical light and a
+/// square area light.
+fn lit_scene() -> Scene {
+    let mut scene = Scene::new();
+    let white = scene.add_material(Material::Lambertian {
+        albedo: Vec3::splat(0.7).into(),
+    });
+    let red = scene.add_material(Material::Lambertian {
+        albedo: Vec3::new(0.7, 0.2, 0.1).into(),
+    });
+    let bulb = scene.add_material(Material::Emissive {
+        radiance: Vec3::new(20.0, 16.0, 12.0),
+    });
+    let panel = scene.add_material(Material::Emissive {
+        radiance: Vec3::new(3.0, 3.0, 4.0),
+    });
+
+    scene.add_sphere(Vec3::new(0.0, -1000.0, 0.0), 1000.0, white);
+    scene.add_sphere(Vec3::new(0.0, 0.5, 0.0), 0.5, red);
+    scene.add_sphere(Vec3::new(1.2, 1.6, 0.3), 0.25, bulb);
+
+    let quad = scene.add_mesh(Mesh {
+        positions: vec![
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(-1.0, 0.0, 1.0),
+        ],
+        indices: vec![0, 1, 2, 0, 2, 3],
+    });
+    scene.add_instance(
+        quad,
+        Affine3::from_translation(Vec3::new(-0.8, 2.5, -0.5))
+            * Affine3::from_scale(Vec3::splat(0.6)),
+        panel,
+    );
+
+    scene
+}
+
+/// Cosine weighted like `scatter()` in `material.glsl`.
+fn scatter_diffuse(hit: &SurfaceHit, rng: &mut Rng) -> Ray {
+    let mut direction = hit.normal + rng.unit_vector();
+    if direction.dot(direction) < 1e-8 {
+        direction = hit.normal;
+    }
+    Ray::new(hit.point + hit.normal * 0.001, direction.normalize())
+}
+
+/// Only finds light by hitting it or escaping to the sky, the reference the
+/// integrator's light sampling has to agree with.
+fn path_trace(integrator: &Integrator, mut ray: Ray, rng: &mut Rng) -> Vec3 {
+    let mut radiance = Vec3::ZERO;
+    let mut throughput = Vec3::ONE;
+
+    for _ in 0..integrator.max_bounces {
+        let hit = match integrator.raycast(&ray, f32::MAX) {
+            Some(hit) => hit,
+            None => {
+                radiance += throughput * integrator.environment_radiance(ray.direction);
+                break;
+            }
+        };
+
+        match hit.material {
+            Material::Emissive { radiance: emission } => {
+                radiance += throughput * emission;
+                break;
+            }
+            Material::Lambertian {
+                albedo: Texture::Solid(albedo),
+            } => {
+                ray = scatter_diffuse(&hit, rng);
+                throughput = throughput * albedo;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    radiance
+}
+
+/// Mean and standard error of `samples` estimates.
+fn estimate(samples: usize, mut estimator: impl FnMut(usize) -> f32) -> (f64, f64) {
+    let mut sum = 0.0;
+    let mut sum_squared = 0.0;
+    for i in 0..samples {
+        let value = estimator(i) as f64;
+        sum += value;
+        sum_squared += value * value;
+    }
+
+    let n = samples as f64;
+    let mean = sum / n;
+    let variance = (sum_squared / n - mean * mean).max(0.0);
+    (mean, (variance / n).sqrt())
+}
+
+/// Compares `Integrator::ray_color` against `path_trace` for rays from the
+/// front of `lit_scene`.
+fn assert_mis_converges(integrator: &Integrator) {
+    let origin = Vec3::new(0.0, 1.2, 3.5);
+    let rays: Vec<Ray> = [
+        Vec3::new(0.0, 0.5, 0.0),
+        Vec3::new(0.3, 0.8, 0.3),
+        Vec3::new(-1.5, 0.0, 0.5),
+        Vec3::new(1.0, 0.0, 1.0),
+        Vec3::new(0.0, 0.0, -2.0),
+        Vec3::new(2.0, 0.0, -1.0),
+    ]
+    .iter()
+    .map(|&target| Ray::new(origin, (target - origin).normalize()))
+    .collect();
+
+    let luminance = |c: Vec3| (c.x + c.y + c.z) / 3.0;
+    let samples = 120_000;
+
+    let mut rng = Rng(0x2545_f491);
+    let (pure, pure_error) = estimate(samples, |i| {
+        luminance(path_trace(integrator, rays[i % rays.len()], &mut rng))
+    });
+
+    let (mis, mis_error) = estimate(samples, |i| {
+        let sampler = Sampler::new(SamplerKind::Pcg, [7, 3], 0, i as u32);
+        luminance(integrator.ray_color(rays[i % rays.len()], sampler))
+    });
+
+    let error = (pure_error * pure_error + mis_error * mis_error).sqrt();
+    assert!(
+        (pure - mis).abs() < 4.0 * error,
+        "path tracing: {} ± {}, with light sampling: {} ± {}",
+        pure,
+        pure_error,
+        mis,
+        mis_error
+    );
+
+    // The point of sampling lights.
+    assert!(
+        mis_error < 0.5 * pure_error,
+        "light sampling didn't reduce noise: {} vs {}",
+        mis_error,
+        pure_error
+    );
+}
+
+#[test]
+fn mis_converges_to_path_tracing() {
+    let scene = lit_scene();
+    let lights = LightList::new(&scene);
+    assert_eq!(lights.lights.len(), 3);
+
+    let integrator = Integrator {
+        scene: &scene,
+        lights: &lights,
+        environment: None,
+        max_bounces: MAX_BOUNCES,
+    };
+    // The sky gradient is only found by escaping.
+    assert_eq!(integrator.environment_probability(), 0.0);
+
+    assert_mis_converges(&integrator);
+}
+
+#[test]
+fn mis_with_an_environment_converges_to_path_tracing() {
+    let scene = lit_scene();
+    let lights = LightList::new(&scene);
+
+    // A dim sky with a brighter patch above the scene.
+    let (width, height) = (16, 8);
+    let mut pixels = vec![Vec3::new(0.2, 0.25, 0.3); (width * height) as usize];
+    for x in 6..10 {
+        pixels[(width + x) as usize] = Vec3::splat(4.0);
+    }
+    let environment = Environment::new(width, height, pixels).unwrap();
+    let distribution = EnvironmentDistribution::new(&environment);
+
+    let integrator = Integrator {
+        scene: &scene,
+        lights: &lights,
+        environment: Some((&environment, &distribution)),
+        max_bounces: MAX_BOUNCES,
+    };
+    assert_eq!(integrator.environment_probability(), 0.5);
+
+    assert_mis_converges(&integrator);
+
+    let unlit = Scene::new();
+    let no_lights = LightList::new(&unlit);
+    let integrator = Integrator {
+        scene: &unlit,
+        lights: &no_lights,
+        ..integrator
+    };
+    assert_eq!(integrator.environment_probability(), 1.0);
+}
+
+#[test]
+fn light_samples_hit_the_light_with_matching_pdf() {
+    let lights = [
+        Light::Sphere {
+            center: Vec3::new(0.5, 2.0, -1.0),
+            radius: 0.4,
+            material: 0,
+        },
+        Light::Triangle {
+            vertices: [
+                Vec3::new(-1.0, 2.0, -1.0),
+                Vec3::new(1.0, 2.5, -1.0),
+                Vec3::new(0.0, 2.0, 1.0),
+            ],
+            material: 0,
+        },
+    ];
+    let origins = [
+        Vec3::ZERO,
+        Vec3::new(3.0, 1.0, 2.0),
+        Vec3::new(-2.0, 4.0, 0.5),
+    ];
+
+    let mut rng = Rng(0x1234_5678);
+    for light in &lights {
+        for &origin in &origins {
+            for _ in 0..1000 {
+                let u = [rng.next_f32(), rng.next_f32()];
+                let sample = light
+                    .sample(origin, u)
+                    .expect("origin is outside the light");
+                let ray = Ray::new(origin, sample.direction);
+
+                let t = match *light {
+                    Light::Sphere { center, radius, .. } => Sphere {
+                        center,
+                        radius,
+                        material: 0,
+                        end_center: None,
+                    }
+                    .intersect(&ray, 0.0, f32::MAX),
+                    Light::Triangle { vertices, .. } => {
+                        // Samples on the edges can miss by rounding.
+                        let grown = vertices.map(|v| v + (v - centroid(vertices)) * 1e-4);
+                        intersect_triangle(&ray, grown, 0.0, f32::MAX)
+                    }
+                }
+                .unwrap_or_else(|| panic!("{:?} from {:?} missed the light", sample, origin));
+
+                assert!(
+                    (t - sample.distance).abs() < 1e-3 * t,
+                    "{:?}: the light is at {}",
+                    sample,
+                    t
+                );
+
+                let pdf = light.pdf(origin, ray.at(sample.distance));
+                assert!(
+                    (pdf - sample.pdf).abs() < 1e-3 * pdf,
+                    "{:?}: pdf() gives {}",
+                    sample,
+                    pdf
+                );
+            }
+        }
+    }
+}
+
+fn centroid(vertices: [Vec3; 3]) -> Vec3 {
+    (vertices[0] + vertices[1] + vertices[2]) / 3.0
+}
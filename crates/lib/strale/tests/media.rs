@@ -0,0 +1,221 @@
+mod common;
+
+use std::f32::consts::PI;
+
+use strale::{
+    math::{Ray, Vec3},
+    renderer::{
+        medium::{sample_collision, transmittance, Fog, Medium, PhaseFunction},
+        scene::Scene,
+    },
+};
+
+use common::{Rng, CHI_SQUARE_31};
+
+fn medium(density: f32, phase: PhaseFunction) -> Medium {
+    Medium {
+        density,
+        albedo: Vec3::splat(0.5),
+        phase,
+    }
+}
+
+#[test]
+fn phase_samples_match_pdf() {
+    let mut rng = Rng(0x2545_f491);
+    let direction = Vec3::new(1.0, 2.0, -0.5).normalize();
+    let samples = 20_000;
+    let bins = 32;
+
+    for phase in [
+        PhaseFunction::Isotropic,
+        PhaseFunction::HenyeyGreenstein { g: -0.6 },
+        PhaseFunction::HenyeyGreenstein { g: 0.3 },
+        PhaseFunction::HenyeyGreenstein { g: 0.7 },
+    ] {
+        // The chance of each bin of cosines, over the ring of directions it
+        // covers.
+        let steps = 256;
+        let expected: Vec<f64> = (0..bins)
+            .map(|bin| {
+                (0..steps)
+                    .map(|step| {
+                        let width = 2.0 / (bins * steps) as f32;
+                        let cosine = -1.0 + ((bin * steps + step) as f32 + 0.5) * width;
+                        (2.0 * PI * phase.pdf(cosine) * width) as f64
+                    })
+                    .sum::<f64>()
+            })
+            .collect();
+        let total: f64 = expected.iter().sum();
+        assert!(
+            (total - 1.0).abs() < 1e-3,
+            "{:?} integrates to {}",
+            phase,
+            total
+        );
+
+        let mut counts = vec![0u32; bins];
+        let mut mean_cosine = 0.0;
+        for _ in 0..samples {
+            let scattered = phase.sample(direction, [rng.next_f32(), rng.next_f32()]);
+            assert!((scattered.length() - 1.0).abs() < 1e-4);
+
+            let cosine = scattered.dot(direction).clamp(-1.0, 0.999_999);
+            counts[((cosine + 1.0) * 0.5 * bins as f32) as usize] += 1;
+            mean_cosine += cosine as f64 / samples as f64;
+        }
+
+        let statistic: f64 = counts
+            .iter()
+            .zip(&expected)
+            .map(|(&count, p)| (count as f64 - p * samples as f64).powi(2) / (p * samples as f64))
+            .sum();
+        assert!(
+            statistic < CHI_SQUARE_31,
+            "{:?} has a chi-square of {}",
+            phase,
+            statistic
+        );
+
+        let g = match phase {
+            PhaseFunction::Isotropic => 0.0,
+            PhaseFunction::HenyeyGreenstein { g } => g as f64,
+        };
+        assert!(
+            (mean_cosine - g).abs() < 0.02,
+            "{:?} has a mean cosine of {}",
+            phase,
+            mean_cosine
+        );
+    }
+}
+
+#[test]
+fn collisions_follow_beer_lambert() {
+    let mut scene = Scene::new();
+    scene.add_volume(Vec3::ZERO, 1.0, medium(2.0, PhaseFunction::Isotropic));
+
+    // Through the middle of the sphere, entering at 4 and leaving at 6.
+    let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let samples = 10_000;
+
+    for (t_max, inside) in [(100.0, 2.0f32), (5.0, 1.0), (3.0, 0.0)] {
+        let mut collisions = 0;
+        for i in 0..samples {
+            let u = (i as f32 + 0.5) / samples as f32;
+            if let Some(collision) = sample_collision(&scene, &ray, t_max, u) {
+                assert!(
+                    (4.0..f32::min(6.0, t_max)).contains(&collision.t),
+                    "collided at {}",
+                    collision.t
+                );
+                collisions += 1;
+            }
+        }
+
+        let passed = 1.0 - collisions as f32 / samples as f32;
+        let expected = (-2.0 * inside).exp();
+        assert!(
+            (passed - expected).abs() < 1e-3,
+            "{} passed up to {}, expected {}",
+            passed,
+            t_max,
+            expected
+        );
+        assert!((transmittance(&scene, &ray, t_max) - expected).abs() < 1e-5);
+    }
+}
+
+#[test]
+fn overlapping_media_collide_in_proportion_to_density() {
+    let mut scene = Scene::new();
+    let fog = medium(0.05, PhaseFunction::Isotropic);
+    let smoke = medium(0.5, PhaseFunction::HenyeyGreenstein { g: 0.5 });
+    scene.fog = Some(Fog {
+        medium: fog,
+        top: 10.0,
+    });
+    scene.add_volume(Vec3::ZERO, 1.0, smoke);
+
+    let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let t_max = 10.0;
+    let mut rng = Rng(0x0bad_cafe);
+    let samples = 40_000;
+
+    let mut passed = 0;
+    let (mut in_sphere, mut in_smoke) = (0, 0);
+    for _ in 0..samples {
+        match sample_collision(&scene, &ray, t_max, rng.next_f32()) {
+            None => passed += 1,
+            Some(collision) if (4.0..6.0).contains(&collision.t) => {
+                in_sphere += 1;
+                if collision.medium == smoke {
+                    in_smoke += 1;
+                }
+            }
+            Some(collision) => assert_eq!(collision.medium, fog),
+        }
+    }
+
+    // 10 units of fog and 2 of smoke.
+    let expected = transmittance(&scene, &ray, t_max);
+    assert!((expected - (-1.5f32).exp()).abs() < 1e-5);
+    let passed = passed as f32 / samples as f32;
+    assert!(
+        (passed - expected).abs() < 0.01,
+        "{} passed, expected {}",
+        passed,
+        expected
+    );
+
+    let smoke_share = in_smoke as f32 / in_sphere as f32;
+    assert!(
+        (smoke_share - 0.5 / 0.55).abs() < 0.02,
+        "the smoke took {} of the collisions in the sphere",
+        smoke_share
+    );
+}
+
+#[test]
+fn fog_only_fills_below_its_top() {
+    let fog = Fog {
+        medium: medium(1.0, PhaseFunction::Isotropic),
+        top: 1.0,
+    };
+    let ray = |origin: [f32; 3], direction: [f32; 3]| {
+        Ray::new(Vec3::from(origin), Vec3::from(direction).normalize())
+    };
+
+    assert_eq!(
+        fog.interval(&ray([0.0, 0.0, 0.0], [0.0, 1.0, 0.0]), 100.0),
+        Some((0.0, 1.0))
+    );
+    assert_eq!(
+        fog.interval(&ray([0.0, 3.0, 0.0], [0.0, -1.0, 0.0]), 100.0),
+        Some((2.0, 100.0))
+    );
+    assert_eq!(
+        fog.interval(&ray([0.0, 3.0, 0.0], [0.0, -1.0, 0.0]), 2.5),
+        Some((2.0, 2.5))
+    );
+    assert_eq!(
+        fog.interval(&ray([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]), 7.0),
+        Some((0.0, 7.0))
+    );
+    assert_eq!(
+        fog.interval(&ray([0.0, 3.0, 0.0], [1.0, 0.0, 0.0]), 7.0),
+        None
+    );
+    assert_eq!(
+        fog.interval(&ray([0.0, 3.0, 0.0], [0.0, 1.0, 0.0]), 7.0),
+        None
+    );
+
+    // Looking up out of the fog, the sky is still visible.
+    let mut scene = Scene::new();
+    scene.fog = Some(fog);
+    let up = ray([0.0, 0.0, 0.0], [0.0, 1.0, 1.0]);
+    let t = 2f32.sqrt();
+    assert!((transmittance(&scene, &up, f32::MAX) - (-t).exp()).abs() < 1e-5);
+}
@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+
+use ash::vk;
+use strale::renderer::vulkan::shader::{
+    DescriptorSetLayoutDesc, PipelineReflection, ReflectedBinding, ShaderReflection,
+};
+
+fn storage_buffer(stage_flags: vk::ShaderStageFlags) -> ReflectedBinding {
+    ReflectedBinding {
+        ty: vk::DescriptorType::STORAGE_BUFFER,
+        count: Some(1),
+        stage_flags,
+    }
+}
+
+/// A stage that uses `bindings` of set 0.
+fn stage(
+    stage: vk::ShaderStageFlags,
+    bindings: impl IntoIterator<Item = (u32, ReflectedBinding)>,
+) -> ShaderReflection {
+    ShaderReflection {
+        stage,
+        descriptor_sets: BTreeMap::from([(0, bindings.into_iter().collect())]),
+        ..Default::default()
+    }
+}
+
+/// An external layout for set 0 with a storage buffer at each of `bindings`.
+fn external_set(
+    bindings: &[u32],
+    stage_flags: vk::ShaderStageFlags,
+) -> (u32, DescriptorSetLayoutDesc) {
+    let bindings = bindings
+        .iter()
+        .map(|binding| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(*binding)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .stage_flags(stage_flags)
+                .build()
+        })
+        .collect();
+
+    (
+        0,
+        DescriptorSetLayoutDesc {
+            layout: vk::DescriptorSetLayout::null(),
+            bindings,
+        },
+    )
+}
+
+fn error(result: anyhow::Result<PipelineReflection>) -> String {
+    format!("{:#}", result.expect_err("the layout should be rejected"))
+}
+
+#[test]
+fn push_constant_size_must_match_the_shaders() {
+    // Like `denoise.comp`, three u32s of push constants.
+    let reflection = ShaderReflection {
+        stage: vk::ShaderStageFlags::COMPUTE,
+        push_constants: Some(vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: 12,
+        }),
+        ..Default::default()
+    };
+
+    let layout = PipelineReflection::new(&[&reflection], &[], 12).unwrap();
+    assert_eq!(layout.push_constants.map(|range| range.size), Some(12));
+
+    let message = error(PipelineReflection::new(&[&reflection], &[], 8));
+    assert!(
+        message.contains("Push constant size mismatch"),
+        "{}",
+        message
+    );
+
+    let message = error(PipelineReflection::new(&[&reflection], &[], 0));
+    assert!(
+        message.contains("shaders expect 12 bytes, got 0"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn push_constant_ranges_of_stages_are_merged() {
+    let range = |stage_flags, offset, size| {
+        Some(vk::PushConstantRange {
+            stage_flags,
+            offset,
+            size,
+        })
+    };
+    let vertex = ShaderReflection {
+        stage: vk::ShaderStageFlags::VERTEX,
+        push_constants: range(vk::ShaderStageFlags::VERTEX, 0, 8),
+        ..Default::default()
+    };
+    let fragment = ShaderReflection {
+        stage: vk::ShaderStageFlags::FRAGMENT,
+        push_constants: range(vk::ShaderStageFlags::FRAGMENT, 16, 4),
+        ..Default::default()
+    };
+
+    let layout = PipelineReflection::new(&[&vertex, &fragment], &[], 20).unwrap();
+    let push_constants = layout.push_constants.unwrap();
+    assert_eq!(push_constants.offset, 0);
+    assert_eq!(push_constants.size, 20);
+    assert_eq!(
+        push_constants.stage_flags,
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+    );
+
+    let message = error(PipelineReflection::new(&[&vertex, &fragment], &[], 8));
+    assert!(
+        message.contains("shaders expect 20 bytes, got 8"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn push_constants_the_shaders_lack_are_rejected() {
+    let reflection = stage(vk::ShaderStageFlags::COMPUTE, []);
+
+    let message = error(PipelineReflection::new(&[&reflection], &[], 4));
+    assert!(
+        message.contains("shaders expect 0 bytes, got 4"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn stages_are_merged() {
+    let vertex = stage(
+        vk::ShaderStageFlags::VERTEX,
+        [(0, storage_buffer(vk::ShaderStageFlags::VERTEX))],
+    );
+    let fragment = stage(
+        vk::ShaderStageFlags::FRAGMENT,
+        [
+            (0, storage_buffer(vk::ShaderStageFlags::FRAGMENT)),
+            (1, storage_buffer(vk::ShaderStageFlags::FRAGMENT)),
+        ],
+    );
+
+    let layout = PipelineReflection::new(&[&vertex, &fragment], &[], 0).unwrap();
+    let set = &layout.descriptor_sets[&0];
+    assert_eq!(
+        set[&0].stage_flags,
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+    );
+    assert_eq!(set[&1].stage_flags, vk::ShaderStageFlags::FRAGMENT);
+}
+
+#[test]
+fn stages_must_agree_on_binding_types() {
+    let vertex = stage(
+        vk::ShaderStageFlags::VERTEX,
+        [(0, storage_buffer(vk::ShaderStageFlags::VERTEX))],
+    );
+    let fragment = stage(
+        vk::ShaderStageFlags::FRAGMENT,
+        [(
+            0,
+            ReflectedBinding {
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                count: Some(1),
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            },
+        )],
+    );
+
+    let message = error(PipelineReflection::new(&[&vertex, &fragment], &[], 0));
+    assert!(
+        message.contains("Shader stages disagree on set 0 binding 0"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn external_sets_must_contain_every_binding() {
+    let compute = stage(
+        vk::ShaderStageFlags::COMPUTE,
+        [
+            (0, storage_buffer(vk::ShaderStageFlags::COMPUTE)),
+            (3, storage_buffer(vk::ShaderStageFlags::COMPUTE)),
+        ],
+    );
+
+    let set = external_set(&[0, 3], vk::ShaderStageFlags::ALL);
+    PipelineReflection::new(&[&compute], &[set], 0).unwrap();
+
+    let set = external_set(&[0], vk::ShaderStageFlags::ALL);
+    let message = error(PipelineReflection::new(&[&compute], &[set], 0));
+    assert!(
+        message.contains("Set 0 binding 3 is used by COMPUTE but missing"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn external_sets_must_match_binding_types() {
+    let compute = stage(
+        vk::ShaderStageFlags::COMPUTE,
+        [(
+            0,
+            ReflectedBinding {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                count: Some(1),
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+            },
+        )],
+    );
+
+    let set = external_set(&[0], vk::ShaderStageFlags::ALL);
+    let message = error(PipelineReflection::new(&[&compute], &[set], 0));
+    assert!(
+        message.contains("Set 0 binding 0 type mismatch"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn external_sets_must_have_enough_descriptors() {
+    let compute = stage(
+        vk::ShaderStageFlags::COMPUTE,
+        [(
+            0,
+            ReflectedBinding {
+                count: Some(4),
+                ..storage_buffer(vk::ShaderStageFlags::COMPUTE)
+            },
+        )],
+    );
+
+    let set = external_set(&[0], vk::ShaderStageFlags::ALL);
+    let message = error(PipelineReflection::new(&[&compute], &[set], 0));
+    assert!(
+        message.contains("Set 0 binding 0 count mismatch"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn external_sets_must_be_visible_to_the_stages() {
+    let compute = stage(
+        vk::ShaderStageFlags::COMPUTE,
+        [(0, storage_buffer(vk::ShaderStageFlags::COMPUTE))],
+    );
+
+    let set = external_set(&[0], vk::ShaderStageFlags::FRAGMENT);
+    let message = error(PipelineReflection::new(&[&compute], &[set], 0));
+    assert!(
+        message.contains("Set 0 binding 0 is not visible to COMPUTE"),
+        "{}",
+        message
+    );
+}
@@ -0,0 +1,259 @@
+mod common;
+
+use strale::renderer::sampler::{
+    sample_ball, sample_disk, sample_sphere, sobol_2d, Sampler, SamplerKind,
+};
+
+use common::{CHI_SQUARE_31, CHI_SQUARE_63};
+
+const KINDS: [SamplerKind; 2] = [SamplerKind::Pcg, SamplerKind::Sobol];
+
+/// The draws of a 64x64 image at 4 samples per pixel, `draw` picking the
+/// numbers from every path's sampler.
+fn draw_image<T>(
+    kind: SamplerKind,
+    frame_index: u32,
+    mut draw: impl FnMut(&mut Sampler) -> T,
+) -> Vec<T> {
+    let mut values = Vec::new();
+    for y in 0..64 {
+        for x in 0..64 {
+            for sample in 0..4 {
+                let mut sampler = Sampler::new(kind, [x, y], frame_index, sample);
+                values.push(draw(&mut sampler));
+            }
+        }
+    }
+    values
+}
+
+fn chi_square(counts: &[u32]) -> f64 {
+    let total: u32 = counts.iter().sum();
+    let expected = total as f64 / counts.len() as f64;
+    counts
+        .iter()
+        .map(|&count| (count as f64 - expected).powi(2) / expected)
+        .sum()
+}
+
+fn bin(u: f32, bins: usize) -> usize {
+    assert!((0.0..1.0).contains(&u), "{} is outside [0, 1)", u);
+    (u * bins as f32) as usize
+}
+
+fn histogram_1d(values: &[f32]) -> Vec<u32> {
+    let mut counts = vec![0; 32];
+    for &u in values {
+        counts[bin(u, 32)] += 1;
+    }
+    counts
+}
+
+fn histogram_2d(values: &[[f32; 2]]) -> Vec<u32> {
+    let mut counts = vec![0; 64];
+    for &[u, v] in values {
+        counts[bin(v, 8) * 8 + bin(u, 8)] += 1;
+    }
+    counts
+}
+
+#[test]
+fn every_dimension_is_uniform() {
+    for kind in KINDS {
+        let points = draw_image(kind, 7, |sampler| {
+            (0..8).map(|_| sampler.sample_2d()).collect::<Vec<_>>()
+        });
+
+        for dimension in 0..8 {
+            for axis in 0..2 {
+                let values: Vec<f32> = points.iter().map(|p| p[dimension][axis]).collect();
+                let statistic = chi_square(&histogram_1d(&values));
+                assert!(
+                    statistic < CHI_SQUARE_31,
+                    "{:?}: axis {} of dimension {} has a chi-square of {}",
+                    kind,
+                    axis,
+                    dimension,
+                    statistic
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn dimensions_are_independent() {
+    for kind in KINDS {
+        let points = draw_image(kind, 3, |sampler| {
+            (0..6).map(|_| sampler.sample_2d()).collect::<Vec<_>>()
+        });
+
+        for a in 0..6 {
+            for b in a..6 {
+                // Within a dimension, the two axes of the point.
+                let pairs: Vec<[f32; 2]> = points
+                    .iter()
+                    .map(|p| if a == b { p[a] } else { [p[a][0], p[b][1]] })
+                    .collect();
+                let statistic = chi_square(&histogram_2d(&pairs));
+                assert!(
+                    statistic < CHI_SQUARE_63,
+                    "{:?}: dimensions {} and {} have a chi-square of {}",
+                    kind,
+                    a,
+                    b,
+                    statistic
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn pixels_and_frames_are_independent() {
+    for kind in KINDS {
+        let first = |pixel: [u32; 2], frame_index: u32| {
+            Sampler::new(kind, pixel, frame_index, 0).sample_1d()
+        };
+
+        let mut neighbours = Vec::new();
+        let mut frames = Vec::new();
+        for y in 0..64 {
+            for x in 0..64 {
+                neighbours.push([first([x, y], 0), first([x + 1, y], 0)]);
+                frames.push([first([x, y], 0), first([x, y], 1)]);
+            }
+        }
+
+        let statistic = chi_square(&histogram_2d(&neighbours));
+        assert!(
+            statistic < CHI_SQUARE_63,
+            "{:?}: neighbouring pixels have a chi-square of {}",
+            kind,
+            statistic
+        );
+
+        // The Sobol sequence only moves on with the sample index.
+        if kind == SamplerKind::Pcg {
+            let statistic = chi_square(&histogram_2d(&frames));
+            assert!(
+                statistic < CHI_SQUARE_63,
+                "consecutive frames have a chi-square of {}",
+                statistic
+            );
+        }
+    }
+}
+
+#[test]
+fn sobol_points_are_stratified() {
+    let log_count = 8;
+    let count = 1 << log_count;
+
+    for seed in [0, 1, 0xdead_beef] {
+        // Every block of points accumulation adds, not just the first.
+        for block in 0..3 {
+            let points: Vec<[f32; 2]> = (0..count)
+                .map(|i| sobol_2d(block * count + i, seed))
+                .collect();
+
+            // A (0, 8, 2)-net has one point in each of these grids' cells.
+            for log_columns in 0..=log_count {
+                let columns = 1 << log_columns;
+                let rows = count >> log_columns;
+                let mut cells = vec![0; count as usize];
+                for &[u, v] in &points {
+                    cells[bin(v, rows as usize) * columns + bin(u, columns)] += 1;
+                }
+                assert!(
+                    cells.iter().all(|&cell| cell == 1),
+                    "block {} of seed {:#x} isn't stratified in {}x{} cells",
+                    block,
+                    seed,
+                    columns,
+                    rows
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn sobol_integrates_with_less_error() {
+    // A quarter disk, whose edge keeps the error from vanishing.
+    let expected = std::f64::consts::FRAC_PI_4;
+    let samples = 64;
+
+    let rms_error = |kind: SamplerKind| {
+        let mut squared_error = 0.0;
+        let pixels = 256;
+        for x in 0..pixels {
+            let mut sum = 0.0;
+            for sample in 0..samples {
+                let mut sampler = Sampler::new(kind, [x, 0], 0, sample);
+                sampler.seek_dimension(1, 0);
+                let [u, v] = sampler.sample_2d();
+                if u * u + v * v < 1.0 {
+                    sum += 1.0;
+                }
+            }
+            squared_error += (sum / samples as f64 - expected).powi(2);
+        }
+        (squared_error / pixels as f64).sqrt()
+    };
+
+    let pcg = rms_error(SamplerKind::Pcg);
+    let sobol = rms_error(SamplerKind::Sobol);
+    assert!(
+        sobol < 0.5 * pcg,
+        "Sobol error {} against PCG error {}",
+        sobol,
+        pcg
+    );
+}
+
+#[test]
+fn sphere_ball_and_disk_samples_are_uniform() {
+    let points = draw_image(SamplerKind::Pcg, 0, |sampler| {
+        let [a, b] = sampler.sample_2d();
+        let [c, _] = sampler.sample_2d();
+        (
+            sample_sphere([a, b]),
+            sample_ball([a, b, c]),
+            sample_disk([a, b]),
+        )
+    });
+
+    // Archimedes: heights on the sphere are uniform, and so are the angles
+    // around the axis.
+    let heights: Vec<f32> = points.iter().map(|(p, _, _)| (p.z + 1.0) * 0.5).collect();
+    let angles: Vec<f32> = points
+        .iter()
+        .map(|(p, _, _)| (p.y.atan2(p.x) / std::f32::consts::TAU).rem_euclid(1.0))
+        .collect();
+    // The cube of the radius in the ball.
+    let volumes: Vec<f32> = points.iter().map(|(_, p, _)| p.length().powi(3)).collect();
+    // The square of the radius on the disk, and the angle around it.
+    let areas: Vec<f32> = points.iter().map(|(_, _, [x, y])| x * x + y * y).collect();
+    let disk_angles: Vec<f32> = points
+        .iter()
+        .map(|(_, _, [x, y])| (y.atan2(*x) / std::f32::consts::TAU).rem_euclid(1.0))
+        .collect();
+
+    for (name, values) in [
+        ("height", heights),
+        ("angle", angles),
+        ("volume", volumes),
+        ("area", areas),
+        ("disk angle", disk_angles),
+    ] {
+        let values: Vec<f32> = values.into_iter().map(|u| u.min(0.999_999)).collect();
+        let statistic = chi_square(&histogram_1d(&values));
+        assert!(
+            statistic < CHI_SQUARE_31,
+            "the {} has a chi-square of {}",
+            name,
+            statistic
+        );
+    }
+}
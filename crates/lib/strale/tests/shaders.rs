@@ -0,0 +1,57 @@
+use strale::renderer::{
+    glsl::{motion_blur_variant, ray_query_variant, MOTION_BLUR_SHADERS, RAY_QUERY_SHADERS},
+    renderers::{
+        ray_tracing::RayTracingPipeline, triangles::TrianglesPipeline, wavefront::WavefrontPipeline,
+    },
+    shader_compiler::embedded_shader,
+};
+
+/// Every tracer starts from embedded SPIR-V, so none of them depends on the
+/// source tree at runtime.
+#[test]
+fn tracer_shaders_are_embedded() {
+    for sources in [
+        TrianglesPipeline::SOURCES,
+        RayTracingPipeline::SOURCES,
+        WavefrontPipeline::SOURCES,
+    ] {
+        for name in sources {
+            assert!(embedded_shader(name).is_ok(), "{} isn't embedded", name);
+        }
+    }
+}
+
+/// The tracers that trace shadow rays with ray queries on devices that
+/// support them.
+#[test]
+fn ray_query_variants_are_embedded() {
+    for name in RAY_QUERY_SHADERS {
+        assert!(
+            TrianglesPipeline::SOURCES.contains(name) || WavefrontPipeline::SOURCES.contains(name)
+        );
+        assert!(
+            embedded_shader(&ray_query_variant(name)).is_ok(),
+            "The ray query variant of {} isn't embedded",
+            name
+        );
+    }
+}
+
+/// The ray tracing pipeline's shaders for devices with ray tracing motion
+/// blur.
+#[test]
+fn motion_blur_variants_are_embedded() {
+    for name in MOTION_BLUR_SHADERS {
+        assert!(RayTracingPipeline::SOURCES.contains(name));
+        assert!(
+            embedded_shader(&motion_blur_variant(name)).is_ok(),
+            "The motion blur variant of {} isn't embedded",
+            name
+        );
+    }
+}
+
+#[test]
+fn unknown_shaders_are_an_error() {
+    assert!(embedded_shader("raytrace.rgen.spv").is_err());
+}
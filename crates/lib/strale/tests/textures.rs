@@ -0,0 +1,196 @@
+mod common;
+
+use strale::{
+    math::Vec3,
+    renderer::{
+        material::Material,
+        scene::{sphere_uv, Scene},
+        texture::{perlin_noise, turbulence, Image, Texture},
+    },
+};
+
+use common::Rng;
+
+fn assert_close(a: Vec3, b: Vec3) {
+    assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn spheres_are_mapped_by_longitude_and_latitude() {
+    for (normal, uv) in [
+        (Vec3::new(1.0, 0.0, 0.0), [0.5, 0.5]),
+        (Vec3::new(0.0, 1.0, 0.0), [0.5, 1.0]),
+        (Vec3::new(0.0, -1.0, 0.0), [0.5, 0.0]),
+        (Vec3::new(0.0, 0.0, 1.0), [0.25, 0.5]),
+        (Vec3::new(0.0, 0.0, -1.0), [0.75, 0.5]),
+    ] {
+        let [u, v] = sphere_uv(normal);
+        assert!(
+            (u - uv[0]).abs() < 1e-5 && (v - uv[1]).abs() < 1e-5,
+            "{:?} maps to {:?}, expected {:?}",
+            normal,
+            [u, v],
+            uv
+        );
+    }
+
+    let mut rng = Rng(0x1234_5678);
+    for _ in 0..1000 {
+        let normal = rng.vec3(-1.0, 1.0).normalize();
+        let [u, v] = sphere_uv(normal);
+        assert!((0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v));
+    }
+}
+
+#[test]
+fn images_are_filtered_and_repeat() {
+    // Red and green on top, blue and white at the bottom.
+    let (red, green, blue, white) = (
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::ONE,
+    );
+    let image = Image::new(2, 2, vec![red, green, blue, white]).unwrap();
+
+    // Texel centers.
+    assert_close(image.sample([0.25, 0.75]), red);
+    assert_close(image.sample([0.75, 0.75]), green);
+    assert_close(image.sample([0.25, 0.25]), blue);
+    assert_close(image.sample([0.75, 0.25]), white);
+
+    // Halfway between texels, including across the edges.
+    assert_close(image.sample([0.5, 0.75]), (red + green) * 0.5);
+    assert_close(image.sample([0.0, 0.25]), (blue + white) * 0.5);
+    assert_close(image.sample([0.25, 1.0]), (red + blue) * 0.5);
+
+    assert_close(image.sample([1.25, -0.25]), image.sample([0.25, 0.75]));
+    assert_close(image.sample([-2.75, 3.25]), image.sample([0.25, 0.25]));
+
+    assert!(Image::new(2, 2, vec![red; 3]).is_err());
+}
+
+#[test]
+fn loaded_images_are_linear() {
+    let path = std::env::temp_dir().join("strale_texture_test.png");
+    image::RgbImage::from_raw(2, 1, vec![255, 128, 0, 0, 0, 0])
+        .unwrap()
+        .save(&path)
+        .unwrap();
+
+    let image = Image::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!((image.width, image.height), (2, 1));
+    assert_close(image.pixels[0], Vec3::new(1.0, 0.215_861, 0.0));
+    assert_close(image.pixels[1], Vec3::ZERO);
+}
+
+#[test]
+fn noise_is_smooth_and_bounded() {
+    let mut rng = Rng(0x9e37_79b9);
+
+    let mut sum = 0.0;
+    let mut sum_squared = 0.0;
+    let samples = 10_000;
+    for _ in 0..samples {
+        let p = rng.vec3(-50.0, 50.0);
+        let n = perlin_noise(p);
+        assert!(n.abs() <= 1.1, "noise of {} at {:?}", n, p);
+
+        // Lipschitz continuous, a small step can't change it by much.
+        let step = rng.vec3(-1e-3, 1e-3);
+        let change = (perlin_noise(p + step) - n).abs();
+        assert!(change <= 4.0 * step.length() + 1e-5, "jumps at {:?}", p);
+
+        sum += n as f64;
+        sum_squared += (n * n) as f64;
+    }
+
+    let mean = sum / samples as f64;
+    let variance = sum_squared / samples as f64 - mean * mean;
+    assert!(mean.abs() < 0.02, "noise has a mean of {}", mean);
+    assert!(variance > 0.02, "noise has a variance of {}", variance);
+
+    // Zero on the lattice.
+    for _ in 0..100 {
+        let p = rng.vec3(-50.0, 50.0);
+        let lattice = Vec3::new(p.x.floor(), p.y.floor(), p.z.floor());
+        assert_eq!(perlin_noise(lattice), 0.0);
+    }
+}
+
+#[test]
+fn turbulence_adds_detail() {
+    let mut rng = Rng(0x0bad_cafe);
+    for _ in 0..1000 {
+        let p = rng.vec3(-10.0, 10.0);
+        let coarse = turbulence(p, 1);
+        let fine = turbulence(p, 6);
+
+        assert_eq!(coarse, perlin_noise(p).abs());
+        assert_eq!(turbulence(p, 0), coarse);
+        // Every octave adds a non-negative amount, at most half the last one's.
+        assert!(fine >= coarse && fine <= coarse + 1.1);
+    }
+}
+
+#[test]
+fn patterns_are_evaluated_in_world_space() {
+    let (even, odd) = (Vec3::ZERO, Vec3::ONE);
+    let checker = Texture::Checker {
+        even,
+        odd,
+        scale: 2.0,
+    };
+    let at = |x: f32, y: f32, z: f32| checker.evaluate(&[], [0.0, 0.0], Vec3::new(x, y, z));
+
+    assert_eq!(at(0.25, 0.25, 0.25), even);
+    assert_eq!(at(0.75, 0.25, 0.25), odd);
+    assert_eq!(at(0.75, 0.75, 0.25), even);
+    assert_eq!(at(-0.25, 0.25, 0.25), odd);
+    assert_eq!(at(-0.25, -0.25, -0.25), odd);
+
+    let mut rng = Rng(0xfeed_f00d);
+    let color = Vec3::new(0.9, 0.5, 0.1);
+    let patterns = [
+        Texture::Noise { color, scale: 4.0 },
+        Texture::Turbulence {
+            color,
+            scale: 4.0,
+            octaves: 7,
+        },
+        Texture::Marble {
+            color,
+            scale: 4.0,
+            octaves: 7,
+        },
+    ];
+    for pattern in patterns {
+        for _ in 0..1000 {
+            let albedo = pattern.evaluate(&[], [0.0, 0.0], rng.vec3(-10.0, 10.0));
+            // A scaled copy of the color.
+            let t = albedo.x / color.x;
+            assert!((0.0..=1.0).contains(&t), "{:?}: {:?}", pattern, albedo);
+            assert_close(albedo, color * t);
+        }
+    }
+
+    let image = Image::new(1, 1, vec![color]).unwrap();
+    assert_close(
+        Texture::Image(0).evaluate(&[image], [0.3, 0.6], Vec3::ZERO),
+        color,
+    );
+}
+
+#[test]
+fn missing_images_are_rejected() {
+    let mut scene = Scene::new();
+    scene.add_material(Material::Lambertian {
+        albedo: Texture::Image(0),
+    });
+    assert!(scene.validate().is_err());
+
+    scene.add_image(Image::new(1, 1, vec![Vec3::ONE]).unwrap());
+    assert!(scene.validate().is_ok());
+}